@@ -1,174 +1,213 @@
 // Implements http://rosettacode.org/wiki/Active_object
-#![feature(std_misc)]
-#![feature(old_io)]
-
-extern crate time;
 extern crate num;
 
 use num::traits::Zero;
-use num::Float;
 use std::f64::consts::PI;
-use std::old_io::timer::Timer;
-use std::sync::{Arc, Mutex};
-use std::time::duration::Duration;
-use std::thread::spawn;
-use std::sync::mpsc::{channel, Sender, SendError};
-use std::ops::Mul;
-
-// Rust supports both shared-memory and actor models of concurrency, and the Integrator utilizes
-// both.  We use a Sender (actor model) to send the Integrator new functions, while we use a Mutex
-// (shared-memory concurrency) to hold the result of the integration.
+use std::ops::{Add, Mul};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use actor::{Actor, Context, Handle, Promise, Restart, Timer, start};
+use numerical_integration::Rule;
+
+// not all of them are needed here
+#[allow(dead_code)]
+mod actor;
+#[allow(dead_code)]
+mod numerical_integration;
+
+// The Integrator is an actor (see actor.rs): it lives on its own thread, and everything it
+// does, it does because of a message.  Setting the input function is a message, the timer
+// ticking is a message, and so is asking for the output--that one carries a Promise for the
+// Integrator to keep, with the answer.  Because only the Integrator's thread ever touches its
+// state, none of it needs a Mutex.
 //
-// Note that these are not the only options here--there are many, many ways you can deal with
-// concurrent access.  But when in doubt, a plain old Mutex is often a good bet.  For example, this
-// might look like a good situation for a RwLock--after all, there's no reason for a read in the
-// main task to block writes.  Unfortunately, unless you have significantly more reads than
-// writes (which is certainly not the case here), a Mutex will usually outperform a RwLock.
-pub struct Integrator<S: 'static, T: Send> {
-    input: Sender<Box<Fn(i64) -> S + Send>>,
-    output: Arc<Mutex<T>>,
+// The messages are handled one at a time in the order they were sent, so a new input function
+// takes over from the next tick on, and an output asked for after some ticks includes them.
+enum Message<S, T> {
+    Input(Box<Fn(f64) -> S + Send>),
+    // the time since the integrator started, in seconds
+    Tick(f64),
+    Output(Promise<T>),
+}
+
+struct State<S, T> {
+    k: Box<Fn(f64) -> S + Send>,
+    // the time of the last tick
+    t: f64,
+    s: T,
+    rule: Rule,
+}
+
+// The type parameter S (the result of the function being integrated) must yield T (the type of
+// the integrated value) when multiplied by f64, the length of a tick in seconds.  We could
+// possibly replace f64 with a generic as well, but it would make things a bit more complex.
+impl<S, T> Actor for State<S, T>
+    where S: 'static + Send + Mul<f64, Output=T>,
+          T: 'static + Send + Copy + Add<Output=T>
+{
+    type Message = Message<S, T>;
+
+    fn handle(&mut self, message: Message<S, T>, _: &mut Context<Message<S, T>>)
+              -> Result<(), String> {
+        match message {
+            Message::Input(k) => self.k = k,
+            Message::Tick(t) => {
+                // The rule (the trapezoid rule, unless we were told otherwise) over however long
+                // it really was since the last tick.
+                let step = self.rule.apply(&*self.k, self.t, t);
+                self.s = self.s + step;
+                self.t = t;
+            }
+            Message::Output(promise) => promise.keep(self.s),
+        }
+        Ok(())
+    }
+}
+
+// Where the ticks come from.  With a real clock the results depend on when the ticks happen to
+// arrive, so for anything that has to come out the same each time there's a simulated clock,
+// which only moves on when told to.
+pub enum Clock {
+    // ticking this often
+    Real(Duration),
+    Simulated,
+}
+
+pub struct Integrator<S: 'static + Send, T: 'static + Send> {
+    // The timer is dropped first, so the ticks stop before the actor does.
+    timer: Option<Timer>,
+    actor: Handle<Message<S, T>>,
 }
 
-// In Rust, time durations are strongly typed.  This is usually exactly what you want, but for a
-// problem like this--where the integrated value has unusual (unspecified?) units--it can actually
-// be a bit tricky.  Right now, Durations can only be multiplied or divided by i32s, so in order to
-// be able to actually do math with them we say that the type parameter S (the result of the
-// function being integrated) must yield T (the type of the integrated value) when multiplied by
-// f64.  We could possibly replace f64 with a generic as well, but it would make things a bit more
-// complex.
-impl<S: Mul<f64, Output=T> + Float + Zero,
-     T: 'static + Clone + Send + Float> Integrator<S, T> {
+impl<S, T> Integrator<S, T>
+    where S: 'static + Send + Zero + Mul<f64, Output=T>,
+          T: 'static + Send + Copy + Zero + Add<Output=T>
+{
     pub fn new(frequency: Duration) -> Integrator<S, T> {
-        // We create a pipe allowing functions to be sent from tx (the sending end) to input (the
-        // receiving end).  In order to change the function we are integrating from the task in
-        // which the Integrator lives, we simply send the function through tx.
-        let (tx, input) = channel();
-        // The easiest way to do shared-memory concurrency in Rust is to use atomic reference
-        // counting, or Arc, around a synchronized type (like Mutex<T>).  Arc gives you a guarantee
-        // that memory will not be freed as long as there is at least one reference to it.
-        // It is similar to C++'s shared_ptr, but it is guaranteed to be safe and is never
-        // incremented unless explicitly cloned (by default, it is moved).
-        let s: Arc<Mutex<T>> = Arc::new(Mutex::new(Zero::zero()));
-        let integrator = Integrator {
-            input: tx,
-            // Here is the aforementioned clone.  We have to do it before s enters the closure,
-            // because once that happens it is moved into the closure (and later, the new task) and
-            // becomes inaccessible to the outside world.
-            output: s.clone(),
-        };
-        spawn(move || -> () {
-            // There is a reasonable argument for failure on failure to initialize the Timer, but
-            // it's essentially certain that if we return from the spawn this early main will
-            // detect that something is amiss.
-            let mut timer = match Timer::new() {
-                Ok(timer) => timer,
-                Err(_) => return
-            };
-            // The frequency is how often we want to "tick" as we update our integrated total.  In
-            // Rust, timers can yield Receivers that are periodically notified with an empty
-            // message (where the period is the frequency).  This is useful because it lets us wait
-            // on either a tick or another type of message (in this case, a request to change the
-            // function we are integrating).
-            let periodic = timer.periodic(frequency);
-            let frequency_ms = frequency.num_milliseconds();
-            let mut t = 0;
-            let mut k: Box<Fn(i64) -> S + Send> = Box::new(|_| Zero::zero());
-            let mut k_0: S = Zero::zero();
-            loop {
-                // Here's the selection we talked about above.  Note that we are careful to call
-                // the *non*-failing function, recv(), here.  The reason we do this is because
-                // recv() will return Err when the sending end of a channel is dropped.  While
-                // this is unlikely to happen for the timer (so again, you could argue for failure
-                // there), it's normal behavior for the sending end of input to be dropped, since
-                // it just happens when the Integrator falls out of scope.  So we handle it cleanly
-                // and break out of the loop, rather than failing.
-                select! {
-                    res = periodic.recv() => match res {
-                        Ok(_) => {
-                            t += frequency_ms;
-                            let k_1: S = k(t);
-                            // Rust Mutexes are a bit different from Mutexes in many other
-                            // languages, in that the protected data is actually encapsulated by
-                            // the Mutex.  The reason for this is that Rust is actually capable of
-                            // enforcing (via its borrow checker) the invariant that the contents
-                            // of a Mutex may only be read when you have acquired its lock.  This
-                            // is enforced by way of a MutexGuard, the return value of lock(),
-                            // which implements some special traits (Deref and DerefMut) that allow
-                            // access to the inner element "through" the guard.  The element so
-                            // acquired has a lifetime bounded by that of the MutexGuard, the
-                            // MutexGuard can only be acquired by taking a lock, and the only way
-                            // to release the lock is by letting the MutexGuard fall out of scope,
-                            // so it's impossible to access the data incorrectly.  There are some
-                            // additional subtleties around the actual implementation, but that's
-                            // the basic idea.
-                            let mut s = s.lock().unwrap();
-                            *s = *s + (k_1 + k_0) * (frequency_ms as f64 / 2.);
-                            k_0 = k_1;
-                        }
-                        Err(_) => break,
-                    },
-                    res = input.recv() => match res {
-                        Ok(k_new) => k = k_new,
-                        Err(_) => break,
-                    }
-                }
+        Integrator::with_clock(Clock::Real(frequency), Rule::trapezoid)
+    }
+
+    // `rule` makes the rule for integrating between ticks.
+    pub fn with_clock(clock: Clock, rule: fn() -> Rule) -> Integrator<S, T> {
+        // If integrating ever panics there's nothing sensible to restart with, so we don't.
+        let actor = start(move || State {
+            k: Box::new(|_| Zero::zero()),
+            t: 0.,
+            s: Zero::zero(),
+            rule: rule(),
+        }, Restart::Never);
+
+        // The frequency is how often we want to "tick" as we update our integrated total.  The
+        // ticks carry the time they really happened, since a busy machine can make them late.
+        let timer = match clock {
+            Clock::Real(frequency) => {
+                let mut timer = Timer::new();
+                let started = Instant::now();
+                timer.send_every(frequency, actor.address(), move |now| {
+                    let elapsed = now - started;
+                    Message::Tick(elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9)
+                });
+                Some(timer)
             }
-        });
-        integrator
+            Clock::Simulated => None,
+        };
+
+        Integrator { timer: timer, actor: actor }
+    }
+
+    // Moves a simulated clock on to t seconds, integrating up to then.
+    pub fn tick(&self, t: f64) {
+        assert!(self.timer.is_none(), "Only a simulated clock can be moved on by hand");
+        let _ = self.actor.send(Message::Tick(t));
     }
 
-    pub fn input(&self, k: Box<Fn(i64) -> S + Send>) ->
-                Result<(), SendError<Box<Fn(i64) -> S + Send>>> {
-        // The meat of the work is done in the other thread, so to set the
-        // input we just send along the Sender we set earlier...
-        self.input.send(k)
+    pub fn input(&self, k: Box<Fn(f64) -> S + Send>) -> Result<(), Box<Fn(f64) -> S + Send>> {
+        // The meat of the work is done in the other thread, so to set the input we just send
+        // it along...
+        self.actor.send(Message::Input(k)).map_err(|message| match message {
+            Message::Input(k) => k,
+            _ => unreachable!(),
+        })
     }
 
     pub fn output(&self) -> T {
-        // ...and to read the input, we simply acquire a lock on the output Mutex and return a
-        // clone.  Why do we have to clone it?  Because, as mentioned above, Rust won't let us
-        // retain access to the interior of the Mutex unless we have possession of its lock.  There
-        // are ways and circumstances in which one can avoid this (e.g. by using atomic types) but
-        // clone() is a perfectly reasonable solution as well, and a lot easier to reason about :)
-        self.output.lock().unwrap().clone()
+        // ...and to read the output, we ask for it and wait for the answer.
+        self.actor.ask(Message::Output).wait().expect("The integrator has stopped")
     }
 }
 
 // This function is fairly straightforward.  We create the integrator, set its input function k(t)
-// to 2pi * f * t, and then wait as described in the Rosetta stone problem.
-#[cfg(not(test))]
-fn integrate() -> f64 {
-    let object = Integrator::new(Duration::milliseconds(10));
-    let mut timer = Timer::new().unwrap();
-    object.input(Box::new(|t: i64| {
-        let f = 1. / Duration::seconds(2).num_milliseconds() as f64;
-        (2. * PI * f * t as f64).sin()
-    })).ok().expect("Failed to set input");
-    timer.sleep(Duration::seconds(2));
+// to sin(2pi * f * t) with f = 0.5Hz, and then wait as described in the Rosetta stone problem.
+// Integrating for one whole period of the sine gives 0.
+fn integrate(period: Duration, f: f64) -> f64 {
+    let object = Integrator::new(Duration::from_millis(10));
+    object.input(Box::new(move |t: f64| (2. * PI * f * t).sin()))
+        .ok().expect("Failed to set input");
+    thread::sleep(period);
+    object.input(Box::new(|_| 0.)).ok().expect("Failed to set input");
+    thread::sleep(period / 4);
+    object.output()
+}
+
+// The same on a simulated clock, ticking every `step` seconds: `period` seconds of the sine, then a
+// quarter of that of nothing.
+fn integrate_simulated(period: f64, f: f64, step: f64, rule: fn() -> Rule) -> f64 {
+    let object = Integrator::with_clock(Clock::Simulated, rule);
+    object.input(Box::new(move |t: f64| (2. * PI * f * t).sin()))
+        .ok().expect("Failed to set input");
+    let ticks = (period / step).round() as usize;
+    for i in 1..ticks + 1 {
+        object.tick(i as f64 * step);
+    }
     object.input(Box::new(|_| 0.)).ok().expect("Failed to set input");
-    timer.sleep(Duration::seconds(1) / 2);
+    for i in ticks + 1..ticks + ticks / 4 + 1 {
+        object.tick(i as f64 * step);
+    }
     object.output()
 }
 
+// What it should come to: the integral of sin(2pi * f * t) from 0 to the period.
+fn exactly(period: f64, f: f64) -> f64 {
+    (1. - (2. * PI * f * period).cos()) / (2. * PI * f)
+}
+
 #[cfg(not(test))]
 fn main() {
-    println!("{}", integrate());
+    println!("{}", integrate(Duration::from_secs(2), 0.5));
+    println!("simulated: {}", integrate_simulated(2., 0.5, 0.01, Rule::trapezoid));
+    println!("exactly: {}", exactly(2., 0.5));
 }
 
 #[test]
 #[ignore] // Will fail on a heavily loaded machine
 fn solution() {
-    // We should just be able to call integrate, but can't represent the closure properly due to
-    // rust-lang/rust issue #17060 if we make frequency or period a variable.
-    // FIXME(pythonesque): When unboxed closures are fixed, fix integrate() to take two arguments.
-    let object = Integrator::new(Duration::milliseconds(10));
-    let mut timer = Timer::new().unwrap();
-    object.input(Box::new(|t: i64| {
-        let f = 1. / (Duration::seconds(2) / 10).num_milliseconds() as f64;
-        (2. * PI * f * t as f64).sin()
-    })).ok().expect("Failed to set input");
-    timer.sleep(Duration::seconds(2) / 10);
-    object.input(Box::new(|_| 0.)).ok().expect("Failed to set input");
-    timer.sleep(Duration::seconds(1) / 10);
-    assert_eq!(object.output() as i64, 0)
+    assert_eq!(integrate(Duration::from_millis(200), 5.) as i64, 0)
+}
+
+#[test]
+fn simulated_solution() {
+    assert!(integrate_simulated(2., 0.5, 0.01, Rule::trapezoid).abs() < 1e-12);
+    assert_eq!(integrate_simulated(2., 0.5, 0.01, Rule::trapezoid),
+               integrate_simulated(2., 0.5, 0.01, Rule::trapezoid));
+
+    // a quarter of the way through, the sine's all above 0
+    let quarter = exactly(0.5, 0.5);
+    assert!((quarter - 1. / PI).abs() < 1e-15);
+    assert!((integrate_simulated(0.5, 0.5, 0.01, Rule::trapezoid) - quarter).abs() < 1e-4);
+    assert!((integrate_simulated(0.5, 0.5, 0.01, Rule::simpson) - quarter).abs() < 1e-9);
+    assert!((integrate_simulated(0.5, 0.5, 0.1, || Rule::gauss_legendre(4)) - quarter).abs() < 1e-12);
+}
+
+#[test]
+fn changing_input() {
+    let object = Integrator::with_clock(Clock::Simulated, Rule::simpson);
+    object.input(Box::new(|t: f64| 2. * t)).ok().unwrap();
+    object.tick(1.);
+    object.tick(3.);
+    assert_eq!(object.output(), 9.);
+    // from the next tick on
+    object.input(Box::new(|_| 1.)).ok().unwrap();
+    object.tick(4.);
+    assert_eq!(object.output(), 10.);
 }
@@ -0,0 +1,513 @@
+// Active objects (actors): each one runs on its own thread, and the only way
+// to talk to it is to send it a message. Used by active_object.rs.
+//
+// An actor's messages go into its mailbox, a channel, and it handles them
+// one at a time, in the order they were sent. A message that needs an
+// answer carries a `Promise` for the actor to keep; the sender waits on the
+// matching `Future`.
+//
+// Each actor is looked after by a supervisor thread. If the actor panics,
+// or returns an error from `handle`, the supervisor can make a new one from
+// scratch to carry on with the same mailbox, depending on its `Restart`
+// policy. Stopping an actor lets it finish the messages already sent to it.
+//
+// A `Timer` sends messages at regular intervals, for actors that have to do
+// something over and over.
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Sender, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub trait Actor: Send + 'static {
+    type Message: Send + 'static;
+
+    // An Err means the actor has crashed.
+    fn handle(&mut self, message: Self::Message, context: &mut Context<Self::Message>)
+              -> Result<(), String>;
+
+    // Called once everything sent before `stop` has been handled.
+    fn stopped(&mut self) {}
+}
+
+enum Envelope<M> {
+    Message(M),
+    Stop,
+}
+
+// Where to send an actor messages. There can be as many of these as you
+// like.
+pub struct Address<M> {
+    sender: Sender<Envelope<M>>,
+}
+
+impl<M> Clone for Address<M> {
+    fn clone(&self) -> Address<M> {
+        Address { sender: self.sender.clone() }
+    }
+}
+
+impl<M: Send + 'static> Address<M> {
+    // Gives the message back if the actor isn't there to take it.
+    pub fn send(&self, message: M) -> Result<(), M> {
+        self.sender.send(Envelope::Message(message)).map_err(|e| match e.0 {
+            Envelope::Message(message) => message,
+            Envelope::Stop => unreachable!(),
+        })
+    }
+
+    // Sends a message with a promise in it, made by `request`, and returns
+    // the future the actor will answer through.
+    pub fn ask<T, F>(&self, request: F) -> Future<T>
+        where F: FnOnce(Promise<T>) -> M
+    {
+        let (promise, future) = promise();
+        // if the actor has gone, so has the promise, and the future says so
+        let _ = self.send(request(promise));
+        future
+    }
+}
+
+// The actor's side of an answer. Dropping it without keeping it breaks it.
+pub struct Promise<T> {
+    sender: Sender<T>,
+}
+
+impl<T> Promise<T> {
+    pub fn keep(self, value: T) {
+        let _ = self.sender.send(value);
+    }
+}
+
+// The asker's side of an answer.
+pub struct Future<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Future<T> {
+    // Waits for the answer. None if the promise was broken.
+    pub fn wait(self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+
+    // The same, giving up after a while.
+    pub fn wait_timeout(self, timeout: Duration) -> Option<T> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+}
+
+pub fn promise<T>() -> (Promise<T>, Future<T>) {
+    let (sender, receiver) = channel();
+    (Promise { sender: sender }, Future { receiver: receiver })
+}
+
+// What an actor gets to see of itself while it handles a message.
+pub struct Context<M> {
+    address: Address<M>,
+    stopping: bool,
+}
+
+impl<M: Send + 'static> Context<M> {
+    pub fn address(&self) -> Address<M> {
+        self.address.clone()
+    }
+
+    // Stops after this message, without handling the rest of the mailbox.
+    pub fn stop(&mut self) {
+        self.stopping = true;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Restart {
+    Never,
+    Always,
+    // no more than this many times
+    UpTo(usize),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Exit {
+    Stopped,
+    // the last crash, when the supervisor gave up
+    Crashed(String),
+}
+
+// The owner's hold on an actor. Dropping it stops the actor and waits for it.
+pub struct Handle<M: Send + 'static> {
+    address: Address<M>,
+    supervisor: Option<JoinHandle<Exit>>,
+    restarts: Arc<Mutex<usize>>,
+}
+
+impl<M: Send + 'static> Handle<M> {
+    pub fn address(&self) -> Address<M> {
+        self.address.clone()
+    }
+
+    pub fn send(&self, message: M) -> Result<(), M> {
+        self.address.send(message)
+    }
+
+    pub fn ask<T, F>(&self, request: F) -> Future<T>
+        where F: FnOnce(Promise<T>) -> M
+    {
+        self.address.ask(request)
+    }
+
+    // How many times the actor has been started again.
+    pub fn restarts(&self) -> usize {
+        *self.restarts.lock().unwrap()
+    }
+
+    // Lets the actor handle what it's been sent so far, then waits for it
+    // to finish.
+    pub fn stop(mut self) -> Exit {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Exit {
+        let _ = self.address.sender.send(Envelope::Stop);
+        match self.supervisor.take() {
+            Some(supervisor) => supervisor.join().unwrap_or(Exit::Crashed("supervisor".to_string())),
+            None => Exit::Stopped,
+        }
+    }
+}
+
+impl<M: Send + 'static> Drop for Handle<M> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+// Starts an actor made by `make`, which is called again to replace it each
+// time it crashes, for as long as `restart` allows.
+pub fn start<A, F>(make: F, restart: Restart) -> Handle<A::Message>
+    where A: Actor, F: Fn() -> A + Send + 'static
+{
+    let (sender, receiver) = channel();
+    let address = Address { sender: sender };
+    let mailbox = Arc::new(Mutex::new(receiver));
+    let restarts = Arc::new(Mutex::new(0));
+
+    let context_address = address.clone();
+    let restart_count = restarts.clone();
+    let supervisor = thread::spawn(move || {
+        loop {
+            let actor = make();
+            let mailbox = mailbox.clone();
+            let address = context_address.clone();
+            let worker = thread::spawn(move || run(actor, &mailbox, address));
+            let error = match worker.join() {
+                Ok(Ok(())) => return Exit::Stopped,
+                Ok(Err(error)) => error,
+                Err(panic) => describe(panic),
+            };
+
+            let mut count = restart_count.lock().unwrap();
+            let again = match restart {
+                Restart::Never => false,
+                Restart::Always => true,
+                Restart::UpTo(most) => *count < most,
+            };
+            if !again {
+                return Exit::Crashed(error);
+            }
+            *count += 1;
+        }
+    });
+
+    Handle { address: address, supervisor: Some(supervisor), restarts: restarts }
+}
+
+fn run<A: Actor>(mut actor: A, mailbox: &Mutex<Receiver<Envelope<A::Message>>>,
+                 address: Address<A::Message>) -> Result<(), String> {
+    let mut context = Context { address: address, stopping: false };
+    // A worker that panicked poisons the lock, but the mailbox is fine.
+    let mailbox = mailbox.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        match mailbox.recv() {
+            Ok(Envelope::Message(message)) => try!(actor.handle(message, &mut context)),
+            Ok(Envelope::Stop) | Err(_) => break,
+        }
+        if context.stopping {
+            break;
+        }
+    }
+    actor.stopped();
+    Ok(())
+}
+
+fn describe(panic: Box<Any + Send>) -> String {
+    if let Some(s) = panic.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = panic.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "panicked".to_string()
+    }
+}
+
+enum Command {
+    Add(usize, Duration, Box<FnMut(Instant) -> bool + Send>),
+    Cancel(usize),
+    Shutdown,
+}
+
+struct Repeat {
+    id: usize,
+    next: Instant,
+    period: Duration,
+    tick: Box<FnMut(Instant) -> bool + Send>,
+}
+
+// Runs things at regular intervals, all on one thread.
+pub struct Timer {
+    commands: Sender<Command>,
+    next_id: usize,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        let (commands, receiver) = channel();
+        let thread = thread::spawn(move || Timer::run(receiver));
+        Timer { commands: commands, next_id: 0, thread: Some(thread) }
+    }
+
+    // Calls `tick` with the time every `period`, starting one period from
+    // now, until it returns false or is cancelled. Each tick is due a whole
+    // period after the one before was due, so a late one doesn't make the
+    // rest late too. A period of zero would never let the timer rest, so
+    // it isn't allowed.
+    pub fn every<F>(&mut self, period: Duration, tick: F) -> usize
+        where F: FnMut(Instant) -> bool + Send + 'static
+    {
+        assert!(period > Duration::from_secs(0), "A timer's period can't be zero");
+        let id = self.next_id;
+        self.next_id += 1;
+        let _ = self.commands.send(Command::Add(id, period, Box::new(tick)));
+        id
+    }
+
+    // Sends the actor the message `make` makes every `period`, for as long
+    // as it's there.
+    pub fn send_every<M, F>(&mut self, period: Duration, address: Address<M>, make: F) -> usize
+        where M: Send + 'static, F: Fn(Instant) -> M + Send + 'static
+    {
+        self.every(period, move |now| address.send(make(now)).is_ok())
+    }
+
+    pub fn cancel(&self, id: usize) {
+        let _ = self.commands.send(Command::Cancel(id));
+    }
+
+    fn run(commands: Receiver<Command>) {
+        let mut repeats: Vec<Repeat> = vec![];
+        loop {
+            let command = match repeats.iter().map(|r| r.next).min() {
+                None => commands.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                Some(next) => {
+                    let now = Instant::now();
+                    // commands sent before the tick was due come first
+                    if next <= now {
+                        commands.try_recv().map_err(|e| match e {
+                            TryRecvError::Empty => RecvTimeoutError::Timeout,
+                            TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+                        })
+                    } else {
+                        commands.recv_timeout(next - now)
+                    }
+                }
+            };
+
+            match command {
+                Ok(Command::Add(id, period, tick)) => {
+                    repeats.push(Repeat { id: id, next: Instant::now() + period, period: period, tick: tick });
+                }
+                Ok(Command::Cancel(id)) => repeats.retain(|r| r.id != id),
+                Ok(Command::Shutdown) | Err(RecvTimeoutError::Disconnected) => return,
+                Err(RecvTimeoutError::Timeout) => {
+                    let now = Instant::now();
+                    let mut i = 0;
+                    while i < repeats.len() {
+                        if repeats[i].next <= now {
+                            let period = repeats[i].period;
+                            repeats[i].next += period;
+                            if !(repeats[i].tick)(now) {
+                                repeats.remove(i);
+                                continue;
+                            }
+                        }
+                        i += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// A counter, to show the pieces working together.
+#[cfg(not(test))]
+enum Count {
+    Add(i64),
+    Get(Promise<i64>),
+}
+
+#[cfg(not(test))]
+struct Counter {
+    total: i64,
+}
+
+#[cfg(not(test))]
+impl Actor for Counter {
+    type Message = Count;
+
+    fn handle(&mut self, message: Count, _: &mut Context<Count>) -> Result<(), String> {
+        match message {
+            Count::Add(n) if self.total + n < 0 => return Err(format!("{} is too many", n)),
+            Count::Add(n) => self.total += n,
+            Count::Get(promise) => promise.keep(self.total),
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+#[cfg(not(test))]
+fn main() {
+    let counter = start(|| Counter { total: 0 }, Restart::UpTo(1));
+    let mut timer = Timer::new();
+    timer.send_every(Duration::from_millis(10), counter.address(), |_| Count::Add(1));
+    thread::sleep(Duration::from_millis(105));
+    println!("ticks after about 100ms: {:?}", counter.ask(Count::Get).wait());
+
+    // going below zero crashes it, and it starts again from nothing
+    let _ = counter.send(Count::Add(-1000));
+    println!("after a crash: {:?} ({} restart)", counter.ask(Count::Get).wait(), counter.restarts());
+    let _ = counter.send(Count::Add(-1000));
+    println!("after another: {:?}", counter.ask(Count::Get).wait());
+    println!("{:?}", counter.stop());
+}
+
+#[cfg(test)]
+struct Log {
+    seen: Vec<i32>,
+}
+
+#[cfg(test)]
+enum LogMessage {
+    Push(i32),
+    Seen(Promise<Vec<i32>>),
+    Crash,
+    Panic,
+    StopNow,
+}
+
+#[cfg(test)]
+impl Actor for Log {
+    type Message = LogMessage;
+
+    fn handle(&mut self, message: LogMessage, context: &mut Context<LogMessage>) -> Result<(), String> {
+        match message {
+            LogMessage::Push(n) => self.seen.push(n),
+            LogMessage::Seen(promise) => promise.keep(self.seen.clone()),
+            LogMessage::Crash => return Err("crashed".to_string()),
+            LogMessage::Panic => panic!("panicked on purpose"),
+            LogMessage::StopNow => context.stop(),
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn messages_in_order() {
+    let log = start(|| Log { seen: vec![] }, Restart::Never);
+    let address = log.address();
+    for n in 0..100 {
+        address.send(LogMessage::Push(n)).ok().unwrap();
+    }
+    assert_eq!(log.ask(LogMessage::Seen).wait(), Some((0..100).collect()));
+    assert_eq!(log.stop(), Exit::Stopped);
+    // nobody's there now
+    assert!(address.send(LogMessage::Push(1)).is_err());
+    assert_eq!(address.ask(LogMessage::Seen).wait(), None);
+}
+
+#[test]
+fn restarts() {
+    let log = start(|| Log { seen: vec![] }, Restart::UpTo(2));
+    log.send(LogMessage::Push(1)).ok().unwrap();
+    log.send(LogMessage::Crash).ok().unwrap();
+    log.send(LogMessage::Push(2)).ok().unwrap();
+    assert_eq!(log.ask(LogMessage::Seen).wait(), Some(vec![2]));
+    log.send(LogMessage::Panic).ok().unwrap();
+    assert_eq!(log.ask(LogMessage::Seen).wait(), Some(vec![]));
+    assert_eq!(log.restarts(), 2);
+    log.send(LogMessage::Crash).ok().unwrap();
+    assert_eq!(log.stop(), Exit::Crashed("crashed".to_string()));
+
+    let log = start(|| Log { seen: vec![] }, Restart::Never);
+    log.send(LogMessage::Panic).ok().unwrap();
+    assert_eq!(log.stop(), Exit::Crashed("panicked on purpose".to_string()));
+}
+
+#[test]
+fn stopping() {
+    // stop waits for what's already been sent
+    let log = start(|| Log { seen: vec![] }, Restart::Never);
+    let address = log.address();
+    let (promise, future) = promise();
+    for n in 0..10 {
+        address.send(LogMessage::Push(n)).ok().unwrap();
+    }
+    address.send(LogMessage::Seen(promise)).ok().unwrap();
+    assert_eq!(log.stop(), Exit::Stopped);
+    assert_eq!(future.wait(), Some((0..10).collect()));
+
+    // an actor can stop itself
+    let log = start(|| Log { seen: vec![] }, Restart::Always);
+    log.send(LogMessage::StopNow).ok().unwrap();
+    log.send(LogMessage::Push(1)).ok().unwrap();
+    assert_eq!(log.ask(LogMessage::Seen).wait_timeout(Duration::from_secs(5)), None);
+    assert_eq!(log.stop(), Exit::Stopped);
+}
+
+#[test]
+fn timer() {
+    let log = start(|| Log { seen: vec![] }, Restart::Never);
+    let mut timer = Timer::new();
+    let mut n = 0;
+    let (promise, future) = promise();
+    let mut promise = Some(promise);
+    let address = log.address();
+    timer.every(Duration::from_millis(1), move |_| {
+        n += 1;
+        address.send(LogMessage::Push(n)).ok().unwrap();
+        if n == 5 {
+            promise.take().unwrap().keep(());
+        }
+        n < 5
+    });
+    let cancelled = timer.send_every(Duration::from_millis(1), log.address(), |_| LogMessage::Push(0));
+    timer.cancel(cancelled);
+    future.wait().unwrap();
+    drop(timer);
+    assert_eq!(log.ask(LogMessage::Seen).wait(), Some(vec![1, 2, 3, 4, 5]));
+}
+
+#[test]
+#[should_panic]
+fn zero_period() {
+    Timer::new().every(Duration::from_secs(0), |_| true);
+}
@@ -1,265 +1,524 @@
 // Implements http://rosettacode.org/wiki/Atomic_updates
 //
-// This is mostly a straight port of the D version.  Originally, the "non-locking" Go solution was
-// tried, because it was supposed to be faster than the version with Mutexes, but my experience was
-// that this was not the case.  Perhaps it is true with green threads.  D's version was much faster
-// and this version seems to achieve parity with the benchmarks on the Rosetta Code site (at least
-// on my machine).  I am pretty sure it could be made faster, though--for example, the Mutex type
-// we're using here was the fourth type I tried but the first to produce acceptable performance
-// (previously I tried, in order, std::sync::RwLock, std::sync::Mutex, and std::sync::Semaphore)
-// and this type still appears to have quite a bit of overhead.
-#![feature(core)]
+// This started out as a straight port of the D version.  Originally, the "non-locking" Go
+// solution was tried, because it was supposed to be faster than the version with Mutexes, but my
+// experience was that this was not the case.  Perhaps it is true with green threads.  D's version
+// was much faster and this version seems to achieve parity with the benchmarks on the Rosetta
+// Code site (at least on my machine).
+//
+// Since then the buckets have grown into a small sharded store, so the different ways of keeping
+// them consistent can be compared side by side: a Mutex per bucket (the D way), one lock for the
+// lot, an optimistic scheme where nobody ever waits for a lock, and software transactional
+// memory (see stm.rs).  Besides how many transactions each gets through, it counts how many had
+// to start again because another got in the way.  Run it with a strategy name to try just that
+// one:
+//
+//     atomic_updates [SECONDS [BUCKETS [WORKERS [sharded|global|optimistic|stm]]]]
 extern crate rand;
 
 use rand::{Rng, weak_rng};
 use rand::distributions::{IndependentSample, Range};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::thread::{self, spawn};
+use std::time::Duration;
+
+use buckets::{Buckets, Global, Optimistic, Sharded, Transactional};
+
+// not all of it is needed here
+#[allow(dead_code)]
+mod stm;
 
 // The reason I used a module here is simply to keep it clearer who can access what.  Rust
 // protects against data races just fine, but it's not as good at protecting against deadlocks or
 // other types of race conditions.
 mod buckets {
+    use std::any::Any;
     use std::sync::atomic::{AtomicUsize, Ordering};
-    use std::sync::Mutex;
+    use std::sync::{Mutex, MutexGuard};
+    use std::thread;
 
-    // We hardcode the number of buckets mostly for convenience.  Now that Rust has dynamically
-    // sized types, this is possibly no longer a problem.
+    use stm::{TVar, atomically, atomically_counting};
 
-    // To expand: in Rust, there are two special kinds, Sync and Send, used for concurrency.
-    //
-    // If T is Sync, and you take an immutable reference of type &T, then it's safe to share between
-    // threads.  Most types are Sync unless they contain non-threadsafe interior mutability: for
-    // example, Cell and RefCell are not Sync, because they can be modified through a & reference
-    // in a non-threadsafe way.  On the other hand, atomic types are Sync even though they can be
-    // modified this way.  So are types accessible through a Mutex.
-    //
-    // The other type is Send.  Send's semantic meanining is "a type that can be sent between
-    // tasks."  For most practical purposes, this means "has no non-'static references" (with a very
-    // few exceptions, like the Rc type, which are non-Send anyway).  The idea is that it is not
-    // safe to send a type between tasks if it has any non-static references, because one doesn't
-    // know when the data it's referencing will be deallocated if it's on some other task's stack.
-    //
-    // Usually, that's a reasonable assumption.  But it means that if we want to put a slice in a
-    // structure and share it between tasks, it can't be a &[T]--it has to be a bare [T].  Arc has
-    // Send + Sync bounds for this reason.  We could also use a Vec, but this would introduce
-    // unnecessary indirection.  With dynamically sized types, this problem may be solved--we can
-    // have a dynamically sized struct that doesn't contain explicit references.  But until that is
-    // fully baked, this seems like the sanest solution.
+    // Why a transaction was turned down.
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub enum KeyError {
+        OutOfRange(usize),
+        // A bucket can only be in a transaction once: with a lock per bucket, taking the same
+        // one twice would deadlock, since Rust mutexes are nonreentrant.
+        Repeated(usize),
+    }
+
+    fn check_keys(keys: &[usize], len: usize) -> Result<(), KeyError> {
+        for (i, &key) in keys.iter().enumerate() {
+            if key >= len {
+                return Err(KeyError::OutOfRange(key));
+            }
+            if keys[..i].contains(&key) {
+                return Err(KeyError::Repeated(key));
+            }
+        }
+        Ok(())
+    }
+
+    // A fixed number of buckets holding values, which can be read and updated from any number of
+    // threads at once.
     //
-    // (Another way to solve this would be associated constants, something Rust does not have yet).
-    pub const N_BUCKETS: usize = 20;
-
-    // We don't really have to hardcode the workers.  This is left over from the Go implementation.
-    // All the counting statistics could be moved outside of buckets and probably should, since
-    // they have no influence on the correctness of the algorithm.
-    pub const N_WORKERS: usize = 2;
-
-    struct Bucket {
-        data: AtomicUsize,       // The actual data.  It is atomic because it is read (not written)
-                                // outside the Mutex, unless a consistent snapshot is required.
-        mutex: Mutex<()>,       // The mutex used to synchronize writes and snapshot reads of the
-                                // bucket.
-                                // As the D solution says, using a per-bucket Mutex dramatically
-                                // improves scalability compared to the alternatives.
+    // If T is Sync, and you take an immutable reference of type &T, then it's safe to share
+    // between threads.  Most types are Sync unless they contain non-threadsafe interior
+    // mutability: for example, Cell and RefCell are not Sync, because they can be modified
+    // through a & reference in a non-threadsafe way.  On the other hand, atomic types are Sync
+    // even though they can be modified this way.  So are types accessible through a Mutex.  All
+    // the Buckets here are Sync, so they can be shared with an Arc without needing a Mutex
+    // around the lot.
+    pub trait Buckets<T>: Send + Sync {
+        fn name(&self) -> &'static str;
+
+        fn len(&self) -> usize;
+
+        // The value of the bucket at index i, or None if out of bounds.  This is used as an
+        // estimate, so there's no compelling reason to demand consistency here.
+        fn get(&self, i: usize) -> Option<T>;
+
+        // Calls `update` with the values of the buckets at `keys`, in that order, and stores
+        // what it leaves there, all in one go: no other transaction or snapshot sees some of the
+        // buckets changed and not others.  `update` may be called more than once, so it should
+        // only depend on the values it's given.
+        fn transaction(&self, keys: &[usize], update: &mut FnMut(&mut [T]))
+                       -> Result<(), KeyError>;
+
+        // A consistent snapshot of all the buckets: a state they really were in at some moment.
+        fn snapshot(&self) -> Vec<T>;
+
+        // How many times transactions have had to start again because of other transactions.
+        // Those that lock first never do.
+        fn aborts(&self) -> usize { 0 }
     }
 
-    pub struct Buckets {
-        buckets: [Bucket; N_BUCKETS ], // Buckets containing values to be transferred.
-        transfers: [AtomicUsize; N_WORKERS ], // Statistics about total transfers this go-around.
+    // Move at most `amount` from one bucket to another.
+    pub fn transfer<B: Buckets<usize> + ?Sized>(buckets: &B, from: usize, to: usize, amount: usize)
+                                               -> Result<(), KeyError> {
+        buckets.transaction(&[from, to], &mut |values| {
+            let real_amount = ::std::cmp::min(values[0], amount);
+            values[0] -= real_amount;
+            values[1] += real_amount;
+        })
     }
 
-    impl Buckets {
-        // Create a new Buckets instance.
-        pub fn new(buckets: [usize; N_BUCKETS]) -> Buckets {
-            // The unsafe initialization here is required because Bucket is not Clone (it can't be,
-            // since neither AtomicUsize nor Mutex are) and we would otherwise have to literally
-            // write out N_BUCKETS different values, which would be painful.  As a result, we have
-            // to be careful not to allow any failure here, or we'll segfault (by Drop-ing empty
-            // buckets).
-            let mut buckets_: [Bucket; N_BUCKETS] = unsafe { ::std::mem::uninitialized() };
-            let mut transfers: [AtomicUsize; N_WORKERS] = unsafe { ::std::mem::uninitialized() };
-            for (dest, &src) in buckets_.iter_mut().zip(buckets.iter()){
-                let bucket = Bucket { data: AtomicUsize::new(src), mutex: Mutex::new(()) };
-                // If we don't use an unsafe write(), the uninitialized mutex in the bucket will be
-                // dropped.
-                unsafe { ::std::ptr::write(dest as *mut _, bucket) }
-            }
-            for t in (&mut transfers[..]).iter_mut() {
-                *t = AtomicUsize::new(0);
-            }
-            Buckets { buckets: buckets_, transfers: transfers }
+    // A Mutex for each bucket.  As the D solution says, using a per-bucket Mutex dramatically
+    // improves scalability compared to the alternatives.
+    pub struct Sharded<T> {
+        buckets: Vec<Mutex<T>>,
+    }
+
+    impl<T: Clone + Send> Sharded<T> {
+        pub fn new(values: Vec<T>) -> Sharded<T> {
+            Sharded { buckets: values.into_iter().map(Mutex::new).collect() }
+        }
+
+        // It's very important to lock our Mutexes in the same order everywhere to avoid
+        // deadlock.  We arbitrarily choose the convention that we lock in ascending index order,
+        // whatever order the keys come in.
+        fn lock(&self, keys: &[usize]) -> Vec<(usize, MutexGuard<T>)> {
+            let mut order = (0..keys.len()).collect::<Vec<_>>();
+            order.sort_by(|&a, &b| keys[a].cmp(&keys[b]));
+            let mut guards = order.into_iter()
+                .map(|i| (i, self.buckets[keys[i]].lock().unwrap()))
+                .collect::<Vec<_>>();
+            guards.sort_by(|a, b| a.0.cmp(&b.0));
+            guards
         }
+    }
+
+    impl<T: Clone + Send> Buckets<T> for Sharded<T> {
+        fn name(&self) -> &'static str { "sharded" }
 
-        // Get the value of the bucket at index i, or None if out of bounds.
-        pub fn get(&self, i: usize) -> Option<usize> {
-            // This is used as an estimate, and is used without the mutex lock, so there's no
-            // compelling reason to demand consistency here.
-            self.buckets.get(i).map( |b| b.data.load(Ordering::Relaxed) )
+        fn len(&self) -> usize { self.buckets.len() }
+
+        fn get(&self, i: usize) -> Option<T> {
+            self.buckets.get(i).map(|b| b.lock().unwrap().clone())
         }
 
-        // Transfer at most `amount` from the bucket at index `from` to that at index `to`, and
-        // increment the transfer count for worker `worker` (like I said, that last part can likely
-        // be done elsewhere).
-        pub fn transfer(&self, from: usize, to: usize, amount: usize, worker: usize) {
-            // The from == to check is important so we don't deadlock, since Rust mutexes are
-            // nonreentrant.
-            if from == to || N_BUCKETS <= from || N_BUCKETS <= to || N_WORKERS <= worker { return }
-            // We know this won't fail, and the compiler seems to know as well.  However, if it
-            // *did* fail, it wouldn't fail while we were holding mutexes (which can cause
-            // problems since they may need to poison other tasks).
-            let ref b1 = self.buckets[from];
-            let ref b2 = self.buckets[to];
-            // It's very important to lock our Mutexes in the same order everywhere to avoid
-            // deadlock.  We arbitrarily choose the convention that we lock in ascending index
-            // order.
-            let (low, high) = if from < to { (b1, b2) } else { (b2, b1) };
-            {
-                // The reason we introduce a new scope here is that we want to make it clear how
-                // long we're locking for.  Locks should be held as briefly as possible and
-                // anything that happens here should really *require* the locks.
-                let _s1 = low.mutex.lock();
-                let _s2 = high.mutex.lock();
-                // It is possible that SeqCst is too strong for this section, but it is hard to
-                // test on x86 because it has unusually strong consistency by default.
-                let v1 = b1.data.load(Ordering::SeqCst);
-                let real_amount = ::std::cmp::min(v1, amount);
-                b1.data.store(v1 - real_amount, Ordering::SeqCst);
-                b2.data.fetch_add(real_amount, Ordering::SeqCst);
+        fn transaction(&self, keys: &[usize], update: &mut FnMut(&mut [T]))
+                       -> Result<(), KeyError> {
+            try!(check_keys(keys, self.buckets.len()));
+            // Locks should be held as briefly as possible and anything that happens here should
+            // really *require* the locks.
+            let mut guards = self.lock(keys);
+            let mut values = guards.iter().map(|g| (*g.1).clone()).collect::<Vec<_>>();
+            update(&mut values);
+            for (guard, value) in guards.iter_mut().zip(values.into_iter()) {
+                *guard.1 = value;
             }
-            // Doing this outside the critical section increases throughput substantially.  Since
-            // this is just a summary statistic, it's okay for it to be a few off.  That's also why
-            // we use Acquire semantics rather than AcqRel or SeqCst here--we only really care that
-            // we synchronize when the transfer count is set to 0.
-            self.transfers[worker].fetch_add(1, Ordering::Acquire);
+            Ok(())
         }
 
-        // Acquire a consistent snapshot of the state of the bucket list.  This should maintain the
-        // invariant that total buckets are conserved.  Also returns the list of transfer counts.
-        pub fn snapshot(&self) -> ([usize; N_BUCKETS], [usize; N_WORKERS ]) {
-            // Since this method is called relatively rarely, we aren't too concerned about
-            // performance here.
-            let mut buckets = [0; N_BUCKETS];
-            let mut transfers = [0; N_WORKERS];
+        fn snapshot(&self) -> Vec<T> {
             // We collect all the locks in order, being careful not to drop any until we're done
             // (so as to preserve consistency of the snapshot).
-            let locks = buckets.iter_mut().zip(self.buckets.iter()).map( |(dest, src)| {
-                let lock = src.mutex.lock();
-                // Is SeqCst necessary here?  Maybe, maybe not, but when in doubt go with SeqCst.
-                *dest = src.data.load(Ordering::SeqCst);
-                lock
-            }).collect::<Vec<_>>();
-            for (dest, src) in transfers.iter_mut().zip(self.transfers.iter()) {
-                // We synchronize with the Acquire in transfer, making sure that our zeroing out
-                // gets noticed.
-                *dest = src.swap(0, Ordering::Release);
+            let keys = (0..self.buckets.len()).collect::<Vec<_>>();
+            self.lock(&keys).iter().map(|g| (*g.1).clone()).collect()
+        }
+    }
+
+    // One Mutex for all the buckets.  Simple, and impossible to get wrong, but only one thread
+    // can do anything at a time.
+    pub struct Global<T> {
+        buckets: Mutex<Vec<T>>,
+        len: usize,
+    }
+
+    impl<T: Clone + Send> Global<T> {
+        pub fn new(values: Vec<T>) -> Global<T> {
+            let len = values.len();
+            Global { buckets: Mutex::new(values), len: len }
+        }
+    }
+
+    impl<T: Clone + Send> Buckets<T> for Global<T> {
+        fn name(&self) -> &'static str { "global" }
+
+        fn len(&self) -> usize { self.len }
+
+        fn get(&self, i: usize) -> Option<T> {
+            self.buckets.lock().unwrap().get(i).cloned()
+        }
+
+        fn transaction(&self, keys: &[usize], update: &mut FnMut(&mut [T]))
+                       -> Result<(), KeyError> {
+            try!(check_keys(keys, self.len));
+            let mut buckets = self.buckets.lock().unwrap();
+            let mut values = keys.iter().map(|&k| buckets[k].clone()).collect::<Vec<_>>();
+            update(&mut values);
+            for (&k, value) in keys.iter().zip(values.into_iter()) {
+                buckets[k] = value;
+            }
+            Ok(())
+        }
+
+        fn snapshot(&self) -> Vec<T> {
+            self.buckets.lock().unwrap().clone()
+        }
+    }
+
+    // Optimistic concurrency: nobody ever waits for a lock.  Each bucket has a stamp next to its
+    // value, which is even while the bucket is free and odd while a transaction is writing it,
+    // and goes up by two each time the value changes.  A transaction reads the values and their
+    // stamps, works out the new values, and then claims each bucket with a compare-and-swap from
+    // the stamp it saw.  If any of them fails, someone else got there first, so it lets go of
+    // what it claimed and starts again.  Since nothing ever waits while holding a bucket there's
+    // no way to deadlock, whatever order the keys are in.
+    //
+    // The values have to fit in an atomic word, so this only holds usizes.
+    pub struct Optimistic {
+        values: Vec<AtomicUsize>,
+        stamps: Vec<AtomicUsize>,
+        aborts: AtomicUsize,
+    }
+
+    impl Optimistic {
+        pub fn new(values: Vec<usize>) -> Optimistic {
+            Optimistic {
+                stamps: values.iter().map(|_| AtomicUsize::new(0)).collect(),
+                values: values.into_iter().map(AtomicUsize::new).collect(),
+                aborts: AtomicUsize::new(0),
+            }
+        }
+
+        // The values at `keys` and the stamps they had, once they hold still: every stamp even,
+        // and the same after reading the values as before.
+        fn read(&self, keys: &[usize]) -> (Vec<usize>, Vec<usize>) {
+            loop {
+                let stamps = keys.iter().map(|&k| self.stamps[k].load(Ordering::SeqCst))
+                    .collect::<Vec<_>>();
+                if stamps.iter().all(|&s| s % 2 == 0) {
+                    let values = keys.iter().map(|&k| self.values[k].load(Ordering::SeqCst))
+                        .collect::<Vec<_>>();
+                    if keys.iter().zip(stamps.iter())
+                           .all(|(&k, &s)| self.stamps[k].load(Ordering::SeqCst) == s) {
+                        return (values, stamps);
+                    }
+                }
+                thread::yield_now();
+            }
+        }
+    }
+
+    impl Buckets<usize> for Optimistic {
+        fn name(&self) -> &'static str { "optimistic" }
+
+        fn len(&self) -> usize { self.values.len() }
+
+        fn get(&self, i: usize) -> Option<usize> {
+            self.values.get(i).map(|v| v.load(Ordering::Relaxed))
+        }
+
+        fn transaction(&self, keys: &[usize], update: &mut FnMut(&mut [usize]))
+                       -> Result<(), KeyError> {
+            try!(check_keys(keys, self.values.len()));
+            loop {
+                let (mut values, stamps) = self.read(keys);
+                update(&mut values);
+
+                let claimed = keys.iter().zip(stamps.iter()).take_while(|&(&k, &s)| {
+                    self.stamps[k].compare_and_swap(s, s + 1, Ordering::SeqCst) == s
+                }).count();
+                if claimed == keys.len() {
+                    for (&k, &value) in keys.iter().zip(values.iter()) {
+                        self.values[k].store(value, Ordering::SeqCst);
+                    }
+                    for (&k, &s) in keys.iter().zip(stamps.iter()) {
+                        self.stamps[k].store(s + 2, Ordering::SeqCst);
+                    }
+                    return Ok(());
+                }
+
+                // Nothing was written, so the buckets go back to the stamps they had.
+                for (&k, &s) in keys.iter().zip(stamps.iter()).take(claimed) {
+                    self.stamps[k].store(s, Ordering::SeqCst);
+                }
+                self.aborts.fetch_add(1, Ordering::Relaxed);
+                thread::yield_now();
+            }
+        }
+
+        fn snapshot(&self) -> Vec<usize> {
+            // A read of every bucket is a consistent snapshot, for the same reason a
+            // transaction's read is.
+            let keys = (0..self.values.len()).collect::<Vec<_>>();
+            self.read(&keys).0
+        }
+
+        fn aborts(&self) -> usize {
+            self.aborts.load(Ordering::Relaxed)
+        }
+    }
+
+    // A TVar for each bucket, so a transaction is an STM transaction: it reads the buckets it
+    // wants, works out the new values and writes them, and the STM sorts out the rest.  Like the
+    // optimistic buckets, nothing waits while anything else is going on, but the STM keeps a
+    // lock on each TVar, so anything can go in the buckets.
+    pub struct Transactional<T> {
+        buckets: Vec<TVar<T>>,
+        aborts: AtomicUsize,
+    }
+
+    impl<T: Any + Clone + Send + Sync> Transactional<T> {
+        pub fn new(values: Vec<T>) -> Transactional<T> {
+            Transactional {
+                buckets: values.into_iter().map(TVar::new).collect(),
+                aborts: AtomicUsize::new(0),
             }
-            // We can drop the locks before we return.  This probably gets optimized out, but it's
-            // rarely a bad idea to drop locks explicitly.
-            drop(locks);
-            (buckets, transfers)
+        }
+    }
+
+    impl<T: Any + Clone + Send + Sync> Buckets<T> for Transactional<T> {
+        fn name(&self) -> &'static str { "stm" }
+
+        fn len(&self) -> usize { self.buckets.len() }
+
+        fn get(&self, i: usize) -> Option<T> {
+            self.buckets.get(i).map(|b| b.read_atomic())
+        }
+
+        fn transaction(&self, keys: &[usize], update: &mut FnMut(&mut [T]))
+                       -> Result<(), KeyError> {
+            try!(check_keys(keys, self.buckets.len()));
+            let ((), aborts) = atomically_counting(|tx| {
+                let mut values = Vec::with_capacity(keys.len());
+                for &k in keys.iter() {
+                    values.push(try!(tx.read(&self.buckets[k])));
+                }
+                update(&mut values);
+                for (&k, value) in keys.iter().zip(values.into_iter()) {
+                    tx.write(&self.buckets[k], value);
+                }
+                Ok(())
+            });
+            self.aborts.fetch_add(aborts, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn snapshot(&self) -> Vec<T> {
+            atomically(|tx| {
+                let mut values = Vec::with_capacity(self.buckets.len());
+                for bucket in self.buckets.iter() {
+                    values.push(try!(tx.read(bucket)));
+                }
+                Ok(values)
+            })
+        }
+
+        fn aborts(&self) -> usize {
+            self.aborts.load(Ordering::Relaxed)
         }
     }
 }
 
-// Convenience method to create a distribution of buckets summing to initial_sum.
-fn make_buckets(initial_sum: usize) -> buckets::Buckets {
-    let mut buckets = [0; buckets::N_BUCKETS];
+// Convenience method to create a distribution of `n` buckets summing to initial_sum.
+fn make_values(n: usize, initial_sum: usize) -> Vec<usize> {
     let mut dist = initial_sum;
-    for (i, b) in (&mut buckets[..]).iter_mut().enumerate() {
-        let v = dist / (buckets::N_BUCKETS - i);
-        *b = v;
+    (0..n).map(|i| {
+        let v = dist / (n - i);
         dist -= v;
+        v
+    }).collect()
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Strategy {
+    Sharded,
+    Global,
+    Optimistic,
+    Stm,
+}
+
+const STRATEGIES: [Strategy; 4] =
+    [Strategy::Sharded, Strategy::Global, Strategy::Optimistic, Strategy::Stm];
+
+fn make_buckets(strategy: Strategy, n: usize, initial_sum: usize) -> Arc<Buckets<usize>> {
+    let values = make_values(n, initial_sum);
+    match strategy {
+        Strategy::Sharded => Arc::new(Sharded::new(values)),
+        Strategy::Global => Arc::new(Global::new(values)),
+        Strategy::Optimistic => Arc::new(Optimistic::new(values)),
+        Strategy::Stm => Arc::new(Transactional::new(values)),
     }
-    buckets::Buckets::new(buckets)
 }
 
 // The equalize task--it chooses two random buckets and tries to make their values the same.
-fn equalize(bl: &buckets::Buckets, running: &AtomicBool, worker: usize) {
+fn equalize(bl: &Buckets<usize>, running: &AtomicBool, transfers: &AtomicUsize) -> usize {
     // We preallocate the Range for improved performance.
-    let between = Range::new(0, buckets::N_BUCKETS);
+    let between = Range::new(0, bl.len());
     // We use the weak random number generator for improved performance.
     let ref mut r = weak_rng();
+    // How many transfers this task has made, to check the statistics against.
+    let mut made = 0;
     // Running is read Relaxed because it's not important that the task stop right away as long as
     // it happens eventually.
     while running.load(Ordering::Relaxed) {
         let b1 = between.ind_sample(r);
         let b2 = between.ind_sample(r);
+        if b1 == b2 { continue }
         let v1 = bl.get(b1).unwrap();
         let v2 = bl.get(b2).unwrap();
-        if v1 > v2 {
-            bl.transfer(b1, b2, (v1 - v2) / 2, worker)
+        let result = if v1 > v2 {
+            buckets::transfer(bl, b1, b2, (v1 - v2) / 2)
         } else {
-            bl.transfer(b2, b1, (v2 - v1) / 2, worker)
-        }
+            buckets::transfer(bl, b2, b1, (v2 - v1) / 2)
+        };
+        result.unwrap();
+        // Since this is just a summary statistic, it's okay for it to be a few off.
+        transfers.fetch_add(1, Ordering::Relaxed);
+        made += 1;
     }
+    made
 }
 
 // The randomize task--it chooses two random buckets and randomly redistributes their values.
-fn randomize(bl: &buckets::Buckets, running: &AtomicBool, worker: usize) {
-    // We preallocate the Range for improved performance.
-    let between = Range::new(0, buckets::N_BUCKETS);
-    // We use the weak random number generator for improved performance.
+fn randomize(bl: &Buckets<usize>, running: &AtomicBool, transfers: &AtomicUsize) -> usize {
+    let between = Range::new(0, bl.len());
     let ref mut r = weak_rng();
-    // Running is read Relaxed because it's not important that the task stop right away as long as
-    // it happens eventually.
+    let mut made = 0;
     while running.load(Ordering::Relaxed) {
         let b1 = between.ind_sample(r);
         let b2 = between.ind_sample(r);
-        bl.transfer(b1, b2, r.gen_range(0, bl.get(b1).unwrap() + 1), worker);
+        if b1 == b2 { continue }
+        let amount = r.gen_range(0, bl.get(b1).unwrap() + 1);
+        buckets::transfer(bl, b1, b2, amount).unwrap();
+        transfers.fetch_add(1, Ordering::Relaxed);
+        made += 1;
+    }
+    made
+}
+
+// The rotate task--it takes several random buckets at once and moves each one's value on to the
+// next, to make sure transactions over more than two buckets hold up too.
+fn rotate(bl: &Buckets<usize>, running: &AtomicBool, transfers: &AtomicUsize) -> usize {
+    let ref mut r = weak_rng();
+    let mut keys = (0..bl.len()).collect::<Vec<_>>();
+    let most = ::std::cmp::min(bl.len(), 5);
+    let mut made = 0;
+    while running.load(Ordering::Relaxed) {
+        r.shuffle(&mut keys);
+        let n = r.gen_range(2, most + 1);
+        bl.transaction(&keys[..n], &mut |values| {
+            let last = values[values.len() - 1];
+            for i in (1..values.len()).rev() {
+                values[i] = values[i - 1];
+            }
+            values[0] = last;
+        }).unwrap();
+        transfers.fetch_add(1, Ordering::Relaxed);
+        made += 1;
     }
+    made
 }
 
+type Task = fn(&Buckets<usize>, &AtomicBool, &AtomicUsize) -> usize;
+
+const TASKS: [(&'static str, Task); 3] =
+    [("equalize", equalize), ("randomize", randomize), ("rotate", rotate)];
+
 // The display task--for a total time of `duration`, it displays information about the update
 // process and checks to make sure that the invariant (that the total remains constant) is
-// preserved.  It prints an update `nticks` times, evenly spaced.
-fn display(bl: &buckets::Buckets, running: &AtomicBool, original_total: usize,
-           duration: u32, nticks: u32) {
-    println!("transfers, N. transfers, buckets, buckets sum:");
+// preserved.  It prints an update `nticks` times, evenly spaced, if `verbose`; either way it
+// checks a snapshot every tick.  Returns the number of transfers it has seen, which it takes
+// out of the counts as it goes.
+fn display(bl: &Buckets<usize>, transfers: &[AtomicUsize], original_total: usize,
+           duration: Duration, nticks: u32, verbose: bool) -> usize {
+    if verbose {
+        println!("transfers, N. transfers, buckets, buckets sum:");
+    }
 
-    let duration = duration / nticks;
-    for _ in (0..nticks) {
+    let tick = duration / nticks;
+    let mut total_transfers = 0;
+    for _ in 0..nticks {
         // Get a consistent snapshot
-        let (s, tc) = bl.snapshot();
+        let s = bl.snapshot();
         // Sum up the buckets
-        let sum = s.iter().map( |&i| i ).sum::<usize>();
-        // Sum up the transfers.
-        let n_transfers = tc.iter().map( |&i| i ).sum::<usize>();
+        let sum = s.iter().fold(0, |sum, &i| sum + i);
+        // Sum up the transfers, starting again from 0.
+        let tc = transfers.iter().map(|t| t.swap(0, Ordering::Relaxed)).collect::<Vec<_>>();
+        let n_transfers = tc.iter().fold(0, |sum, &i| sum + i);
+        total_transfers += n_transfers;
         // Print the relevant information.
-        println!("{:?}, {}, {:?}, {}", tc, n_transfers, s, sum);
+        if verbose {
+            println!("{:?}, {}, {:?}, {}", tc, n_transfers, s, sum);
+        }
         // Check the invariant, failing if necessary.
         assert_eq!(sum, original_total);
         // Sleep before printing again.
-        thread::sleep_ms(duration);
+        thread::sleep(tick);
     }
-    // We're done--cleanly exit the other update tasks.
-    running.store(false, Ordering::Relaxed);
+    total_transfers
 }
 
-// Putting together all three tasks.
-fn perform_atomic_updates(duration: u32, original_total: usize, num_ticks: u32)
-{
-    // Worker IDs for the two updater tasks.
-    const ID_EQUALIZE: usize = 0;
-    const ID_RANDOMIZE: usize = 1;
-
+// Putting together all the tasks: `workers` threads doing the updates, taking turns at which
+// kind, and the display task checking on them.  Returns the number of transactions made.
+fn perform_atomic_updates(bl: Arc<Buckets<usize>>, workers: usize, duration: Duration,
+                          original_total: usize, num_ticks: u32, verbose: bool) -> usize {
     // `running` is an atomic boolean that we use to signal when to stop to the updater tasks.
-    let running = AtomicBool::new(true);
-    // We use an Arc here in order to share a reference to the buckets between threads.  Since the
-    // Buckets are already Sync, and we don't need &mut references to them, there's no need to
-    // create a Mutex here.
-    let arc = Arc::new((make_buckets(original_total), running));
-    // Cloning the arc bumps the reference count.
-    let arc_ = arc.clone();
-    // Start off the equalize task
-    spawn(move || { equalize(&arc_.0, &arc_.1, ID_EQUALIZE) });
-    let arc_ = arc.clone();
-    // Start off the randomize task
-    spawn(move || { randomize(&arc_.0, &arc_.1, ID_RANDOMIZE) });
-    let (ref bl, ref running) = *arc;
+    let running = Arc::new(AtomicBool::new(true));
+    let transfers = Arc::new((0..workers).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>());
+    let threads = (0..workers).map(|worker| {
+        let (bl, running, transfers) = (bl.clone(), running.clone(), transfers.clone());
+        let task = TASKS[worker % TASKS.len()].1;
+        spawn(move || task(&*bl, &running, &transfers[worker]))
+    }).collect::<Vec<_>>();
+
     // Run the display task in the current thread, so failure propagates to the user.
-    display(bl, running, original_total, duration, num_ticks);
+    let total = display(&*bl, &transfers, original_total, duration, num_ticks, verbose);
+    // We're done--cleanly exit the other update tasks.
+    running.store(false, Ordering::Relaxed);
+    let made = threads.into_iter().fold(0, |sum, thread| sum + thread.join().unwrap());
+    let sum = bl.snapshot().iter().fold(0, |sum, &i| sum + i);
+    assert_eq!(sum, original_total);
+    // Whatever was made after the last tick is still in the counts.
+    let total = total + transfers.iter().fold(0, |sum, t| sum + t.load(Ordering::Relaxed));
+    // Every transfer is counted once.
+    assert_eq!(total, made);
+    total
 }
 
 const ORIGINAL_TOTAL: usize = 1000;
@@ -267,12 +526,124 @@ const NUM_TICKS: u32 = 10;
 
 #[cfg(not(test))]
 fn main() {
-    // Run for 10 seconds
-    perform_atomic_updates(10000, ORIGINAL_TOTAL, NUM_TICKS);
+    use std::env;
+    use std::time::Instant;
+
+    let usage =
+        "usage: atomic_updates [SECONDS [BUCKETS [WORKERS [sharded|global|optimistic|stm]]]]";
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let numbers = args.iter().take(3).map(|s| s.parse::<u64>()).collect::<Result<Vec<_>, _>>();
+    let numbers = match numbers {
+        Ok(numbers) => numbers,
+        Err(_) => return println!("{}", usage),
+    };
+    let seconds = numbers.get(0).cloned().unwrap_or(10);
+    let n_buckets = numbers.get(1).cloned().unwrap_or(20) as usize;
+    let workers = numbers.get(2).cloned().unwrap_or(3) as usize;
+    if n_buckets < 2 || workers == 0 {
+        return println!("{}", usage);
+    }
+    let strategies = match args.get(3).map(|s| &s[..]) {
+        None => STRATEGIES.to_vec(),
+        Some("sharded") => vec![Strategy::Sharded],
+        Some("global") => vec![Strategy::Global],
+        Some("optimistic") => vec![Strategy::Optimistic],
+        Some("stm") => vec![Strategy::Stm],
+        Some(_) => return println!("{}", usage),
+    };
+
+    // One strategy on its own shows what's happening; more than one, how they compare.
+    let verbose = strategies.len() == 1;
+    let duration = Duration::from_secs(seconds) / strategies.len() as u32;
+    for &strategy in strategies.iter() {
+        let bl = make_buckets(strategy, n_buckets, ORIGINAL_TOTAL);
+        let started = Instant::now();
+        let transactions = perform_atomic_updates(bl.clone(), workers, duration, ORIGINAL_TOTAL,
+                                                  NUM_TICKS, verbose);
+        let elapsed = started.elapsed();
+        let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+        let aborts = bl.aborts();
+        println!("{:>10}: {} transactions, {:.0} a second, {} aborts ({:.2}%), total still {}",
+                 bl.name(), transactions, transactions as f64 / secs, aborts,
+                 100. * aborts as f64 / (transactions + aborts) as f64, ORIGINAL_TOTAL);
+    }
 }
 
 #[test]
 fn test_atomic_updates() {
-    // Run for 1/10th of a second
-    perform_atomic_updates(100, ORIGINAL_TOTAL, NUM_TICKS);
+    // Run for 1/10th of a second each
+    for &strategy in STRATEGIES.iter() {
+        let bl = make_buckets(strategy, 20, ORIGINAL_TOTAL);
+        let transactions = perform_atomic_updates(bl, 3, Duration::from_millis(100),
+                                                  ORIGINAL_TOTAL, NUM_TICKS, false);
+        assert!(transactions > 0);
+    }
+}
+
+#[test]
+fn test_transfer_counts() {
+    // Each tick takes what's been counted since the last one, so nothing is counted twice.
+    let bl = make_buckets(Strategy::Global, 3, ORIGINAL_TOTAL);
+    let transfers = [AtomicUsize::new(5), AtomicUsize::new(7)];
+    assert_eq!(display(&*bl, &transfers, ORIGINAL_TOTAL, Duration::from_millis(2), 2, false), 12);
+    assert_eq!(transfers[0].load(Ordering::Relaxed) + transfers[1].load(Ordering::Relaxed), 0);
+
+    // and with workers counting their own, the total is what they made
+    for &strategy in STRATEGIES.iter() {
+        let bl = make_buckets(strategy, 5, ORIGINAL_TOTAL);
+        assert!(perform_atomic_updates(bl, 3, Duration::from_millis(20), ORIGINAL_TOTAL, 2,
+                                       false) > 0);
+    }
+}
+
+#[test]
+fn test_contention() {
+    // lots of workers fighting over a few buckets
+    for &strategy in STRATEGIES.iter() {
+        let bl = make_buckets(strategy, 3, ORIGINAL_TOTAL);
+        perform_atomic_updates(bl.clone(), 8, Duration::from_millis(100), ORIGINAL_TOTAL,
+                               NUM_TICKS, false);
+        // only the ones that don't wait for locks ever have to start again
+        if strategy == Strategy::Sharded || strategy == Strategy::Global {
+            assert_eq!(bl.aborts(), 0);
+        }
+    }
+}
+
+#[test]
+fn test_transactions() {
+    use buckets::KeyError;
+
+    for &strategy in STRATEGIES.iter() {
+        let bl = make_buckets(strategy, 5, 10);
+        assert_eq!(bl.name(), format!("{:?}", strategy).to_lowercase());
+        assert_eq!(bl.len(), 5);
+        assert_eq!(bl.snapshot(), [2, 2, 2, 2, 2]);
+        buckets::transfer(&*bl, 4, 0, 1).unwrap();
+        // no more than there is
+        buckets::transfer(&*bl, 1, 3, 5).unwrap();
+        assert_eq!(bl.snapshot(), [3, 0, 2, 4, 1]);
+        assert_eq!(bl.get(3), Some(4));
+        assert_eq!(bl.get(5), None);
+
+        // the values come in the order of the keys
+        bl.transaction(&[3, 0, 4], &mut |values| {
+            assert_eq!(values, [4, 3, 1]);
+            values.swap(0, 2);
+        }).unwrap();
+        assert_eq!(bl.snapshot(), [3, 0, 2, 1, 4]);
+
+        assert_eq!(buckets::transfer(&*bl, 1, 1, 1), Err(KeyError::Repeated(1)));
+        assert_eq!(buckets::transfer(&*bl, 1, 5, 1), Err(KeyError::OutOfRange(5)));
+        assert_eq!(bl.transaction(&[], &mut |_| {}), Ok(()));
+    }
+
+    // anything that can be cloned, when there are locks
+    let names = Sharded::new(vec!["a".to_string(), "b".to_string()]);
+    names.transaction(&[1, 0], &mut |values| values[0].push_str(&values[1].clone())).unwrap();
+    assert_eq!(names.snapshot(), ["a", "ba"]);
+    let names = Transactional::new(vec!["a".to_string(), "b".to_string()]);
+    names.transaction(&[0, 1], &mut |values| values.swap(0, 1)).unwrap();
+    assert_eq!(names.snapshot(), ["b", "a"]);
+    assert_eq!(Global::new(vec![1.5]).get(0), Some(1.5));
 }
@@ -1,25 +1,106 @@
 // http://rosettacode.org/wiki/Bulls_and_cows
+// http://rosettacode.org/wiki/Bulls_and_cows/Player
+//
+// Either you guess the computer's number, or it guesses yours. The rules can
+// be changed at run time: how many digits, which digits, and whether they
+// may repeat. Mastermind is the same game with colours and repeats allowed.
 extern crate rand;
+use std::cmp::min;
+use std::collections::HashMap;
 use std::fmt::{self, Display};
 
-const NUMBER_OF_DIGITS: usize = 4;
+use rand::Rng;
 
-/// generates a random NUMBER_OF_DIGITS
-fn generate_digits() -> Vec<u32> {
-    use rand;
+/// the most symbols a game can use
+const MAX_SYMBOLS: usize = 36;
 
-    let mut rng = rand::thread_rng();
-    rand::sample(&mut rng, (1u32..10), 4)
+/// what the secret and the guesses are made of. A code is the positions of
+/// its digits in `alphabet`.
+struct Rules {
+    digits: usize,
+    alphabet: Vec<char>,
+    duplicates: bool,
+}
+
+impl Rules {
+    fn new(digits: usize, alphabet: &str, duplicates: bool) -> Result<Rules, String> {
+        let alphabet = alphabet.chars().collect::<Vec<_>>();
+        if digits == 0 {
+            return Err("a code needs at least one digit".to_string());
+        }
+        if alphabet.is_empty() {
+            return Err("there have to be some symbols to make codes from".to_string());
+        }
+        if alphabet.len() > MAX_SYMBOLS {
+            return Err(format!("no more than {} symbols, please", MAX_SYMBOLS));
+        }
+        if alphabet.iter().enumerate().any(|(i, c)| alphabet[..i].contains(c)) {
+            return Err("every symbol has to be different".to_string());
+        }
+        if !duplicates && digits > alphabet.len() {
+            return Err(format!("can't make {} different digits out of {}", digits, alphabet.len()));
+        }
+        Ok(Rules { digits: digits, alphabet: alphabet, duplicates: duplicates })
+    }
+
+    /// four different digits from 1 to 9
+    fn bulls_and_cows() -> Rules {
+        Rules::new(4, "123456789", false).unwrap()
+    }
+
+    /// four pegs of six colours, which may repeat
+    fn mastermind() -> Rules {
+        Rules::new(4, "RGBYOW", true).unwrap()
+    }
+
+    /// every code there is, in order
+    fn all_codes(&self) -> Vec<Vec<u32>> {
+        let mut codes = vec![];
+        let mut code = Vec::with_capacity(self.digits);
+        self.add_codes(&mut code, &mut codes);
+        codes
+    }
+
+    fn add_codes(&self, code: &mut Vec<u32>, codes: &mut Vec<Vec<u32>>) {
+        if code.len() == self.digits {
+            codes.push(code.clone());
+            return;
+        }
+        for symbol in 0..self.alphabet.len() as u32 {
+            if self.duplicates || !code.contains(&symbol) {
+                code.push(symbol);
+                self.add_codes(code, codes);
+                code.pop();
+            }
+        }
+    }
+
+    #[cfg(not(test))]
+    fn show(&self, code: &[u32]) -> String {
+        code.iter().map(|&s| self.alphabet[s as usize]).collect()
+    }
+}
+
+/// generates a random code
+fn generate_digits<R: Rng>(rules: &Rules, rng: &mut R) -> Vec<u32> {
+    let mut symbols = (0..rules.alphabet.len() as u32).collect::<Vec<_>>();
+    if rules.duplicates {
+        (0..rules.digits).map(|_| *rng.choose(&symbols).unwrap()).collect()
+    } else {
+        rng.shuffle(&mut symbols);
+        symbols.truncate(rules.digits);
+        symbols
+    }
 }
 
 /// types of errors we can have when parsing a malformed guess
-enum ParseError { NotValidDigit, ExpectedNumberOfDigits(usize), NoDuplicates, }
+enum ParseError { NotValidDigit(char), ExpectedNumberOfDigits(usize), NoDuplicates, }
 
 /// printable description for each ParseError
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            ParseError::NotValidDigit => Display::fmt("only digits from 1 to 9, please", f),
+            ParseError::NotValidDigit(c) => write!(f, "'{}' isn't one of the digits, please", c),
             ParseError::ExpectedNumberOfDigits(exp) =>
                 write!(f , "you need to guess with {} digits" , exp),
             ParseError::NoDuplicates => Display::fmt("no duplicates, please",f),
@@ -28,70 +109,236 @@ impl fmt::Display for ParseError {
 }
 
 /// a well-formed guess string should be like
-/// "1543", with NUMBER_OF_DIGITS digits, no repetitions,
-/// no separators or other characters. Parse the guess string as a
-/// Vec<usize> or return a ParseError.
-/// This could trivially return a [usize, ..NUMBER_OF_DIGITS] instead of
-/// a Vec<usize> and avoid dynamic allocations. However, in the more
-/// general case, NUMBER_OF_DIGITS would not be a constant, but a runtime
-/// configuration (which would make using a stack-allocated array more
-/// difficult)
-fn parse_guess_string(guess: &str) ->
+/// "1543", with as many digits as the rules say, all from the rules'
+/// alphabet, no repetitions unless the rules allow them, and no
+/// separators or other characters. Parse the guess string as the
+/// positions of its digits in the alphabet or return a ParseError.
+fn parse_guess_string(rules: &Rules, guess: &str) ->
     Result<Vec<u32>, ParseError> {
-    let mut ret = Vec::with_capacity(NUMBER_OF_DIGITS);
+    let mut ret = Vec::with_capacity(rules.digits);
 
-    for (i, c) in guess.char_indices() {
-        // check that our guess contains the right number of digits
-        if i >= NUMBER_OF_DIGITS {
-            return Err(ParseError::ExpectedNumberOfDigits(NUMBER_OF_DIGITS))
-        }
-        match c.to_digit(10) {
-            Some(d) if d > 0 => {
+    for c in guess.chars() {
+        match rules.alphabet.iter().position(|&a| a == c) {
+            Some(d) => {
+                    let d = d as u32;
                     // the guess should not contain duplicate digits
-                    if ret.contains(&d) { return Err(ParseError::NoDuplicates) }
+                    if !rules.duplicates && ret.contains(&d) { return Err(ParseError::NoDuplicates) }
                     ret.push(d);
                 },
-            _ => return Err(ParseError::NotValidDigit)
+            None => return Err(ParseError::NotValidDigit(c))
         }
     }
 
+    // check that our guess contains the right number of digits
+    if ret.len() != rules.digits {
+        return Err(ParseError::ExpectedNumberOfDigits(rules.digits))
+    }
     return Ok(ret);
 }
 
-/// returns a tuple with the count of Bulls and Cows in the guess
+/// returns a tuple with the count of Bulls and Cows in the guess. A
+/// digit that's repeated only scores as many times as it's in both.
 fn calculate_score(given_digits: &[u32], guessed_digits: &[u32]) ->
     (usize, usize) {
     let mut bulls = 0;
-    let mut cows = 0;
-    for i in (0..NUMBER_OF_DIGITS) {
-        let pos = guessed_digits.iter()
-            .position(|&a| a == given_digits[i]);
-
-        match pos {
-            None => (),
-            Some(p) if p == i => bulls += 1,
-            Some(_) => cows += 1,
+    let mut given = [0; MAX_SYMBOLS];
+    let mut guessed = [0; MAX_SYMBOLS];
+    for (&a, &b) in given_digits.iter().zip(guessed_digits.iter()) {
+        if a == b {
+            bulls += 1;
+        } else {
+            given[a as usize] += 1;
+            guessed[b as usize] += 1;
         }
     }
+    let cows = given.iter().zip(guessed.iter()).fold(0, |sum, (&a, &b)| sum + min(a, b));
     return (bulls, cows);
 }
 
+/// how the computer picks its next guess out of all the codes, looking at
+/// how they'd split up the codes that could still be the secret by the
+/// score each would get
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Choice {
+    /// the smallest worst case, as in Knuth's Mastermind solver
+    Minimax,
+    /// the most information on average
+    Entropy,
+}
+
+/// The computer's side of the game when it's guessing. Its guesses only
+/// depend on the scores they've had, so it keeps them by those to save
+/// working them out again.
+struct Solver<'a> {
+    rules: &'a Rules,
+    choice: Choice,
+    codes: Vec<Vec<u32>>,
+    guesses: HashMap<Vec<(usize, usize)>, usize>,
+}
+
+impl<'a> Solver<'a> {
+    fn new(rules: &'a Rules, choice: Choice) -> Solver<'a> {
+        Solver { rules: rules, choice: choice, codes: rules.all_codes(), guesses: HashMap::new() }
+    }
+
+    /// Plays a game, asking `answer` for the score of each guess. Returns
+    /// the guesses up to and including the right one, or None if no code
+    /// gets all the scores it was given.
+    fn play(&mut self, answer: &mut FnMut(&[u32]) -> (usize, usize)) -> Option<Vec<Vec<u32>>> {
+        let mut candidates = (0..self.codes.len()).collect::<Vec<_>>();
+        let mut scores = vec![];
+        let mut guesses = vec![];
+        loop {
+            if candidates.is_empty() {
+                return None;
+            }
+            let guess = match self.guesses.get(&scores).cloned() {
+                Some(guess) => guess,
+                None => {
+                    let guess = self.choose(&candidates, scores.is_empty());
+                    self.guesses.insert(scores.clone(), guess);
+                    guess
+                }
+            };
+
+            let score = answer(&self.codes[guess]);
+            guesses.push(self.codes[guess].clone());
+            if score.0 == self.rules.digits {
+                return Some(guesses);
+            }
+            scores.push(score);
+            let codes = &self.codes;
+            candidates.retain(|&c| calculate_score(&codes[c], &codes[guess]) == score);
+        }
+    }
+
+    /// The best guess for splitting up the candidates. Guesses that could
+    /// be right win ties. Before the first guess every code could be the
+    /// secret, so only the codes that use the symbols in order (0, 0, 1, 2
+    /// but not 1, 1, 0, 2) need to be looked at: the others are the same
+    /// guess with the symbols renamed.
+    fn choose(&self, candidates: &[usize], first: bool) -> usize {
+        if candidates.len() == 1 {
+            return candidates[0];
+        }
+
+        let digits = self.rules.digits;
+        let mut best = (std::f64::INFINITY, 0);
+        let mut counts = vec![0usize; (digits + 1) * (digits + 1)];
+        for (guess, code) in self.codes.iter().enumerate() {
+            if first && !in_order(code) {
+                continue;
+            }
+            for n in counts.iter_mut() {
+                *n = 0;
+            }
+            for &c in candidates.iter() {
+                let (bulls, cows) = calculate_score(&self.codes[c], code);
+                counts[bulls * (digits + 1) + cows] += 1;
+            }
+
+            // lower is better
+            let cost = match self.choice {
+                Choice::Minimax => *counts.iter().max().unwrap() as f64,
+                Choice::Entropy => {
+                    let total = candidates.len() as f64;
+                    counts.iter().filter(|&&n| n > 0)
+                        .map(|&n| n as f64 / total * (n as f64 / total).ln())
+                        .fold(0.0, |sum, x| sum + x)
+                }
+            };
+            let possible = counts[digits * (digits + 1)] > 0;
+            if cost < best.0 || cost == best.0 && possible && !candidates.contains(&best.1) {
+                best = (cost, guess);
+            }
+        }
+        best.1
+    }
+}
+
+/// whether each new symbol in a code is the next one after those before it
+fn in_order(code: &[u32]) -> bool {
+    let mut next = 0;
+    for &s in code.iter() {
+        if s > next {
+            return false;
+        }
+        if s == next {
+            next += 1;
+        }
+    }
+    true
+}
+
 #[cfg(not(test))]
 fn main() {
-    let mut reader = std::io::stdin();
+    let usage = "usage: bulls_and_cows [play|solve|average] [-n DIGITS] [-a ALPHABET] \
+                 [--duplicates] [--mastermind] [--entropy]";
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let mut mode = "play";
+    let defaults = Rules::bulls_and_cows();
+    let (mut digits, mut duplicates) = (defaults.digits, defaults.duplicates);
+    let mut alphabet = defaults.alphabet.iter().cloned().collect::<String>();
+    let mut choice = Choice::Minimax;
+    let mut i = 0;
+    while i < args.len() {
+        match &args[i][..] {
+            "play" | "solve" | "average" => mode = &args[i],
+            "-n" if i + 1 < args.len() => {
+                i += 1;
+                match args[i].parse() {
+                    Ok(n) => digits = n,
+                    Err(_) => return println!("{}", usage),
+                }
+            }
+            "-a" if i + 1 < args.len() => {
+                i += 1;
+                alphabet = args[i].clone();
+            }
+            "--duplicates" => duplicates = true,
+            "--mastermind" => {
+                let rules = Rules::mastermind();
+                digits = rules.digits;
+                alphabet = rules.alphabet.iter().cloned().collect();
+                duplicates = rules.duplicates;
+            }
+            "--entropy" => choice = Choice::Entropy,
+            _ => return println!("{}", usage),
+        }
+        i += 1;
+    }
+    let rules = match Rules::new(digits, &alphabet, duplicates) {
+        Ok(rules) => rules,
+        Err(msg) => return println!("{}", msg),
+    };
+
+    match mode {
+        "solve" => solve(&rules, choice),
+        "average" => average(&rules, choice),
+        _ => play(&rules),
+    }
+}
+
+/// you guess the computer's code
+#[cfg(not(test))]
+fn play(rules: &Rules) {
+    let reader = std::io::stdin();
+    let mut rng = rand::thread_rng();
     loop {
-        let given_digits = generate_digits();
-        println!("I have chosen my {} digits. Please guess what they are" ,
-                 NUMBER_OF_DIGITS);
+        let given_digits = generate_digits(rules, &mut rng);
+        println!("I have chosen my {} digits from {}. Please guess what they are" ,
+                 rules.digits, rules.alphabet.iter().cloned().collect::<String>());
         loop {
             let mut guess_string = String::new();
-            let _ = reader.read_line(&mut guess_string).unwrap();
-            let digits_maybe = parse_guess_string(&guess_string.trim());
+            if reader.read_line(&mut guess_string).unwrap() == 0 {
+                return;
+            }
+            let digits_maybe = parse_guess_string(rules, &guess_string.trim());
             match digits_maybe {
                 Err(msg) => { println!("{}" , msg); }
                 Ok(guess_digits) => {
                     match calculate_score(&given_digits, &guess_digits) {
-                        (NUMBER_OF_DIGITS, _) => {
+                        (bulls, _) if bulls == rules.digits => {
                             println!("you win!");
                             break ;
                         }
@@ -104,46 +351,129 @@ fn main() {
     }
 }
 
+/// the computer guesses your code
+#[cfg(not(test))]
+fn solve(rules: &Rules, choice: Choice) {
+    let reader = std::io::stdin();
+    println!("Think of {} digits from {}, and score my guesses as \"bulls cows\"",
+             rules.digits, rules.alphabet.iter().cloned().collect::<String>());
+    let mut solver = Solver::new(rules, choice);
+    let result = solver.play(&mut |guess| {
+        println!("My guess: {}", rules.show(guess));
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                std::process::exit(0);
+            }
+            let numbers = line.split_whitespace().map(|s| s.parse::<usize>()).collect::<Vec<_>>();
+            if numbers.len() == 2 {
+                if let (&Ok(bulls), &Ok(cows)) = (&numbers[0], &numbers[1]) {
+                    if bulls + cows <= rules.digits {
+                        return (bulls, cows);
+                    }
+                }
+            }
+            println!("bulls and cows, like \"1 2\", please");
+        }
+    });
+    match result {
+        Some(guesses) => println!("Got it in {}!", guesses.len()),
+        None => println!("Nothing fits those scores!"),
+    }
+}
+
+/// plays every code and counts the guesses
+#[cfg(not(test))]
+fn average(rules: &Rules, choice: Choice) {
+    let codes = rules.all_codes();
+    let mut solver = Solver::new(rules, choice);
+    let mut games = vec![];
+    for secret in codes.iter() {
+        let guesses = solver.play(&mut |guess| calculate_score(secret, guess)).unwrap();
+        if guesses.len() >= games.len() {
+            games.resize(guesses.len() + 1, 0);
+        }
+        games[guesses.len()] += 1;
+    }
+    let total = games.iter().enumerate().fold(0, |sum, (n, &count)| sum + n * count);
+    println!("{} codes, {:.4} guesses on average", codes.len(), total as f64 / codes.len() as f64);
+    for (n, &count) in games.iter().enumerate().skip(1) {
+        println!("{:>2} guesses: {}", n, count);
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::ParseError;
+    use super::{ParseError, Rules, Solver, Choice};
 
     #[test]
     fn generate_digits() {
-        // test we generate NUMBER_OF_DIGITS unique
-        // digits between 1 and 9
-        let mut digits = super::generate_digits();
-        assert!(digits.iter().all(|&d| d > 0u32));
+        // test we generate unique digits from the alphabet
+        let rules = super::Rules::bulls_and_cows();
+        let mut rng = super::rand::thread_rng();
+        let mut digits = super::generate_digits(&rules, &mut rng);
+        assert!(digits.iter().all(|&d| d < 9));
         digits.sort();
         digits.dedup();
-        assert_eq!(digits.len(), super::NUMBER_OF_DIGITS)
+        assert_eq!(digits.len(), rules.digits);
+
+        let rules = Rules::new(6, "AB", true).unwrap();
+        let digits = super::generate_digits(&rules, &mut rng);
+        assert_eq!(digits.len(), 6);
+        assert!(digits.iter().all(|&d| d < 2));
+    }
+
+    #[test]
+    fn rules() {
+        assert_eq!(Rules::bulls_and_cows().all_codes().len(), 9 * 8 * 7 * 6);
+        assert_eq!(Rules::mastermind().all_codes().len(), 6 * 6 * 6 * 6);
+        assert_eq!(Rules::new(2, "abc", false).unwrap().all_codes(),
+                   vec![vec![0, 1], vec![0, 2], vec![1, 0], vec![1, 2], vec![2, 0], vec![2, 1]]);
+        assert!(Rules::new(4, "123", false).is_err());
+        assert!(Rules::new(4, "123", true).is_ok());
+        assert!(Rules::new(2, "1231", true).is_err());
+        assert!(Rules::new(0, "123", true).is_err());
+        assert!(Rules::new(1, "", true).is_err());
     }
 
     #[test]
     fn parse_guess_string() {
-        match super::parse_guess_string("1234") {
-            Ok(p) => assert_eq!(p, vec![1, 2, 3, 4]),
+        let rules = Rules::bulls_and_cows();
+        match super::parse_guess_string(&rules, "1234") {
+            Ok(p) => assert_eq!(p, vec![0, 1, 2, 3]),
             _ => panic!("Failed parsing a valid string")
         }
 
-        match super::parse_guess_string("0123") {
+        match super::parse_guess_string(&rules, "0123") {
             Ok(_) => panic!("parsed a string containing a 0"),
-            Err(err) => if let ParseError::NotValidDigit = err { ()
+            Err(err) => if let ParseError::NotValidDigit('0') = err { ()
                 } else { panic!("Expected a NotValidDigit error") }
         }
 
-        match super::parse_guess_string("1213") {
+        match super::parse_guess_string(&rules, "1213") {
             Ok(_) => panic!("parsed a string containing a repeated digit"),
             Err(err) => if let ParseError::NoDuplicates = err { ()
                 } else { panic!("Expected a NoDuplicates error") }
         }
 
-        match super::parse_guess_string("12354") {
+        match super::parse_guess_string(&rules, "12354") {
             Ok(_) => panic!("parsed a string longer than 4 digits"),
             Err(err) => if let ParseError::ExpectedNumberOfDigits(4) = err { ()
             } else { panic!("Expected a ExpectedNumberOfDigits error")
             }
         }
+
+        match super::parse_guess_string(&rules, "123") {
+            Ok(_) => panic!("parsed a string shorter than 4 digits"),
+            Err(err) => if let ParseError::ExpectedNumberOfDigits(4) = err { ()
+            } else { panic!("Expected a ExpectedNumberOfDigits error")
+            }
+        }
+
+        match super::parse_guess_string(&Rules::mastermind(), "RRBW") {
+            Ok(p) => assert_eq!(p, vec![0, 0, 2, 5]),
+            _ => panic!("Failed parsing a valid string")
+        }
     }
 
     #[test]
@@ -154,5 +484,62 @@ mod test {
             (2, 2));
         assert_eq!(super::calculate_score(&[1,2,3,4], &[5,6,7,8]),
             (0, 0));
+        // repeated digits only count as often as they're in both
+        assert_eq!(super::calculate_score(&[1,1,2,2], &[2,1,1,1]),
+            (1, 2));
+        assert_eq!(super::calculate_score(&[3,3,3,3], &[3,4,4,4]),
+            (1, 0));
+    }
+
+    // plays every secret, and checks the solver finds it
+    fn guesses(rules: &Rules, choice: Choice) -> (usize, usize) {
+        let codes = rules.all_codes();
+        let mut solver = Solver::new(rules, choice);
+        let (mut total, mut most) = (0, 0);
+        for secret in codes.iter() {
+            let guesses = solver.play(&mut |guess| super::calculate_score(secret, guess)).unwrap();
+            assert_eq!(guesses.last(), Some(secret));
+            for guess in guesses.iter() {
+                assert_eq!(guess.len(), rules.digits);
+            }
+            total += guesses.len();
+            most = if guesses.len() > most { guesses.len() } else { most };
+        }
+        (total, most)
+    }
+
+    #[test]
+    fn solver() {
+        let rules = Rules::new(3, "123456", false).unwrap();
+        let (total, most) = guesses(&rules, Choice::Minimax);
+        assert!(most <= 6);
+        assert!((total as f64) / 120.0 < 4.5);
+        let (total, _) = guesses(&rules, Choice::Entropy);
+        assert!((total as f64) / 120.0 < 4.5);
+
+        // 3 pegs of 4 colours
+        let rules = Rules::new(3, "RGBY", true).unwrap();
+        let (_, most) = guesses(&rules, Choice::Minimax);
+        assert!(most <= 5);
+    }
+
+    // all 5040 codes of four different digits from 0 to 9: takes a while
+    #[test]
+    #[ignore]
+    fn ten_digits() {
+        let rules = Rules::new(4, "0123456789", false).unwrap();
+        let codes = rules.all_codes().len() as f64;
+        assert_eq!(codes, 5040.0);
+        let (total, _) = guesses(&rules, Choice::Entropy);
+        assert!((total as f64) / codes < 5.25);
+        let (total, _) = guesses(&rules, Choice::Minimax);
+        assert!((total as f64) / codes < 5.39);
+    }
+
+    #[test]
+    fn inconsistent_scores() {
+        let rules = Rules::new(2, "123", false).unwrap();
+        let mut solver = Solver::new(&rules, Choice::Minimax);
+        assert_eq!(solver.play(&mut |_| (0, 0)), None);
     }
 }
@@ -1,61 +1,207 @@
 // Implements http://rosettacode.org/wiki/Checkpoint_synchronization
 //
-// We implement this task using Rust's Barriers.  Barriers are simply thread
-// synchronization points--if a task waits at a barrier, it will not continue
-// until the number of tasks for which the variable was initialized are also
-// waiting at the barrier, at which point all of them will stop waiting.  This
-// can be used to allow threads to do asynchronous work and guarantee properties
-// at checkpoints.
+// We implement this task with a Phaser, like Java's.  A phaser is a reusable
+// barrier: if a task waits at it, it will not continue until all the tasks
+// registered with it have arrived, at which point all of them stop waiting and
+// the phaser moves on to the next phase.  Unlike Rust's Barrier, the number of
+// tasks isn't fixed: they can register and deregister between phases, which
+// is what the task asks for (workers joining and leaving the workshop).  This
+// can be used to allow threads to do asynchronous work and guarantee
+// properties at checkpoints.
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Barrier};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 use std::thread::spawn;
 use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
 
+struct State {
+    phase: usize,
+    // How many tasks are registered, and how many of them have arrived in
+    // this phase.
+    parties: usize,
+    arrived: usize,
+    terminated: bool,
+    // Run each time a phase completes, with the phase and the number of
+    // parties, by the last task to arrive.  If it returns true the phaser
+    // terminates.
+    action: Box<FnMut(usize, usize) -> bool + Send>,
+}
+
+pub struct Phaser {
+    state: Mutex<State>,
+    advanced: Condvar,
+}
+
+impl Phaser {
+    // A phaser that terminates when every party has deregistered, as Java's
+    // does by default.
+    pub fn new(parties: usize) -> Phaser {
+        Phaser::with_action(parties, |_, parties| parties == 0)
+    }
+
+    // The action is run while the phaser is locked, so it mustn't use the
+    // phaser itself.
+    pub fn with_action<F>(parties: usize, action: F) -> Phaser
+        where F: FnMut(usize, usize) -> bool + Send + 'static
+    {
+        Phaser {
+            state: Mutex::new(State {
+                phase: 0,
+                parties: parties,
+                arrived: 0,
+                terminated: false,
+                action: Box::new(action),
+            }),
+            advanced: Condvar::new(),
+        }
+    }
+
+    // Adds a party, which takes part from the current phase on, and returns
+    // the phase.
+    pub fn register(&self) -> usize {
+        self.bulk_register(1)
+    }
+
+    pub fn bulk_register(&self, parties: usize) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.parties += parties;
+        state.phase
+    }
+
+    // Arrives without waiting for the others, returning the phase arrived at.
+    pub fn arrive(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        self.arrive_locked(state, 0).0
+    }
+
+    // Arrives and leaves: the phase can complete without this party, now and
+    // from then on.
+    pub fn arrive_and_deregister(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        self.arrive_locked(state, 1).0
+    }
+
+    // Arrives and waits for the others, returning the phase that's next.
+    pub fn arrive_and_await_advance(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        let (phase, state) = self.arrive_locked(state, 0);
+        self.await_locked(state, phase, None).unwrap()
+    }
+
+    // The same, giving up on waiting after `timeout` (but still counted as
+    // having arrived).
+    pub fn arrive_and_await_advance_timeout(&self, timeout: Duration) -> Option<usize> {
+        let deadline = Instant::now() + timeout;
+        let state = self.state.lock().unwrap();
+        let (phase, state) = self.arrive_locked(state, 0);
+        self.await_locked(state, phase, Some(deadline))
+    }
+
+    // Waits for `phase` to complete, returning the phase that's next (right
+    // away if it already has, or if the phaser has terminated).
+    pub fn await_advance(&self, phase: usize) -> usize {
+        let state = self.state.lock().unwrap();
+        self.await_locked(state, phase, None).unwrap()
+    }
+
+    pub fn await_advance_timeout(&self, phase: usize, timeout: Duration) -> Option<usize> {
+        let deadline = Instant::now() + timeout;
+        let state = self.state.lock().unwrap();
+        self.await_locked(state, phase, Some(deadline))
+    }
+
+    pub fn phase(&self) -> usize {
+        self.state.lock().unwrap().phase
+    }
+
+    pub fn parties(&self) -> usize {
+        self.state.lock().unwrap().parties
+    }
+
+    pub fn arrived(&self) -> usize {
+        self.state.lock().unwrap().arrived
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.state.lock().unwrap().terminated
+    }
+
+    fn arrive_locked<'a>(&self, mut state: MutexGuard<'a, State>, leaving: usize)
+                         -> (usize, MutexGuard<'a, State>) {
+        let phase = state.phase;
+        if state.terminated {
+            return (phase, state);
+        }
+        assert!(state.arrived < state.parties, "More arrivals than registered parties");
+        state.arrived += 1;
+        state.parties -= leaving;
+        state.arrived -= leaving;
+        if state.arrived == state.parties {
+            // The last one to arrive moves everyone on.
+            let parties = state.parties;
+            state.terminated = (state.action)(phase, parties);
+            state.arrived = 0;
+            state.phase += 1;
+            self.advanced.notify_all();
+        }
+        (phase, state)
+    }
+
+    fn await_locked(&self, mut state: MutexGuard<State>, phase: usize, deadline: Option<Instant>)
+                    -> Option<usize> {
+        while state.phase == phase && !state.terminated {
+            state = match deadline {
+                None => self.advanced.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    self.advanced.wait_timeout(state, deadline - now).unwrap().0
+                }
+            };
+        }
+        Some(state.phase)
+    }
+}
 
 pub fn checkpoint() {
     const NUM_TASKS: usize = 10;
     const NUM_ITERATIONS: u8 = 10;
 
-    let barrier = Barrier::new(NUM_TASKS);
-    let mut events: [AtomicBool; NUM_TASKS];
-    unsafe {
-        // Unsafe because it's hard to initialize arrays whose type is not Clone.
-        events = ::std::mem::uninitialized();
-        for e in events.iter_mut() {
-            // Events are initially off
-            *e = AtomicBool::new(false);
-        }
-    }
+    let phaser = Phaser::new(NUM_TASKS);
+    // Events are initially off
+    let events = (0..NUM_TASKS).map(|_| AtomicBool::new(false)).collect::<Vec<_>>();
     // Arc for sharing between tasks
-    let arc = Arc::new((barrier, events));
+    let arc = Arc::new((phaser, events));
     // Channel for communicating when tasks are done
     let (tx, rx) = channel();
-    for i in (0..NUM_TASKS) {
+    for i in 0..NUM_TASKS {
         let arc = arc.clone();
         let tx = tx.clone();
         // Spawn a new worker
         spawn( move || -> () {
-            let (ref barrier, ref events) = *arc;
+            let (ref phaser, ref events) = *arc;
             // Assign an event to this task
             let ref event = events[i];
             // Start processing events
-            for _ in (0..NUM_ITERATIONS) {
+            for _ in 0..NUM_ITERATIONS {
                 // Between checkpoints 4 and 1, turn this task's event on.
                 event.store(true, Ordering::Release);
                 // Checkpoint 1
-                barrier.wait();
+                phaser.arrive_and_await_advance();
                 // Between checkpoints 1 and 2, all events are on.
                 assert!(events.iter().all( |e| e.load(Ordering::Acquire) ));
                 // Checkpoint 2
-                barrier.wait();
+                phaser.arrive_and_await_advance();
                 // Between checkpoints 2 and 3, turn this task's event off.
                 event.store(false, Ordering::Release);
                 // Checkpoint 3
-                barrier.wait();
+                phaser.arrive_and_await_advance();
                 // Between checkpoints 3 and 4, all events are off.
                 assert!(events.iter().all( |e| !e.load(Ordering::Acquire) ));
                 // Checkpoint 4
-                barrier.wait();
+                phaser.arrive_and_await_advance();
             }
             // Finish processing events.
             tx.send(()).unwrap();
@@ -63,17 +209,132 @@ pub fn checkpoint() {
     }
     drop(tx);
     // The main thread will not exit until all tasks have exited.
-    for _ in (0..NUM_TASKS) {
+    for _ in 0..NUM_TASKS {
         rx.recv().unwrap();
     }
 }
 
+// The workshop from the task: each worker makes one part of every assembly,
+// and an assembly is done when all the parts are.  Worker i joins when
+// assembly `joins[i]` starts, makes parts for `stays[i]` assemblies (or as
+// many as are left), and then goes home.  Returns how many workers made each
+// assembly.
+pub fn workshop(assemblies: usize, joins: &[usize], stays: &[usize]) -> Vec<usize> {
+    let made = Arc::new(Mutex::new(vec![0; assemblies]));
+    // The foreman (this thread) is registered from the start.
+    let phaser = Arc::new(Phaser::new(1));
+
+    let mut workers = vec![];
+    for assembly in 0..assemblies {
+        for (i, _) in joins.iter().enumerate().filter(|&(i, &j)| j == assembly && stays[i] > 0) {
+            // Registered here, so the assembly can't be finished without them.
+            phaser.register();
+            let (phaser, made) = (phaser.clone(), made.clone());
+            let last = ::std::cmp::min(assembly + stays[i], assemblies) - 1;
+            workers.push(spawn(move || {
+                for phase in assembly..last + 1 {
+                    made.lock().unwrap()[phase] += 1;
+                    if phase == last {
+                        phaser.arrive_and_deregister();
+                    } else {
+                        phaser.arrive_and_await_advance();
+                    }
+                }
+            }));
+        }
+        phaser.arrive_and_await_advance();
+    }
+    phaser.arrive_and_deregister();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let made = made.lock().unwrap().clone();
+    made
+}
+
 #[cfg(not(test))]
 fn main() {
     checkpoint();
+
+    let made = workshop(6, &[0, 0, 1, 3, 3], &[3, 6, 2, 3, 1]);
+    for (assembly, workers) in made.iter().enumerate() {
+        println!("Assembly {} made by {} workers", assembly, workers);
+    }
 }
 
 #[test]
 fn test_checkpoint() {
     checkpoint();
 }
+
+#[test]
+fn test_workshop() {
+    assert_eq!(workshop(6, &[0, 0, 1, 3, 3], &[3, 6, 2, 3, 1]), [2, 3, 3, 3, 2, 2]);
+    // staying past the end, or not at all
+    assert_eq!(workshop(3, &[1, 2], &[5, 0]), [0, 1, 1]);
+    assert_eq!(workshop(2, &[], &[]), [0, 0]);
+}
+
+#[test]
+fn test_phases() {
+    let phaser = Phaser::new(2);
+    assert_eq!(phaser.register(), 0);
+    assert_eq!(phaser.arrive(), 0);
+    assert_eq!(phaser.arrive_and_deregister(), 0);
+    assert_eq!((phaser.phase(), phaser.parties(), phaser.arrived()), (0, 2, 1));
+    // the last to arrive doesn't wait
+    assert_eq!(phaser.arrive_and_await_advance(), 1);
+    assert_eq!(phaser.await_advance(0), 1);
+
+    // a waiter is let go when the one it's waiting for leaves instead
+    let phaser = Arc::new(phaser);
+    let waiter = {
+        let phaser = phaser.clone();
+        spawn(move || phaser.arrive_and_await_advance())
+    };
+    while phaser.arrived() == 0 {
+        ::std::thread::yield_now();
+    }
+    phaser.arrive_and_deregister();
+    assert_eq!(waiter.join().unwrap(), 2);
+    assert_eq!(phaser.parties(), 1);
+
+    // once nobody is left it's terminated
+    phaser.arrive_and_deregister();
+    assert!(phaser.is_terminated());
+    assert_eq!(phaser.await_advance(3), 3);
+}
+
+#[test]
+fn test_timeout() {
+    let phaser = Phaser::new(2);
+    let started = Instant::now();
+    assert_eq!(phaser.arrive_and_await_advance_timeout(Duration::from_millis(20)), None);
+    assert!(started.elapsed() >= Duration::from_millis(20));
+    // still arrived
+    assert_eq!(phaser.arrived(), 1);
+    assert_eq!(phaser.await_advance_timeout(0, Duration::from_millis(1)), None);
+    phaser.arrive();
+    assert_eq!(phaser.await_advance_timeout(0, Duration::from_millis(1)), Some(1));
+}
+
+#[test]
+fn test_action() {
+    let seen = Arc::new(Mutex::new(vec![]));
+    let phaser = {
+        let seen = seen.clone();
+        Arc::new(Phaser::with_action(3, move |phase, parties| {
+            seen.lock().unwrap().push((phase, parties));
+            phase == 4
+        }))
+    };
+    let threads = (0..3).map(|_| {
+        let phaser = phaser.clone();
+        spawn(move || (0..10).map(|_| phaser.arrive_and_await_advance()).collect::<Vec<_>>())
+    }).collect::<Vec<_>>();
+    for thread in threads {
+        // after terminating, waiting doesn't wait any more
+        assert_eq!(thread.join().unwrap(), [1, 2, 3, 4, 5, 5, 5, 5, 5, 5]);
+    }
+    assert_eq!(*seen.lock().unwrap(), [(0, 3), (1, 3), (2, 3), (3, 3), (4, 3)]);
+}
@@ -1,19 +1,23 @@
 // Implements http://rosettacode.org/wiki/Closest-pair_problem
 
-// We interpret complex numbers as points in the Cartesian plane, here.
-// We also use the sweepline/plane sweep closest pairs algorithm
+// We use the sweepline/plane sweep closest pairs algorithm
 // (http://www.cs.mcgill.ca/~cs251/ClosestPair/ClosestPairPS.html) instead
 // of the divide-and-conquer algorithm, since it's (arguably)
 // easier to implement, and an efficient implementation does not require
 // use of unsafe.
-extern crate num;
-
+//
+// For the k closest pairs of points in any number of dimensions, we use a
+// grid instead: once we know a distance r such that at least k pairs are
+// within r of each other, the k closest pairs are all between points in the
+// same or neighbouring cells of a grid with cells of side r.
 use std::cmp::{PartialOrd, Ordering};
-use num::complex::Complex;
-use std::collections::BTreeSet;
-type Point = Complex<f32>;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
+use std::fmt;
+use geometry::Point2 as Point;
+
+mod geometry;
 
-// Wrapper around Point (i.e. Complex<f32>) so that we can use a TreeSet
+// Wrapper around Point so that we can use a TreeSet
 #[derive(PartialEq)]
 struct YSortedPoint {
     point: Point
@@ -21,7 +25,7 @@ struct YSortedPoint {
 
 impl PartialOrd for YSortedPoint {
     fn partial_cmp(&self, other: &YSortedPoint) -> Option<Ordering> {
-        (self.point.im, self.point.re).partial_cmp(&(other.point.im, other.point.re))
+        (self.point.y, self.point.x).partial_cmp(&(other.point.y, other.point.x))
     }
 }
 
@@ -33,12 +37,34 @@ impl Ord for YSortedPoint {
 
 impl Eq for YSortedPoint {}
 
-fn closest_pair(points: &mut [Point]) -> Option<(Point, Point)> {
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The point at this index has a coordinate that is NaN or infinite.
+    NotFinite(usize),
+    /// The point at this index has a different number of dimensions from
+    /// the first one.
+    DimensionMismatch(usize)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NotFinite(i) => write!(f, "point {} has a coordinate that isn't finite", i),
+            Error::DimensionMismatch(i) =>
+                write!(f, "point {} doesn't have the same number of dimensions as point 0", i)
+        }
+    }
+}
+
+fn closest_pair(points: &mut [Point]) -> Result<Option<(Point, Point)>, Error> {
+    if let Some(i) = points.iter().position(|p| !p.x.is_finite() || !p.y.is_finite()) {
+        return Err(Error::NotFinite(i))
+    }
     if points.len() < 2 {
-        return None
+        return Ok(None)
     }
 
-    points.sort_by(|a, b| (a.re, a.im).partial_cmp(&(b.re, b.im)).unwrap());
+    points.sort_by(|a, b| a.lex_cmp(b));
 
     let mut closest_pair = (points[0], points[1]);
     let mut closest_distance_sqr = (points[0] - points[1]).norm_sqr();
@@ -58,7 +84,7 @@ fn closest_pair(points: &mut [Point]) -> Option<(Point, Point)> {
         // along the x-axis
         while leftmost_idx < idx {
             let leftmost_point = &points[leftmost_idx];
-            if (leftmost_point.re - point.re).powi(2) < closest_distance_sqr {
+            if (leftmost_point.x - point.x).powi(2) < closest_distance_sqr {
                 break;
             }
             strip.remove(&YSortedPoint { point: leftmost_point.clone() });
@@ -68,7 +94,7 @@ fn closest_pair(points: &mut [Point]) -> Option<(Point, Point)> {
         // Compare to points in bounding box
         {
             let low_bound = YSortedPoint {
-                point: Point { re: ::std::f32::INFINITY, im: point.im - closest_distance }
+                point: Point { x: ::std::f64::INFINITY, y: point.y - closest_distance }
             };
             let mut strip_iter = strip.iter().skip_while(|&p| p < &low_bound);
             loop {
@@ -76,7 +102,7 @@ fn closest_pair(points: &mut [Point]) -> Option<(Point, Point)> {
                     None => break,
                     Some(p) => p.point
                 };
-                if point2.im - point.im >= closest_distance {
+                if point2.y - point.y >= closest_distance {
                     // we've reached the end of the box
                     break;
                 }
@@ -93,52 +119,311 @@ fn closest_pair(points: &mut [Point]) -> Option<(Point, Point)> {
         strip.insert(YSortedPoint { point: point.clone() });
     }
 
-    Some(closest_pair)
+    Ok(Some(closest_pair))
+}
+
+fn dist_sqr(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b.iter()).fold(0.0, |sum, (x, y)| sum + (x - y) * (x - y))
+}
+
+// A pair of points, as (squared distance, i, j) with i < j. Ordered by
+// distance, then by index so that results don't depend on the algorithm.
+#[derive(PartialEq, Debug)]
+struct Pair(f64, usize, usize);
+
+impl Eq for Pair {}
+
+impl PartialOrd for Pair {
+    fn partial_cmp(&self, other: &Pair) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pair {
+    fn cmp(&self, other: &Pair) -> Ordering {
+        (self.0, self.1, self.2).partial_cmp(&(other.0, other.1, other.2)).unwrap()
+    }
+}
+
+/// The k closest pairs among points with any number of dimensions, nearest
+/// first, as (i, j, distance) with i < j indices into `points`. Pairs at the
+/// same distance are ordered by their indices. If there are fewer than k
+/// pairs, all of them are returned.
+pub fn k_closest_pairs(points: &[Vec<f64>], k: usize) -> Result<Vec<(usize, usize, f64)>, Error> {
+    for (i, p) in points.iter().enumerate() {
+        if p.len() != points[0].len() {
+            return Err(Error::DimensionMismatch(i));
+        }
+        if p.iter().any(|c| !c.is_finite()) {
+            return Err(Error::NotFinite(i));
+        }
+    }
+    let n = points.len();
+    let k = ::std::cmp::min(k, n * n.saturating_sub(1) / 2);
+    if k == 0 {
+        return Ok(vec![]);
+    }
+
+    // Points next to each other along the first axis give n - 1 different
+    // pairs, so the kth smallest of their distances has at least k pairs
+    // within it. Past n - 1 pairs, keep doubling it until there are enough.
+    let mut order: Vec<usize> = (0..n).collect();
+    if !points[0].is_empty() {
+        order.sort_by(|&i, &j| points[i][0].partial_cmp(&points[j][0]).unwrap());
+    }
+    let mut nearby: Vec<f64> = order.windows(2).map(|w| dist_sqr(&points[w[0]], &points[w[1]]))
+                                    .collect();
+    nearby.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mut radius_sqr = nearby[::std::cmp::min(k, nearby.len()) - 1];
+    loop {
+        let (count, best) = pairs_within(points, radius_sqr, k);
+        if count >= k {
+            return Ok(best.into_sorted_vec().into_iter().map(|Pair(d, i, j)| (i, j, d.sqrt()))
+                          .collect());
+        }
+        radius_sqr = if radius_sqr == 0.0 {
+            // Not enough repeated points, so move on to the nearest distinct
+            // ones.
+            *nearby.iter().find(|&&d| d > 0.0).unwrap()
+        } else {
+            radius_sqr * 4.0
+        };
+    }
+}
+
+/// Counts the pairs of points within `sqrt(radius_sqr)` of each other, and
+/// returns the k closest of them.
+fn pairs_within(points: &[Vec<f64>], radius_sqr: f64, k: usize) -> (usize, BinaryHeap<Pair>) {
+    let mut count = 0;
+    let mut best = BinaryHeap::with_capacity(k + 1);
+    {
+        let mut compare = |i: usize, j: usize| {
+            let d = dist_sqr(&points[i], &points[j]);
+            if d <= radius_sqr {
+                count += 1;
+                best.push(Pair(d, ::std::cmp::min(i, j), ::std::cmp::max(i, j)));
+                if best.len() > k {
+                    best.pop();
+                }
+            }
+        };
+
+        if radius_sqr == 0.0 {
+            // Only repeated points are close enough, and they end up next to
+            // each other once sorted.
+            let mut order: Vec<usize> = (0..points.len()).collect();
+            order.sort_by(|&i, &j| points[i].partial_cmp(&points[j]).unwrap());
+            for (a, &i) in order.iter().enumerate() {
+                for &j in order[a + 1..].iter().take_while(|&&j| points[j] == points[i]) {
+                    compare(i, j);
+                }
+            }
+            return (count, best);
+        }
+
+        let side = radius_sqr.sqrt();
+        let mut cells: HashMap<Vec<i64>, Vec<usize>> = HashMap::new();
+        for (i, p) in points.iter().enumerate() {
+            let cell = p.iter().map(|&c| {
+                (c / side).floor().max(::std::i64::MIN as f64).min(::std::i64::MAX as f64) as i64
+            }).collect();
+            cells.entry(cell).or_insert_with(Vec::new).push(i);
+        }
+
+        let dims = points[0].len();
+        let keys: Vec<&Vec<i64>> = cells.keys().collect();
+        for (c, key) in keys.iter().enumerate() {
+            let members = &cells[*key];
+            for (a, &i) in members.iter().enumerate() {
+                for &j in &members[a + 1..] {
+                    compare(i, j);
+                }
+            }
+            // There are 3^d - 1 neighbouring cells, so in many dimensions it's
+            // quicker to go through the occupied cells instead. Either way,
+            // each pair of cells is only looked at once.
+            if dims < 20 && 3usize.pow(dims as u32) < keys.len() {
+                let mut offset = vec![-1i64; dims];
+                loop {
+                    let first = offset.iter().cloned().find(|&o| o != 0);
+                    if first == Some(1) {
+                        let neighbour: Vec<i64> = key.iter().zip(offset.iter())
+                                                     .map(|(&k, &o)| k.wrapping_add(o)).collect();
+                        if let Some(others) = cells.get(&neighbour) {
+                            for &i in members {
+                                for &j in others {
+                                    compare(i, j);
+                                }
+                            }
+                        }
+                    }
+                    match offset.iter().position(|&o| o < 1) {
+                        None => break,
+                        Some(d) => {
+                            offset[d] += 1;
+                            for o in &mut offset[..d] {
+                                *o = -1;
+                            }
+                        }
+                    }
+                }
+            } else {
+                for other in &keys[c + 1..] {
+                    let adjacent = key.iter().zip(other.iter())
+                                      .all(|(&a, &b)| a.wrapping_sub(b).abs() <= 1);
+                    if adjacent {
+                        for &i in members {
+                            for &j in &cells[*other] {
+                                compare(i, j);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    (count, best)
 }
 
 #[cfg(not(test))]
 pub fn main() {
     let mut test_data = [
-        Complex::new(0.654682, 0.925557),
-        Complex::new(0.409382, 0.619391),
-        Complex::new(0.891663, 0.888594),
-        Complex::new(0.716629, 0.996200),
-        Complex::new(0.477721, 0.946355),
-        Complex::new(0.925092, 0.818220),
-        Complex::new(0.624291, 0.142924),
-        Complex::new(0.211332, 0.221507),
-        Complex::new(0.293786, 0.691701),
-        Complex::new(0.839186, 0.728260)
+        Point::new(0.654682, 0.925557),
+        Point::new(0.409382, 0.619391),
+        Point::new(0.891663, 0.888594),
+        Point::new(0.716629, 0.996200),
+        Point::new(0.477721, 0.946355),
+        Point::new(0.925092, 0.818220),
+        Point::new(0.624291, 0.142924),
+        Point::new(0.211332, 0.221507),
+        Point::new(0.293786, 0.691701),
+        Point::new(0.839186, 0.728260)
     ];
-    let (p1, p2) = closest_pair(&mut test_data[..]).unwrap();
+    let (p1, p2) = closest_pair(&mut test_data[..]).unwrap().unwrap();
     println!("Closest pair: {} and {}", p1, p2);
     println!("Distance: {}", (p1 - p2).norm_sqr().sqrt());
+
+    let points_3d = vec![
+        vec![0.654682, 0.925557, 0.409382],
+        vec![0.619391, 0.891663, 0.888594],
+        vec![0.716629, 0.996200, 0.477721],
+        vec![0.946355, 0.925092, 0.818220],
+        vec![0.624291, 0.142924, 0.211332],
+        vec![0.221507, 0.293786, 0.691701],
+        vec![0.839186, 0.728260, 0.654682]
+    ];
+    println!("Three closest pairs in 3d:");
+    for (i, j, distance) in k_closest_pairs(&points_3d, 3).unwrap() {
+        println!("  {:?} and {:?}, distance {}", points_3d[i], points_3d[j], distance);
+    }
+
+    let with_nan = vec![vec![0.0, 1.0], vec![::std::f64::NAN, 2.0]];
+    if let Err(e) = k_closest_pairs(&with_nan, 1) {
+        println!("Error: {}", e);
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::closest_pair;
-    use num::complex::Complex;
+    use super::{closest_pair, k_closest_pairs, dist_sqr, Error};
+    use geometry::Point2 as Point;
+    use std::f64::{NAN, INFINITY};
+
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn float(&mut self) -> f64 {
+            (self.next() >> 11) as f64 / (1u64 << 53) as f64
+        }
+    }
+
+    fn brute_force(points: &[Vec<f64>], k: usize) -> Vec<(usize, usize, f64)> {
+        let mut pairs = vec![];
+        for i in 0..points.len() {
+            for j in i + 1..points.len() {
+                pairs.push((dist_sqr(&points[i], &points[j]), i, j));
+            }
+        }
+        pairs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        pairs.into_iter().take(k).map(|(d, i, j)| (i, j, d.sqrt())).collect()
+    }
 
     #[test]
     fn random_floats() {
         let mut test_data = [
-            Complex::new(0.654682, 0.925557),
-            Complex::new(0.409382, 0.619391),
-            Complex::new(0.891663, 0.888594),
-            Complex::new(0.716629, 0.996200),
-            Complex::new(0.477721, 0.946355),
-            Complex::new(0.925092, 0.818220),
-            Complex::new(0.624291, 0.142924),
-            Complex::new(0.211332, 0.221507),
-            Complex::new(0.293786, 0.691701),
-            Complex::new(0.839186, 0.728260)
+            Point::new(0.654682, 0.925557),
+            Point::new(0.409382, 0.619391),
+            Point::new(0.891663, 0.888594),
+            Point::new(0.716629, 0.996200),
+            Point::new(0.477721, 0.946355),
+            Point::new(0.925092, 0.818220),
+            Point::new(0.624291, 0.142924),
+            Point::new(0.211332, 0.221507),
+            Point::new(0.293786, 0.691701),
+            Point::new(0.839186, 0.728260)
         ];
-        let (p1, p2) = closest_pair(&mut test_data[..]).unwrap();
-        assert!((p1.re - 0.891663).abs() < 1e-6f32);
-        assert!((p1.im - 0.888594).abs() < 1e-6f32);
-        assert!((p2.re - 0.925092).abs() < 1e-6f32);
-        assert!((p2.im - 0.818220).abs() < 1e-6f32);
-        assert!(((p1 - p2).norm_sqr() - 0.0779102f32.powi(2)).abs() < 1e-6f32);
+        let (p1, p2) = closest_pair(&mut test_data[..]).unwrap().unwrap();
+        assert!((p1.x - 0.891663).abs() < 1e-6f64);
+        assert!((p1.y - 0.888594).abs() < 1e-6f64);
+        assert!((p2.x - 0.925092).abs() < 1e-6f64);
+        assert!((p2.y - 0.818220).abs() < 1e-6f64);
+        assert!(((p1 - p2).norm_sqr() - 0.0779102f64.powi(2)).abs() < 1e-6f64);
+    }
+
+    #[test]
+    fn closest_pair_matches_brute_force() {
+        let mut rng = XorShift(3);
+        for n in 2..60 {
+            let mut points: Vec<Point> = (0..n).map(|_| Point::new(rng.float(), rng.float())).collect();
+            let as_vecs: Vec<Vec<f64>> = points.iter().map(|p| vec![p.x, p.y]).collect();
+            let expected = brute_force(&as_vecs, 1)[0].2;
+            let (p1, p2) = closest_pair(&mut points[..]).unwrap().unwrap();
+            assert_eq!((p1 - p2).norm_sqr().sqrt(), expected);
+        }
+        assert_eq!(closest_pair(&mut [Point::new(1.0, 2.0)]), Ok(None));
+    }
+
+    #[test]
+    fn k_closest_pairs_match_brute_force() {
+        let mut rng = XorShift(5);
+        for round in 0..200 {
+            let dims = 1 + round % 5;
+            let n = (rng.next() % 40) as usize;
+            // Every third round uses a small grid, with many repeated points
+            // and equal distances.
+            let points: Vec<Vec<f64>> = (0..n).map(|_| (0..dims).map(|_| {
+                if round % 3 == 0 { (rng.next() % 4) as f64 } else { rng.float() * 100.0 - 50.0 }
+            }).collect()).collect();
+            for &k in &[0, 1, 2, 7, 50, 1000] {
+                assert_eq!(k_closest_pairs(&points, k).unwrap(), brute_force(&points, k));
+            }
+        }
+    }
+
+    #[test]
+    fn many_dimensions() {
+        let mut rng = XorShift(7);
+        let points: Vec<Vec<f64>> = (0..50).map(|_| (0..30).map(|_| rng.float()).collect())
+                                           .collect();
+        assert_eq!(k_closest_pairs(&points, 10).unwrap(), brute_force(&points, 10));
+    }
+
+    #[test]
+    fn bad_points() {
+        let points = vec![vec![0.0, 1.0], vec![2.0, NAN], vec![1.0, 1.0]];
+        assert_eq!(k_closest_pairs(&points, 1), Err(Error::NotFinite(1)));
+        let points = vec![vec![0.0, 1.0], vec![2.0, 3.0], vec![INFINITY, 1.0]];
+        assert_eq!(k_closest_pairs(&points, 1), Err(Error::NotFinite(2)));
+        let points = vec![vec![0.0, 1.0], vec![2.0, 3.0, 4.0]];
+        assert_eq!(k_closest_pairs(&points, 1), Err(Error::DimensionMismatch(1)));
+        let mut points = [Point::new(0.0, 0.0), Point::new(NAN, 1.0)];
+        assert_eq!(closest_pair(&mut points), Err(Error::NotFinite(1)));
     }
 }
@@ -1,165 +1,1744 @@
 // Implements http://rosettacode.org/wiki/Dijkstra's_algorithm
+//
+// Besides single-pair Dijkstra this file contains a small, reusable weighted
+// graph library: interned vertex names, directed and undirected graphs, any
+// numeric edge weight, and single-source Dijkstra, Bellman-Ford, A* and
+// Floyd-Warshall shortest paths, topological sorting, strongly connected
+// components, minimum spanning trees, maximum flows and bipartite matching.
+// Graphs can be read and written as edge lists, Graphviz DOT and GraphML.
+//
+// Usage: dijkstras_algorithm [FILE SOURCE TARGET [--dot OUTPUT]]
+//
+// With a graph file, prints the shortest path between two of its vertices and
+// optionally writes the graph as DOT with the path highlighted.
 
-use std::collections::{HashMap, BinaryHeap, LinkedList};
-use std::collections::hash_map::Entry::{Occupied, Vacant};
-use std::iter::repeat;
-use std::cmp::Ordering;
-use std::usize;
+use std::collections::{HashMap, BinaryHeap, VecDeque};
+use std::cmp::{self, Ordering};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::ops::{Add, Sub};
+use std::path::Path;
+use std::str::FromStr;
 
-type Node = usize;
-type Cost = usize;
-type Edge = (Node, Node);
+pub type Node = usize;
 
+/// The types that can be used as edge weights.
+pub trait Weight: Copy + PartialOrd + Add<Output=Self> + Sub<Output=Self> + fmt::Debug {
+    fn zero() -> Self;
+    fn one() -> Self;
+}
+
+macro_rules! weight_impls {
+    ($($t:ty)*) => ($(
+        impl Weight for $t {
+            fn zero() -> $t { 0 as $t }
+            fn one() -> $t { 1 as $t }
+        }
+    )*)
+}
+
+weight_impls! { usize u8 u16 u32 u64 isize i8 i16 i32 i64 f32 f64 }
+
+/// Errors reported by the graph algorithms.
+#[derive(Debug, PartialEq)]
+pub enum GraphError {
+    /// A vertex name that is not in the graph.
+    UnknownVertex(String),
+    /// Dijkstra's algorithm was given an edge with a negative weight.
+    NegativeWeight(String, String),
+    /// A cycle of negative total weight, so shortest paths are undefined.
+    NegativeCycle(Vec<String>),
+    /// A cycle in a graph which should not have one.
+    Cycle(Vec<String>),
+    /// The graph cannot be split into two sides.
+    NotBipartite,
+    /// A line of a graph file which could not be parsed.
+    Parse(usize, String),
+    Io(String),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GraphError::UnknownVertex(ref v) => write!(f, "unknown vertex '{}'", v),
+            GraphError::NegativeWeight(ref u, ref v) => {
+                write!(f, "edge {} -> {} has a negative weight", u, v)
+            }
+            GraphError::NegativeCycle(ref cycle) => {
+                write!(f, "negative cycle {}", cycle.join(" -> "))
+            }
+            GraphError::Cycle(ref cycle) => write!(f, "cycle {}", cycle.join(" -> ")),
+            GraphError::NotBipartite => write!(f, "the graph is not bipartite"),
+            GraphError::Parse(line, ref message) => write!(f, "line {}: {}", line, message),
+            GraphError::Io(ref message) => write!(f, "{}", message),
+        }
+    }
+}
 
 /// The DistPair struct is for the Priority Queue.
-#[derive(Eq, PartialEq, PartialOrd)]
-struct DistPair(Node, Cost);
-impl Ord for DistPair {
-    fn cmp(&self, other:&DistPair) -> Ordering {
-        let DistPair(_, dist_a) = *self;
-        let DistPair(_, dist_b) = *other;
-        dist_b.cmp(&dist_a) //Intentionally reversed
+#[derive(PartialEq)]
+struct DistPair<W>(Node, W);
+
+impl<W: PartialOrd> Eq for DistPair<W> {}
+
+impl<W: PartialOrd> PartialOrd for DistPair<W> {
+    fn partial_cmp(&self, other: &DistPair<W>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<W: PartialOrd> Ord for DistPair<W> {
+    fn cmp(&self, other: &DistPair<W>) -> Ordering {
+        let DistPair(_, ref dist_a) = *self;
+        let DistPair(_, ref dist_b) = *other;
+        dist_b.partial_cmp(dist_a).unwrap_or(Ordering::Equal) //Intentionally reversed
+    }
+}
+
+/// Graph structure, represented as an Adjancency List. Vertex names are
+/// interned: each is stored once and referred to by its index.
+#[derive(Clone, Debug)]
+pub struct Graph<W = usize> {
+    directed: bool,
+    vertices: Vec<String>,
+    indices: HashMap<String, Node>,
+    adj_list: Vec<Vec<(Node, W)>>,
+}
+
+impl<W: Weight> Graph<W> {
+    /// Creates an empty directed graph.
+    pub fn new() -> Graph<W> {
+        Graph::directed()
+    }
+
+    pub fn directed() -> Graph<W> {
+        Graph { directed: true, vertices: Vec::new(), indices: HashMap::new(), adj_list: Vec::new() }
+    }
+
+    /// Creates an empty undirected graph, where every edge can be travelled
+    /// both ways.
+    pub fn undirected() -> Graph<W> {
+        Graph { directed: false, ..Graph::directed() }
+    }
+
+    pub fn is_directed(&self) -> bool {
+        self.directed
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    /// Returns the number of edges; an undirected edge counts once.
+    pub fn edge_count(&self) -> usize {
+        let arcs: usize = self.adj_list.iter().map(|a| a.len()).sum();
+        if self.directed {
+            arcs
+        } else {
+            let loops = self.adj_list.iter().enumerate()
+                .map(|(u, a)| a.iter().filter(|&&(v, _)| v == u).count())
+                .sum::<usize>();
+            (arcs + loops) / 2
+        }
+    }
+
+    /// Returns the index of the vertex, or None if vertex
+    /// not found.
+    pub fn vertex_index(&self, vertex: &str) -> Option<Node> {
+        self.indices.get(vertex).cloned()
+    }
+
+    pub fn vertex_name(&self, vertex: Node) -> &str {
+        &self.vertices[vertex]
+    }
+
+    /// Returns the index of the vertex. If vertex is not found, inserts
+    /// the vertex.
+    pub fn get_or_insert_vertex(&mut self, vertex: &str) -> Node {
+        if let Some(idx) = self.vertex_index(vertex) {
+            return idx;
+        }
+        self.adj_list.push(Vec::new());
+        self.vertices.push(vertex.to_string());
+        self.indices.insert(vertex.to_string(), self.vertices.len() - 1);
+        self.vertices.len() - 1
+    }
+
+    /// Returns the outgoing edges of a vertex with their weights.
+    pub fn neighbours(&self, vertex: Node) -> &[(Node, W)] {
+        &self.adj_list[vertex]
+    }
+
+    /// Returns the weight of the edge between two vertices, if there is one.
+    pub fn weight(&self, from: Node, to: Node) -> Option<W> {
+        self.adj_list[from].iter().find(|&&(v, _)| v == to).map(|&(_, w)| w)
+    }
+
+    /// Returns every edge once, as (from, to, weight).
+    pub fn edges(&self) -> Vec<(Node, Node, W)> {
+        let mut edges = Vec::new();
+        for (u, adj) in self.adj_list.iter().enumerate() {
+            for &(v, w) in adj {
+                if self.directed || u <= v {
+                    edges.push((u, v, w));
+                }
+            }
+        }
+        edges
+    }
+
+    fn set_arc(&mut self, from: Node, to: Node, cost: W) {
+        match self.adj_list[from].iter().position(|&(v, _)| v == to) {
+            Some(i) => self.adj_list[from][i].1 = cost,
+            None => self.adj_list[from].push((to, cost)),
+        }
+    }
+
+    /// Adds the given edge to the graph, replacing the weight of an existing
+    /// edge between the same vertices.
+    pub fn add_edge(&mut self, from: &str, to: &str, cost: W) {
+        let from_idx = self.get_or_insert_vertex(from);
+        let to_idx = self.get_or_insert_vertex(to);
+        self.add_edge_by_index(from_idx, to_idx, cost);
+    }
+
+    pub fn add_edge_by_index(&mut self, from: Node, to: Node, cost: W) {
+        self.set_arc(from, to, cost);
+        if !self.directed {
+            self.set_arc(to, from, cost);
+        }
+    }
+
+    fn names(&self, path: &[Node]) -> Vec<&str> {
+        path.iter().map(|&v| self.vertex_name(v)).collect()
+    }
+
+    fn index_of(&self, vertex: &str) -> Result<Node, GraphError> {
+        self.vertex_index(vertex).ok_or(GraphError::UnknownVertex(vertex.to_string()))
+    }
+
+    fn check_non_negative(&self) -> Result<(), GraphError> {
+        for (u, v, w) in self.edges() {
+            if w < W::zero() {
+                return Err(GraphError::NegativeWeight(self.vertices[u].clone(),
+                                                      self.vertices[v].clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Implements Dijkstra's Algorithm. This uses a Priority Queue to
+    /// determine which vertex to visit first. Terminates once the target
+    /// vertex has been settled, or runs to completion if there is none.
+    /// Edges must not have negative weights.
+    fn dijkstra_from(&self, source: Node, target: Option<Node>,
+                     heuristic: &Fn(Node) -> W) -> ShortestPaths<W> {
+        let mut paths = ShortestPaths::new(source, self.vertex_count());
+        let mut settled = vec![false; self.vertex_count()];
+        let mut queue: BinaryHeap<DistPair<W>> = BinaryHeap::new();
+        queue.push(DistPair(source, heuristic(source)));
+
+        while let Some(DistPair(u, _)) = queue.pop() {
+            if settled[u] {
+                continue; // A stale entry, u was reached more cheaply before.
+            }
+            settled[u] = true;
+            if Some(u) == target {
+                break;
+            }
+            let dist_u = paths.dist[u].unwrap();
+            for &(v, cost_uv) in &self.adj_list[u] {
+                let alt = dist_u + cost_uv;
+                let better = match paths.dist[v] {
+                    None => true,
+                    Some(d) => alt < d,
+                };
+                if better && !settled[v] {
+                    paths.dist[v] = Some(alt);
+                    paths.prev[v] = Some(u);
+                    queue.push(DistPair(v, alt + heuristic(v)));
+                }
+            }
+        }
+        paths
+    }
+
+    /// Returns vector of vertices representing the shortest path from source
+    /// to target, or an empty vector if there's no path, or if the source or
+    /// target is not in the graph.
+    pub fn dijkstra(&self, source: &str, target: &str) -> Vec<&str> {
+        match self.shortest_path(source, target) {
+            Ok(Some((_, path))) => self.names(&path),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the length of the shortest path from source to target and the
+    /// vertices on it, or None if the target cannot be reached.
+    pub fn shortest_path(&self, source: &str, target: &str)
+                         -> Result<Option<(W, Vec<Node>)>, GraphError> {
+        let (s, t) = (try!(self.index_of(source)), try!(self.index_of(target)));
+        try!(self.check_non_negative());
+        let paths = self.dijkstra_from(s, Some(t), &|_| W::zero());
+        Ok(paths.distance(t).map(|d| (d, paths.path_to(t).unwrap())))
+    }
+
+    /// Computes the shortest paths from source to every vertex with
+    /// Dijkstra's algorithm.
+    pub fn distances(&self, source: &str) -> Result<ShortestPaths<W>, GraphError> {
+        let s = try!(self.index_of(source));
+        try!(self.check_non_negative());
+        Ok(self.dijkstra_from(s, None, &|_| W::zero()))
+    }
+
+    /// Implements A*: Dijkstra's algorithm guided by a heuristic which
+    /// estimates the remaining distance from a vertex to the target. The
+    /// result is a shortest path as long as the heuristic never overestimates
+    /// and is consistent.
+    pub fn a_star<F>(&self, source: &str, target: &str, heuristic: F)
+                     -> Result<Option<(W, Vec<Node>)>, GraphError>
+        where F: Fn(Node) -> W
+    {
+        let (s, t) = (try!(self.index_of(source)), try!(self.index_of(target)));
+        try!(self.check_non_negative());
+        let paths = self.dijkstra_from(s, Some(t), &heuristic);
+        Ok(paths.distance(t).map(|d| (d, paths.path_to(t).unwrap())))
+    }
+
+    /// Implements the Bellman-Ford algorithm, which allows negative weights.
+    /// Returns an error holding a negative cycle if one can be reached from
+    /// the source.
+    pub fn bellman_ford(&self, source: &str) -> Result<ShortestPaths<W>, GraphError> {
+        let s = try!(self.index_of(source));
+        let n = self.vertex_count();
+        let mut paths = ShortestPaths::new(s, n);
+        let edges = self.arcs();
+
+        let relax = |paths: &mut ShortestPaths<W>| -> Option<Node> {
+            let mut changed = None;
+            for &(u, v, w) in &edges {
+                if let Some(du) = paths.dist[u] {
+                    let better = match paths.dist[v] {
+                        None => true,
+                        Some(dv) => du + w < dv,
+                    };
+                    if better {
+                        paths.dist[v] = Some(du + w);
+                        paths.prev[v] = Some(u);
+                        changed = Some(v);
+                    }
+                }
+            }
+            changed
+        };
+
+        for _ in 1..n {
+            if relax(&mut paths).is_none() {
+                return Ok(paths);
+            }
+        }
+        match relax(&mut paths) {
+            None => Ok(paths),
+            Some(v) => {
+                // Walking back n steps from a vertex that was still improved
+                // is guaranteed to end up on the cycle.
+                let mut on_cycle = v;
+                for _ in 0..n {
+                    on_cycle = paths.prev[on_cycle].unwrap();
+                }
+                let mut cycle = vec![on_cycle];
+                let mut u = paths.prev[on_cycle].unwrap();
+                while u != on_cycle {
+                    cycle.push(u);
+                    u = paths.prev[u].unwrap();
+                }
+                cycle.push(on_cycle);
+                cycle.reverse();
+                Err(GraphError::NegativeCycle(self.names(&cycle).iter()
+                                                  .map(|s| s.to_string()).collect()))
+            }
+        }
+    }
+
+    /// Every edge in each direction it can be travelled.
+    fn arcs(&self) -> Vec<(Node, Node, W)> {
+        let mut arcs = Vec::new();
+        for (u, adj) in self.adj_list.iter().enumerate() {
+            for &(v, w) in adj {
+                arcs.push((u, v, w));
+            }
+        }
+        arcs
+    }
+
+    /// Implements the Floyd-Warshall algorithm, computing the shortest paths
+    /// between every pair of vertices. Negative weights are allowed, but a
+    /// negative cycle is reported as an error.
+    pub fn floyd_warshall(&self) -> Result<AllPairs<W>, GraphError> {
+        let n = self.vertex_count();
+        let mut dist: Vec<Vec<Option<W>>> = vec![vec![None; n]; n];
+        let mut next: Vec<Vec<Option<Node>>> = vec![vec![None; n]; n];
+        for v in 0..n {
+            dist[v][v] = Some(W::zero());
+            next[v][v] = Some(v);
+        }
+        for (u, v, w) in self.arcs() {
+            if u != v || w < W::zero() {
+                dist[u][v] = Some(w);
+                next[u][v] = Some(v);
+            }
+        }
+
+        for k in 0..n {
+            for i in 0..n {
+                let dik = match dist[i][k] {
+                    Some(d) => d,
+                    None => continue,
+                };
+                for j in 0..n {
+                    if let Some(dkj) = dist[k][j] {
+                        let better = match dist[i][j] {
+                            None => true,
+                            Some(dij) => dik + dkj < dij,
+                        };
+                        if better {
+                            dist[i][j] = Some(dik + dkj);
+                            next[i][j] = next[i][k];
+                        }
+                    }
+                }
+            }
+        }
+
+        for v in 0..n {
+            if dist[v][v].unwrap() < W::zero() {
+                // Follow the successors round the cycle through v.
+                let mut cycle = vec![v];
+                let mut u = next[v][v].unwrap();
+                while u != v && cycle.len() <= n {
+                    cycle.push(u);
+                    u = next[u][v].unwrap();
+                }
+                cycle.push(v);
+                return Err(GraphError::NegativeCycle(self.names(&cycle).iter()
+                                                         .map(|s| s.to_string()).collect()));
+            }
+        }
+        Ok(AllPairs { dist: dist, next: next })
+    }
+}
+
+/// The result of a single-source shortest path search.
+#[derive(Clone, Debug)]
+pub struct ShortestPaths<W> {
+    source: Node,
+    dist: Vec<Option<W>>,
+    prev: Vec<Option<Node>>,
+}
+
+impl<W: Weight> ShortestPaths<W> {
+    fn new(source: Node, vertices: usize) -> ShortestPaths<W> {
+        let mut dist = vec![None; vertices];
+        dist[source] = Some(W::zero());
+        ShortestPaths { source: source, dist: dist, prev: vec![None; vertices] }
+    }
+
+    pub fn source(&self) -> Node {
+        self.source
+    }
+
+    /// The length of the shortest path to a vertex, or None if it cannot be
+    /// reached.
+    pub fn distance(&self, vertex: Node) -> Option<W> {
+        self.dist[vertex]
+    }
+
+    /// The vertices on the shortest path to a vertex, starting at the source.
+    pub fn path_to(&self, vertex: Node) -> Option<Vec<Node>> {
+        if self.dist[vertex].is_none() {
+            return None;
+        }
+        let mut path = vec![vertex];
+        let mut curr = vertex;
+        while let Some(parent) = self.prev[curr] {
+            path.push(parent);
+            curr = parent;
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+/// The result of an all-pairs shortest path search.
+#[derive(Clone, Debug)]
+pub struct AllPairs<W> {
+    dist: Vec<Vec<Option<W>>>,
+    next: Vec<Vec<Option<Node>>>,
+}
+
+impl<W: Weight> AllPairs<W> {
+    pub fn distance(&self, from: Node, to: Node) -> Option<W> {
+        self.dist[from][to]
+    }
+
+    pub fn path(&self, from: Node, to: Node) -> Option<Vec<Node>> {
+        if self.next[from][to].is_none() {
+            return None;
+        }
+        let mut path = vec![from];
+        let mut curr = from;
+        while curr != to {
+            curr = self.next[curr][to].unwrap();
+            path.push(curr);
+        }
+        Some(path)
+    }
+}
+
+// Reading and writing graphs as edge lists, Graphviz DOT and GraphML.
+impl<W: Weight + FromStr + fmt::Display> Graph<W> {
+    /// Adds the edges of an edge list: one edge per line, given as the names
+    /// of its two vertices and an optional weight (1 if omitted), separated by
+    /// whitespace. A line with a single name adds an isolated vertex. Blank
+    /// lines and lines starting with `#` are ignored.
+    pub fn add_edge_list(&mut self, text: &str) -> Result<(), GraphError> {
+        for (i, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() || fields[0].starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| GraphError::Parse(i + 1, message.to_string());
+            match fields.len() {
+                1 => { self.get_or_insert_vertex(fields[0]); }
+                2 => self.add_edge(fields[0], fields[1], W::one()),
+                3 => {
+                    let weight = try!(fields[2].parse().map_err(|_| {
+                        error(&format!("invalid weight '{}'", fields[2]))
+                    }));
+                    self.add_edge(fields[0], fields[1], weight);
+                }
+                _ => return Err(error("expected two vertices and an optional weight")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the graph as an edge list, see `add_edge_list`. Vertex names
+    /// must not contain whitespace.
+    pub fn to_edge_list(&self) -> String {
+        let mut text = String::new();
+        for (u, v, w) in self.edges() {
+            text.push_str(&format!("{} {} {}\n", self.vertices[u], self.vertices[v], w));
+        }
+        for (v, name) in self.vertices.iter().enumerate() {
+            let isolated = self.adj_list[v].is_empty() &&
+                           self.adj_list.iter().all(|adj| adj.iter().all(|&(u, _)| u != v));
+            if isolated {
+                text.push_str(&format!("{}\n", name));
+            }
+        }
+        text
+    }
+
+    /// Reads a graph from an edge list file, see `add_edge_list`.
+    pub fn read_edge_list<P: AsRef<Path>>(path: P, directed: bool) -> Result<Graph<W>, GraphError> {
+        let text = try!(read_text(path));
+        let mut graph = if directed { Graph::directed() } else { Graph::undirected() };
+        try!(graph.add_edge_list(&text));
+        Ok(graph)
+    }
+
+    /// Reads a graph file, choosing the format by its extension: `.dot` and
+    /// `.gv` for DOT, `.graphml` for GraphML, and an edge list otherwise.
+    /// DOT and GraphML files say themselves whether the graph is directed.
+    pub fn read_file<P: AsRef<Path>>(path: P, directed: bool) -> Result<Graph<W>, GraphError> {
+        let extension = path.as_ref().extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        match extension.as_ref().map(|e| &e[..]) {
+            Some("dot") | Some("gv") => Graph::from_dot(&try!(read_text(path))),
+            Some("graphml") => Graph::from_graphml(&try!(read_text(path))),
+            _ => Graph::read_edge_list(path, directed),
+        }
+    }
+
+    /// Parses a graph in Graphviz DOT format. Edge weights are taken from the
+    /// `weight` attribute, or failing that a numeric `label`, and default to 1.
+    /// Subgraphs are flattened into the graph.
+    pub fn from_dot(text: &str) -> Result<Graph<W>, GraphError> {
+        dot::parse(text)
+    }
+
+    /// Writes the graph in Graphviz DOT format.
+    pub fn to_dot(&self) -> String {
+        dot::write(self, &[])
+    }
+
+    /// Writes the graph in Graphviz DOT format, with the vertices and edges of
+    /// the given path (such as one found by `shortest_path`) drawn in red in
+    /// a subgraph of their own.
+    pub fn path_to_dot(&self, path: &[Node]) -> String {
+        dot::write(self, path)
+    }
+
+    /// Parses the subset of GraphML with nodes, edges and a `weight` edge
+    /// attribute declared by a `key` element. Other data is ignored.
+    pub fn from_graphml(text: &str) -> Result<Graph<W>, GraphError> {
+        graphml::parse(text)
+    }
+
+    pub fn to_graphml(&self) -> String {
+        graphml::write(self)
+    }
+}
+
+fn read_text<P: AsRef<Path>>(path: P) -> Result<String, GraphError> {
+    let mut text = String::new();
+    try!(File::open(path).and_then(|mut f| f.read_to_string(&mut text))
+                         .map_err(|e| GraphError::Io(e.to_string())));
+    Ok(text)
+}
+
+/// Reading and writing Graphviz DOT.
+mod dot {
+    use std::fmt;
+    use std::str::FromStr;
+    use super::{Graph, GraphError, Node, Weight};
+
+    #[derive(Clone, PartialEq, Debug)]
+    enum Token {
+        Id(String),
+        /// `->` or `--`.
+        EdgeOp(&'static str),
+        Punct(char),
+    }
+
+    /// Splits DOT source into tokens, each with its line number. Comments and
+    /// preprocessor-style `#` lines are dropped.
+    fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, GraphError> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut tokens = Vec::new();
+        let (mut i, mut line) = (0, 1);
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).cloned();
+            if c == '\n' {
+                line += 1;
+                i += 1;
+            } else if c.is_whitespace() {
+                i += 1;
+            } else if c == '#' && (i == 0 || chars[i - 1] == '\n') || c == '/' && next == Some('/') {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            } else if c == '/' && next == Some('*') {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    if chars[i] == '\n' {
+                        line += 1;
+                    }
+                    i += 1;
+                }
+                i += 2;
+            } else if c == '-' && (next == Some('>') || next == Some('-')) {
+                tokens.push((Token::EdgeOp(if next == Some('>') { "->" } else { "--" }), line));
+                i += 2;
+            } else if "{}[]=;,:".contains(c) {
+                tokens.push((Token::Punct(c), line));
+                i += 1;
+            } else if c == '"' {
+                let start = line;
+                let mut id = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(GraphError::Parse(start, "unterminated string".to_string())),
+                        Some(&'"') => break,
+                        Some(&'\\') if chars.get(i + 1) == Some(&'"') => {
+                            id.push('"');
+                            i += 1;
+                        }
+                        Some(&'\\') if chars.get(i + 1) == Some(&'\n') => {
+                            line += 1;
+                            i += 1;
+                        }
+                        Some(&c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            id.push(c);
+                        }
+                    }
+                    i += 1;
+                }
+                tokens.push((Token::Id(id), start));
+                i += 1;
+            } else if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' {
+                let start = i;
+                while i < chars.len() &&
+                      (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.' ||
+                       chars[i] == '-' && chars.get(i + 1).map_or(true, |&n| n != '>' && n != '-')) {
+                    i += 1;
+                }
+                tokens.push((Token::Id(chars[start..i].iter().cloned().collect()), line));
+            } else {
+                return Err(GraphError::Parse(line, format!("unexpected '{}'", c)));
+            }
+        }
+        Ok(tokens)
+    }
+
+    struct Parser<W> {
+        tokens: Vec<(Token, usize)>,
+        pos: usize,
+        graph: Graph<W>,
+        edge_op: &'static str,
+    }
+
+    impl<W: Weight + FromStr> Parser<W> {
+        fn line(&self) -> usize {
+            self.tokens.get(self.pos).or(self.tokens.last()).map_or(1, |t| t.1)
+        }
+
+        fn error<T>(&self, message: &str) -> Result<T, GraphError> {
+            Err(GraphError::Parse(self.line(), message.to_string()))
+        }
+
+        fn peek(&self) -> Option<&Token> {
+            self.tokens.get(self.pos).map(|t| &t.0)
+        }
+
+        fn next(&mut self) -> Option<Token> {
+            self.pos += 1;
+            self.tokens.get(self.pos - 1).map(|t| t.0.clone())
+        }
+
+        fn is_keyword(&self, keyword: &str) -> bool {
+            match self.peek() {
+                Some(&Token::Id(ref id)) => id.to_lowercase() == keyword,
+                _ => false,
+            }
+        }
+
+        fn expect(&mut self, c: char) -> Result<(), GraphError> {
+            match self.next() {
+                Some(Token::Punct(p)) if p == c => Ok(()),
+                _ => {
+                    self.pos -= 1;
+                    self.error(&format!("expected '{}'", c))
+                }
+            }
+        }
+
+        fn id(&mut self) -> Result<String, GraphError> {
+            match self.next() {
+                Some(Token::Id(id)) => Ok(id),
+                _ => {
+                    self.pos -= 1;
+                    self.error("expected an identifier")
+                }
+            }
+        }
+
+        /// Parses a sequence of bracketed attribute lists.
+        fn attributes(&mut self) -> Result<Vec<(String, String)>, GraphError> {
+            let mut attributes = Vec::new();
+            while self.peek() == Some(&Token::Punct('[')) {
+                self.pos += 1;
+                while self.peek() != Some(&Token::Punct(']')) {
+                    let name = try!(self.id());
+                    let value = if self.peek() == Some(&Token::Punct('=')) {
+                        self.pos += 1;
+                        try!(self.id())
+                    } else {
+                        "true".to_string()
+                    };
+                    attributes.push((name, value));
+                    if let Some(&Token::Punct(c)) = self.peek() {
+                        if c == ',' || c == ';' {
+                            self.pos += 1;
+                        }
+                    }
+                }
+                try!(self.expect(']'));
+            }
+            Ok(attributes)
+        }
+
+        /// Reads the weight from the attributes of a statement on the given line.
+        /// A label is only taken as the weight if it is a number, since labels
+        /// are usually just text.
+        fn weight(attributes: &[(String, String)], default: W, line: usize) -> Result<W, GraphError> {
+            let find = |name: &str| attributes.iter().rev().find(|a| a.0 == name).map(|a| &a.1);
+            match find("weight") {
+                Some(value) => value.parse().map_err(|_| {
+                    GraphError::Parse(line, format!("invalid weight '{}'", value))
+                }),
+                None => Ok(find("label").and_then(|label| label.parse().ok()).unwrap_or(default)),
+            }
+        }
+
+        /// Parses the statements of a graph or subgraph body up to its closing
+        /// brace, with the given default edge weight.
+        fn statements(&mut self, mut default_weight: W) -> Result<(), GraphError> {
+            loop {
+                match self.peek().cloned() {
+                    None => return self.error("expected '}'"),
+                    Some(Token::Punct('}')) => {
+                        self.pos += 1;
+                        return Ok(());
+                    }
+                    Some(Token::Punct(';')) => self.pos += 1,
+                    Some(Token::Punct('{')) => {
+                        self.pos += 1;
+                        try!(self.statements(default_weight));
+                    }
+                    Some(Token::Id(ref id)) if id.to_lowercase() == "subgraph" => {
+                        self.pos += 1;
+                        if let Some(&Token::Id(_)) = self.peek() {
+                            self.pos += 1;
+                        }
+                        try!(self.expect('{'));
+                        try!(self.statements(default_weight));
+                    }
+                    Some(Token::Id(ref id)) if ["graph", "node", "edge"].contains(&&id.to_lowercase()[..]) => {
+                        let line = self.line();
+                        self.pos += 1;
+                        let attributes = try!(self.attributes());
+                        if id.to_lowercase() == "edge" {
+                            default_weight = try!(Parser::weight(&attributes, default_weight, line));
+                        }
+                    }
+                    Some(Token::Id(_)) => {
+                        let line = self.line();
+                        let first = try!(self.id());
+                        if self.peek() == Some(&Token::Punct('=')) {
+                            // A graph attribute.
+                            self.pos += 1;
+                            try!(self.id());
+                            continue;
+                        }
+                        let mut chain = vec![first];
+                        while let Some(&Token::EdgeOp(op)) = self.peek() {
+                            if op != self.edge_op {
+                                return self.error(&format!("'{}' in a graph using '{}'", op, self.edge_op));
+                            }
+                            self.pos += 1;
+                            if self.peek() == Some(&Token::Punct('{')) {
+                                return self.error("subgraphs as edge ends are not supported");
+                            }
+                            chain.push(try!(self.id()));
+                        }
+                        let attributes = try!(self.attributes());
+                        if chain.len() == 1 {
+                            self.graph.get_or_insert_vertex(&chain[0]);
+                        } else {
+                            let weight = try!(Parser::weight(&attributes, default_weight, line));
+                            for pair in chain.windows(2) {
+                                self.graph.add_edge(&pair[0], &pair[1], weight);
+                            }
+                        }
+                    }
+                    Some(_) => return self.error("expected a statement"),
+                }
+            }
+        }
+    }
+
+    pub fn parse<W: Weight + FromStr>(text: &str) -> Result<Graph<W>, GraphError> {
+        let mut parser = Parser {
+            tokens: try!(tokenize(text)),
+            pos: 0,
+            graph: Graph::new(),
+            edge_op: "->",
+        };
+        if parser.is_keyword("strict") {
+            parser.pos += 1;
+        }
+        if parser.is_keyword("graph") {
+            parser.graph = Graph::undirected();
+            parser.edge_op = "--";
+        } else if !parser.is_keyword("digraph") {
+            return parser.error("expected 'graph' or 'digraph'");
+        }
+        parser.pos += 1;
+        if let Some(&Token::Id(_)) = parser.peek() {
+            parser.pos += 1;
+        }
+        try!(parser.expect('{'));
+        try!(parser.statements(W::one()));
+        if parser.pos < parser.tokens.len() {
+            return parser.error("unexpected text after the graph");
+        }
+        Ok(parser.graph)
+    }
+
+    fn quote(id: &str) -> String {
+        format!("\"{}\"", id.replace("\\", "\\\\").replace("\"", "\\\""))
+    }
+
+    /// Writes the graph, putting the vertices and edges of `path` in a red
+    /// subgraph.
+    pub fn write<W: Weight + fmt::Display>(graph: &Graph<W>, path: &[Node]) -> String {
+        let edge_op = if graph.is_directed() { "->" } else { "--" };
+        let on_path = |u: Node, v: Node| {
+            path.windows(2).any(|p| (p[0], p[1]) == (u, v) ||
+                                    !graph.is_directed() && (p[1], p[0]) == (u, v))
+        };
+        let edge = |u: Node, v: Node, w: W| {
+            format!("    {} {} {} [weight={}, label=\"{}\"];\n",
+                    quote(graph.vertex_name(u)), edge_op, quote(graph.vertex_name(v)), w, w)
+        };
+
+        let mut out = format!("{} {{\n", if graph.is_directed() { "digraph" } else { "graph" });
+        for v in 0..graph.vertex_count() {
+            if !path.contains(&v) {
+                out.push_str(&format!("    {};\n", quote(graph.vertex_name(v))));
+            }
+        }
+        for (u, v, w) in graph.edges() {
+            if !on_path(u, v) {
+                out.push_str(&edge(u, v, w));
+            }
+        }
+        if !path.is_empty() {
+            out.push_str("    subgraph path {\n");
+            out.push_str("        node [color=red];\n");
+            out.push_str("        edge [color=red, penwidth=2];\n");
+            let mut vertices = path.to_vec();
+            vertices.sort();
+            vertices.dedup();
+            for &v in &vertices {
+                out.push_str(&format!("        {};\n", quote(graph.vertex_name(v))));
+            }
+            for (u, v, w) in graph.edges() {
+                if on_path(u, v) {
+                    out.push_str("    ");
+                    out.push_str(&edge(u, v, w));
+                }
+            }
+            out.push_str("    }\n");
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Reading and writing a subset of GraphML.
+mod graphml {
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::str::FromStr;
+    use super::{Graph, GraphError, Weight};
+
+    #[derive(PartialEq, Debug)]
+    enum Event {
+        /// An opening tag with its attributes, and whether it closes itself.
+        Start(String, HashMap<String, String>, bool),
+        End(String),
+        Text(String),
+    }
+
+    fn unescape(text: &str) -> String {
+        text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"")
+            .replace("&apos;", "'").replace("&amp;", "&")
+    }
+
+    fn escape(text: &str) -> String {
+        text.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;").replace("\"", "&quot;")
+    }
+
+    /// A minimal XML reader: tags, attributes and text, each with its line
+    /// number. Comments, declarations and processing instructions are skipped.
+    fn events(text: &str) -> Result<Vec<(Event, usize)>, GraphError> {
+        let mut events = Vec::new();
+        let mut rest = text;
+        let mut line = 1;
+        while !rest.is_empty() {
+            let (skip, end) = if rest.starts_with("<!--") {
+                (true, rest.find("-->").map(|i| i + 3))
+            } else if rest.starts_with("<?") || rest.starts_with("<!") {
+                (true, rest.find('>').map(|i| i + 1))
+            } else if rest.starts_with('<') {
+                (false, rest.find('>').map(|i| i + 1))
+            } else {
+                (false, Some(rest.find('<').unwrap_or(rest.len())))
+            };
+            let end = match end {
+                Some(end) => end,
+                None => return Err(GraphError::Parse(line, "unterminated tag".to_string())),
+            };
+            let (item, remainder) = rest.split_at(end);
+            if !skip {
+                if !item.starts_with('<') {
+                    if !item.trim().is_empty() {
+                        events.push((Event::Text(unescape(item.trim())), line));
+                    }
+                } else if item.starts_with("</") {
+                    events.push((Event::End(item[2..item.len() - 1].trim().to_string()), line));
+                } else {
+                    events.push((try!(start_tag(item, line)), line));
+                }
+            }
+            line += item.matches('\n').count();
+            rest = remainder;
+        }
+        Ok(events)
+    }
+
+    /// Parses `<name attr="value" ...>` or `<name ... />`.
+    fn start_tag(tag: &str, line: usize) -> Result<Event, GraphError> {
+        let self_closing = tag.ends_with("/>");
+        let inner = &tag[1..tag.len() - if self_closing { 2 } else { 1 }];
+        let name_end = inner.find(char::is_whitespace).unwrap_or(inner.len());
+        let (name, mut rest) = inner.split_at(name_end);
+        let mut attributes = HashMap::new();
+        loop {
+            rest = rest.trim_left();
+            if rest.is_empty() {
+                break;
+            }
+            let malformed = || GraphError::Parse(line, format!("malformed attributes in <{}>", name));
+            let eq = try!(rest.find('=').ok_or_else(&malformed));
+            let key = rest[..eq].trim().to_string();
+            let value_part = rest[eq + 1..].trim_left();
+            let quote = try!(value_part.chars().next().ok_or_else(&malformed));
+            if quote != '"' && quote != '\'' {
+                return Err(malformed());
+            }
+            let close = try!(value_part[1..].find(quote).ok_or_else(&malformed));
+            attributes.insert(key, unescape(&value_part[1..close + 1]));
+            rest = &value_part[close + 2..];
+        }
+        Ok(Event::Start(name.to_string(), attributes, self_closing))
+    }
+
+    pub fn parse<W: Weight + FromStr>(text: &str) -> Result<Graph<W>, GraphError> {
+        let mut graph = None;
+        // The id and default value of the key holding edge weights.
+        let mut weight_key: Option<(String, Option<W>)> = None;
+        // The edge being read: its ends and its weight so far.
+        let mut edge: Option<(String, String, Option<W>)> = None;
+        let mut data_key: Option<String> = None;
+        // The id of the <key> being read, and of the one whose <default> is open.
+        let mut open_key: Option<String> = None;
+        let mut default_key: Option<String> = None;
+
+        let events = try!(events(text));
+        for (event, line) in events {
+            let error = |message: String| Err(GraphError::Parse(line, message));
+            let parse_weight = |value: &str| -> Result<W, GraphError> {
+                value.parse().map_err(|_| GraphError::Parse(line, format!("invalid weight '{}'", value)))
+            };
+            match event {
+                Event::Start(name, attributes, self_closing) => {
+                    let attr = |key: &str| attributes.get(key).cloned();
+                    match &name[..] {
+                        "key" => {
+                            open_key = attr("id");
+                            let for_edges = attr("for").map_or(true, |f| f == "edge" || f == "all");
+                            if attr("attr.name") == Some("weight".to_string()) && for_edges {
+                                let id = match attr("id") {
+                                    Some(id) => id,
+                                    None => return error("<key> without an id".to_string()),
+                                };
+                                weight_key = Some((id, None));
+                            }
+                        }
+                        "default" => default_key = open_key.clone(),
+                        "graph" => {
+                            if graph.is_some() {
+                                return error("nested graphs are not supported".to_string());
+                            }
+                            graph = Some(if attr("edgedefault") == Some("undirected".to_string()) {
+                                Graph::undirected()
+                            } else {
+                                Graph::directed()
+                            });
+                        }
+                        "node" | "edge" if graph.is_none() => {
+                            return error(format!("<{}> outside <graph>", name));
+                        }
+                        "node" => match attr("id") {
+                            Some(id) => { graph.as_mut().unwrap().get_or_insert_vertex(&id); }
+                            None => return error("<node> without an id".to_string()),
+                        },
+                        "edge" => match (attr("source"), attr("target")) {
+                            (Some(source), Some(target)) => edge = Some((source, target, None)),
+                            _ => return error("<edge> needs a source and a target".to_string()),
+                        },
+                        "data" => data_key = attr("key"),
+                        _ => {}
+                    }
+                    if self_closing {
+                        match &name[..] {
+                            "edge" => {
+                                let (source, target, _) = edge.take().unwrap();
+                                let default = weight_key.as_ref().and_then(|k| k.1);
+                                let graph = graph.as_mut().unwrap();
+                                graph.add_edge(&source, &target, default.unwrap_or(W::one()));
+                            }
+                            "data" => data_key = None,
+                            "key" => open_key = None,
+                            _ => {}
+                        }
+                    }
+                }
+                Event::Text(text) => {
+                    let is_weight = |key: &Option<String>| {
+                        weight_key.as_ref().map_or(false, |k| Some(&k.0) == key.as_ref())
+                    };
+                    if default_key.is_some() {
+                        // Other keys' defaults are nothing to do with weights.
+                        if is_weight(&default_key) {
+                            let default = try!(parse_weight(&text));
+                            weight_key.as_mut().unwrap().1 = Some(default);
+                        }
+                    } else if is_weight(&data_key) {
+                        if let Some(ref mut e) = edge {
+                            e.2 = Some(try!(parse_weight(&text)));
+                        }
+                    }
+                }
+                Event::End(name) => match &name[..] {
+                    "default" => default_key = None,
+                    "key" => open_key = None,
+                    "data" => data_key = None,
+                    "edge" => {
+                        let (source, target, weight) = match edge.take() {
+                            Some(e) => e,
+                            None => return error("unexpected </edge>".to_string()),
+                        };
+                        let default = weight_key.as_ref().and_then(|k| k.1);
+                        let weight = weight.or(default).unwrap_or(W::one());
+                        graph.as_mut().unwrap().add_edge(&source, &target, weight);
+                    }
+                    _ => {}
+                },
+            }
+        }
+        graph.ok_or(GraphError::Parse(1, "no <graph> element".to_string()))
     }
-}
 
-/// Graph structure, represented as an Adjancency List.
-struct Graph<'a> {
-    vertices: Vec<&'a str>,
-    adj_list: Vec<Vec<Node>>,
-    costs: HashMap<Edge, Cost>,
+    pub fn write<W: Weight + fmt::Display>(graph: &Graph<W>) -> String {
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        out.push_str("  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"double\"/>\n");
+        out.push_str(&format!("  <graph id=\"G\" edgedefault=\"{}\">\n",
+                              if graph.is_directed() { "directed" } else { "undirected" }));
+        for v in 0..graph.vertex_count() {
+            out.push_str(&format!("    <node id=\"{}\"/>\n", escape(graph.vertex_name(v))));
+        }
+        for (u, v, w) in graph.edges() {
+            out.push_str(&format!("    <edge source=\"{}\" target=\"{}\">\
+                                   <data key=\"weight\">{}</data></edge>\n",
+                                  escape(graph.vertex_name(u)), escape(graph.vertex_name(v)), w));
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
 }
 
-impl<'a> Graph<'a> {
-    fn new() -> Graph<'a> {
-        let vertices:Vec<&str> = Vec::new();
-        let adj_list:Vec<Vec<Node>> = Vec::new();
-        let costs:HashMap<Edge, Cost> = HashMap::new();
+// Ordering, connectivity, spanning trees and flows.
+impl<W: Weight> Graph<W> {
+    fn cycle_error(&self, cycle: &[Node]) -> GraphError {
+        GraphError::Cycle(self.names(cycle).iter().map(|s| s.to_string()).collect())
+    }
 
-        Graph{vertices: vertices, adj_list: adj_list, costs: costs}
+    /// The incoming edges of every vertex.
+    fn predecessors(&self) -> Vec<Vec<Node>> {
+        let mut preds = vec![Vec::new(); self.vertex_count()];
+        for (u, adj) in self.adj_list.iter().enumerate() {
+            for &(v, _) in adj {
+                preds[v].push(u);
+            }
+        }
+        preds
     }
 
-    /// Returns the index of the vertex, or None if vertex
-    /// not found.
-    fn vertex_index(&self, vertex: &str) -> Option<Node> {
-        for (idx, &v) in self.vertices.iter().enumerate(){
-            if v == vertex{return Some(idx)}
+    /// Sorts the vertices so that every edge points forwards, using Kahn's
+    /// algorithm. If the graph has a cycle, one is returned in the error.
+    pub fn topological_sort(&self) -> Result<Vec<Node>, GraphError> {
+        let n = self.vertex_count();
+        let mut in_degree = vec![0; n];
+        for adj in &self.adj_list {
+            for &(v, _) in adj {
+                in_degree[v] += 1;
+            }
+        }
+        let mut ready: VecDeque<Node> = (0..n).filter(|&v| in_degree[v] == 0).collect();
+        let mut order = Vec::with_capacity(n);
+        while let Some(u) = ready.pop_front() {
+            order.push(u);
+            for &(v, _) in &self.adj_list[u] {
+                in_degree[v] -= 1;
+                if in_degree[v] == 0 {
+                    ready.push_back(v);
+                }
+            }
+        }
+        if order.len() == n {
+            return Ok(order);
         }
-        return None
+
+        // Every vertex left over has a predecessor which is left over too, so
+        // walking backwards from one of them must come round in a cycle.
+        let preds = self.predecessors();
+        let mut position = vec![None; n];
+        let mut walk = Vec::new();
+        let mut v = (0..n).find(|&v| in_degree[v] > 0).unwrap();
+        while position[v].is_none() {
+            position[v] = Some(walk.len());
+            walk.push(v);
+            v = *preds[v].iter().find(|&&p| in_degree[p] > 0).unwrap();
+        }
+        let mut cycle = walk[position[v].unwrap()..].to_vec();
+        cycle.reverse();
+        let first = cycle[0];
+        cycle.push(first);
+        Err(self.cycle_error(&cycle))
     }
 
-    /// Returns the index of the vertex. If vertex is not found, inserts
-    /// the vertex.
-    fn get_or_insert_vertex(&mut self, vertex: &'a str) -> Node {
-        self.vertex_index(vertex)
-            .unwrap_or_else( || {
-                self.adj_list.push(Vec::new());
-                self.vertices.push(vertex);
-                self.vertices.len()-1
-            })
+    /// Sorts the vertices so that every edge points forwards, using depth
+    /// first search. If the graph has a cycle, one is returned in the error.
+    pub fn topological_sort_dfs(&self) -> Result<Vec<Node>, GraphError> {
+        // 0: not visited yet, 1: on the current path, 2: finished.
+        fn visit<W>(adj_list: &[Vec<(Node, W)>], u: Node, state: &mut [u8], path: &mut Vec<Node>,
+                    order: &mut Vec<Node>) -> Result<(), Vec<Node>> {
+            state[u] = 1;
+            path.push(u);
+            for &(v, _) in &adj_list[u] {
+                match state[v] {
+                    0 => try!(visit(adj_list, v, state, path, order)),
+                    1 => {
+                        let start = path.iter().position(|&p| p == v).unwrap();
+                        let mut cycle = path[start..].to_vec();
+                        cycle.push(v);
+                        return Err(cycle);
+                    }
+                    _ => {}
+                }
+            }
+            path.pop();
+            state[u] = 2;
+            order.push(u);
+            Ok(())
+        }
+
+        let n = self.vertex_count();
+        let mut state = vec![0; n];
+        let mut order = Vec::with_capacity(n);
+        for v in 0..n {
+            if state[v] == 0 {
+                let mut path = Vec::new();
+                if let Err(cycle) = visit(&self.adj_list, v, &mut state, &mut path, &mut order) {
+                    return Err(self.cycle_error(&cycle));
+                }
+            }
+        }
+        order.reverse();
+        Ok(order)
     }
 
-    /// Adds the given edge to the graph.
-    fn add_edge(&mut self, from: &'a str, to: &'a str, cost: usize) {
-        let from_idx = self.get_or_insert_vertex(from);
-        let to_idx = self.get_or_insert_vertex(to);
+    /// Finds the strongly connected components with Tarjan's algorithm. The
+    /// components come out in reverse topological order.
+    pub fn tarjan_scc(&self) -> Vec<Vec<Node>> {
+        struct State {
+            index: Vec<Option<usize>>,
+            low_link: Vec<usize>,
+            on_stack: Vec<bool>,
+            stack: Vec<Node>,
+            next_index: usize,
+            components: Vec<Vec<Node>>,
+        }
+
+        fn connect<W>(adj_list: &[Vec<(Node, W)>], u: Node, s: &mut State) {
+            s.index[u] = Some(s.next_index);
+            s.low_link[u] = s.next_index;
+            s.next_index += 1;
+            s.stack.push(u);
+            s.on_stack[u] = true;
 
-        match self.costs.entry((from_idx, to_idx)) {
-            Vacant(entry)   => {
-                self.adj_list[from_idx].push(to_idx);
-                entry.insert(cost);
-            },
-            Occupied(entry) => {
-                *entry.into_mut() = cost;
+            for &(v, _) in &adj_list[u] {
+                match s.index[v] {
+                    None => {
+                        connect(adj_list, v, s);
+                        s.low_link[u] = cmp::min(s.low_link[u], s.low_link[v]);
+                    }
+                    Some(index) if s.on_stack[v] => {
+                        s.low_link[u] = cmp::min(s.low_link[u], index);
+                    }
+                    _ => {}
+                }
+            }
+
+            if Some(s.low_link[u]) == s.index[u] {
+                let mut component = Vec::new();
+                loop {
+                    let v = s.stack.pop().unwrap();
+                    s.on_stack[v] = false;
+                    component.push(v);
+                    if v == u {
+                        break;
+                    }
+                }
+                s.components.push(component);
             }
+        }
+
+        let n = self.vertex_count();
+        let mut state = State {
+            index: vec![None; n],
+            low_link: vec![0; n],
+            on_stack: vec![false; n],
+            stack: Vec::new(),
+            next_index: 0,
+            components: Vec::new(),
         };
+        for v in 0..n {
+            if state.index[v].is_none() {
+                connect(&self.adj_list, v, &mut state);
+            }
+        }
+        state.components
     }
 
-    /// Implements Dijkstra's Algorithm. This uses a Priority Queue to
-    /// determine which vertex to visit first. Terminates on discovering
-    /// the target vertex.
-    ///
-    /// Returns vector of vertices representing the path, or an empty vector
-    /// if there's no path, or if the source or target is not in the graph.
-    fn dijkstra(&'a self, source: &str, target: &str) -> Vec<&str> {
-        let num_vert = self.vertices.len();
-        let mut dist:Vec<usize> = repeat(usize::MAX).take(num_vert)
-            .collect(); //Close enough to infinity
-        let mut prev:HashMap<Node, Node> = HashMap::new();
-        let mut queue:BinaryHeap<DistPair> = BinaryHeap::new();
-
-        let source_idx = match self.vertex_index(source) {
-            Some(idx) => idx,
-            None      => return Vec::new() // Source not in graph, return empty path.
-        };
+    /// Finds the strongly connected components with Kosaraju's algorithm. The
+    /// components come out in topological order.
+    pub fn kosaraju_scc(&self) -> Vec<Vec<Node>> {
+        fn finish_order<W>(adj_list: &[Vec<(Node, W)>], u: Node, seen: &mut [bool],
+                           order: &mut Vec<Node>) {
+            seen[u] = true;
+            for &(v, _) in &adj_list[u] {
+                if !seen[v] {
+                    finish_order(adj_list, v, seen, order);
+                }
+            }
+            order.push(u);
+        }
 
-        let target_idx = match self.vertex_index(target) {
-            Some(idx) => idx,
-            None      => return Vec::new() // Target not in graph, return empty path.
-        };
+        fn collect(preds: &[Vec<Node>], u: Node, component: &mut [Option<usize>], id: usize,
+                   members: &mut Vec<Node>) {
+            component[u] = Some(id);
+            members.push(u);
+            for &v in &preds[u] {
+                if component[v].is_none() {
+                    collect(preds, v, component, id, members);
+                }
+            }
+        }
 
-        dist[source_idx] = 0;
-        queue.push(DistPair(source_idx, dist[source_idx]));
-
-        loop{
-            match queue.pop() {
-                None     => break,
-                Some(DistPair(u, dist_u)) => {
-                    for &v in &(self.adj_list[u]) {
-                        let cost_uv = match self.costs.get(&(u, v)) {
-                            Some(&x) => x,
-                            None     => usize::MAX,
-                        };
-                        let alt = dist_u + cost_uv;
-                        if alt < dist[v] {
-                            match prev.entry(v) {
-                                Vacant(entry) => {entry.insert(u);},
-                                Occupied(entry) => {
-                                    *entry.into_mut() = u;
-                                }
-                            }
-                            dist[v] = alt;
-                            queue.push(DistPair(v, dist[v]));
+        let n = self.vertex_count();
+        let mut seen = vec![false; n];
+        let mut order = Vec::with_capacity(n);
+        for v in 0..n {
+            if !seen[v] {
+                finish_order(&self.adj_list, v, &mut seen, &mut order);
+            }
+        }
+
+        let preds = self.predecessors();
+        let mut component = vec![None; n];
+        let mut components = Vec::new();
+        for &v in order.iter().rev() {
+            if component[v].is_none() {
+                let mut members = Vec::new();
+                collect(&preds, v, &mut component, components.len(), &mut members);
+                components.push(members);
+            }
+        }
+        components
+    }
+
+    /// Finds a minimum spanning forest with Kruskal's algorithm, treating
+    /// every edge as undirected. Returns the total weight and the edges.
+    pub fn kruskal(&self) -> (W, Vec<(Node, Node, W)>) {
+        fn find(parent: &mut [Node], v: Node) -> Node {
+            let root = if parent[v] == v { v } else { find(parent, parent[v]) };
+            parent[v] = root;
+            root
+        }
+
+        let mut edges = self.edges();
+        edges.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal));
+        let mut parent: Vec<Node> = (0..self.vertex_count()).collect();
+        let mut total = W::zero();
+        let mut tree = Vec::new();
+        for (u, v, w) in edges {
+            let (ru, rv) = (find(&mut parent, u), find(&mut parent, v));
+            if ru != rv {
+                parent[ru] = rv;
+                total = total + w;
+                tree.push((u, v, w));
+            }
+        }
+        (total, tree)
+    }
+
+    /// Finds a minimum spanning forest with Prim's algorithm, treating every
+    /// edge as undirected. Returns the total weight and the edges.
+    pub fn prim(&self) -> (W, Vec<(Node, Node, W)>) {
+        let n = self.vertex_count();
+        let mut adj = self.adj_list.clone();
+        if self.directed {
+            for (u, v, w) in self.arcs() {
+                adj[v].push((u, w));
+            }
+        }
+
+        let mut in_tree = vec![false; n];
+        let mut parent: Vec<Option<Node>> = vec![None; n];
+        let mut key: Vec<Option<W>> = vec![None; n];
+        let mut total = W::zero();
+        let mut tree = Vec::new();
+        for root in 0..n {
+            if in_tree[root] {
+                continue;
+            }
+            let mut queue = BinaryHeap::new();
+            queue.push(DistPair(root, W::zero()));
+            while let Some(DistPair(u, w)) = queue.pop() {
+                if in_tree[u] {
+                    continue;
+                }
+                in_tree[u] = true;
+                if let Some(p) = parent[u] {
+                    total = total + w;
+                    tree.push((p, u, w));
+                }
+                for &(v, w) in &adj[u] {
+                    let better = match key[v] {
+                        None => true,
+                        Some(k) => w < k,
+                    };
+                    if !in_tree[v] && better {
+                        key[v] = Some(w);
+                        parent[v] = Some(u);
+                        queue.push(DistPair(v, w));
+                    }
+                }
+            }
+        }
+        (total, tree)
+    }
+
+    fn flow_network(&self, source: &str, sink: &str)
+                    -> Result<(FlowNetwork<W>, Node, Node), GraphError> {
+        let (s, t) = (try!(self.index_of(source)), try!(self.index_of(sink)));
+        try!(self.check_non_negative());
+        let mut network = FlowNetwork::new(self.vertex_count());
+        for (u, v, w) in self.arcs() {
+            network.add_arc(u, v, w);
+        }
+        Ok((network, s, t))
+    }
+
+    /// Computes a maximum flow from source to sink with the Edmonds-Karp
+    /// algorithm, using edge weights as capacities.
+    pub fn edmonds_karp(&self, source: &str, sink: &str) -> Result<MaxFlow<W>, GraphError> {
+        let (mut network, s, t) = try!(self.flow_network(source, sink));
+        let value = network.edmonds_karp(s, t);
+        Ok(network.result(s, value))
+    }
+
+    /// Computes a maximum flow from source to sink with Dinic's algorithm,
+    /// using edge weights as capacities.
+    pub fn dinic(&self, source: &str, sink: &str) -> Result<MaxFlow<W>, GraphError> {
+        let (mut network, s, t) = try!(self.flow_network(source, sink));
+        if s == t {
+            // Nothing has to flow anywhere, as Edmonds-Karp finds too.
+            return Ok(network.result(s, W::zero()));
+        }
+        let value = network.dinic(s, t);
+        Ok(network.result(s, value))
+    }
+
+    /// Finds a maximum matching of a bipartite graph, returned as pairs of
+    /// vertices. Edge directions and weights are ignored.
+    pub fn bipartite_matching(&self) -> Result<Vec<(Node, Node)>, GraphError> {
+        let n = self.vertex_count();
+        let mut adj = vec![Vec::new(); n];
+        for (u, v, _) in self.arcs() {
+            adj[u].push(v);
+            adj[v].push(u);
+        }
+
+        // Two-colour the graph to find its sides.
+        let mut left: Vec<Option<bool>> = vec![None; n];
+        for start in 0..n {
+            if left[start].is_some() {
+                continue;
+            }
+            left[start] = Some(true);
+            let mut queue = VecDeque::new();
+            queue.push_back(start);
+            while let Some(u) = queue.pop_front() {
+                for &v in &adj[u] {
+                    match left[v] {
+                        None => {
+                            left[v] = Some(!left[u].unwrap());
+                            queue.push_back(v);
                         }
-                        if v == target_idx { break; }
+                        Some(side) if side == left[u].unwrap() => {
+                            return Err(GraphError::NotBipartite);
+                        }
+                        _ => {}
                     }
                 }
-            };
+            }
         }
 
-        let mut temp_path:LinkedList<&str> = LinkedList::new();
-        let mut curr = target_idx;
-        temp_path.push_front(self.vertices[curr]);
-        loop{
-            match prev.get(&curr){
-                Some(&parent) => {
-                    curr = parent;
-                    temp_path.push_front(self.vertices[curr]);
-                    if curr == source_idx { break; }
-                },
-                None => return Vec::new(),
+        // A unit capacity network from an extra source through the left side
+        // to the right side and on to an extra sink.
+        let (s, t) = (n, n + 1);
+        let mut network = FlowNetwork::new(n + 2);
+        for u in 0..n {
+            if left[u] == Some(true) {
+                network.add_arc(s, u, 1);
+                for &v in &adj[u] {
+                    network.add_arc(u, v, 1);
+                }
+            } else {
+                network.add_arc(u, t, 1);
             }
         }
-        return temp_path.into_iter().collect();
+        let size = network.dinic(s, t);
+        let mut matching: Vec<(Node, Node)> = network.result(s, size).flows().iter()
+            .filter(|&&(u, v, _)| u < n && v < n)
+            .map(|&(u, v, _)| (u, v))
+            .collect();
+        matching.sort();
+        Ok(matching)
     }
 }
 
-#[test]
-fn test_dijkstras() {
-    let mut graph = Graph::new();
-    graph.add_edge("a", "b", 7);
-    graph.add_edge("b", "c", 10);
-    graph.add_edge("c", "d", 5);
-    graph.add_edge("a", "d", 30);
-    graph.add_edge("y", "z", 10); //Disconnected from the rest
+fn min<W: PartialOrd>(a: W, b: W) -> W {
+    if b < a { b } else { a }
+}
+
+/// A flow network in residual form. Arcs are stored in pairs, so the reverse
+/// of arc `i` is arc `i ^ 1`.
+struct FlowNetwork<W> {
+    adj: Vec<Vec<usize>>,
+    to: Vec<Node>,
+    capacity: Vec<W>,
+    residual: Vec<W>,
+}
+
+impl<W: Weight> FlowNetwork<W> {
+    fn new(vertices: usize) -> FlowNetwork<W> {
+        FlowNetwork { adj: vec![Vec::new(); vertices], to: vec![], capacity: vec![], residual: vec![] }
+    }
+
+    fn add_arc(&mut self, from: Node, to: Node, capacity: W) {
+        for &(u, v, c) in &[(from, to, capacity), (to, from, W::zero())] {
+            self.adj[u].push(self.to.len());
+            self.to.push(v);
+            self.capacity.push(c);
+            self.residual.push(c);
+        }
+    }
+
+    /// Breadth first search distances from s in the residual network.
+    fn levels(&self, s: Node) -> Vec<Option<usize>> {
+        let mut level = vec![None; self.adj.len()];
+        level[s] = Some(0);
+        let mut queue = VecDeque::new();
+        queue.push_back(s);
+        while let Some(u) = queue.pop_front() {
+            for &arc in &self.adj[u] {
+                let v = self.to[arc];
+                if level[v].is_none() && self.residual[arc] > W::zero() {
+                    level[v] = Some(level[u].unwrap() + 1);
+                    queue.push_back(v);
+                }
+            }
+        }
+        level
+    }
+
+    fn edmonds_karp(&mut self, s: Node, t: Node) -> W {
+        let mut total = W::zero();
+        loop {
+            // Find a shortest augmenting path.
+            let mut via: Vec<Option<usize>> = vec![None; self.adj.len()];
+            let mut queue = VecDeque::new();
+            queue.push_back(s);
+            while let Some(u) = queue.pop_front() {
+                for &arc in &self.adj[u] {
+                    let v = self.to[arc];
+                    if v != s && via[v].is_none() && self.residual[arc] > W::zero() {
+                        via[v] = Some(arc);
+                        queue.push_back(v);
+                    }
+                }
+            }
+            if via[t].is_none() {
+                return total;
+            }
+
+            let mut bottleneck = None;
+            let mut v = t;
+            while let Some(arc) = via[v] {
+                let r = self.residual[arc];
+                bottleneck = Some(bottleneck.map_or(r, |b| min(b, r)));
+                v = self.to[arc ^ 1];
+            }
+            let bottleneck = bottleneck.unwrap();
+            let mut v = t;
+            while let Some(arc) = via[v] {
+                self.residual[arc] = self.residual[arc] - bottleneck;
+                self.residual[arc ^ 1] = self.residual[arc ^ 1] + bottleneck;
+                v = self.to[arc ^ 1];
+            }
+            total = total + bottleneck;
+        }
+    }
+
+    fn dinic(&mut self, s: Node, t: Node) -> W {
+        fn push<W: Weight>(net: &mut FlowNetwork<W>, u: Node, t: Node, limit: W,
+                           level: &[Option<usize>], next: &mut [usize]) -> W {
+            if u == t {
+                return limit;
+            }
+            while next[u] < net.adj[u].len() {
+                let arc = net.adj[u][next[u]];
+                let v = net.to[arc];
+                if net.residual[arc] > W::zero() &&
+                   level[v] == level[u].map(|l| l + 1) {
+                    let pushed = push(net, v, t, min(limit, net.residual[arc]), level, next);
+                    if pushed > W::zero() {
+                        net.residual[arc] = net.residual[arc] - pushed;
+                        net.residual[arc ^ 1] = net.residual[arc ^ 1] + pushed;
+                        return pushed;
+                    }
+                }
+                next[u] += 1;
+            }
+            W::zero()
+        }
+
+        let mut total = W::zero();
+        loop {
+            let level = self.levels(s);
+            if level[t].is_none() {
+                return total;
+            }
+            let mut next = vec![0; self.adj.len()];
+            // Any limit will do as long as it is at least the largest flow
+            // out of the source.
+            let limit = self.adj[s].iter().fold(W::zero(), |sum, &arc| sum + self.residual[arc]);
+            let mut phase = W::zero();
+            loop {
+                let pushed = push(self, s, t, limit, &level, &mut next);
+                if !(pushed > W::zero()) {
+                    break;
+                }
+                phase = phase + pushed;
+            }
+            if !(phase > W::zero()) {
+                return total;
+            }
+            total = total + phase;
+        }
+    }
+
+    /// Reads off the flow on each arc and the minimum cut, which separates the
+    /// vertices still reachable from the source from the others.
+    fn result(&self, s: Node, value: W) -> MaxFlow<W> {
+        let reachable = self.levels(s);
+        let mut flows = Vec::new();
+        let mut cut = Vec::new();
+        for (u, arcs) in self.adj.iter().enumerate() {
+            for &arc in arcs {
+                if arc % 2 == 1 {
+                    continue; // A reverse arc.
+                }
+                let v = self.to[arc];
+                let flow = self.capacity[arc] - self.residual[arc];
+                if flow > W::zero() {
+                    flows.push((u, v, flow));
+                }
+                if reachable[u].is_some() && reachable[v].is_none() {
+                    cut.push((u, v, self.capacity[arc]));
+                }
+            }
+        }
+        MaxFlow { value: value, flows: flows, min_cut: cut }
+    }
+}
+
+/// The result of a maximum flow computation.
+#[derive(Clone, Debug)]
+pub struct MaxFlow<W> {
+    value: W,
+    flows: Vec<(Node, Node, W)>,
+    min_cut: Vec<(Node, Node, W)>,
+}
+
+impl<W: Weight> MaxFlow<W> {
+    /// The total flow from source to sink.
+    pub fn value(&self) -> W {
+        self.value
+    }
+
+    /// The edges carrying flow, as (from, to, flow).
+    pub fn flows(&self) -> &[(Node, Node, W)] {
+        &self.flows
+    }
+
+    /// The edges of a minimum cut, as (from, to, capacity). Their capacities
+    /// add up to the value of the flow.
+    pub fn min_cut(&self) -> &[(Node, Node, W)] {
+        &self.min_cut
+    }
+}
+
+/// Finds a shortest path in a graph file, see the usage note at the top.
+#[cfg(not(test))]
+fn route(file: &str, source: &str, target: &str, dot: Option<&str>) -> Result<(), GraphError> {
+    use std::io::Write;
 
-    assert_eq!(graph.dijkstra("a", "d"), vec!["a", "b", "c", "d"]);
-    assert!(graph.dijkstra("a", "y").is_empty());
-    assert!(graph.dijkstra("e", "y").is_empty());
-    assert!(graph.dijkstra("a", "e").is_empty());
+    let graph: Graph<f64> = try!(Graph::read_file(file, true));
+    match try!(graph.shortest_path(source, target)) {
+        None => println!("There is no path from {} to {}", source, target),
+        Some((cost, path)) => {
+            println!("Path is: {:?}", graph.names(&path));
+            println!("Cost: {}", cost);
+            if let Some(output) = dot {
+                try!(File::create(output)
+                         .and_then(|mut f| f.write_all(graph.path_to_dot(&path).as_bytes()))
+                         .map_err(|e| GraphError::Io(e.to_string())));
+            }
+        }
+    }
+    Ok(())
 }
 
 #[cfg(not(test))]
 fn main(){
+    use std::env;
+    use std::process;
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        let result = match args.len() {
+            3 => route(&args[0], &args[1], &args[2], None),
+            5 if args[3] == "--dot" => route(&args[0], &args[1], &args[2], Some(&args[4])),
+            _ => {
+                println!("Usage: dijkstras_algorithm [FILE SOURCE TARGET [--dot OUTPUT]]");
+                process::exit(2);
+            }
+        };
+        if let Err(e) = result {
+            println!("Error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
     let mut graph = Graph::new();
     graph.add_edge("a", "b", 7);
     graph.add_edge("a", "c", 9);
@@ -173,4 +1752,427 @@ fn main(){
 
     let path = graph.dijkstra("a", "e");
     println!("Path is: {:?}", path);
+
+    let paths = graph.distances("a").unwrap();
+    for v in 0..graph.vertex_count() {
+        println!("Distance from a to {}: {:?}", graph.vertex_name(v), paths.distance(v));
+    }
+
+    let mut graph: Graph<i32> = Graph::new();
+    graph.add_edge("a", "b", 4);
+    graph.add_edge("b", "c", -3);
+    graph.add_edge("c", "a", -2);
+    match graph.bellman_ford("a") {
+        Ok(_) => println!("No negative cycle"),
+        Err(e) => println!("Bellman-Ford: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Graph, GraphError, Node};
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+
+    #[test]
+    fn test_dijkstras() {
+        let mut graph = Graph::new();
+        graph.add_edge("a", "b", 7);
+        graph.add_edge("b", "c", 10);
+        graph.add_edge("c", "d", 5);
+        graph.add_edge("a", "d", 30);
+        graph.add_edge("y", "z", 10); //Disconnected from the rest
+
+        assert_eq!(graph.dijkstra("a", "d"), vec!["a", "b", "c", "d"]);
+        assert!(graph.dijkstra("a", "y").is_empty());
+        assert!(graph.dijkstra("e", "y").is_empty());
+        assert!(graph.dijkstra("a", "e").is_empty());
+    }
+
+    fn rosetta_graph() -> Graph {
+        let mut graph = Graph::new();
+        for &(u, v, w) in &[("a", "b", 7), ("a", "c", 9), ("a", "f", 14), ("b", "c", 10),
+                            ("b", "d", 15), ("c", "d", 11), ("c", "f", 2), ("d", "e", 6),
+                            ("e", "f", 9)] {
+            graph.add_edge(u, v, w);
+        }
+        graph
+    }
+
+    #[test]
+    fn single_source() {
+        let graph = rosetta_graph();
+        let paths = graph.distances("a").unwrap();
+        let expected = [("a", 0), ("b", 7), ("c", 9), ("d", 20), ("e", 26), ("f", 11)];
+        for &(v, d) in &expected {
+            assert_eq!(paths.distance(graph.vertex_index(v).unwrap()), Some(d));
+        }
+        let e = graph.vertex_index("e").unwrap();
+        let path: Vec<&str> = paths.path_to(e).unwrap().iter()
+                                   .map(|&v| graph.vertex_name(v)).collect();
+        assert_eq!(path, vec!["a", "c", "d", "e"]);
+        assert_eq!(graph.shortest_path("a", "e").unwrap().unwrap().0, 26);
+        assert_eq!(graph.distances("x").unwrap_err(), GraphError::UnknownVertex("x".to_string()));
+    }
+
+    #[test]
+    fn undirected() {
+        let mut graph = Graph::undirected();
+        graph.add_edge("a", "b", 1.5);
+        graph.add_edge("b", "c", 2.0);
+        graph.add_edge("c", "b", 0.5); // replaces b - c
+        assert_eq!(graph.edge_count(), 2);
+        assert_eq!(graph.dijkstra("c", "a"), vec!["c", "b", "a"]);
+        assert_eq!(graph.shortest_path("c", "a").unwrap().unwrap().0, 2.0);
+    }
+
+    #[test]
+    fn negative_weights() {
+        let mut graph: Graph<i64> = Graph::new();
+        graph.add_edge("s", "a", 4);
+        graph.add_edge("s", "b", 5);
+        graph.add_edge("b", "a", -3);
+        graph.add_edge("a", "t", 1);
+        assert_eq!(graph.distances("s").unwrap_err(),
+                   GraphError::NegativeWeight("b".to_string(), "a".to_string()));
+
+        let paths = graph.bellman_ford("s").unwrap();
+        let t = graph.vertex_index("t").unwrap();
+        assert_eq!(paths.distance(t), Some(3));
+        assert_eq!(paths.path_to(t).unwrap().len(), 4);
+
+        let all = graph.floyd_warshall().unwrap();
+        let s = graph.vertex_index("s").unwrap();
+        assert_eq!(all.distance(s, t), Some(3));
+        assert_eq!(all.path(s, t), paths.path_to(t));
+        assert_eq!(all.distance(t, s), None);
+    }
+
+    #[test]
+    fn negative_cycle() {
+        let mut graph: Graph<i32> = Graph::new();
+        graph.add_edge("s", "a", 1);
+        graph.add_edge("a", "b", 4);
+        graph.add_edge("b", "c", -3);
+        graph.add_edge("c", "a", -2);
+        // The cycle may be reported starting at any of its vertices.
+        let check = |result| match result {
+            Err(GraphError::NegativeCycle(cycle)) => {
+                let cycle: Vec<String> = cycle;
+                assert_eq!(cycle.len(), 4);
+                assert_eq!(cycle.first(), cycle.last());
+                let mut vertices = cycle[1..].to_vec();
+                vertices.sort();
+                assert_eq!(vertices, vec!["a", "b", "c"]);
+            }
+            _ => panic!("expected a negative cycle"),
+        };
+        check(graph.bellman_ford("s").map(|_| ()));
+        check(graph.floyd_warshall().map(|_| ()));
+    }
+
+    #[test]
+    fn a_star_on_grid() {
+        // A 10x10 grid with a wall down the middle, open only at the bottom.
+        let mut graph = Graph::undirected();
+        let name = |x: i32, y: i32| format!("{},{}", x, y);
+        for y in 0..10 {
+            for x in 0..10 {
+                if x < 9 && !(x == 4 && y < 9) {
+                    graph.add_edge(&name(x, y), &name(x + 1, y), 1);
+                }
+                if y < 9 {
+                    graph.add_edge(&name(x, y), &name(x, y + 1), 1);
+                }
+            }
+        }
+        let coords = |g: &Graph<i32>, v: Node| -> (i32, i32) {
+            let c: Vec<i32> = g.vertex_name(v).split(',').map(|n| n.parse().unwrap()).collect();
+            (c[0], c[1])
+        };
+        let manhattan = |v: Node| {
+            let (x, y) = coords(&graph, v);
+            (9 - x).abs() + y
+        };
+        let (cost, path) = graph.a_star("0,0", "9,0", manhattan).unwrap().unwrap();
+        assert_eq!(cost, 27);
+        assert_eq!(path.len(), 28);
+        assert_eq!(graph.shortest_path("0,0", "9,0").unwrap().unwrap().0, 27);
+    }
+
+    #[test]
+    fn floyd_warshall_matches_dijkstra() {
+        let graph = rosetta_graph();
+        let all = graph.floyd_warshall().unwrap();
+        for u in 0..graph.vertex_count() {
+            let paths = graph.distances(graph.vertex_name(u)).unwrap();
+            for v in 0..graph.vertex_count() {
+                assert_eq!(all.distance(u, v), paths.distance(v));
+            }
+        }
+    }
+
+    fn names(graph: &Graph<i32>, vertices: &[Node]) -> Vec<String> {
+        vertices.iter().map(|&v| graph.vertex_name(v).to_string()).collect()
+    }
+
+    fn edge_list(text: &str) -> Graph<i32> {
+        let mut graph = Graph::new();
+        graph.add_edge_list(text).unwrap();
+        graph
+    }
+
+    #[test]
+    fn topological_sorts() {
+        let graph = edge_list("# getting dressed\n\
+                               socks shoes\nshirt tie\ntie jacket\npants shoes\n\
+                               pants belt\nshirt belt\nbelt jacket\nwatch\n");
+        assert_eq!(graph.vertex_count(), 8);
+        for order in &[graph.topological_sort().unwrap(), graph.topological_sort_dfs().unwrap()] {
+            assert_eq!(order.len(), 8);
+            let position = |v: &str| order.iter().position(|&u| u == graph.vertex_index(v).unwrap());
+            for (u, v, _) in graph.edges() {
+                assert!(position(graph.vertex_name(u)) < position(graph.vertex_name(v)));
+            }
+        }
+
+        let cyclic = edge_list("a b\nb c\nc d\nd b\nd e\n");
+        for result in vec![cyclic.topological_sort(), cyclic.topological_sort_dfs()] {
+            match result {
+                Err(GraphError::Cycle(cycle)) => {
+                    assert_eq!(cycle.len(), 4);
+                    for pair in cycle.windows(2) {
+                        let (u, v) = (cyclic.vertex_index(&pair[0]).unwrap(),
+                                      cyclic.vertex_index(&pair[1]).unwrap());
+                        assert!(cyclic.weight(u, v).is_some());
+                    }
+                }
+                result => panic!("expected a cycle, got {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn strongly_connected_components() {
+        let graph = edge_list("a b\nb c\nc a\nb d\nd e\ne f\nf d\ng f\ng h\nh g\n");
+        let normalise = |components: Vec<Vec<Node>>| {
+            let mut components: Vec<Vec<String>> = components.iter().map(|c| {
+                let mut c = names(&graph, c);
+                c.sort();
+                c
+            }).collect();
+            components.sort();
+            components
+        };
+        let expected = vec![vec!["a", "b", "c"], vec!["d", "e", "f"], vec!["g", "h"]];
+        assert_eq!(normalise(graph.tarjan_scc()), expected);
+        assert_eq!(normalise(graph.kosaraju_scc()), expected);
+        // Tarjan finds sinks first, Kosaraju sources first.
+        assert_eq!(names(&graph, &graph.tarjan_scc()[0]).len(), 3);
+        assert!(names(&graph, &graph.tarjan_scc()[0]).contains(&"d".to_string()));
+        assert!(names(&graph, &graph.kosaraju_scc()[2]).contains(&"d".to_string()));
+    }
+
+    #[test]
+    fn minimum_spanning_trees() {
+        let mut graph: Graph<i32> = Graph::undirected();
+        graph.add_edge_list("a b 4\na h 8\nb c 8\nb h 11\nc d 7\nc f 4\nc i 2\n\
+                             d e 9\nd f 14\ne f 10\nf g 2\ng h 1\ng i 6\nh i 7\n\
+                             x y 3\n").unwrap();
+        let (total, tree) = graph.kruskal();
+        assert_eq!(total, 37 + 3);
+        assert_eq!(tree.len(), 8 + 1);
+        let (total, tree) = graph.prim();
+        assert_eq!(total, 40);
+        assert_eq!(tree.len(), 9);
+    }
+
+    #[test]
+    fn maximum_flow() {
+        // The example network from CLRS, with a maximum flow of 23.
+        let graph = edge_list("s v1 16\ns v2 13\nv2 v1 4\nv1 v3 12\nv3 v2 9\n\
+                               v2 v4 14\nv4 v3 7\nv3 t 20\nv4 t 4\n");
+        for flow in &[graph.edmonds_karp("s", "t").unwrap(), graph.dinic("s", "t").unwrap()] {
+            assert_eq!(flow.value(), 23);
+            let cut: i32 = flow.min_cut().iter().map(|&(_, _, c)| c).sum();
+            assert_eq!(cut, 23);
+            let into_t: i32 = flow.flows().iter()
+                .filter(|&&(_, v, _)| graph.vertex_name(v) == "t")
+                .map(|&(_, _, f)| f).sum();
+            assert_eq!(into_t, 23);
+        }
+        assert_eq!(graph.dinic("t", "s").unwrap().value(), 0);
+        assert!(graph.dinic("s", "nowhere").is_err());
+
+        // From a vertex to itself, with and without capacity going out.
+        for &v in &["s", "t"] {
+            let (karp, dinic) = (graph.edmonds_karp(v, v).unwrap(), graph.dinic(v, v).unwrap());
+            assert_eq!(dinic.value(), 0);
+            assert_eq!((dinic.value(), dinic.flows(), dinic.min_cut()),
+                       (karp.value(), karp.flows(), karp.min_cut()));
+        }
+        let mut real: Graph<f64> = Graph::new();
+        real.add_edge("a", "b", 1.5);
+        assert_eq!(real.dinic("a", "a").unwrap().value(), 0.);
+    }
+
+    #[test]
+    fn bipartite_matching() {
+        let mut graph: Graph<i32> = Graph::undirected();
+        graph.add_edge_list("alice chess\nalice go\nbob chess\ncarol go\ncarol poker\n\
+                             dave poker\n").unwrap();
+        let matching = graph.bipartite_matching().unwrap();
+        assert_eq!(matching.len(), 3);
+        let mut used: Vec<Node> = matching.iter().flat_map(|&(u, v)| vec![u, v]).collect();
+        used.sort();
+        used.dedup();
+        assert_eq!(used.len(), 6);
+        for &(u, v) in &matching {
+            assert!(graph.weight(u, v).is_some());
+        }
+
+        graph.add_edge("chess", "go", 1);
+        graph.add_edge("go", "bob", 1);
+        assert_eq!(graph.bipartite_matching(), Err(GraphError::NotBipartite));
+    }
+
+    #[test]
+    fn edge_list_files() {
+        let mut graph: Graph<i32> = Graph::new();
+        assert_eq!(graph.add_edge_list("a b 1\na b c d\n"),
+                   Err(GraphError::Parse(2, "expected two vertices and an optional weight"
+                                               .to_string())));
+        assert_eq!(graph.add_edge_list("a b x\n"),
+                   Err(GraphError::Parse(1, "invalid weight 'x'".to_string())));
+
+        let path = env::temp_dir().join("rosetta_dijkstra_edge_list.txt");
+        File::create(&path).unwrap().write_all(b"a b 7\nb c 10\nc d 5\na d 30\n").unwrap();
+        let graph: Graph<i32> = Graph::read_edge_list(&path, false).unwrap();
+        assert!(!graph.is_directed());
+        assert_eq!(graph.dijkstra("d", "a"), vec!["d", "c", "b", "a"]);
+        assert!(Graph::<i32>::read_edge_list(env::temp_dir().join("no/such/file"), true).is_err());
+    }
+
+    #[test]
+    fn dot() {
+        let text = "# 1 \"routes.gv\"
+            /* Routes between towns */
+            strict digraph routes {
+                rankdir = LR;
+                edge [weight=5]
+                a -> b -> c  // two edges of weight 5
+                c -> \"d e\" [label=2, color=blue];
+                \"d e\" -> a [weight = 3.5]
+                subgraph cluster_0 { f; f -> a }
+            }";
+        let graph: Graph<f64> = Graph::from_dot(text).unwrap();
+        assert!(graph.is_directed());
+        assert_eq!(graph.vertex_count(), 5);
+        assert_eq!(graph.edge_count(), 5);
+        let weight = |u: &str, v: &str| {
+            graph.weight(graph.vertex_index(u).unwrap(), graph.vertex_index(v).unwrap())
+        };
+        assert_eq!(weight("a", "b"), Some(5.0));
+        assert_eq!(weight("c", "d e"), Some(2.0));
+        assert_eq!(weight("d e", "a"), Some(3.5));
+        // Subgraphs inherit the default attributes.
+        assert_eq!(weight("f", "a"), Some(5.0));
+
+        let again: Graph<f64> = Graph::from_dot(&graph.to_dot()).unwrap();
+        assert_eq!(again.edges(), graph.edges());
+
+        let undirected: Graph<i32> = Graph::from_dot("graph { x -- y [weight=2]; y -- z }").unwrap();
+        assert!(!undirected.is_directed());
+        assert_eq!(undirected.dijkstra("z", "x"), vec!["z", "y", "x"]);
+
+        assert_eq!(Graph::<i32>::from_dot("graph { a -> b }").unwrap_err(),
+                   GraphError::Parse(1, "'->' in a graph using '--'".to_string()));
+        assert_eq!(Graph::<i32>::from_dot("digraph {\n a -> b [weight=x]\n}").unwrap_err(),
+                   GraphError::Parse(2, "invalid weight 'x'".to_string()));
+
+        // Text labels are just labels.
+        let labelled: Graph<i32> = Graph::from_dot("digraph { edge [label=road]; a -> b; \
+                                                    b -> c [label=\"toll road\", weight=4] }")
+            .unwrap();
+        let index = |v: &str| labelled.vertex_index(v).unwrap();
+        assert_eq!(labelled.weight(index("a"), index("b")), Some(1));
+        assert_eq!(labelled.weight(index("b"), index("c")), Some(4));
+        assert!(Graph::<i32>::from_dot("digraph { a -> b ").is_err());
+        assert!(Graph::<i32>::from_dot("digraph { \"a -> b }").is_err());
+    }
+
+    #[test]
+    fn highlighted_path() {
+        let graph = rosetta_graph();
+        let (_, path) = graph.shortest_path("a", "e").unwrap().unwrap();
+        let dot = graph.path_to_dot(&path);
+        let subgraph = &dot[dot.find("subgraph path").unwrap()..];
+        for edge in &["\"a\" -> \"c\"", "\"c\" -> \"d\"", "\"d\" -> \"e\""] {
+            assert!(subgraph.contains(edge));
+        }
+        assert!(!subgraph.contains("\"a\" -> \"b\""));
+        assert!(subgraph.contains("edge [color=red, penwidth=2];"));
+
+        // The highlighted graph still reads back as the same graph.
+        let again: Graph<usize> = Graph::from_dot(&dot).unwrap();
+        assert_eq!(again.edge_count(), graph.edge_count());
+        assert_eq!(again.dijkstra("a", "e"), vec!["a", "c", "d", "e"]);
+    }
+
+    #[test]
+    fn graphml() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+              <key id="d0" for="node" attr.name="color" attr.type="string"/>
+              <key id="d1" for="edge" attr.name="weight" attr.type="double">
+                <default>1.5</default>
+              </key>
+              <graph id="G" edgedefault="undirected">
+                <!-- vertices -->
+                <node id="n0"><data key="d0">green</data></node>
+                <node id="n1"/>
+                <node id="a &amp; b"/>
+                <edge source="n0" target="n1"><data key="d1">2.0</data></edge>
+                <edge source="n1" target="a &amp; b"/>
+              </graph>
+            </graphml>"#;
+        let graph: Graph<f64> = Graph::from_graphml(text).unwrap();
+        assert!(!graph.is_directed());
+        assert_eq!(graph.vertex_count(), 3);
+        assert_eq!(graph.shortest_path("a & b", "n0").unwrap().unwrap().0, 3.5);
+
+        let again: Graph<f64> = Graph::from_graphml(&graph.to_graphml()).unwrap();
+        assert_eq!(again.edges(), graph.edges());
+        assert!(again.to_graphml().contains("<node id=\"a &amp; b\"/>"));
+
+        // Defaults for other keys are left alone.
+        let text = text.replace(r#"<key id="d0" for="node" attr.name="color" attr.type="string"/>"#,
+                                r#"<key id="c" for="node" attr.name="color"><default>red</default></key>
+              <key id="s" for="edge" attr.name="size"><default>7</default></key>"#);
+        let graph: Graph<f64> = Graph::from_graphml(&text).unwrap();
+        assert_eq!(graph.shortest_path("a & b", "n0").unwrap().unwrap().0, 3.5);
+
+        assert!(Graph::<f64>::from_graphml("<graphml><node id=\"x\"/></graphml>").is_err());
+        assert_eq!(Graph::<f64>::from_graphml("<graphml>\n<graph>\n<edge source=\"a\">")
+                       .unwrap_err(),
+                   GraphError::Parse(3, "<edge> needs a source and a target".to_string()));
+    }
+
+    #[test]
+    fn edge_list_round_trip() {
+        let mut graph: Graph<i32> = Graph::undirected();
+        graph.add_edge_list("a b 3\nb c\nlonely\n").unwrap();
+        let text = graph.to_edge_list();
+        assert_eq!(text, "a b 3\nb c 1\nlonely\n");
+        let mut again: Graph<i32> = Graph::undirected();
+        again.add_edge_list(&text).unwrap();
+        assert_eq!(again.to_edge_list(), text);
+
+        let path = env::temp_dir().join("rosetta_dijkstra_graph.gv");
+        File::create(&path).unwrap().write_all(graph.to_dot().as_bytes()).unwrap();
+        let from_file: Graph<i32> = Graph::read_file(&path, true).unwrap();
+        assert!(!from_file.is_directed());
+        assert_eq!(from_file.to_edge_list(), text);
+    }
 }
@@ -0,0 +1,308 @@
+// An exact cover solver, used by sudoku.rs, n_queens.rs and pentomino.rs.
+//
+// Given a matrix of 0s and 1s, an exact cover is a set of rows with exactly
+// one 1 in every column. Knuth's Algorithm X finds them all: choose the
+// column with the fewest 1s, try each row with a 1 there in turn, take out
+// every column that row covers and every row clashing with it, and recurse
+// on what's left. Dancing Links keeps the 1s of the matrix in circular
+// doubly linked lists, so that taking rows and columns out and putting them
+// back in the same order afterwards is only a matter of relinking (Knuth,
+// "Dancing Links", 2000).
+//
+// Secondary columns need not be covered, but can't be covered twice. That's
+// what the diagonals of N-queens need, for example.
+//
+// The links are indices into vectors rather than pointers. Node 0 is the
+// root, nodes 1 to the number of columns the column headers, and the 1s of
+// the rows come after that.
+
+const ROOT: usize = 0;
+
+pub struct ExactCover {
+    left: Vec<usize>,
+    right: Vec<usize>,
+    up: Vec<usize>,
+    down: Vec<usize>,
+    // the header of each node's column, and the row it belongs to
+    column: Vec<usize>,
+    row: Vec<usize>,
+    // the number of 1s left in each column, indexed by header
+    size: Vec<usize>,
+    columns: usize,
+    rows: usize
+}
+
+impl ExactCover {
+    // Columns 0 to primary - 1 must be covered exactly once, the next
+    // `secondary` ones at most once.
+    pub fn new(primary: usize, secondary: usize) -> ExactCover {
+        let columns = primary + secondary;
+        let mut dlx = ExactCover {
+            left: Vec::with_capacity(columns + 1),
+            right: Vec::with_capacity(columns + 1),
+            up: Vec::with_capacity(columns + 1),
+            down: Vec::with_capacity(columns + 1),
+            column: Vec::with_capacity(columns + 1),
+            row: Vec::with_capacity(columns + 1),
+            size: vec![0; columns + 1],
+            columns: columns,
+            rows: 0
+        };
+
+        // The root and the primary headers form a ring. The secondary
+        // headers are rings of their own, so they're never chosen.
+        for i in 0 .. columns + 1 {
+            if i <= primary {
+                dlx.left.push(if i == 0 { primary } else { i - 1 });
+                dlx.right.push(if i == primary { ROOT } else { i + 1 });
+            } else {
+                dlx.left.push(i);
+                dlx.right.push(i);
+            }
+            dlx.up.push(i);
+            dlx.down.push(i);
+            dlx.column.push(i);
+            dlx.row.push(!0);
+        }
+        dlx
+    }
+
+    // Adds a row with 1s in the given columns, and returns its index. Rows
+    // are numbered from 0 in the order they're added. A row without any
+    // primary column is never part of a solution.
+    pub fn add_row(&mut self, columns: &[usize]) -> usize {
+        let first = self.left.len();
+        for &c in columns.iter() {
+            assert!(c < self.columns, "there is no column {}", c);
+            let header = c + 1;
+            let node = self.left.len();
+
+            self.up.push(self.up[header]);
+            self.down.push(header);
+            let above = self.up[header];
+            self.down[above] = node;
+            self.up[header] = node;
+            self.size[header] += 1;
+
+            if node == first {
+                self.left.push(node);
+                self.right.push(node);
+            } else {
+                let last = self.left[first];
+                self.left.push(last);
+                self.right.push(first);
+                self.right[last] = node;
+                self.left[first] = node;
+            }
+            self.column.push(header);
+            self.row.push(self.rows);
+        }
+        self.rows += 1;
+        self.rows - 1
+    }
+
+    // Takes a column out of the header ring, and every row with a 1 in it
+    // out of the other columns.
+    fn cover(&mut self, c: usize) {
+        let (l, r) = (self.left[c], self.right[c]);
+        self.right[l] = r;
+        self.left[r] = l;
+
+        let mut i = self.down[c];
+        while i != c {
+            let mut j = self.right[i];
+            while j != i {
+                let (u, d) = (self.up[j], self.down[j]);
+                self.down[u] = d;
+                self.up[d] = u;
+                let header = self.column[j];
+                self.size[header] -= 1;
+                j = self.right[j];
+            }
+            i = self.down[i];
+        }
+    }
+
+    // Undoes `cover`, in exactly the reverse order.
+    fn uncover(&mut self, c: usize) {
+        let mut i = self.up[c];
+        while i != c {
+            let mut j = self.left[i];
+            while j != i {
+                let (u, d) = (self.up[j], self.down[j]);
+                self.down[u] = j;
+                self.up[d] = j;
+                let header = self.column[j];
+                self.size[header] += 1;
+                j = self.left[j];
+            }
+            i = self.up[i];
+        }
+
+        let (l, r) = (self.left[c], self.right[c]);
+        self.right[l] = c;
+        self.left[r] = c;
+    }
+
+    // Returns false once `found` asks to stop.
+    fn search(&mut self, chosen: &mut Vec<usize>, found: &mut FnMut(&[usize]) -> bool) -> bool {
+        if self.right[ROOT] == ROOT {
+            return found(chosen);
+        }
+
+        // The column with the fewest 1s left keeps the search tree small.
+        let mut c = self.right[ROOT];
+        let mut j = self.right[c];
+        while j != ROOT {
+            if self.size[j] < self.size[c] { c = j }
+            j = self.right[j];
+        }
+        if self.size[c] == 0 { return true }
+
+        self.cover(c);
+        let mut r = self.down[c];
+        while r != c {
+            chosen.push(self.row[r]);
+            let mut j = self.right[r];
+            while j != r {
+                let column = self.column[j];
+                self.cover(column);
+                j = self.right[j];
+            }
+
+            let more = self.search(chosen, found);
+
+            let mut j = self.left[r];
+            while j != r {
+                let column = self.column[j];
+                self.uncover(column);
+                j = self.left[j];
+            }
+            chosen.pop();
+
+            if !more {
+                self.uncover(c);
+                return false;
+            }
+            r = self.down[r];
+        }
+        self.uncover(c);
+        true
+    }
+
+    // Calls `found` with the rows of each exact cover in turn, for as long
+    // as it returns true. The matrix is left as it was.
+    pub fn each_solution<F: FnMut(&[usize]) -> bool>(&mut self, mut found: F) {
+        self.search(&mut vec![], &mut found);
+    }
+
+    // Up to `limit` exact covers, each with its rows in increasing order.
+    pub fn solutions(&mut self, limit: usize) -> Vec<Vec<usize>> {
+        let mut solutions = vec![];
+        if limit == 0 { return solutions }
+        self.each_solution(|rows| {
+            let mut rows = rows.to_vec();
+            rows.sort();
+            solutions.push(rows);
+            solutions.len() < limit
+        });
+        solutions
+    }
+
+    pub fn count(&mut self) -> usize {
+        let mut count = 0;
+        self.each_solution(|_| { count += 1; true });
+        count
+    }
+}
+
+#[allow(dead_code)]
+#[cfg(not(test))]
+fn main() {
+    // the example from Knuth's paper
+    let names = ["CEF", "ADG", "BCF", "AD", "BG", "DEG"];
+    let mut dlx = ExactCover::new(7, 0);
+    for name in names.iter() {
+        let columns = name.bytes().map(|b| (b - b'A') as usize).collect::<Vec<_>>();
+        dlx.add_row(&columns);
+    }
+    for solution in dlx.solutions(10) {
+        let rows = solution.iter().map(|&r| names[r]).collect::<Vec<_>>();
+        println!("{}", rows.join(" "));
+    }
+}
+
+#[test]
+fn knuth() {
+    let mut dlx = ExactCover::new(7, 0);
+    for row in [vec![2, 4, 5], vec![0, 3, 6], vec![1, 2, 5], vec![0, 3],
+                vec![1, 6], vec![3, 4, 6]].iter() {
+        dlx.add_row(row);
+    }
+    assert_eq!(dlx.solutions(10), [[0, 3, 4]]);
+    assert_eq!(dlx.count(), 1);
+}
+
+#[test]
+fn secondary_columns() {
+    // column 2 is optional, but only one row may use it
+    let mut dlx = ExactCover::new(2, 1);
+    for row in [vec![0, 2], vec![1, 2], vec![0], vec![1]].iter() {
+        dlx.add_row(row);
+    }
+    assert_eq!(dlx.solutions(10), [vec![0, 3], vec![1, 2], vec![2, 3]]);
+    assert_eq!(dlx.solutions(2).len(), 2);
+    assert_eq!(dlx.solutions(0).len(), 0);
+    assert_eq!(dlx.count(), 3);
+
+    // nothing to cover
+    assert_eq!(ExactCover::new(0, 3).solutions(10), [Vec::<usize>::new()]);
+    // a column no row covers
+    let mut dlx = ExactCover::new(2, 0);
+    dlx.add_row(&[0]);
+    assert_eq!(dlx.count(), 0);
+}
+
+#[test]
+fn matches_brute_force() {
+    let mut seed = 2463534242u32;
+    let mut next = |n: u32| {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        (seed % n) as usize
+    };
+
+    for _ in 0 .. 200 {
+        let (primary, secondary) = (next(6) + 1, next(3));
+        let columns = primary + secondary;
+        let rows = (0 .. next(12))
+            .map(|_| (0 .. columns).filter(|_| next(3) == 0).collect::<Vec<_>>())
+            .filter(|row| row.iter().any(|&c| c < primary))
+            .collect::<Vec<_>>();
+
+        let mut expected = vec![];
+        for mask in 0 .. 1usize << rows.len() {
+            let mut covered = vec![0; columns];
+            for (r, row) in rows.iter().enumerate() {
+                if mask & 1 << r == 0 { continue }
+                for &c in row.iter() { covered[c] += 1 }
+            }
+            if covered[.. primary].iter().all(|&n| n == 1) &&
+               covered[primary ..].iter().all(|&n| n <= 1) {
+                expected.push((0 .. rows.len()).filter(|&r| mask & 1 << r != 0)
+                              .collect::<Vec<_>>());
+            }
+        }
+
+        let mut dlx = ExactCover::new(primary, secondary);
+        for row in rows.iter() {
+            dlx.add_row(row);
+        }
+        let mut found = dlx.solutions(1000);
+        found.sort();
+        expected.sort();
+        assert_eq!(found, expected);
+        assert_eq!(dlx.count(), expected.len());
+    }
+}
@@ -0,0 +1,968 @@
+// Computational geometry in the plane, used by closest-pair.rs.
+//
+// Covers http://rosettacode.org/wiki/Convex_hull,
+// http://rosettacode.org/wiki/Shoelace_formula_for_polygonal_area and
+// http://rosettacode.org/wiki/Ray-casting_algorithm, along with segment
+// intersection and Delaunay triangulation.
+//
+// Floating point arithmetic can't tell reliably whether three points are
+// collinear, or four cocircular, and algorithms built on such decisions can
+// loop forever or produce nonsense when they get them wrong. The predicates
+// here first evaluate a determinant in plain floating point, and when the
+// result is too close to zero to trust, evaluate it again exactly using
+// floating point expansions (Shewchuk, "Adaptive Precision Floating-Point
+// Arithmetic and Fast Robust Geometric Predicates", 1997).
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::ops::{Add, Sub, Mul};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Point2 {
+    pub x: f64,
+    pub y: f64
+}
+
+impl Point2 {
+    pub fn new(x: f64, y: f64) -> Point2 {
+        Point2 { x: x, y: y }
+    }
+
+    pub fn dot(self, other: Point2) -> f64 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn cross(self, other: Point2) -> f64 {
+        self.x * other.y - self.y * other.x
+    }
+
+    pub fn norm_sqr(self) -> f64 {
+        self.dot(self)
+    }
+
+    /// Orders points by x, then by y.
+    pub fn lex_cmp(&self, other: &Point2) -> Ordering {
+        (self.x, self.y).partial_cmp(&(other.x, other.y)).expect("coordinates must not be NaN")
+    }
+}
+
+impl fmt::Display for Point2 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+impl Add for Point2 {
+    type Output = Point2;
+
+    fn add(self, other: Point2) -> Point2 {
+        Point2::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Point2 {
+    type Output = Point2;
+
+    fn sub(self, other: Point2) -> Point2 {
+        Point2::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<f64> for Point2 {
+    type Output = Point2;
+
+    fn mul(self, k: f64) -> Point2 {
+        Point2::new(self.x * k, self.y * k)
+    }
+}
+
+// A number represented exactly as the sum of floating point components that
+// don't overlap, ordered by increasing magnitude, with zeros left out.
+#[derive(Clone)]
+struct Expansion(Vec<f64>);
+
+// Returns a + b as a rounded sum and the error of rounding it.
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let x = a + b;
+    let b_virtual = x - a;
+    let a_virtual = x - b_virtual;
+    (x, (a - a_virtual) + (b - b_virtual))
+}
+
+// Splits a into two halves of 26 bits each.
+fn split(a: f64) -> (f64, f64) {
+    let c = 134217729.0 * a; // 2^27 + 1
+    let hi = c - (c - a);
+    (hi, a - hi)
+}
+
+// Returns a * b as a rounded product and the error of rounding it.
+fn two_product(a: f64, b: f64) -> (f64, f64) {
+    let x = a * b;
+    let (a_hi, a_lo) = split(a);
+    let (b_hi, b_lo) = split(b);
+    (x, a_lo * b_lo - (((x - a_hi * b_hi) - a_lo * b_hi) - a_hi * b_lo))
+}
+
+impl Expansion {
+    fn from(a: f64) -> Expansion {
+        Expansion(vec![a]).without_zeros()
+    }
+
+    fn diff(a: f64, b: f64) -> Expansion {
+        let (x, y) = two_sum(a, -b);
+        Expansion(vec![y, x]).without_zeros()
+    }
+
+    fn without_zeros(self) -> Expansion {
+        Expansion(self.0.into_iter().filter(|&c| c != 0.0).collect())
+    }
+
+    // Adds a single component, keeping the sum exact.
+    fn grow(self, b: f64) -> Expansion {
+        let mut q = b;
+        let mut h = Vec::with_capacity(self.0.len() + 1);
+        for e in self.0 {
+            let (sum, err) = two_sum(q, e);
+            if err != 0.0 {
+                h.push(err);
+            }
+            q = sum;
+        }
+        if q != 0.0 {
+            h.push(q);
+        }
+        Expansion(h)
+    }
+
+    fn add(self, other: &Expansion) -> Expansion {
+        other.0.iter().fold(self, |acc, &b| acc.grow(b))
+    }
+
+    fn sub(self, other: &Expansion) -> Expansion {
+        other.0.iter().fold(self, |acc, &b| acc.grow(-b))
+    }
+
+    fn neg(self) -> Expansion {
+        Expansion(self.0.into_iter().map(|c| -c).collect())
+    }
+
+    fn scale(&self, b: f64) -> Expansion {
+        self.0.iter().fold(Expansion(vec![]), |acc, &e| {
+            let (x, y) = two_product(e, b);
+            acc.grow(y).grow(x)
+        })
+    }
+
+    fn mul(&self, other: &Expansion) -> Expansion {
+        other.0.iter().fold(Expansion(vec![]), |acc, &b| acc.add(&self.scale(b)))
+    }
+
+    // The largest component decides the sign, since the others can't make up
+    // for it.
+    fn sign(&self) -> Ordering {
+        match self.0.last() {
+            None => Ordering::Equal,
+            Some(&c) => if c > 0.0 { Ordering::Greater } else { Ordering::Less }
+        }
+    }
+}
+
+const EPSILON: f64 = 1.1102230246251565e-16; // 2^-53
+
+fn float_sign(x: f64) -> Ordering {
+    x.partial_cmp(&0.0).expect("coordinates must not be NaN")
+}
+
+/// Sign of the cross product (b - a) x (d - c).
+fn cross_sign(a: Point2, b: Point2, c: Point2, d: Point2) -> Ordering {
+    let left = (b.x - a.x) * (d.y - c.y);
+    let right = (b.y - a.y) * (d.x - c.x);
+    let det = left - right;
+    let bound = (3.0 + 16.0 * EPSILON) * EPSILON * (left.abs() + right.abs());
+    if det > bound || -det > bound {
+        return float_sign(det);
+    }
+    Expansion::diff(b.x, a.x).mul(&Expansion::diff(d.y, c.y))
+        .sub(&Expansion::diff(b.y, a.y).mul(&Expansion::diff(d.x, c.x)))
+        .sign()
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Orientation {
+    Clockwise,
+    Collinear,
+    CounterClockwise
+}
+
+/// Whether a, b and c turn counterclockwise (left), clockwise (right) or lie
+/// on a line. The answer is always exact.
+pub fn orientation(a: Point2, b: Point2, c: Point2) -> Orientation {
+    match cross_sign(a, b, a, c) {
+        Ordering::Greater => Orientation::CounterClockwise,
+        Ordering::Less => Orientation::Clockwise,
+        Ordering::Equal => Orientation::Collinear
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Location {
+    Inside,
+    Boundary,
+    Outside
+}
+
+/// Where d lies relative to the circle through a, b and c, which must be in
+/// counterclockwise order. The answer is always exact.
+pub fn in_circle(a: Point2, b: Point2, c: Point2, d: Point2) -> Location {
+    let (adx, ady) = (a.x - d.x, a.y - d.y);
+    let (bdx, bdy) = (b.x - d.x, b.y - d.y);
+    let (cdx, cdy) = (c.x - d.x, c.y - d.y);
+    let alift = adx * adx + ady * ady;
+    let blift = bdx * bdx + bdy * bdy;
+    let clift = cdx * cdx + cdy * cdy;
+    let det = alift * (bdx * cdy - cdx * bdy)
+            + blift * (cdx * ady - adx * cdy)
+            + clift * (adx * bdy - bdx * ady);
+    let permanent = ((bdx * cdy).abs() + (cdx * bdy).abs()) * alift
+                  + ((cdx * ady).abs() + (adx * cdy).abs()) * blift
+                  + ((adx * bdy).abs() + (bdx * ady).abs()) * clift;
+    let bound = (10.0 + 96.0 * EPSILON) * EPSILON * permanent;
+    let sign = if det > bound || -det > bound {
+        float_sign(det)
+    } else {
+        let (adx, ady) = (Expansion::diff(a.x, d.x), Expansion::diff(a.y, d.y));
+        let (bdx, bdy) = (Expansion::diff(b.x, d.x), Expansion::diff(b.y, d.y));
+        let (cdx, cdy) = (Expansion::diff(c.x, d.x), Expansion::diff(c.y, d.y));
+        let lift = |dx: &Expansion, dy: &Expansion| dx.mul(dx).add(&dy.mul(dy));
+        let cross = |ux: &Expansion, uy: &Expansion, vx: &Expansion, vy: &Expansion| {
+            ux.mul(vy).sub(&uy.mul(vx))
+        };
+        lift(&adx, &ady).mul(&cross(&bdx, &bdy, &cdx, &cdy))
+            .add(&lift(&bdx, &bdy).mul(&cross(&cdx, &cdy, &adx, &ady)))
+            .add(&lift(&cdx, &cdy).mul(&cross(&adx, &ady, &bdx, &bdy)))
+            .sign()
+    };
+    match sign {
+        Ordering::Greater => Location::Inside,
+        Ordering::Less => Location::Outside,
+        Ordering::Equal => Location::Boundary
+    }
+}
+
+fn sorted_unique(points: &[Point2]) -> Vec<Point2> {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a.lex_cmp(b));
+    sorted.dedup();
+    sorted
+}
+
+/// The convex hull of `points` in counterclockwise order, starting from the
+/// leftmost point, using Andrew's monotone chain algorithm. Points on the
+/// edges of the hull are left out.
+pub fn convex_hull(points: &[Point2]) -> Vec<Point2> {
+    let sorted = sorted_unique(points);
+    if sorted.len() < 3 {
+        return sorted;
+    }
+
+    let mut lower: Vec<Point2> = Vec::new();
+    for &p in &sorted {
+        while lower.len() >= 2 &&
+              orientation(lower[lower.len() - 2], lower[lower.len() - 1], p)
+                  != Orientation::CounterClockwise {
+            lower.pop();
+        }
+        lower.push(p);
+    }
+    let mut upper: Vec<Point2> = Vec::new();
+    for &p in sorted.iter().rev() {
+        while upper.len() >= 2 &&
+              orientation(upper[upper.len() - 2], upper[upper.len() - 1], p)
+                  != Orientation::CounterClockwise {
+            upper.pop();
+        }
+        upper.push(p);
+    }
+
+    // Each chain ends where the other one starts.
+    lower.pop();
+    upper.pop();
+    lower.extend(upper.into_iter());
+    lower
+}
+
+/// The area of a simple polygon, positive if its vertices are in
+/// counterclockwise order and negative otherwise.
+pub fn signed_area(polygon: &[Point2]) -> f64 {
+    let n = polygon.len();
+    (0..n).fold(0.0, |sum, i| sum + polygon[i].cross(polygon[(i + 1) % n])) / 2.0
+}
+
+/// The area of a simple polygon, using the shoelace formula.
+pub fn polygon_area(polygon: &[Point2]) -> f64 {
+    signed_area(polygon).abs()
+}
+
+/// Where `p` lies relative to a polygon, found by its winding number. The
+/// polygon need not be convex, or even simple; points it winds around any
+/// number of times other than zero are inside.
+pub fn point_in_polygon(p: Point2, polygon: &[Point2]) -> Location {
+    let n = polygon.len();
+    let mut winding = 0;
+    for i in 0..n {
+        let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+        if Segment::new(a, b).contains(p) {
+            return Location::Boundary;
+        }
+        if a.y <= p.y {
+            if b.y > p.y && orientation(a, b, p) == Orientation::CounterClockwise {
+                winding += 1;
+            }
+        } else if b.y <= p.y && orientation(a, b, p) == Orientation::Clockwise {
+            winding -= 1;
+        }
+    }
+    if winding == 0 { Location::Outside } else { Location::Inside }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Segment {
+    pub a: Point2,
+    pub b: Point2
+}
+
+impl Segment {
+    pub fn new(a: Point2, b: Point2) -> Segment {
+        Segment { a: a, b: b }
+    }
+
+    fn left(&self) -> Point2 {
+        if self.b.lex_cmp(&self.a) == Ordering::Less { self.b } else { self.a }
+    }
+
+    fn right(&self) -> Point2 {
+        if self.b.lex_cmp(&self.a) == Ordering::Less { self.a } else { self.b }
+    }
+
+    /// Whether `p` lies on the segment, endpoints included.
+    pub fn contains(&self, p: Point2) -> bool {
+        orientation(self.a, self.b, p) == Orientation::Collinear &&
+            self.a.x.min(self.b.x) <= p.x && p.x <= self.a.x.max(self.b.x) &&
+            self.a.y.min(self.b.y) <= p.y && p.y <= self.a.y.max(self.b.y)
+    }
+
+    /// Whether the two segments have at least one point in common.
+    pub fn intersects(&self, other: &Segment) -> bool {
+        let o1 = orientation(self.a, self.b, other.a);
+        let o2 = orientation(self.a, self.b, other.b);
+        let o3 = orientation(other.a, other.b, self.a);
+        let o4 = orientation(other.a, other.b, self.b);
+        if o1 != o2 && o3 != o4 {
+            return true;
+        }
+        self.contains(other.a) || self.contains(other.b) ||
+            other.contains(self.a) || other.contains(self.b)
+    }
+
+    /// The leftmost point the two segments have in common, if any. Crossings
+    /// away from the endpoints are rounded to the nearest representable
+    /// point.
+    pub fn intersection(&self, other: &Segment) -> Option<Point2> {
+        if !self.intersects(other) {
+            return None;
+        }
+        let endpoints = [(self, other.a), (self, other.b), (other, self.a), (other, self.b)];
+        let mut touching: Option<Point2> = None;
+        for &(s, p) in endpoints.iter() {
+            if s.contains(p) && touching.map_or(true, |t| p.lex_cmp(&t) == Ordering::Less) {
+                touching = Some(p);
+            }
+        }
+        if touching.is_some() {
+            return touching;
+        }
+        let (r, s) = (self.b - self.a, other.b - other.a);
+        let t = (other.a - self.a).cross(s) / r.cross(s);
+        Some(self.a + r * t)
+    }
+}
+
+// A point where segments meet, with exact homogeneous coordinates
+// (x / w, y / w) and w > 0, so that the sweep never has to round a crossing.
+// Ordered from left to right.
+#[derive(Clone)]
+struct EventPoint {
+    x: Expansion,
+    y: Expansion,
+    w: Expansion
+}
+
+impl EventPoint {
+    fn new(p: Point2) -> EventPoint {
+        EventPoint { x: Expansion::from(p.x), y: Expansion::from(p.y), w: Expansion::from(1.0) }
+    }
+
+    // Where the lines through two segments cross, if they aren't parallel.
+    fn crossing(s: &Segment, t: &Segment) -> Option<EventPoint> {
+        let (rx, ry) = (Expansion::diff(s.b.x, s.a.x), Expansion::diff(s.b.y, s.a.y));
+        let (sx, sy) = (Expansion::diff(t.b.x, t.a.x), Expansion::diff(t.b.y, t.a.y));
+        let (qx, qy) = (Expansion::diff(t.a.x, s.a.x), Expansion::diff(t.a.y, s.a.y));
+        let den = rx.mul(&sy).sub(&ry.mul(&sx));
+        let num = qx.mul(&sy).sub(&qy.mul(&sx));
+        let point = EventPoint {
+            x: Expansion::from(s.a.x).mul(&den).add(&num.mul(&rx)),
+            y: Expansion::from(s.a.y).mul(&den).add(&num.mul(&ry)),
+            w: den
+        };
+        match point.w.sign() {
+            Ordering::Equal => None,
+            Ordering::Greater => Some(point),
+            Ordering::Less => Some(EventPoint { x: point.x.neg(), y: point.y.neg(), w: point.w.neg() })
+        }
+    }
+
+    // The orientation of a, b and this point.
+    fn orientation(&self, a: Point2, b: Point2) -> Orientation {
+        let (dx, dy) = (Expansion::diff(b.x, a.x), Expansion::diff(b.y, a.y));
+        let px = self.x.clone().sub(&self.w.scale(a.x));
+        let py = self.y.clone().sub(&self.w.scale(a.y));
+        match dx.mul(&py).sub(&dy.mul(&px)).sign() {
+            Ordering::Greater => Orientation::CounterClockwise,
+            Ordering::Less => Orientation::Clockwise,
+            Ordering::Equal => Orientation::Collinear
+        }
+    }
+}
+
+impl PartialEq for EventPoint {
+    fn eq(&self, other: &EventPoint) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for EventPoint {}
+
+impl PartialOrd for EventPoint {
+    fn partial_cmp(&self, other: &EventPoint) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for EventPoint {
+    fn cmp(&self, other: &EventPoint) -> Ordering {
+        let x = self.x.mul(&other.w).sub(&other.x.mul(&self.w)).sign();
+        if x != Ordering::Equal {
+            return x;
+        }
+        self.y.mul(&other.w).sub(&other.y.mul(&self.w)).sign()
+    }
+}
+
+/// All pairs of segments that intersect, found with the Bentley-Ottmann
+/// sweep, which only tests segments that are next to each other on the
+/// sweep line. Pairs are given as indices into `segments`, smallest first.
+///
+/// Segments touching at endpoints, overlapping segments and vertical
+/// segments are all handled, and the points where segments cross are kept
+/// exactly, so the answer is always right.
+pub fn segment_intersections(segments: &[Segment]) -> Vec<(usize, usize)> {
+    // Each event point holds the segments starting there. Segments ending or
+    // crossing at a point are found in the sweep line.
+    let mut events: BTreeMap<EventPoint, Vec<usize>> = BTreeMap::new();
+    for (i, s) in segments.iter().enumerate() {
+        events.entry(EventPoint::new(s.left())).or_insert_with(Vec::new).push(i);
+        events.entry(EventPoint::new(s.right())).or_insert_with(Vec::new);
+    }
+
+    // Segments crossing the sweep line from bottom to top. Each event point
+    // splits them into those below it, those through it and those above.
+    let mut status: Vec<usize> = Vec::new();
+    let mut found = BTreeSet::new();
+    let side = |s: usize, p: &EventPoint| {
+        let s = &segments[s];
+        p.orientation(s.left(), s.right())
+    };
+    // Orders segments leaving the same point from bottom to top.
+    let by_slope = |s: &usize, t: &usize| {
+        let (s, t) = (&segments[*s], &segments[*t]);
+        cross_sign(t.left(), t.right(), s.left(), s.right())
+    };
+
+    while let Some(p) = events.keys().next().cloned() {
+        let starts = events.remove(&p).unwrap();
+
+        let (mut lo, mut hi) = (0, status.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if side(status[mid], &p) == Orientation::CounterClockwise {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        hi = lo;
+        while hi < status.len() && side(status[hi], &p) == Orientation::Collinear {
+            hi += 1;
+        }
+
+        let mut here: Vec<usize> = status[lo..hi].to_vec();
+        here.extend(starts.into_iter());
+        for (i, &s) in here.iter().enumerate() {
+            for &t in &here[i + 1..] {
+                found.insert((::std::cmp::min(s, t), ::std::cmp::max(s, t)));
+            }
+        }
+
+        // Segments that carry on past p go back in, in the order they leave it.
+        let mut leaving: Vec<usize> = here.into_iter()
+            .filter(|&s| EventPoint::new(segments[s].right()) > p)
+            .collect();
+        leaving.sort_by(&by_slope);
+        let rest = status.split_off(hi);
+        status.truncate(lo);
+        status.extend(leaving.iter().cloned());
+        status.extend(rest.into_iter());
+
+        // Neighbours that meet to the right of p make a new event. Parallel
+        // segments can only meet where one of them starts, which is already
+        // an event.
+        let mut check = |s: usize, t: usize| {
+            let (s, t) = (&segments[s], &segments[t]);
+            if s.intersects(t) {
+                if let Some(q) = EventPoint::crossing(s, t) {
+                    if q > p {
+                        events.entry(q).or_insert_with(Vec::new);
+                    }
+                }
+            }
+        };
+        let after = lo + leaving.len();
+        if leaving.is_empty() {
+            if lo > 0 && lo < status.len() {
+                check(status[lo - 1], status[lo]);
+            }
+        } else {
+            if lo > 0 {
+                check(status[lo - 1], status[lo]);
+            }
+            if after < status.len() {
+                check(status[after - 1], status[after]);
+            }
+        }
+    }
+
+    found.into_iter().collect()
+}
+
+/// The Delaunay triangulation of `points`, as triples of indices into
+/// `points` in counterclockwise order. Where four or more points lie on the
+/// same empty circle, any triangulation of them may be chosen; repeated
+/// points are only used once.
+///
+/// The points are first triangulated by sweeping from left to right and
+/// joining each point to the edges of the hull so far that it can see, and
+/// the edges are then flipped until every one of them is locally Delaunay.
+pub fn delaunay(points: &[Point2]) -> Vec<[usize; 3]> {
+    let mut order: Vec<usize> = (0..points.len()).collect();
+    order.sort_by(|&i, &j| points[i].lex_cmp(&points[j]));
+    let mut unique: Vec<usize> = Vec::with_capacity(order.len());
+    for i in order {
+        if unique.last().map_or(true, |&j| points[j] != points[i]) {
+            unique.push(i);
+        }
+    }
+    let order = unique;
+    let ccw = |a: usize, b: usize, c: usize| orientation(points[a], points[b], points[c]);
+
+    // The sweep can't start until there's a point off the first line.
+    let first = match (2..order.len()).find(|&k| {
+        ccw(order[0], order[1], order[k]) != Orientation::Collinear
+    }) {
+        None => return vec![],
+        Some(k) => k
+    };
+    let mut triangles: Vec<[usize; 3]> = Vec::new();
+    let apex = order[first];
+    let mut hull: Vec<usize> = order[..first].to_vec();
+    if ccw(order[0], order[first - 1], apex) == Orientation::CounterClockwise {
+        for w in order[..first].windows(2) {
+            triangles.push([w[0], w[1], apex]);
+        }
+    } else {
+        for w in order[..first].windows(2) {
+            triangles.push([w[1], w[0], apex]);
+        }
+        hull.reverse();
+    }
+    hull.push(apex);
+
+    for &p in &order[first + 1..] {
+        // The hull edges that p sees from outside form a single run.
+        let n = hull.len();
+        let visible = |i: usize| ccw(hull[i % n], hull[(i + 1) % n], p) == Orientation::Clockwise;
+        let start = (0..n).find(|&i| visible(i) && !visible(i + n - 1)).unwrap();
+        let mut end = start;
+        while visible(end) {
+            triangles.push([hull[(end + 1) % n], hull[end % n], p]);
+            end += 1;
+        }
+        let mut new_hull = vec![hull[start], p];
+        new_hull.extend((end..start + n).map(|i| hull[i % n]));
+        hull = new_hull;
+    }
+
+    // Lawson's flip algorithm. Each directed edge belongs to at most one
+    // triangle.
+    let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+    for (t, tri) in triangles.iter().enumerate() {
+        for k in 0..3 {
+            edges.insert((tri[k], tri[(k + 1) % 3]), t);
+        }
+    }
+    let mut pending: Vec<(usize, usize)> = edges.keys().cloned().filter(|&(a, b)| a < b).collect();
+    while let Some((a, b)) = pending.pop() {
+        let (t1, t2) = match (edges.get(&(a, b)), edges.get(&(b, a))) {
+            (Some(&t1), Some(&t2)) => (t1, t2),
+            _ => continue
+        };
+        let opposite = |tri: [usize; 3], a: usize, b: usize| {
+            *tri.iter().find(|&&v| v != a && v != b).unwrap()
+        };
+        let c = opposite(triangles[t1], a, b);
+        let d = opposite(triangles[t2], a, b);
+        if in_circle(points[a], points[b], points[c], points[d]) != Location::Inside {
+            continue;
+        }
+        // Triangles (a, b, c) and (b, a, d) become (a, d, c) and (d, b, c).
+        triangles[t1] = [a, d, c];
+        triangles[t2] = [d, b, c];
+        edges.remove(&(a, b));
+        edges.remove(&(b, a));
+        for &(t, tri) in &[(t1, [a, d, c]), (t2, [d, b, c])] {
+            for k in 0..3 {
+                edges.insert((tri[k], tri[(k + 1) % 3]), t);
+            }
+        }
+        pending.extend([(a, d), (d, b), (b, c), (c, a)].iter().cloned());
+    }
+    triangles
+}
+
+// Needed so closest-pair compiles cleanly, because it uses this code as a
+// library
+#[allow(dead_code)]
+#[cfg(not(test))]
+fn main() {
+    let points = [
+        Point2::new(0.654682, 0.925557),
+        Point2::new(0.409382, 0.619391),
+        Point2::new(0.891663, 0.888594),
+        Point2::new(0.716629, 0.996200),
+        Point2::new(0.477721, 0.946355),
+        Point2::new(0.925092, 0.818220),
+        Point2::new(0.624291, 0.142924),
+        Point2::new(0.211332, 0.221507),
+        Point2::new(0.293786, 0.691701),
+        Point2::new(0.839186, 0.728260)
+    ];
+
+    let hull = convex_hull(&points);
+    print!("Convex hull:");
+    for p in &hull {
+        print!(" {}", p);
+    }
+    println!("");
+    println!("Hull area: {}", polygon_area(&hull));
+    let centre = Point2::new(0.5, 0.5);
+    println!("{} is {:?} the hull", centre, point_in_polygon(centre, &hull));
+
+    let triangles = delaunay(&points);
+    println!("Delaunay triangulation ({} triangles):", triangles.len());
+    for t in &triangles {
+        println!("  {} {} {}", points[t[0]], points[t[1]], points[t[2]]);
+    }
+
+    let segments: Vec<Segment> = points.windows(2).map(|w| Segment::new(w[0], w[1])).collect();
+    println!("Crossings of the path through the points:");
+    for (s, t) in segment_intersections(&segments) {
+        // Consecutive segments share an endpoint.
+        if t > s + 1 {
+            println!("  segments {} and {} at {}", s, t, segments[s].intersection(&segments[t]).unwrap());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Point2, Segment, Orientation, Location, orientation, in_circle, convex_hull,
+                signed_area, polygon_area, point_in_polygon, segment_intersections, delaunay};
+
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn float(&mut self) -> f64 {
+            (self.next() >> 11) as f64 / (1u64 << 53) as f64
+        }
+
+        fn point(&mut self) -> Point2 {
+            Point2::new(self.float(), self.float())
+        }
+
+        // A point on a small grid, where degenerate cases are common.
+        fn grid_point(&mut self, size: u64) -> Point2 {
+            Point2::new((self.next() % size) as f64, (self.next() % size) as f64)
+        }
+    }
+
+    #[test]
+    fn exact_orientation() {
+        // Near the line through q and r, floating point arithmetic gets the
+        // orientation of p wrong for many of these points (Kettner et al.,
+        // "Classroom Examples of Robustness Problems in Geometric
+        // Computations").
+        let (q, r) = (Point2::new(12.0, 12.0), Point2::new(24.0, 24.0));
+        let ulp = 2f64.powi(-53);
+        for i in 0..64 {
+            for j in 0..64 {
+                let p = Point2::new(0.5 + i as f64 * ulp, 0.5 + j as f64 * ulp);
+                let expected = if j > i {
+                    Orientation::CounterClockwise
+                } else if j < i {
+                    Orientation::Clockwise
+                } else {
+                    Orientation::Collinear
+                };
+                assert_eq!(orientation(p, q, r), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn exact_in_circle() {
+        // The unit circle through three points, scaled by a power of two so
+        // it stays exact, and points just inside and outside of it.
+        let scale = 2f64.powi(-40);
+        let circle = [Point2::new(scale, 0.0), Point2::new(0.0, scale), Point2::new(-scale, 0.0)];
+        let on = Point2::new(0.0, -scale);
+        assert_eq!(in_circle(circle[0], circle[1], circle[2], on), Location::Boundary);
+        let tiny = scale * 2f64.powi(-52);
+        assert_eq!(in_circle(circle[0], circle[1], circle[2], Point2::new(0.0, -scale + tiny)),
+                   Location::Inside);
+        assert_eq!(in_circle(circle[0], circle[1], circle[2], Point2::new(0.0, -scale - tiny)),
+                   Location::Outside);
+
+        // Integer points are small enough to check exactly.
+        let mut rng = XorShift(5);
+        for _ in 0..2000 {
+            let (a, b, c, d) = (rng.grid_point(8), rng.grid_point(8), rng.grid_point(8),
+                                rng.grid_point(8));
+            if orientation(a, b, c) != Orientation::CounterClockwise {
+                continue;
+            }
+            let lift = |p: Point2| (p.x - d.x) * (p.x - d.x) + (p.y - d.y) * (p.y - d.y);
+            let det = lift(a) * ((b.x - d.x) * (c.y - d.y) - (c.x - d.x) * (b.y - d.y))
+                    + lift(b) * ((c.x - d.x) * (a.y - d.y) - (a.x - d.x) * (c.y - d.y))
+                    + lift(c) * ((a.x - d.x) * (b.y - d.y) - (b.x - d.x) * (a.y - d.y));
+            let expected = if det > 0.0 {
+                Location::Inside
+            } else if det < 0.0 {
+                Location::Outside
+            } else {
+                Location::Boundary
+            };
+            assert_eq!(in_circle(a, b, c, d), expected);
+        }
+    }
+
+    // The vertices of the hull: points that aren't inside a triangle of other
+    // points or between two of them.
+    fn brute_force_hull(points: &[Point2]) -> Vec<Point2> {
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a.lex_cmp(b));
+        points.dedup();
+        let n = points.len();
+        let inside = |p: Point2, a: Point2, b: Point2, c: Point2| {
+            match orientation(a, b, c) {
+                Orientation::Collinear => false,
+                Orientation::CounterClockwise =>
+                    orientation(a, b, p) != Orientation::Clockwise &&
+                    orientation(b, c, p) != Orientation::Clockwise &&
+                    orientation(c, a, p) != Orientation::Clockwise,
+                Orientation::Clockwise =>
+                    orientation(a, b, p) != Orientation::CounterClockwise &&
+                    orientation(b, c, p) != Orientation::CounterClockwise &&
+                    orientation(c, a, p) != Orientation::CounterClockwise
+            }
+        };
+        points.iter().cloned().filter(|&p| {
+            for i in 0..n {
+                for j in i + 1..n {
+                    let (a, b) = (points[i], points[j]);
+                    if a == p || b == p {
+                        continue;
+                    }
+                    if Segment::new(a, b).contains(p) {
+                        return false;
+                    }
+                    for k in j + 1..n {
+                        let c = points[k];
+                        if c != p && inside(p, a, b, c) {
+                            return false;
+                        }
+                    }
+                }
+            }
+            true
+        }).collect()
+    }
+
+    #[test]
+    fn convex_hull_matches_brute_force() {
+        let mut rng = XorShift(7);
+        for round in 0..40 {
+            let points: Vec<Point2> = (0..25).map(|_| {
+                if round % 2 == 0 { rng.point() } else { rng.grid_point(6) }
+            }).collect();
+            let hull = convex_hull(&points);
+            let mut sorted = hull.clone();
+            sorted.sort_by(|a, b| a.lex_cmp(b));
+            assert_eq!(sorted, brute_force_hull(&points));
+            for i in 0..hull.len() {
+                let (a, b, c) = (hull[i], hull[(i + 1) % hull.len()], hull[(i + 2) % hull.len()]);
+                assert_eq!(orientation(a, b, c), Orientation::CounterClockwise);
+            }
+        }
+        assert_eq!(convex_hull(&[]), vec![]);
+        let line = [Point2::new(2.0, 2.0), Point2::new(0.0, 0.0), Point2::new(1.0, 1.0)];
+        assert_eq!(convex_hull(&line), vec![Point2::new(0.0, 0.0), Point2::new(2.0, 2.0)]);
+    }
+
+    #[test]
+    fn segment_intersections_match_brute_force() {
+        let mut rng = XorShift(11);
+        for round in 0..60 {
+            let segments: Vec<Segment> = (0..30).map(|_| {
+                if round % 2 == 0 {
+                    Segment::new(rng.point(), rng.point())
+                } else {
+                    Segment::new(rng.grid_point(7), rng.grid_point(7))
+                }
+            }).collect();
+            let mut expected = vec![];
+            for i in 0..segments.len() {
+                for j in i + 1..segments.len() {
+                    if segments[i].intersects(&segments[j]) {
+                        expected.push((i, j));
+                    }
+                }
+            }
+            assert_eq!(segment_intersections(&segments), expected);
+        }
+
+        let p = |x: f64, y: f64| Point2::new(x, y);
+        let segments = [
+            Segment::new(p(0.0, 0.0), p(2.0, 2.0)),
+            Segment::new(p(2.0, 2.0), p(4.0, 0.0)),   // shares an endpoint with 0
+            Segment::new(p(1.0, 0.0), p(1.0, 5.0)),   // vertical, crosses 0
+            Segment::new(p(1.0, 1.0), p(3.0, 3.0)),   // overlaps 0
+            Segment::new(p(5.0, 5.0), p(6.0, 6.0)),   // on the same line, apart
+        ];
+        assert_eq!(segment_intersections(&segments), vec![(0, 1), (0, 2), (0, 3), (1, 3), (2, 3)]);
+        assert_eq!(segments[0].intersection(&segments[2]), Some(p(1.0, 1.0)));
+        assert_eq!(segments[0].intersection(&segments[3]), Some(p(1.0, 1.0)));
+        assert_eq!(segments[1].intersection(&segments[3]), Some(p(2.0, 2.0)));
+        assert_eq!(segments[0].intersection(&segments[4]), None);
+        let crossing = Segment::new(p(0.0, 1.0), p(3.0, 0.0)).intersection(&segments[0]).unwrap();
+        assert!((crossing - p(0.75, 0.75)).norm_sqr() < 1e-24);
+    }
+
+    // A random polygon that is star-shaped around its first vertex's centre.
+    fn star_polygon(rng: &mut XorShift, n: usize) -> (Point2, Vec<Point2>) {
+        let centre = Point2::new(0.5, 0.5);
+        let mut angles: Vec<f64> = (0..n).map(|_| rng.float() * 2.0 * ::std::f64::consts::PI).collect();
+        angles.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let polygon = angles.iter().map(|&a| {
+            let r = 0.1 + 0.4 * rng.float();
+            Point2::new(0.5 + r * a.cos(), 0.5 + r * a.sin())
+        }).collect();
+        (centre, polygon)
+    }
+
+    #[test]
+    fn polygons() {
+        let square = [Point2::new(0.0, 0.0), Point2::new(2.0, 0.0),
+                      Point2::new(2.0, 2.0), Point2::new(0.0, 2.0)];
+        assert_eq!(signed_area(&square), 4.0);
+        let reversed: Vec<Point2> = square.iter().rev().cloned().collect();
+        assert_eq!(signed_area(&reversed), -4.0);
+        assert_eq!(point_in_polygon(Point2::new(1.0, 1.0), &square), Location::Inside);
+        assert_eq!(point_in_polygon(Point2::new(2.0, 1.0), &square), Location::Boundary);
+        assert_eq!(point_in_polygon(Point2::new(3.0, 1.0), &square), Location::Outside);
+
+        // The triangles fanning out from the centre of a star-shaped polygon
+        // cover it exactly once.
+        let mut rng = XorShift(13);
+        for _ in 0..30 {
+            let (centre, polygon) = star_polygon(&mut rng, 12);
+            let n = polygon.len();
+            let fan_area = (0..n).fold(0.0, |sum, i| {
+                sum + signed_area(&[centre, polygon[i], polygon[(i + 1) % n]])
+            });
+            assert!((polygon_area(&polygon) - fan_area).abs() < 1e-12);
+            for &v in &polygon {
+                assert_eq!(point_in_polygon(v, &polygon), Location::Boundary);
+            }
+            for _ in 0..100 {
+                let p = rng.point();
+                let in_fan = (0..n).any(|i| {
+                    let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+                    orientation(centre, a, p) != Orientation::Clockwise &&
+                    orientation(a, b, p) == Orientation::CounterClockwise &&
+                    orientation(b, centre, p) != Orientation::Clockwise
+                });
+                let location = point_in_polygon(p, &polygon);
+                assert!(location != Location::Boundary);
+                assert_eq!(location == Location::Inside, in_fan);
+            }
+        }
+    }
+
+    #[test]
+    fn delaunay_circles_are_empty() {
+        let mut rng = XorShift(17);
+        for round in 0..20 {
+            let points: Vec<Point2> = (0..40).map(|_| {
+                if round % 2 == 0 { rng.point() } else { rng.grid_point(8) }
+            }).collect();
+            let triangles = delaunay(&points);
+            for t in &triangles {
+                let (a, b, c) = (points[t[0]], points[t[1]], points[t[2]]);
+                assert_eq!(orientation(a, b, c), Orientation::CounterClockwise);
+                for &p in &points {
+                    assert!(in_circle(a, b, c, p) != Location::Inside);
+                }
+            }
+            // The triangles tile the hull.
+            let area = triangles.iter().fold(0.0, |sum, t| {
+                sum + signed_area(&[points[t[0]], points[t[1]], points[t[2]]])
+            });
+            assert!((area - polygon_area(&convex_hull(&points))).abs() < 1e-9);
+            if round % 2 == 0 {
+                // In general position, Euler's formula fixes the count.
+                let h = convex_hull(&points).len();
+                assert_eq!(triangles.len(), 2 * points.len() - 2 - h);
+            }
+        }
+        assert!(delaunay(&[Point2::new(0.0, 0.0), Point2::new(1.0, 1.0),
+                           Point2::new(2.0, 2.0)]).is_empty());
+    }
+}
@@ -1,235 +1,764 @@
 // Implements http://rosettacode.org/wiki/K-d_tree
+//
+// The tree is generic over the point type, carries a payload with every
+// point, and answers nearest neighbour, k-nearest neighbour, radius and box
+// queries. Points can be inserted and removed after construction; the tree is
+// rebuilt from scratch once enough changes have piled up to unbalance it.
+//
+// Points have a fixed number of dimensions known at compile time, so they are
+// plain arrays such as `[f32; 3]` rather than vectors. The tree itself is
+// stored as a flat implicit array: the node for a range of the array is the
+// element in the middle of the range, and its left and right subtrees are the
+// elements before and after it. There are no pointers to follow and no
+// allocation per node, and the two halves of a range can be built on
+// separate threads.
+//
+// Besides exact searches, the tree can trade accuracy for speed, which
+// matters in higher dimensions where an exact search ends up visiting most of
+// the tree. An epsilon-approximate search returns a point at most 1 + epsilon
+// times as far away as the nearest one, and a best-bin-first search explores
+// the most promising branches first and stops after a fixed number of nodes.
 
 extern crate time;
 extern crate rand;
 
 use rand::Rng;
 use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt::Debug;
+use std::thread;
 #[cfg(not(test))]
-use time::get_time;
-use std::ops::Sub;
+use time::{get_time, Timespec};
+use std::ops::{Add, Sub, Mul};
 use std::cmp::Ordering::Less;
 
-#[derive(Clone, PartialEq, Debug)]
-struct Point {
-    pub coords: Vec<f32>
+/// The numeric types that can be used as coordinates. Squared distances are
+/// computed in a wider `Distance` type, so that small integer coordinates
+/// don't overflow: `i8` uses `i32`, and the other integers use `i64`. That is
+/// plenty for `i16`, but `i32`, `i64` and `isize` coordinates have to stay
+/// within +/-2^28 for the squared distances in 16 dimensions to fit.
+pub trait Coordinate: Copy + PartialOrd + Debug {
+    type Distance: Distance;
+
+    fn widen(self) -> Self::Distance;
+}
+
+/// The types squared distances are computed in.
+pub trait Distance: Copy + PartialOrd + Debug +
+                    Add<Output=Self> + Sub<Output=Self> + Mul<Output=Self> {
+    fn zero() -> Self;
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! coordinate_impls {
+    ($($t:ty => $d:ty)*) => ($(
+        impl Coordinate for $t {
+            type Distance = $d;
+
+            fn widen(self) -> $d { self as $d }
+        }
+    )*)
+}
+
+coordinate_impls! { f32 => f32 f64 => f64 i8 => i32 i16 => i64 i32 => i64 i64 => i64 isize => i64 }
+
+macro_rules! distance_impls {
+    ($($t:ty)*) => ($(
+        impl Distance for $t {
+            fn zero() -> $t { 0 as $t }
+            fn to_f64(self) -> f64 { self as f64 }
+        }
+    )*)
+}
+
+distance_impls! { f32 f64 i32 i64 }
+
+/// The squared distance between two points.
+pub type DistSq<T> = <<T as Point>::Coord as Coordinate>::Distance;
+
+/// A point with a fixed number of dimensions.
+pub trait Point: Copy + PartialEq + Debug {
+    type Coord: Coordinate;
+
+    fn dims() -> usize;
+    fn coord(&self, dim: usize) -> Self::Coord;
+
+    fn dist_sq(&self, other: &Self) -> DistSq<Self> {
+        (0..Self::dims()).fold(Distance::zero(), |a, dim| {
+            let d = self.coord(dim).widen() - other.coord(dim).widen();
+            a + d*d
+        })
+    }
+}
+
+macro_rules! array_points {
+    ($($n:expr)*) => ($(
+        impl<T: Coordinate> Point for [T; $n] {
+            type Coord = T;
+
+            fn dims() -> usize { $n }
+            fn coord(&self, dim: usize) -> T { self[dim] }
+        }
+    )*)
+}
+
+array_points! { 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 }
+
+fn partial_cmp<T: PartialOrd>(a: &T, b: &T) -> Ordering {
+    a.partial_cmp(b).expect("coordinates must be comparable")
+}
+
+/// A point found by a query, with its payload and squared distance.
+#[derive(Debug, PartialEq)]
+pub struct Neighbor<'a, T: Point + 'a, P: 'a> {
+    pub point: &'a T,
+    pub payload: &'a P,
+    pub dist_sq: DistSq<T>,
+}
+
+// Ordered by distance, so a BinaryHeap of these is a max-heap keeping the
+// farthest of the best candidates on top.
+struct Candidate<'a, T: Point + 'a, P: 'a>(Neighbor<'a, T, P>);
+
+impl<'a, T: Point, P> PartialEq for Candidate<'a, T, P> {
+    fn eq(&self, other: &Candidate<'a, T, P>) -> bool {
+        self.0.dist_sq == other.0.dist_sq
+    }
 }
 
-impl<'a, 'b> Sub<&'b Point> for &'a Point {
-    type Output = Point;
+impl<'a, T: Point, P> Eq for Candidate<'a, T, P> {}
 
-    fn sub(self, rhs: &Point) -> Point {
-        assert_eq!(self.coords.len(), rhs.coords.len());
-        Point {coords: self.coords.iter().zip(rhs.coords.iter()).map(|(x, &y)| *x - y).collect()}
+impl<'a, T: Point, P> PartialOrd for Candidate<'a, T, P> {
+    fn partial_cmp(&self, other: &Candidate<'a, T, P>) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-impl Point {
-    fn norm_sq(&self) -> f32 {
-        self.coords.iter().fold(0f32, |a, &b| a + b*b)
+impl<'a, T: Point, P> Ord for Candidate<'a, T, P> {
+    fn cmp(&self, other: &Candidate<'a, T, P>) -> Ordering {
+        partial_cmp(&self.0.dist_sq, &other.0.dist_sq)
     }
 }
 
-struct KDTreeNode {
-    point: Point,
+// A branch of the tree waiting to be explored by a best-bin-first search.
+// Ordered so that a BinaryHeap of these pops the nearest branch first.
+struct Branch<C> {
+    dist_sq: C,
+    lo: usize,
+    hi: usize,
     dim: usize,
-    // Construction could become faster if we use an arena allocator,
-    // but this is easier to use.
-    left: Option<Box<KDTreeNode>>,
-    right: Option<Box<KDTreeNode>>
 }
 
-impl KDTreeNode {
-    // Create a new KDTreeNode around the `dim`th dimension.
-    // Alternatively, we could dynamically determine the dimension to
-    // split on by using the longest dimension.
-    pub fn new(points: &mut [Point], dim: usize) -> KDTreeNode {
-        let points_len = points.len();
-        if points_len == 1 {
-            return KDTreeNode {
-                point: points[0].clone(),
-                dim: dim,
-                left: None,
-                right: None
-            };
+impl<C: Distance> PartialEq for Branch<C> {
+    fn eq(&self, other: &Branch<C>) -> bool {
+        self.dist_sq == other.dist_sq
+    }
+}
+
+impl<C: Distance> Eq for Branch<C> {}
+
+impl<C: Distance> PartialOrd for Branch<C> {
+    fn partial_cmp(&self, other: &Branch<C>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<C: Distance> Ord for Branch<C> {
+    fn cmp(&self, other: &Branch<C>) -> Ordering {
+        partial_cmp(&other.dist_sq, &self.dist_sq)
+    }
+}
+
+/// Whether a branch whose points are at least `bound` away can hold a point
+/// nearer than `best`, once distances are shrunk by `scale`.
+fn may_be_nearer<C: Distance>(bound: C, best: C, scale: f64) -> bool {
+    if scale == 1.0 {
+        bound <= best
+    } else {
+        bound.to_f64() * scale <= best.to_f64()
+    }
+}
+
+/// The fraction of the exact nearest neighbours that an approximate search
+/// found. Points as near as the farthest exact neighbour count as found, so
+/// ties don't matter.
+pub fn recall<T: Point, P>(exact: &[Neighbor<T, P>], approx: &[Neighbor<T, P>]) -> f64 {
+    match exact.last() {
+        None => 1.0,
+        Some(farthest) => {
+            let found = approx.iter().filter(|n| n.dist_sq <= farthest.dist_sq).count();
+            ::std::cmp::min(found, exact.len()) as f64 / exact.len() as f64
         }
+    }
+}
 
-        // Split around the median
-        let pivot = quickselect_by(points, points_len/2,
-            &|a, b| a.coords[dim].partial_cmp(&b.coords[dim]).unwrap());
+// Ranges shorter than this are never split between threads.
+const MIN_PARALLEL_LEN: usize = 1 << 12;
 
-        let left = Some(Box::new(KDTreeNode::new(&mut points[0..points_len/2],
-                (dim + 1) % pivot.coords.len())));
-        let right = if points.len() >= 3 {
-            Some(Box::new(KDTreeNode::new(& mut points[points_len/2+1..points_len],
-                (dim + 1) % pivot.coords.len())))
-        } else {
-            None
-        };
+/// Arranges `items` into the implicit tree layout, splitting on `dim` first.
+// Alternatively, we could dynamically determine the dimension to
+// split on by using the longest dimension.
+fn build<T: Point, P>(items: &mut [(T, P)], dim: usize) {
+    if items.len() <= 1 {
+        return;
+    }
+    // Split around the median
+    let median = items.len() / 2;
+    quickselect_by(items, median, &|a, b| partial_cmp(&a.0.coord(dim), &b.0.coord(dim)));
+    let next_dim = (dim + 1) % T::dims();
+    build(&mut items[..median], next_dim);
+    build(&mut items[median+1..], next_dim);
+}
+
+/// Like `build`, but hands the right half of each range to a new thread
+/// until `threads` threads are busy.
+fn build_parallel<T, P>(mut items: Vec<(T, P)>, dim: usize, threads: usize) -> Vec<(T, P)>
+    where T: Point + Send + 'static, P: Send + 'static
+{
+    if threads <= 1 || items.len() < MIN_PARALLEL_LEN {
+        build(&mut items[..], dim);
+        return items;
+    }
+    let median = items.len() / 2;
+    quickselect_by(&mut items[..], median, &|a, b| partial_cmp(&a.0.coord(dim), &b.0.coord(dim)));
+    let next_dim = (dim + 1) % T::dims();
+
+    // The halves have to be moved to their threads, since a spawned thread
+    // can't borrow from this one.
+    let right = items.split_off(median + 1);
+    let right_threads = threads / 2;
+    let right = thread::spawn(move || build_parallel(right, next_dim, right_threads));
+    let pivot = items.pop().unwrap();
+    let mut items = build_parallel(items, next_dim, threads - right_threads);
+    items.push(pivot);
+    items.extend(right.join().unwrap().into_iter());
+    items
+}
+
+/// A k-d tree holding points with a payload of type `P` each.
+pub struct KDTree<T, P = ()> {
+    // The balanced part of the tree, in implicit layout.
+    points: Vec<T>,
+    // None once a point has been removed. The point stays in place as a
+    // splitting plane until the next rebuild.
+    payloads: Vec<Option<P>>,
+    removed: usize,
+    // Points inserted since the last rebuild, searched linearly.
+    pending: Vec<(T, P)>,
+}
+
+impl<T: Point, P> KDTree<T, P> {
+    /// Creates an empty tree.
+    pub fn new() -> KDTree<T, P> {
+        KDTree { points: Vec::new(), payloads: Vec::new(), removed: 0, pending: Vec::new() }
+    }
+
+    /// Builds a balanced tree from points and their payloads.
+    pub fn with_payloads(mut items: Vec<(T, P)>) -> KDTree<T, P> {
+        build(&mut items[..], 0);
+        KDTree::from_layout(items)
+    }
+
+    /// Builds a balanced tree from points and their payloads, using up to
+    /// `threads` threads.
+    pub fn with_payloads_parallel(items: Vec<(T, P)>, threads: usize) -> KDTree<T, P>
+        where T: Send + 'static, P: Send + 'static
+    {
+        KDTree::from_layout(build_parallel(items, 0, threads))
+    }
 
-        KDTreeNode {
-            point: pivot.clone(),
-            dim: dim,
-            left: left,
-            right: right
+    fn from_layout(items: Vec<(T, P)>) -> KDTree<T, P> {
+        let mut points = Vec::with_capacity(items.len());
+        let mut payloads = Vec::with_capacity(items.len());
+        for (point, payload) in items {
+            points.push(point);
+            payloads.push(Some(payload));
         }
+        KDTree { points: points, payloads: payloads, removed: 0, pending: Vec::new() }
     }
 
-    pub fn find_nearest_neighbor<'a>(&'a self, point: &Point) -> (&'a Point, usize) {
-        self.find_nearest_neighbor_helper(point, &self.point, (point - &self.point).norm_sq(), 1)
+    pub fn len(&self) -> usize {
+        self.points.len() - self.removed + self.pending.len()
     }
 
-    fn find_nearest_neighbor_helper<'a>(&'a self, point: &Point, best: &'a Point,
-                                        best_dist_sq: f32, n_visited: usize) -> (&'a Point, usize) {
-        let mut my_best = best;
-        let mut my_best_dist_sq = best_dist_sq;
-        let mut my_n_visited = n_visited;
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-        // We should always examine the near side
-        if self.point.coords[self.dim] < point.coords[self.dim] {
-            if self.right.is_some() {
-                let (a, b) = self.right.as_ref().unwrap().find_nearest_neighbor_helper(
-                    point,
-                    my_best,
-                    my_best_dist_sq,
-                    my_n_visited);
-                my_best = a;
-                my_n_visited = b;
-            }
+    /// Adds a point. Several points may have the same coordinates.
+    pub fn insert(&mut self, point: T, payload: P) {
+        self.pending.push((point, payload));
+        self.changed();
+    }
+
+    /// Removes a point with exactly the given coordinates, returning its
+    /// payload, or None if there is no such point.
+    pub fn remove(&mut self, point: &T) -> Option<P> {
+        if let Some(i) = self.pending.iter().position(|&(ref p, _)| p == point) {
+            return Some(self.pending.swap_remove(i).1);
+        }
+        let len = self.points.len();
+        let removed = self.remove_helper(point, 0, len, 0);
+        if removed.is_some() {
+            self.removed += 1;
+            self.changed();
+        }
+        removed
+    }
+
+    fn remove_helper(&mut self, point: &T, lo: usize, hi: usize, dim: usize) -> Option<P> {
+        if lo == hi {
+            return None;
+        }
+        let mid = lo + (hi - lo) / 2;
+        if self.payloads[mid].is_some() && self.points[mid] == *point {
+            return self.payloads[mid].take();
+        }
+        let split = self.points[mid].coord(dim);
+        let c = point.coord(dim);
+        let next_dim = (dim + 1) % T::dims();
+        // Points equal to the split value can be on either side.
+        let mut removed = None;
+        if c <= split {
+            removed = self.remove_helper(point, lo, mid, next_dim);
+        }
+        if removed.is_none() && split <= c {
+            removed = self.remove_helper(point, mid + 1, hi, next_dim);
+        }
+        removed
+    }
+
+    /// Rebuilds the tree once the unbalanced part has grown to an eighth of
+    /// it, or half of it is made of removed points. That keeps both the
+    /// linear scan of new points and the cost of rebuilding small on average.
+    fn changed(&mut self) {
+        let live = self.len();
+        if (self.pending.len() > 16 && self.pending.len() * 8 > live) ||
+           (self.removed > 16 && self.removed > live) {
+            self.rebalance();
+        }
+    }
+
+    /// Rebuilds the tree from its remaining points, balancing it and
+    /// dropping removed points.
+    pub fn rebalance(&mut self) {
+        let old = ::std::mem::replace(self, KDTree::new());
+        let mut items: Vec<(T, P)> = old.points.into_iter().zip(old.payloads.into_iter())
+            .filter_map(|(point, payload)| payload.map(|payload| (point, payload)))
+            .collect();
+        items.extend(old.pending.into_iter());
+        *self = KDTree::with_payloads(items);
+    }
+
+    /// Returns the child range on the same side of the splitting plane at
+    /// `mid` as `point` first, then the other one.
+    fn near_far(&self, point: &T, lo: usize, mid: usize, hi: usize, dim: usize)
+                -> ((usize, usize), (usize, usize)) {
+        if self.points[mid].coord(dim) < point.coord(dim) {
+            ((mid + 1, hi), (lo, mid))
         } else {
-            if self.left.is_some() {
-                let (a, b) = self.left.as_ref().unwrap().find_nearest_neighbor_helper(
-                    point,
-                    my_best,
-                    my_best_dist_sq,
-                    my_n_visited);
-                my_best = a;
-                my_n_visited = b;
+            ((lo, mid), (mid + 1, hi))
+        }
+    }
+
+    /// Squared distance from `point` to the splitting plane at `mid`.
+    fn axis_dist_sq(&self, point: &T, mid: usize, dim: usize) -> DistSq<T> {
+        let d = self.points[mid].coord(dim).widen() - point.coord(dim).widen();
+        d * d
+    }
+
+    fn neighbor(&self, i: usize, point: &T) -> Option<Neighbor<T, P>> {
+        self.payloads[i].as_ref().map(|payload| Neighbor {
+            point: &self.points[i],
+            payload: payload,
+            dist_sq: point.dist_sq(&self.points[i]),
+        })
+    }
+
+    /// Returns the nearest point and the number of nodes visited to find it.
+    pub fn find_nearest_neighbor(&self, point: &T) -> Option<(Neighbor<T, P>, usize)> {
+        self.find_approximate_neighbor(point, 0.0)
+    }
+
+    /// Returns a point at most `1 + epsilon` times as far away as the
+    /// nearest one, and the number of nodes visited to find it. Branches are
+    /// skipped unless they could hold a point that much nearer.
+    pub fn find_approximate_neighbor(&self, point: &T, epsilon: f64)
+                                     -> Option<(Neighbor<T, P>, usize)> {
+        assert!(epsilon >= 0.0);
+        let scale = (1.0 + epsilon) * (1.0 + epsilon);
+        let (mut best, mut n_visited) =
+            self.find_nearest_neighbor_helper(point, 0, self.points.len(), 0, scale, None, 0);
+        for &(ref p, ref payload) in &self.pending {
+            let dist_sq = point.dist_sq(p);
+            if best.as_ref().map_or(true, |b| dist_sq < b.dist_sq) {
+                best = Some(Neighbor { point: p, payload: payload, dist_sq: dist_sq });
             }
+            n_visited += 1;
         }
+        best.map(|b| (b, n_visited))
+    }
+
+    fn find_nearest_neighbor_helper<'a>(&'a self, point: &T, lo: usize, hi: usize, dim: usize,
+                                        scale: f64, best: Option<Neighbor<'a, T, P>>,
+                                        n_visited: usize) -> (Option<Neighbor<'a, T, P>>, usize) {
+        if lo == hi {
+            return (best, n_visited);
+        }
+        let mid = lo + (hi - lo) / 2;
+        let next_dim = (dim + 1) % T::dims();
+        let mut my_best = best;
+        let mut my_n_visited = n_visited;
+
+        // We should always examine the near side
+        let (near, far) = self.near_far(point, lo, mid, hi, dim);
+        let (a, b) = self.find_nearest_neighbor_helper(point, near.0, near.1, next_dim, scale,
+                                                       my_best, my_n_visited);
+        my_best = a;
+        my_n_visited = b;
 
         // distance along this node's axis
-        let axis_dist_sq = (self.point.coords[self.dim] - point.coords[self.dim]).powi(2);
-        if axis_dist_sq <= my_best_dist_sq {
+        let axis_dist_sq = self.axis_dist_sq(point, mid, dim);
+        if my_best.as_ref().map_or(true, |b| may_be_nearer(axis_dist_sq, b.dist_sq, scale)) {
             // self can only be nearer than best if axis_dist_sq is less than
             // best_dist_sq because axis_dist_sq is a lower bound for
             // self_dist_sq
-            let self_dist_sq = (point - &self.point).norm_sq();
-            if self_dist_sq < my_best_dist_sq {
-                my_best = &self.point;
-                my_best_dist_sq = self_dist_sq;
+            if let Some(candidate) = self.neighbor(mid, point) {
+                if my_best.as_ref().map_or(true, |b| candidate.dist_sq < b.dist_sq) {
+                    my_best = Some(candidate);
+                }
             }
 
             // bookkeeping
             my_n_visited += 1;
 
             // same reasoning applies for the far side of the split
-            if self.point.coords[self.dim] < point.coords[self.dim] {
-                if self.left.is_some() {
-                    let (a, b) = self.left.as_ref().unwrap().find_nearest_neighbor_helper(
-                        point,
-                        my_best,
-                        my_best_dist_sq,
-                        my_n_visited);
-                    my_best = a;
-                    my_n_visited = b;
+            let (a, b) = self.find_nearest_neighbor_helper(point, far.0, far.1, next_dim, scale,
+                                                           my_best, my_n_visited);
+            my_best = a;
+            my_n_visited = b;
+        }
+
+        (my_best, my_n_visited)
+    }
+
+    /// Returns the k nearest points, nearest first.
+    pub fn k_nearest(&self, point: &T, k: usize) -> Vec<Neighbor<T, P>> {
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        if k > 0 {
+            self.k_nearest_helper(point, k, 0, self.points.len(), 0, &mut heap);
+            for &(ref p, ref payload) in &self.pending {
+                let dist_sq = point.dist_sq(p);
+                offer(&mut heap, k, Neighbor { point: p, payload: payload, dist_sq: dist_sq });
+            }
+        }
+        heap.into_sorted_vec().into_iter().map(|c| c.0).collect()
+    }
+
+    fn k_nearest_helper<'a>(&'a self, point: &T, k: usize, lo: usize, hi: usize, dim: usize,
+                            heap: &mut BinaryHeap<Candidate<'a, T, P>>) {
+        if lo == hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let next_dim = (dim + 1) % T::dims();
+        let (near, far) = self.near_far(point, lo, mid, hi, dim);
+        self.k_nearest_helper(point, k, near.0, near.1, next_dim, heap);
+
+        if let Some(candidate) = self.neighbor(mid, point) {
+            offer(heap, k, candidate);
+        }
+
+        // The far side can only hold better points if the splitting plane is
+        // nearer than the worst of the k best so far.
+        let axis_dist_sq = self.axis_dist_sq(point, mid, dim);
+        if heap.len() < k || heap.peek().map_or(false, |worst| axis_dist_sq < worst.0.dist_sq) {
+            self.k_nearest_helper(point, k, far.0, far.1, next_dim, heap);
+        }
+    }
+
+    /// Returns up to k near points, nearest first, and the number of nodes
+    /// visited to find them. Unexplored branches are kept in a queue ordered
+    /// by their distance from `point`, and the search stops once `max_visits`
+    /// nodes have been visited, so with a small budget the result may miss
+    /// some of the k nearest points. Points inserted since the last rebuild
+    /// are always checked, and count towards the visits.
+    pub fn find_best_bin_first(&self, point: &T, k: usize, max_visits: usize)
+                               -> (Vec<Neighbor<T, P>>, usize) {
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        let mut n_visited = 0;
+        if k == 0 {
+            return (vec![], 0);
+        }
+        for &(ref p, ref payload) in &self.pending {
+            let dist_sq = point.dist_sq(p);
+            offer(&mut heap, k, Neighbor { point: p, payload: payload, dist_sq: dist_sq });
+            n_visited += 1;
+        }
+
+        let mut branches = BinaryHeap::new();
+        branches.push(Branch { dist_sq: Distance::zero(), lo: 0, hi: self.points.len(), dim: 0 });
+        'search: while let Some(branch) = branches.pop() {
+            if heap.len() == k && heap.peek().map_or(false, |w| branch.dist_sq >= w.0.dist_sq) {
+                // Every branch left is at least this far away.
+                break;
+            }
+            // Descend to a leaf, queueing the far side of every split.
+            let (mut lo, mut hi, mut dim) = (branch.lo, branch.hi, branch.dim);
+            while lo < hi {
+                if n_visited >= max_visits {
+                    break 'search;
                 }
-            } else {
-                if self.right.is_some() {
-                    let (a, b) = self.right.as_ref().unwrap().find_nearest_neighbor_helper(
-                        point,
-                        my_best,
-                        my_best_dist_sq,
-                        my_n_visited);
-                    my_best = a;
-                    my_n_visited = b;
+                let mid = lo + (hi - lo) / 2;
+                let next_dim = (dim + 1) % T::dims();
+                n_visited += 1;
+                if let Some(candidate) = self.neighbor(mid, point) {
+                    offer(&mut heap, k, candidate);
                 }
+                let (near, far) = self.near_far(point, lo, mid, hi, dim);
+                if far.0 < far.1 {
+                    branches.push(Branch {
+                        dist_sq: self.axis_dist_sq(point, mid, dim),
+                        lo: far.0,
+                        hi: far.1,
+                        dim: next_dim
+                    });
+                }
+                lo = near.0;
+                hi = near.1;
+                dim = next_dim;
             }
         }
+        (heap.into_sorted_vec().into_iter().map(|c| c.0).collect(), n_visited)
+    }
 
-        (my_best, my_n_visited)
+    /// Returns every point within `radius` of `point`, nearest first.
+    pub fn within_radius(&self, point: &T, radius: T::Coord) -> Vec<Neighbor<T, P>> {
+        let radius_sq = radius.widen() * radius.widen();
+        let mut found = Vec::new();
+        self.radius_helper(point, radius_sq, 0, self.points.len(), 0, &mut found);
+        for &(ref p, ref payload) in &self.pending {
+            let dist_sq = point.dist_sq(p);
+            if dist_sq <= radius_sq {
+                found.push(Neighbor { point: p, payload: payload, dist_sq: dist_sq });
+            }
+        }
+        found.sort_by(|a, b| partial_cmp(&a.dist_sq, &b.dist_sq));
+        found
+    }
+
+    fn radius_helper<'a>(&'a self, point: &T, radius_sq: DistSq<T>,
+                         lo: usize, hi: usize, dim: usize,
+                         found: &mut Vec<Neighbor<'a, T, P>>) {
+        if lo == hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let next_dim = (dim + 1) % T::dims();
+        let (near, far) = self.near_far(point, lo, mid, hi, dim);
+        self.radius_helper(point, radius_sq, near.0, near.1, next_dim, found);
+        if let Some(candidate) = self.neighbor(mid, point) {
+            if candidate.dist_sq <= radius_sq {
+                found.push(candidate);
+            }
+        }
+        if self.axis_dist_sq(point, mid, dim) <= radius_sq {
+            self.radius_helper(point, radius_sq, far.0, far.1, next_dim, found);
+        }
+    }
+
+    /// Returns every point inside the axis-aligned box between `min` and
+    /// `max`, borders included.
+    pub fn in_box(&self, min: &T, max: &T) -> Vec<(&T, &P)> {
+        let inside = |p: &T| (0..T::dims()).all(|d| min.coord(d) <= p.coord(d) &&
+                                                    p.coord(d) <= max.coord(d));
+        let mut found = Vec::new();
+        self.box_helper(&inside, min, max, 0, self.points.len(), 0, &mut found);
+        for &(ref p, ref payload) in &self.pending {
+            if inside(p) {
+                found.push((p, payload));
+            }
+        }
+        found
+    }
+
+    fn box_helper<'a>(&'a self, inside: &Fn(&T) -> bool, min: &T, max: &T,
+                      lo: usize, hi: usize, dim: usize,
+                      found: &mut Vec<(&'a T, &'a P)>) {
+        if lo == hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let next_dim = (dim + 1) % T::dims();
+        let split = self.points[mid].coord(dim);
+        // Points equal to the split value can be on either side.
+        if min.coord(dim) <= split {
+            self.box_helper(inside, min, max, lo, mid, next_dim, found);
+        }
+        if let Some(ref payload) = self.payloads[mid] {
+            if inside(&self.points[mid]) {
+                found.push((&self.points[mid], payload));
+            }
+        }
+        if split <= max.coord(dim) {
+            self.box_helper(inside, min, max, mid + 1, hi, next_dim, found);
+        }
+    }
+}
+
+/// Adds `candidate` to a heap holding the k best candidates so far.
+fn offer<'a, T: Point, P>(heap: &mut BinaryHeap<Candidate<'a, T, P>>, k: usize,
+                          candidate: Neighbor<'a, T, P>) {
+    if heap.len() < k || heap.peek().map_or(false, |worst| candidate.dist_sq < worst.0.dist_sq) {
+        heap.push(Candidate(candidate));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+}
+
+impl<T: Point> KDTree<T, ()> {
+    /// Builds a balanced tree from points without payloads.
+    pub fn from_points(points: Vec<T>) -> KDTree<T, ()> {
+        KDTree::with_payloads(points.into_iter().map(|p| (p, ())).collect())
     }
 }
 
+#[cfg(not(test))]
+fn ms_since(start: Timespec) -> f32 {
+    let end = get_time();
+    ((end.sec - start.sec)*1000) as f32 + ((end.nsec - start.nsec) as f32)/1000000f32
+}
+
 #[cfg(not(test))]
 pub fn main() {
     // wordpress
-    let mut wp_points: Vec<Point> = [
+    let wp_points = vec![
         [2f32, 3f32],
         [5f32, 4f32],
         [9f32, 6f32],
         [4f32, 7f32],
         [8f32, 1f32],
         [7f32, 2f32]
-    ].iter().map(|x| Point { coords: x.to_vec() }).collect();
-    let wp_tree = KDTreeNode::new(&mut wp_points[..], 0);
+    ];
+    let wp_tree = KDTree::from_points(wp_points);
 
-    let wp_target = Point { coords: vec![9f32, 2f32] };
-    let (point, n_visited) = wp_tree.find_nearest_neighbor(&wp_target);
+    let wp_target = [9f32, 2f32];
+    let (nearest, n_visited) = wp_tree.find_nearest_neighbor(&wp_target).unwrap();
     println!("Wikipedia example data:");
     println!("Point: [9, 2]");
-    println!("Nearest neighbor: {:?}", point);
-    println!("Distance: {}", (point - &wp_target).norm_sq().sqrt());
+    println!("Nearest neighbor: {:?}", nearest.point);
+    println!("Distance: {}", nearest.dist_sq.sqrt());
     println!("Nodes visited: {}", n_visited);
+    let three: Vec<&[f32; 2]> = wp_tree.k_nearest(&wp_target, 3).iter().map(|n| n.point).collect();
+    println!("Three nearest neighbors: {:?}", three);
 
     // randomly generated 3D
-    let n_random = 1000;
-    let make_random_point = || Point {
-        coords: (0..3).map(
-				|_| (rand::thread_rng().gen::<f32>()-0.5f32)*1000f32
-			).collect()
+    let make_random_point = || {
+        let mut rng = rand::thread_rng();
+        let mut coord = || (rng.gen::<f32>()-0.5f32)*1000f32;
+        [coord(), coord(), coord()]
     };
-    let mut random_points: Vec<Point> = (0..n_random)
-		.map(|_| make_random_point()).collect();
-
-    let start_cons_time = get_time();
-    let random_tree = KDTreeNode::new(&mut random_points[..], 0);
-    let end_cons_time = get_time();
-    println!("1,000 3d points (Construction time: {}ms)",
-             ((end_cons_time.sec - start_cons_time.sec)*1000) as f32 +
-             ((end_cons_time.nsec - start_cons_time.nsec) as f32)/1000000f32);
-
-    let random_target = make_random_point();
-
-    let (point, n_visited) = random_tree.find_nearest_neighbor(&random_target);
-    println!("Point: {:?}", random_target);
-    println!("Nearest neighbor: {:?}", point);
-    println!("Distance: {}", (point - &random_target).norm_sq().sqrt());
-    println!("Nodes visited: {}", n_visited);
 
-    // benchmark search time
-    let n_searches = 1000;
-    let random_targets: Vec<Point> = (0..n_searches).map(
-		|_| make_random_point()
-    ).collect();
-
-    let start_search_time = get_time();
-    let mut total_n_visited = 0;
-    for target in &random_targets {
-        let (_, n_visited) = random_tree.find_nearest_neighbor(target);
-        total_n_visited += n_visited;
-    }
-    let end_search_time = get_time();
-    println!("Visited an average of {} nodes on {} searches in {} ms",
-             total_n_visited as f32 / n_searches as f32,
-             n_searches,
-             ((end_search_time.sec - start_search_time.sec)*1000) as f32 +
-             ((end_search_time.nsec - start_search_time.nsec) as f32)/1000000f32);
+    for &n_random in &[1000, 1000000] {
+        let random_points: Vec<[f32; 3]> = (0..n_random)
+            .map(|_| make_random_point()).collect();
+
+        let start_cons_time = get_time();
+        let random_tree = KDTree::from_points(random_points.clone());
+        let cons_time = ms_since(start_cons_time);
+
+        let threads = 4;
+        let start_par_time = get_time();
+        let par_tree = KDTree::with_payloads_parallel(
+            random_points.into_iter().map(|p| (p, ())).collect(), threads);
+        let par_time = ms_since(start_par_time);
+        println!("{} 3d points (Construction time: {}ms, {}ms on {} threads)",
+                 n_random, cons_time, par_time, threads);
+
+        let random_target = make_random_point();
+
+        let (nearest, n_visited) = random_tree.find_nearest_neighbor(&random_target).unwrap();
+        let (par_nearest, _) = par_tree.find_nearest_neighbor(&random_target).unwrap();
+        assert_eq!(nearest.dist_sq, par_nearest.dist_sq);
+        println!("Point: {:?}", random_target);
+        println!("Nearest neighbor: {:?}", nearest.point);
+        println!("Distance: {}", nearest.dist_sq.sqrt());
+        println!("Nodes visited: {}", n_visited);
+        println!("Points within 100: {}", random_tree.within_radius(&random_target, 100f32).len());
+
+        // benchmark search time
+        let n_searches = 1000;
+        let random_targets: Vec<[f32; 3]> = (0..n_searches).map(
+            |_| make_random_point()
+        ).collect();
+
+        let start_search_time = get_time();
+        let mut total_n_visited = 0;
+        for target in &random_targets {
+            let (_, n_visited) = random_tree.find_nearest_neighbor(target).unwrap();
+            total_n_visited += n_visited;
+        }
+        println!("Visited an average of {} nodes on {} searches in {} ms",
+                 total_n_visited as f32 / n_searches as f32,
+                 n_searches,
+                 ms_since(start_search_time));
+    }
+
+    // approximate searches in 16 dimensions, where the exact search has to
+    // look at a large part of the tree
+    let n_high = 100000;
+    let make_high_point = || {
+        let mut rng = rand::thread_rng();
+        let mut p = [0f32; 16];
+        for c in p.iter_mut() {
+            *c = rng.gen::<f32>();
+        }
+        p
+    };
+    let high_tree = KDTree::from_points((0..n_high).map(|_| make_high_point()).collect());
+    let high_targets: Vec<[f32; 16]> = (0..200).map(|_| make_high_point()).collect();
+    let k = 10;
+    let exact: Vec<_> = high_targets.iter().map(|t| high_tree.k_nearest(t, k)).collect();
+    println!("{} 16d points, {} searches:", n_high, high_targets.len());
+
+    let report = |name: String, results: Vec<(Vec<Neighbor<[f32; 16], ()>>, usize)>, ms: f32| {
+        let n = results.len() as f64;
+        let visited = results.iter().map(|r| r.1).fold(0, |a, b| a + b) as f64 / n;
+        let recall = results.iter().zip(exact.iter())
+                            .map(|(r, e)| recall(&e[..r.0.len()], &r.0))
+                            .fold(0.0, |a, b| a + b) / n;
+        println!("  {:<28} {:>9.1} nodes  recall {:.3}  {:>8.2} ms", name, visited, recall, ms);
+    };
+    for &epsilon in &[0.0, 0.5, 1.0, 2.0] {
+        let start = get_time();
+        let results = high_targets.iter().map(|t| {
+            let (nearest, n_visited) = high_tree.find_approximate_neighbor(t, epsilon).unwrap();
+            (vec![nearest], n_visited)
+        }).collect();
+        report(format!("epsilon {} (1-NN)", epsilon), results, ms_since(start));
+    }
+    for &max_visits in &[100, 1000, 10000] {
+        let start = get_time();
+        let results = high_targets.iter()
+                                  .map(|t| high_tree.find_best_bin_first(t, k, max_visits))
+                                  .collect();
+        report(format!("best bin first {} ({}-NN)", max_visits, k), results, ms_since(start));
+    }
 }
 
-fn quickselect_by<T>(arr: &mut [T], position: usize, cmp: &Fn(&T, &T) -> Ordering) -> T
-    where T: Clone
-{
+/// Rearranges `arr` so that the element at `position` is the one that would
+/// be there if it was sorted, with no greater elements before it and no
+/// smaller ones after it.
+fn quickselect_by<T>(arr: &mut [T], position: usize, cmp: &Fn(&T, &T) -> Ordering) {
     let mut pivot_index = rand::thread_rng().gen_range(0, arr.len());
     // Need to wrap in another closure or we get ownership complaints.
     // Tried using an unboxed closure to get around this but couldn't get it to work.
     pivot_index = partition_by(arr, pivot_index, &|a: &T, b: &T| cmp(a, b));
     let array_len = arr.len();
-    if position == pivot_index {
-        arr[position].clone()
-    } else if position < pivot_index {
+    if position < pivot_index {
         quickselect_by(&mut arr[0..pivot_index], position, cmp)
-    } else {
+    } else if position > pivot_index {
         quickselect_by(&mut arr[pivot_index+1..array_len], position - pivot_index - 1, cmp)
     }
 }
@@ -251,22 +780,244 @@ fn partition_by<T>(arr: &mut [T], pivot_index: usize, cmp: &Fn(&T, &T) -> Orderi
 
 #[cfg(test)]
 mod test {
-    use super::{Point, KDTreeNode};
+    use super::{DistSq, Point, KDTree, Neighbor, recall};
 
     #[test]
     fn wp() {
-        let mut wp_points: Vec<Point> = [
+        let wp_points = vec![
             [2f32, 3f32],
             [5f32, 4f32],
             [9f32, 6f32],
             [4f32, 7f32],
             [8f32, 1f32],
             [7f32, 2f32]
-        ].iter().map(|x| Point { coords: x.to_vec() }).collect();
-        let wp_tree = KDTreeNode::new(&mut wp_points[..], 0);
+        ];
+        let wp_tree = KDTree::from_points(wp_points);
+
+        let wp_target = [9f32, 2f32];
+        let (nearest, _) = wp_tree.find_nearest_neighbor(&wp_target).unwrap();
+        assert_eq!(*nearest.point, [8f32, 1f32]);
+    }
+
+    /// Reproducible pseudo-random coordinates between -100 and 100.
+    struct XorShift(u32);
+
+    impl XorShift {
+        fn next(&mut self) -> i64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            (self.0 % 201) as i64 - 100
+        }
+
+        fn points_2d(&mut self, n: usize) -> Vec<([i64; 2], usize)> {
+            (0..n).map(|i| ([self.next(), self.next()], i)).collect()
+        }
+
+        fn points_3d(&mut self, n: usize) -> Vec<([i64; 3], usize)> {
+            (0..n).map(|i| ([self.next(), self.next(), self.next()], i)).collect()
+        }
+    }
+
+    fn brute_force<T: Point>(items: &[(T, usize)], target: &T) -> Vec<(DistSq<T>, usize)> {
+        let mut all: Vec<(DistSq<T>, usize)> = items.iter()
+            .map(|&(ref p, i)| (p.dist_sq(target), i))
+            .collect();
+        all.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        all
+    }
+
+    /// Checks that every point in the layout is on the correct side of the
+    /// splitting planes above it.
+    fn check_layout<T: Point, P>(tree: &KDTree<T, P>, lo: usize, hi: usize, dim: usize) {
+        if lo == hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let split = tree.points[mid].coord(dim);
+        assert!(tree.points[lo..mid].iter().all(|p| p.coord(dim) <= split));
+        assert!(tree.points[mid+1..hi].iter().all(|p| p.coord(dim) >= split));
+        check_layout(tree, lo, mid, (dim + 1) % T::dims());
+        check_layout(tree, mid + 1, hi, (dim + 1) % T::dims());
+    }
+
+    #[test]
+    fn k_nearest_matches_brute_force() {
+        let items = XorShift(7).points_3d(500);
+        let tree = KDTree::with_payloads(items.clone());
+        check_layout(&tree, 0, 500, 0);
+        for (target, _) in XorShift(11).points_3d(50) {
+            let expected = brute_force(&items, &target);
+            for &k in &[1, 5, 40, 600] {
+                let found: Vec<i64> = tree.k_nearest(&target, k).iter().map(|n| n.dist_sq).collect();
+                let wanted: Vec<i64> = expected.iter().take(k).map(|e| e.0).collect();
+                assert_eq!(found, wanted);
+            }
+            let (nearest, _) = tree.find_nearest_neighbor(&target).unwrap();
+            assert_eq!(nearest.dist_sq, expected[0].0);
+        }
+        assert!(tree.k_nearest(&items[0].0, 0).is_empty());
+    }
+
+    #[test]
+    fn parallel_build() {
+        let items = XorShift(19).points_3d(20000);
+        let tree = KDTree::with_payloads_parallel(items.clone(), 4);
+        assert_eq!(tree.len(), 20000);
+        check_layout(&tree, 0, 20000, 0);
+        let mut payloads: Vec<usize> = tree.payloads.iter().map(|p| p.unwrap()).collect();
+        payloads.sort();
+        assert_eq!(payloads, (0..20000).collect::<Vec<usize>>());
+        for (target, _) in XorShift(23).points_3d(20) {
+            let found: Vec<i64> = tree.k_nearest(&target, 10).iter().map(|n| n.dist_sq).collect();
+            let wanted: Vec<i64> = brute_force(&items, &target).iter().take(10)
+                                                              .map(|e| e.0).collect();
+            assert_eq!(found, wanted);
+        }
+    }
+
+    #[test]
+    fn approximate_search() {
+        let mut rng = XorShift(29);
+        let items: Vec<([f64; 6], usize)> = (0..3000).map(|i| {
+            let mut p = [0f64; 6];
+            for c in p.iter_mut() {
+                *c = rng.next() as f64 / 7.0;
+            }
+            (p, i)
+        }).collect();
+        let tree = KDTree::with_payloads(items.clone());
+        for &(target, _) in items.iter().take(40) {
+            let target = [target[0] + 0.5, target[1], target[2] - 0.3, target[3],
+                          target[4], target[5] + 1.0];
+            let (exact, exact_visited) = tree.find_nearest_neighbor(&target).unwrap();
+            assert_eq!(exact.dist_sq, brute_force(&items, &target)[0].0);
+            for &epsilon in &[0.0, 0.5, 2.0] {
+                let (approx, visited) = tree.find_approximate_neighbor(&target, epsilon).unwrap();
+                assert!(approx.dist_sq.sqrt() <= (1.0 + epsilon) * exact.dist_sq.sqrt() + 1e-9);
+                assert!(visited <= exact_visited);
+                if epsilon == 0.0 {
+                    assert_eq!(approx.dist_sq, exact.dist_sq);
+                }
+            }
+
+            let exact = tree.k_nearest(&target, 5);
+            let (all, _) = tree.find_best_bin_first(&target, 5, 3000);
+            assert_eq!(recall(&exact, &all), 1.0);
+            let dists = |ns: &[Neighbor<[f64; 6], usize>]| -> Vec<f64> {
+                ns.iter().map(|n| n.dist_sq).collect()
+            };
+            assert_eq!(dists(&all), dists(&exact));
+            let (some, visited) = tree.find_best_bin_first(&target, 5, 50);
+            assert!(visited <= 50);
+            assert_eq!(some.len(), 5);
+            let r = recall(&exact, &some);
+            assert!(0.0 <= r && r <= 1.0);
+        }
+    }
+
+    #[test]
+    fn recall_counts_ties() {
+        let tree = KDTree::with_payloads(vec![([0i32], 'a'), ([2], 'b'), ([-2], 'c'), ([5], 'd')]);
+        let exact = tree.k_nearest(&[0], 2);
+        let other = tree.k_nearest(&[0], 3);
+        assert_eq!(recall(&exact, &other[..2]), 1.0);
+        assert_eq!(recall(&exact, &other[2..]), 0.5);
+        assert_eq!(recall(&exact, &[]), 0.0);
+    }
+
+    #[test]
+    fn radius_and_box_queries() {
+        let items = XorShift(3).points_2d(400);
+        let tree = KDTree::with_payloads(items.clone());
+        for (target, _) in XorShift(5).points_2d(30) {
+            let mut found: Vec<usize> = tree.within_radius(&target, 30).iter()
+                                            .map(|n| *n.payload).collect();
+            let mut wanted: Vec<usize> = brute_force(&items, &target).iter()
+                                             .filter(|e| e.0 <= 900).map(|e| e.1).collect();
+            found.sort();
+            wanted.sort();
+            assert_eq!(found, wanted);
+        }
+
+        let (min, max) = ([-20, 0], [35, 50]);
+        let mut found: Vec<usize> = tree.in_box(&min, &max).iter().map(|&(_, &i)| i).collect();
+        let mut wanted: Vec<usize> = items.iter()
+            .filter(|&&(ref p, _)| -20 <= p[0] && p[0] <= 35 && 0 <= p[1] && p[1] <= 50)
+            .map(|&(_, i)| i).collect();
+        found.sort();
+        wanted.sort();
+        assert!(!wanted.is_empty());
+        assert_eq!(found, wanted);
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let items = XorShift(13).points_2d(300);
+        let mut tree = KDTree::new();
+        // Inserting in sorted order would make a plain k-d tree a long chain.
+        let mut sorted = items.clone();
+        sorted.sort_by(|a, b| a.0.cmp(&b.0));
+        for (p, i) in sorted {
+            tree.insert(p, i);
+        }
+        assert_eq!(tree.len(), 300);
+        assert!(tree.pending.len() * 8 <= tree.len());
+
+        for &(ref p, i) in &items {
+            if i % 2 == 0 {
+                assert!(tree.remove(p).is_some());
+            }
+        }
+        assert_eq!(tree.len(), 150);
+        assert_eq!(tree.remove(&[1000, 1000]), None);
+
+        // Removing a point leaves any duplicates of it in the tree.
+        let remaining: Vec<([i64; 2], usize)> = {
+            let mut all = vec![];
+            for &(ref p, _) in &items {
+                for n in tree.within_radius(p, 0) {
+                    all.push((*n.point, *n.payload));
+                }
+            }
+            all.sort_by(|a, b| a.1.cmp(&b.1));
+            all.dedup();
+            all
+        };
+        assert_eq!(remaining.len(), 150);
+        for (target, _) in XorShift(17).points_2d(20) {
+            let found: Vec<i64> = tree.k_nearest(&target, 3).iter().map(|n| n.dist_sq).collect();
+            let wanted: Vec<i64> = brute_force(&remaining, &target).iter().take(3)
+                                                                  .map(|e| e.0).collect();
+            assert_eq!(found, wanted);
+        }
+    }
+
+    #[test]
+    fn floating_point_payloads() {
+        let cities = vec![
+            ([51.51f64, -0.13], "London"),
+            ([48.86, 2.35], "Paris"),
+            ([52.52, 13.40], "Berlin"),
+            ([40.42, -3.70], "Madrid"),
+        ];
+        let tree = KDTree::with_payloads(cities);
+        let brussels = [50.85, 4.35];
+        let names: Vec<&str> = tree.k_nearest(&brussels, 2).iter().map(|n| *n.payload).collect();
+        assert_eq!(names, vec!["Paris", "London"]);
+    }
+
+    #[test]
+    fn small_integer_coordinates() {
+        // squared distances that don't fit in the coordinate type
+        let corners = vec![[-128i8, -128], [127, 127], [0, 0]];
+        let tree = KDTree::from_points(corners);
+        let far: Vec<i32> = tree.k_nearest(&[127, 127], 3).iter().map(|n| n.dist_sq).collect();
+        assert_eq!(far, vec![0, 2 * 127 * 127, 2 * 255 * 255]);
+        assert_eq!(tree.within_radius(&[-128, -128], 127).len(), 1);
 
-        let wp_target = Point { coords: vec![9f32, 2f32] };
-        let (point, _) = wp_tree.find_nearest_neighbor(&wp_target);
-        assert_eq!(*point, Point { coords: vec![8f32, 1f32] });
+        let tree = KDTree::from_points(vec![[i16::min_value(), i16::max_value()]]);
+        let nearest = tree.find_nearest_neighbor(&[i16::max_value(), i16::min_value()]);
+        assert_eq!(nearest.unwrap().0.dist_sq, 2 * 65535 * 65535);
     }
 }
@@ -1,7 +1,17 @@
 // http://rosettacode.org/wiki/Knapsack_problem/0-1
+// Also covers the bounded, unbounded and continuous variants:
+// http://rosettacode.org/wiki/Knapsack_problem/Bounded
+// http://rosettacode.org/wiki/Knapsack_problem/Unbounded
+// http://rosettacode.org/wiki/Knapsack_problem/Continuous
 
-
-use std::cmp::max;
+use std::cmp::Ordering;
+#[cfg(not(test))]
+use std::env;
+use std::fmt;
+#[cfg(not(test))]
+use std::fs::File;
+#[cfg(not(test))]
+use std::io::Read;
 use std::iter::repeat;
 
 // This struct is used to store our items that we want in our knap-sack.
@@ -17,6 +27,15 @@ struct Want<'a> {
     value: usize
 }
 
+// An item constrained in several dimensions at once, e.g. weight and volume.
+#[derive(Clone)]
+struct Package<'a> {
+    #[allow(dead_code)]
+    name: &'a str,
+    sizes: Vec<usize>,
+    value: usize
+}
+
 // Global, immutable allocation of our items. This is so we can reference
 // this in multiple functions.
 const ITEMS: &'static [Want<'static>] = &[
@@ -44,106 +63,507 @@ const ITEMS: &'static [Want<'static>] = &[
     Want {name: "book",                   weight: 30,  value: 10}
 ];
 
+// The counts from http://rosettacode.org/wiki/Knapsack_problem/Bounded (that
+// task also changes a few of the values, we keep ours)
+const COUNTS: &'static [usize] = &[
+    1, 1, 2, 2, 2, 3, 3, 3, 1, 3, 1, 1, 2, 2, 1, 1, 1, 1, 1, 1, 1, 2
+];
+
+// The 0-1 problem, generalised to any number of constraints, is solved by
+// every other solver in this file, so it works on plain sizes and values.
+//
+// Imagine we wrote a recursive function(item, max_weight) that returns a
+// usize corresponding to the maximum cumulative value by considering a
+// subset of items such that the combined weight <= max_weight.
+//
+// fn best_value(item: usize, max_weight: usize) -> usize{
+//     if item == 0 {
+//         return 0;
+//     }
+//     if xs[item - 1].weight > max_weight {
+//         return best_value(item - 1, max_weight, xs);
+//     }
+//     return max(best_value(item - 1, max_weight, xs),
+//                best_value(item - 1, max_weight - xs[item - 1].weight, xs)
+//                + xs[item - 1].value);
+//     }
+//
+// best_value(xs_len, max_weight) is equal to the maximum value that we
+// can add to the bag.
+//
+// The problem with using this function is that it performs redudant
+// calculations.
+//
+// The dynamic programming solution is to precompute all of the values we
+// need. Row i of the table only depends on row i - 1, so a single row is
+// enough if we walk the capacities from the top down: every entry we read
+// still holds the value for the previous item.
+//
+// With several constraints the row is indexed by a capacity vector, which we
+// flatten with the strides of a (cap_1 + 1) x (cap_2 + 1) x ... array.
+struct Capacity {
+    limits: Vec<usize>,
+    strides: Vec<usize>,
+    len: usize
+}
+
+impl Capacity {
+    fn new(limits: &[usize]) -> Capacity {
+        let mut strides = Vec::with_capacity(limits.len());
+        let mut len = 1;
+        for &limit in limits.iter() {
+            strides.push(len);
+            len *= limit + 1;
+        }
+        Capacity { limits: limits.to_vec(), strides: strides, len: len }
+    }
+
+    fn fits(&self, sizes: &[usize]) -> bool {
+        sizes.iter().zip(self.limits.iter()).all(|(s, l)| s <= l)
+    }
+
+    fn index(&self, sizes: &[usize]) -> usize {
+        sizes.iter().zip(self.strides.iter()).fold(0, |a, (s, d)| a + s * d)
+    }
+
+    fn decode(&self, mut index: usize) -> Vec<usize> {
+        let mut sizes = repeat(0).take(self.limits.len()).collect::<Vec<_>>();
+        for d in (0..self.limits.len()).rev() {
+            sizes[d] = index / self.strides[d];
+            index %= self.strides[d];
+        }
+        sizes
+    }
+}
+
+// best[c] is the best value we can get from `items` with sizes <= c.
+fn best_values(items: &[usize], sizes: &[Vec<usize>], values: &[usize],
+               cap: &Capacity) -> Vec<usize> {
+    let mut best: Vec<usize> = repeat(0).take(cap.len).collect();
+    let mut room = repeat(0).take(cap.limits.len()).collect::<Vec<_>>();
+    for &i in items.iter() {
+        if !cap.fits(&sizes[i]) {
+            continue;
+        }
+        let offset = cap.index(&sizes[i]);
+        for c in (offset..cap.len).rev() {
+            // the offset only says the item fits in total, every constraint
+            // must have room for it on its own
+            let mut rest = c;
+            let mut fits = true;
+            for d in (0..room.len()).rev() {
+                room[d] = rest / cap.strides[d];
+                rest %= cap.strides[d];
+                if room[d] < sizes[i][d] {
+                    fits = false;
+                    break;
+                }
+            }
+            if fits && best[c - offset] + values[i] > best[c] {
+                best[c] = best[c - offset] + values[i];
+            }
+        }
+    }
+    best
+}
+
+// A single row of the table gives the best value but not which items made it.
+// To reconstruct the solution without going back to the full table we split
+// the items in half (Hirschberg's trick): the best way to share the capacity
+// between the halves is found from one row for each half, and each half is
+// then solved on its own share. The memory stays one row (per level of the
+// recursion) and the running time at most doubles.
+fn solve_01(items: &[usize], sizes: &[Vec<usize>], values: &[usize],
+            limits: &[usize], chosen: &mut Vec<usize>) {
+    let cap = Capacity::new(limits);
+    match items.len() {
+        0 => return,
+        1 => {
+            let i = items[0];
+            if values[i] > 0 && cap.fits(&sizes[i]) {
+                chosen.push(i);
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let (first, second) = items.split_at(items.len() / 2);
+    let split = {
+        let left = best_values(first, sizes, values, &cap);
+        let right = best_values(second, sizes, values, &cap);
+        let top = cap.len - 1;
+        let mut split = 0;
+        for c in 1..cap.len {
+            if left[c] + right[top - c] > left[split] + right[top - split] {
+                split = c;
+            }
+        }
+        split
+    };
+    let left_limits = cap.decode(split);
+    let right_limits = limits.iter().zip(left_limits.iter())
+        .map(|(l, s)| l - s).collect::<Vec<_>>();
+    solve_01(first, sizes, values, &left_limits, chosen);
+    solve_01(second, sizes, values, &right_limits, chosen);
+}
+
+// Returns the indices of the chosen items, in increasing order.
+fn knap_01_indices(sizes: &[Vec<usize>], values: &[usize], limits: &[usize])
+                   -> Vec<usize> {
+    let items = (0..values.len()).collect::<Vec<_>>();
+    let mut chosen = Vec::new();
+    solve_01(&items, sizes, values, limits, &mut chosen);
+    chosen.sort();
+    chosen
+}
+
 // This is a bottom-up dynamic programming solution to the 0-1 knap-sack problem.
 //      maximize value
 //      subject to weights <= max_weight
+// It uses O(max_weight) memory and returns the items in their original order.
 fn knap_01_dp<'a>(xs: &[Want<'a>], max_weight: usize) -> Vec<Want<'a>> {
+    let sizes = xs.iter().map(|x| vec![x.weight]).collect::<Vec<_>>();
+    let values = xs.iter().map(|x| x.value).collect::<Vec<_>>();
+    knap_01_indices(&sizes, &values, &[max_weight])
+        .into_iter().map(|i| xs[i]).collect()
+}
+
+// The 0-1 problem with one capacity per dimension of the packages' sizes,
+// e.g. both a weight and a volume limit. Memory is the product of the
+// capacities (each plus one), so keep them small.
+fn knap_multi_dp<'a>(xs: &[Package<'a>], capacities: &[usize]) -> Vec<Package<'a>> {
+    for x in xs.iter() {
+        assert_eq!(x.sizes.len(), capacities.len());
+    }
+    let sizes = xs.iter().map(|x| x.sizes.clone()).collect::<Vec<_>>();
+    let values = xs.iter().map(|x| x.value).collect::<Vec<_>>();
+    knap_01_indices(&sizes, &values, capacities)
+        .into_iter().map(|i| xs[i].clone()).collect()
+}
+
+// Each item may be taken up to the given number of times. Splitting a count
+// into bundles of 1, 2, 4, ... (and whatever is left over) lets every number
+// of copies be made out of distinct bundles, so the 0-1 solver does the rest
+// with only log(count) pseudo-items per item.
+fn knap_bounded_dp<'a>(xs: &[(Want<'a>, usize)], max_weight: usize)
+                       -> Vec<(Want<'a>, usize)> {
+    let mut sizes = Vec::new();
+    let mut values = Vec::new();
+    let mut bundles = Vec::new();
+    for (i, &(x, count)) in xs.iter().enumerate() {
+        let mut left = count;
+        let mut bundle = 1;
+        while left > 0 {
+            let n = if bundle < left { bundle } else { left };
+            sizes.push(vec![x.weight * n]);
+            values.push(x.value * n);
+            bundles.push((i, n));
+            left -= n;
+            bundle *= 2;
+        }
+    }
+
+    let mut counts: Vec<usize> = repeat(0).take(xs.len()).collect();
+    for b in knap_01_indices(&sizes, &values, &[max_weight]) {
+        let (i, n) = bundles[b];
+        counts[i] += n;
+    }
+    xs.iter().zip(counts.into_iter())
+        .filter(|&(_, n)| n > 0)
+        .map(|(&(x, _), n)| (x, n))
+        .collect()
+}
+
+// Each item may be taken any number of times. Here the row can be filled
+// from the bottom up, and remembering which item was added last at each
+// weight is enough to walk the solution back.
+fn knap_unbounded_dp<'a>(xs: &[Want<'a>], max_weight: usize)
+                         -> Vec<(Want<'a>, usize)> {
+    // a weightless item of any value would fill the bag forever
+    assert!(xs.iter().all(|x| x.weight > 0 || x.value == 0));
+
+    let mut best: Vec<usize> = repeat(0).take(max_weight + 1).collect();
+    let mut last: Vec<Option<usize>> = repeat(None).take(max_weight + 1).collect();
+    for w in 1..max_weight + 1 {
+        best[w] = best[w - 1];
+        for (i, x) in xs.iter().enumerate() {
+            if x.weight > 0 && x.weight <= w && best[w - x.weight] + x.value > best[w] {
+                best[w] = best[w - x.weight] + x.value;
+                last[w] = Some(i);
+            }
+        }
+    }
 
-    // Save this value, so we don't have to make repeated calls.
-    let xs_len = xs.len();
-
-    // Imagine we wrote a recursive function(item, max_weight) that returns a
-    // usize corresponding to the maximum cumulative value by considering a
-    // subset of items such that the combined weight <= max_weight.
-    //
-    // fn best_value(item: usize, max_weight: usize) -> usize{
-    //     if item == 0 {
-    //         return 0;
-    //     }
-    //     if xs[item - 1].weight > max_weight {
-    //         return best_value(item - 1, max_weight, xs);
-    //     }
-    //     return max(best_value(item - 1, max_weight, xs),
-    //                best_value(item - 1, max_weight - xs[item - 1].weight, xs)
-    //                + xs[item - 1].value);
-    //     }
-    //
-    // best_value(xs_len, max_weight) is equal to the maximum value that we
-    // can add to the bag.
-    //
-    // The problem with using this function is that it performs redudant
-    // calculations.
-    //
-    // The dynamic programming solution is to precompute all of the values we
-    // need and put them into a 2D array.
-    //
-    // In a similar vein, the top-down solution would be to memoize the
-    // function then compute the results on demand.
-
-    let zero_vec: Vec<usize> = repeat(0).take(max_weight + 1).collect();
-    let mut best_value: Vec<Vec<usize>> = repeat(zero_vec)
-		.take(xs_len + 1).collect();
-
-    // loop over the items
-    for i in (0..xs_len) {
-        // loop over the weights
-        for w in (1..max_weight + 1) {
-            // do we have room in our knapsack?
-            if xs[i].weight > w {
-                // if we don't, then we'll say that the value doesn't change
-                // when considering this item
-                best_value[i + 1][w] = best_value[i][w].clone();
+    let mut counts: Vec<usize> = repeat(0).take(xs.len()).collect();
+    let mut w = max_weight;
+    while w > 0 {
+        match last[w] {
+            Some(i) => {
+                counts[i] += 1;
+                w -= xs[i].weight;
+            }
+            None => w -= 1
+        }
+    }
+    xs.iter().zip(counts.into_iter())
+        .filter(|&(_, n)| n > 0)
+        .map(|(&x, n)| (x, n))
+        .collect()
+}
+
+// Compares items by value per unit of weight, the best first. Weightless
+// items come before everything else.
+fn by_density(a: &Want, b: &Want) -> Ordering {
+    if a.weight == 0 || b.weight == 0 {
+        a.weight.cmp(&b.weight)
+    } else {
+        // a.value / a.weight > b.value / b.weight without dividing
+        (b.value * a.weight).cmp(&(a.value * b.weight))
+    }
+}
+
+// When the items can be cut, the greedy choice is optimal: take the densest
+// items first and a fraction of the first one that doesn't fit.
+fn knap_fractional<'a>(xs: &[Want<'a>], max_weight: f64) -> Vec<(Want<'a>, f64)> {
+    let mut sorted = xs.to_vec();
+    sorted.sort_by(by_density);
+
+    let mut room = max_weight;
+    let mut result = Vec::new();
+    for x in sorted.into_iter() {
+        if x.value == 0 {
+            continue;
+        }
+        if (x.weight as f64) <= room {
+            room -= x.weight as f64;
+            result.push((x, 1.0));
+        } else {
+            if room > 0.0 {
+                result.push((x, room / x.weight as f64));
+            }
+            break;
+        }
+    }
+    result
+}
+
+struct Search<'a, 'b: 'a> {
+    items: &'a [Want<'b>],
+    taken: Vec<bool>,
+    best: Vec<bool>,
+    best_value: usize
+}
+
+impl<'a, 'b> Search<'a, 'b> {
+    // The value of the fractional relaxation of what's left: nothing we can
+    // do from here beats it.
+    fn bound(&self, next: usize, mut room: usize, value: usize) -> usize {
+        let mut bound = value;
+        for x in self.items[next..].iter() {
+            if x.weight <= room {
+                room -= x.weight;
+                bound += x.value;
             } else {
-                // if we do, then we have to see if the value we gain by adding
-                // the item, given the weight, is better than not adding the item
-                best_value[i + 1][w] =
-                    max(best_value[i][w].clone(),
-                        best_value[i][w - xs[i].weight] + xs[i].value);
+                bound += (room as f64 * x.value as f64 / x.weight as f64) as usize;
+                break;
             }
         }
+        bound
     }
 
-    // a variable representing the weight left in the bag
-    let mut left_weight = max_weight.clone();
+    fn go(&mut self, next: usize, room: usize, value: usize) {
+        if value > self.best_value {
+            self.best_value = value;
+            self.best = self.taken.clone();
+        }
+        if next == self.items.len() || self.bound(next, room, value) <= self.best_value {
+            return;
+        }
+        let x = self.items[next];
+        if x.weight <= room {
+            self.taken[next] = true;
+            self.go(next + 1, room - x.weight, value + x.value);
+            self.taken[next] = false;
+        }
+        self.go(next + 1, room, value);
+    }
+}
+
+// The DP needs memory and time proportional to the capacity, which is
+// hopeless when the weights are in grams and the bag holds tonnes. A depth
+// first search over the items, densest first, that drops any branch whose
+// fractional bound can't beat the best solution so far only depends on the
+// number of items (exponentially in the worst case, but rarely in practice).
+fn knap_01_branch_and_bound<'a>(xs: &[Want<'a>], max_weight: usize) -> Vec<Want<'a>> {
+    let mut order = (0..xs.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| by_density(&xs[a], &xs[b]));
+    let sorted = order.iter().map(|&i| xs[i]).collect::<Vec<_>>();
 
-    // a possibly over-allocated dynamically sized vector to push results to
-    let mut result = Vec::with_capacity(xs_len);
+    let mut search = Search {
+        items: &sorted,
+        taken: repeat(false).take(xs.len()).collect(),
+        best: repeat(false).take(xs.len()).collect(),
+        best_value: 0
+    };
+    search.go(0, max_weight, 0);
 
-    // we built up the solution space through a forward pass over the data,
-    // now we have to traverse backwards to get the solution
-    for i in (1..xs_len+1).rev() {
-        // We can check if an item should be added to the knap-sack by comparing
-        // best_value with and without this item. If best_value added this
-        // item then so should we.
-        if best_value[i][left_weight] != best_value[i - 1][left_weight] {
-            result.push(xs[i - 1]);
-            // we remove the weight of the object from the remaining weight
-            // we can add to the bag
-            left_weight -= xs[i - 1].weight;
+    let mut chosen = order.iter().zip(search.best.iter())
+        .filter(|&(_, &taken)| taken)
+        .map(|(&i, _)| i)
+        .collect::<Vec<_>>();
+    chosen.sort();
+    chosen.into_iter().map(|i| xs[i]).collect()
+}
+
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    MissingField(usize, &'static str),
+    BadNumber(usize, String),
+    ExtraField(usize)
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::MissingField(line, field) =>
+                write!(f, "line {}: missing {}", line, field),
+            ParseError::BadNumber(line, ref s) =>
+                write!(f, "line {}: '{}' is not a number", line, s),
+            ParseError::ExtraField(line) =>
+                write!(f, "line {}: too many fields", line),
         }
     }
+}
+
+// Reads items as `name,weight,value[,count]`, one per line. The count
+// defaults to 1. Blank lines, lines starting with '#' and a header line
+// starting with "name" are skipped, and quotes around fields are dropped.
+fn parse_csv<'a>(text: &'a str) -> Result<Vec<(Want<'a>, usize)>, ParseError> {
+    let mut items = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line_no = n + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("#") {
+            continue;
+        }
+        let mut fields = line.split(',').map(|f| f.trim().trim_matches('"').trim());
+        let name = fields.next().unwrap();
+        if items.is_empty() && name.to_lowercase() == "name" {
+            continue;
+        }
 
-    return result;
+        let mut number = |field: &'static str, default: Option<usize>| {
+            match fields.next() {
+                Some(s) => s.parse::<usize>()
+                    .map_err(|_| ParseError::BadNumber(line_no, s.to_string())),
+                None => default.ok_or(ParseError::MissingField(line_no, field))
+            }
+        };
+        let weight = try!(number("weight", None));
+        let value = try!(number("value", None));
+        let count = try!(number("count", Some(1)));
+        if fields.next().is_some() {
+            return Err(ParseError::ExtraField(line_no));
+        }
+        items.push((Want { name: name, weight: weight, value: value }, count));
+    }
+    Ok(items)
+}
+
+#[cfg(not(test))]
+fn print_totals<'a, I: Iterator<Item=(Want<'a>, usize)>>(items: I) {
+    let (mut weights, mut values) = (0, 0);
+    for (i, n) in items {
+        println!("Item: {}, Count: {}, Weight: {}, Value: {}",
+                 i.name, n, i.weight * n, i.value * n);
+        weights += i.weight * n;
+        values += i.value * n;
+    }
+    println!("Total Weight: {}", weights);
+    println!("Total Value: {}", values);
+}
+
+#[cfg(not(test))]
+fn solve_file(path: &str, max_weight: usize) {
+    let mut text = String::new();
+    if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut text)) {
+        println!("{}: {}", path, e);
+        return;
+    }
+    match parse_csv(&text) {
+        Ok(items) => {
+            if items.iter().all(|&(_, n)| n == 1) {
+                let xs = items.iter().map(|&(x, _)| x).collect::<Vec<_>>();
+                print_totals(knap_01_dp(&xs, max_weight).into_iter().map(|x| (x, 1)));
+            } else {
+                print_totals(knap_bounded_dp(&items, max_weight).into_iter());
+            }
+        }
+        Err(e) => println!("{}: {}", path, e)
+    }
 }
 
 #[cfg(not(test))]
 fn main () {
+    // knapsack_0-1 [ITEMS.csv [CAPACITY]]
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() > 1 {
+        let max_weight = match args.get(2).map(|s| s.parse::<usize>()) {
+            Some(Ok(w)) => w,
+            Some(Err(_)) => {
+                println!("The capacity must be a whole number");
+                return;
+            }
+            None => 400
+        };
+        solve_file(&args[1], max_weight);
+        return;
+    }
+
+    println!("0-1:");
     let xs = knap_01_dp(ITEMS, 400);
+    print_totals(xs.into_iter().map(|x| (x, 1)));
 
-    // Print the items. We have to reverse the order because we solved the
-    // problem backward.
-    for i in xs.iter().rev() {
-        println!("Item: {}, Weight: {}, Value: {}", i.name, i.weight, i.value);
-    }
+    println!("\nBounded:");
+    let bounded = ITEMS.iter().cloned().zip(COUNTS.iter().cloned()).collect::<Vec<_>>();
+    print_totals(knap_bounded_dp(&bounded, 400).into_iter());
 
-    // Print the sum of weights.
-    let weights = xs.iter().fold(0, |a, &b| a + b.weight);
-    println!("Total Weight: {}", weights);
+    println!("\nUnbounded:");
+    print_totals(knap_unbounded_dp(ITEMS, 400).into_iter());
 
-    // Print the sum of the values.
-    let values = xs.iter().fold(0, |a, &b| a + b.value);
-    println!("Total Value: {}", values);
+    println!("\nContinuous:");
+    let mut value = 0.0;
+    for (i, f) in knap_fractional(ITEMS, 400.0) {
+        println!("Item: {}, Fraction: {:.3}", i.name, f);
+        value += i.value as f64 * f;
+    }
+    println!("Total Value: {:.3}", value);
+
+    // http://rosettacode.org/wiki/Knapsack_problem/Unbounded with the weights
+    // in tenths and the volumes in thousandths, each taken at most 100 times
+    println!("\nWeight and volume:");
+    let mut packages = Vec::new();
+    for &(name, weight, volume, value) in [("panacea", 3, 25, 3000),
+                                           ("ichor", 2, 15, 1800),
+                                           ("gold", 20, 2, 2500)].iter() {
+        for _ in 0..12 {
+            packages.push(Package { name: name, sizes: vec![weight, volume], value: value });
+        }
+    }
+    let chosen = knap_multi_dp(&packages, &[250, 250]);
+    for &name in ["panacea", "ichor", "gold"].iter() {
+        println!("Item: {}, Count: {}", name, chosen.iter().filter(|p| p.name == name).count());
+    }
+    println!("Total Value: {}", chosen.iter().fold(0, |a, p| a + p.value));
 
+    // the same items in milligrams with a 400 kg bag
+    println!("\nBranch and bound:");
+    let heavy = ITEMS.iter()
+        .map(|x| Want { name: x.name, weight: x.weight * 1000, value: x.value })
+        .collect::<Vec<_>>();
+    print_totals(knap_01_branch_and_bound(&heavy, 400_000)
+                 .into_iter().map(|x| (x, 1)));
 }
 
 #[test]
@@ -154,3 +574,173 @@ fn test_dp_results() {
     assert_eq!(dp_weights, 396);
     assert_eq!(dp_values, 1030);
 }
+
+#[test]
+fn test_bounded_results() {
+    let xs = ITEMS.iter().cloned().zip(COUNTS.iter().cloned()).collect::<Vec<_>>();
+    let results = knap_bounded_dp(&xs, 400);
+    assert_eq!(results.iter().fold(0, |a, &(x, n)| a + x.weight * n), 394);
+    assert_eq!(results.iter().fold(0, |a, &(x, n)| a + x.value * n), 1200);
+}
+
+#[cfg(test)]
+struct XorShift(u32);
+
+#[cfg(test)]
+impl XorShift {
+    fn next(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as usize % n
+    }
+}
+
+#[cfg(test)]
+fn random_items(rng: &mut XorShift, n: usize) -> Vec<Want<'static>> {
+    (0..n).map(|_| Want { name: "x", weight: rng.next(20), value: rng.next(50) })
+        .collect()
+}
+
+// the best value over every subset, and every subset that achieves it fits
+#[cfg(test)]
+fn brute_force(sizes: &[Vec<usize>], values: &[usize], limits: &[usize]) -> usize {
+    let mut best = 0;
+    for mask in 0..1usize << values.len() {
+        let mut used = repeat(0).take(limits.len()).collect::<Vec<_>>();
+        let mut value = 0;
+        for i in 0..values.len() {
+            if mask & 1 << i != 0 {
+                for d in 0..limits.len() {
+                    used[d] += sizes[i][d];
+                }
+                value += values[i];
+            }
+        }
+        if used.iter().zip(limits.iter()).all(|(u, l)| u <= l) && value > best {
+            best = value;
+        }
+    }
+    best
+}
+
+#[test]
+fn test_01_matches_brute_force() {
+    let mut rng = XorShift(2463534242);
+    for _ in 0..200 {
+        let n = rng.next(12);
+        let xs = random_items(&mut rng, n);
+        let max_weight = rng.next(80);
+        let sizes = xs.iter().map(|x| vec![x.weight]).collect::<Vec<_>>();
+        let values = xs.iter().map(|x| x.value).collect::<Vec<_>>();
+        let best = brute_force(&sizes, &values, &[max_weight]);
+
+        for chosen in vec![knap_01_dp(&xs, max_weight),
+                           knap_01_branch_and_bound(&xs, max_weight)] {
+            assert!(chosen.iter().fold(0, |a, x| a + x.weight) <= max_weight);
+            assert_eq!(chosen.iter().fold(0, |a, x| a + x.value), best);
+        }
+    }
+}
+
+#[test]
+fn test_multi_matches_brute_force() {
+    let mut rng = XorShift(88675123);
+    for _ in 0..100 {
+        let n = rng.next(10);
+        let xs = (0..n).map(|_| Package {
+            name: "x",
+            sizes: vec![rng.next(10), rng.next(10), rng.next(10)],
+            value: rng.next(50)
+        }).collect::<Vec<_>>();
+        let limits = [rng.next(30), rng.next(30), rng.next(30)];
+        let sizes = xs.iter().map(|x| x.sizes.clone()).collect::<Vec<_>>();
+        let values = xs.iter().map(|x| x.value).collect::<Vec<_>>();
+
+        let chosen = knap_multi_dp(&xs, &limits);
+        for d in 0..3 {
+            assert!(chosen.iter().fold(0, |a, x| a + x.sizes[d]) <= limits[d]);
+        }
+        assert_eq!(chosen.iter().fold(0, |a, x| a + x.value),
+                   brute_force(&sizes, &values, &limits));
+    }
+}
+
+#[test]
+fn test_bounded_and_unbounded() {
+    let mut rng = XorShift(521288629);
+    for _ in 0..100 {
+        let n = rng.next(5);
+        let xs = random_items(&mut rng, n).into_iter()
+            .map(|x| (Want { weight: x.weight + 1, ..x }, rng.next(5)))
+            .collect::<Vec<_>>();
+        let max_weight = rng.next(100);
+
+        // as a 0-1 problem with every copy listed separately
+        let copies = xs.iter()
+            .flat_map(|&(x, n)| repeat(x).take(n))
+            .collect::<Vec<_>>();
+        let expected = knap_01_dp(&copies, max_weight).iter().fold(0, |a, x| a + x.value);
+        let bounded = knap_bounded_dp(&xs, max_weight);
+        for &(x, n) in bounded.iter() {
+            assert!(xs.iter().any(|&(y, m)| y.weight == x.weight && y.value == x.value && n <= m));
+        }
+        assert!(bounded.iter().fold(0, |a, &(x, n)| a + x.weight * n) <= max_weight);
+        assert_eq!(bounded.iter().fold(0, |a, &(x, n)| a + x.value * n), expected);
+
+        // the same with as many copies as could ever fit
+        let items = xs.iter().map(|&(x, _)| x).collect::<Vec<_>>();
+        let copies = items.iter()
+            .flat_map(|&x| repeat(x).take(max_weight / x.weight))
+            .collect::<Vec<_>>();
+        let expected = knap_01_dp(&copies, max_weight).iter().fold(0, |a, x| a + x.value);
+        let unbounded = knap_unbounded_dp(&items, max_weight);
+        assert!(unbounded.iter().fold(0, |a, &(x, n)| a + x.weight * n) <= max_weight);
+        assert_eq!(unbounded.iter().fold(0, |a, &(x, n)| a + x.value * n), expected);
+    }
+}
+
+#[test]
+fn test_fractional() {
+    // http://rosettacode.org/wiki/Knapsack_problem/Continuous, in tenths
+    let xs = [Want { name: "beef",    weight: 38, value: 36 },
+              Want { name: "pork",    weight: 54, value: 43 },
+              Want { name: "ham",     weight: 36, value: 90 },
+              Want { name: "greaves", weight: 24, value: 45 },
+              Want { name: "flitch",  weight: 40, value: 30 },
+              Want { name: "brawn",   weight: 25, value: 56 },
+              Want { name: "welt",    weight: 37, value: 67 },
+              Want { name: "salami",  weight: 30, value: 95 },
+              Want { name: "sausage", weight: 59, value: 98 }];
+    let taken = knap_fractional(&xs, 150.0);
+    let names = taken.iter().map(|&(x, _)| x.name).collect::<Vec<_>>();
+    assert_eq!(names, ["salami", "ham", "brawn", "greaves", "welt"]);
+    let weight = taken.iter().fold(0.0, |a, &(x, f)| a + x.weight as f64 * f);
+    let value = taken.iter().fold(0.0, |a, &(x, f)| a + x.value as f64 * f);
+    assert!((weight - 150.0).abs() < 1e-9);
+    assert!((value - 349.378378).abs() < 1e-6);
+
+    // never worse than any integral solution
+    let best = knap_01_dp(&xs, 150).iter().fold(0, |a, x| a + x.value);
+    assert!(value >= best as f64);
+}
+
+#[test]
+fn test_parse_csv() {
+    let text = "name,weight,value,count\n\
+                # comment\n\
+                \"map\", 9, 150\n\
+                \n\
+                water,153,200,2\n";
+    let items = parse_csv(text).unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!((items[0].0.name, items[0].0.weight, items[0].0.value, items[0].1),
+               ("map", 9, 150, 1));
+    assert_eq!((items[1].0.name, items[1].0.weight, items[1].0.value, items[1].1),
+               ("water", 153, 200, 2));
+
+    assert_eq!(parse_csv("map,9").err(), Some(ParseError::MissingField(1, "value")));
+    assert_eq!(parse_csv("map,9,x").err(),
+               Some(ParseError::BadNumber(1, "x".to_string())));
+    assert_eq!(parse_csv("# items\nmap,9,1,1,1").err(), Some(ParseError::ExtraField(2)));
+}
@@ -1,75 +1,172 @@
 // Implements http://rosettacode.org/wiki/Metered_concurrency
 // Rust has a perfectly good Semaphore type already.  It lacks count(), though, so we can't use it
 // directly.
+//
+// Threads waiting for resources queue up, and get them in the order they asked, even when one of
+// them wants several at once: someone asking for one doesn't get to jump ahead of someone
+// asking for three just because one is free.  A waiter can give up after a while, and the number
+// of resources can be changed while it's in use.
 
-#![feature(unsafe_destructor)]
-
-use std::sync::Arc;
-use std::sync::atomic::AtomicUsize;
-use std::sync::atomic::Ordering::SeqCst;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread::{self, spawn};
 use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+
+struct State {
+    // Remaining resource count.  This can go below zero if the semaphore is made smaller while
+    // the resources are in use.
+    available: isize,
+    capacity: usize,
+    // The tickets of the threads waiting, in the order they arrived.
+    queue: VecDeque<u64>,
+    next_ticket: u64,
+}
 
 pub struct CountingSemaphore {
-    count: AtomicUsize, // Remaining resource count
-    backoff: u32, // How long to sleep if a resource is being contended
+    state: Mutex<State>,
+    // Everyone waiting waits on this, and it's notified whenever the one at the front of the
+    // queue might be able to go.
+    changed: Condvar,
 }
 
 pub struct CountingSemaphoreGuard<'a> {
     sem: &'a CountingSemaphore, // A reference to the owning semaphore.
+    permits: usize, // How many resources it holds.
 }
 
 impl CountingSemaphore {
-    // Create a semaphore with `max` available resources and a linearly increasing backoff of
-    // `backoff` (used during spinlock contention).
-    pub fn new(max: usize, backoff: u32) -> CountingSemaphore {
-        CountingSemaphore { count: AtomicUsize::new(max), backoff: backoff }
+    // Create a semaphore with `max` available resources.
+    pub fn new(max: usize) -> CountingSemaphore {
+        CountingSemaphore {
+            state: Mutex::new(State {
+                available: max as isize,
+                capacity: max,
+                queue: VecDeque::new(),
+                next_ticket: 0,
+            }),
+            changed: Condvar::new(),
+        }
     }
 
     // Acquire a resource, returning a RAII CountingSemaphoreGuard.
     pub fn acquire(&self) -> CountingSemaphoreGuard {
-        // Spinlock until remaining resource count is at least 1
-        let mut backoff = self.backoff;
+        self.acquire_many(1)
+    }
+
+    // Acquire `n` resources at once.  If there are never that many to be had, this waits
+    // forever.
+    pub fn acquire_many(&self, n: usize) -> CountingSemaphoreGuard {
+        self.acquire_until(n, None).unwrap()
+    }
+
+    // Acquire a resource if one is free and nobody is waiting for one already.
+    pub fn try_acquire(&self) -> Option<CountingSemaphoreGuard> {
+        self.try_acquire_many(1)
+    }
+
+    pub fn try_acquire_many(&self, n: usize) -> Option<CountingSemaphoreGuard> {
+        let mut state = self.state.lock().unwrap();
+        if state.queue.is_empty() && state.available >= n as isize {
+            state.available -= n as isize;
+            Some(CountingSemaphoreGuard { sem: self, permits: n })
+        } else {
+            None
+        }
+    }
+
+    // Acquire a resource, giving up if it takes longer than `timeout`.
+    pub fn acquire_timeout(&self, timeout: Duration) -> Option<CountingSemaphoreGuard> {
+        self.acquire_many_timeout(1, timeout)
+    }
+
+    pub fn acquire_many_timeout(&self, n: usize, timeout: Duration)
+                                -> Option<CountingSemaphoreGuard> {
+        self.acquire_until(n, Some(Instant::now() + timeout))
+    }
+
+    fn acquire_until(&self, n: usize, deadline: Option<Instant>)
+                     -> Option<CountingSemaphoreGuard> {
+        let mut state = self.state.lock().unwrap();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.queue.push_back(ticket);
         loop {
-            // Probably don't need SeqCst here, but it doesn't hurt.
-            let count = self.count.load(SeqCst);
-            // The check for 0 is necessary to make sure we don't go negative, which is why this
-            // must be a compare-and-swap rather than a straight decrement.
-            if count == 0 || self.count.compare_and_swap(count, count-1, SeqCst) != count {
-                // Linear backoff a la Servo's spinlock contention.
-                thread::sleep_ms(backoff);
-                backoff = backoff + self.backoff;
-            } else {
-                // We successfully acquired the resource.
-                break
+            if state.queue.front() == Some(&ticket) && state.available >= n as isize {
+                state.queue.pop_front();
+                state.available -= n as isize;
+                // Whoever is next in line might be able to go too, and they won't hear about it
+                // from anyone else.
+                self.changed.notify_all();
+                return Some(CountingSemaphoreGuard { sem: self, permits: n });
             }
+            state = match deadline {
+                None => self.changed.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        // Giving up might leave someone else at the front.
+                        let position = state.queue.iter().position(|&t| t == ticket).unwrap();
+                        state.queue.remove(position);
+                        self.changed.notify_all();
+                        return None;
+                    }
+                    self.changed.wait_timeout(state, deadline - now).unwrap().0
+                }
+            };
         }
-        CountingSemaphoreGuard { sem: self }
     }
 
     // Return remaining resource count
     pub fn count(&self) -> usize {
-        self.count.load(SeqCst)
+        let available = self.state.lock().unwrap().available;
+        if available < 0 { 0 } else { available as usize }
+    }
+
+    // The number of resources there are altogether.
+    pub fn capacity(&self) -> usize {
+        self.state.lock().unwrap().capacity
+    }
+
+    // How many threads are waiting for resources.
+    pub fn waiting(&self) -> usize {
+        self.state.lock().unwrap().queue.len()
+    }
+
+    // Change the number of resources there are altogether.  Making it smaller doesn't take any
+    // back from the threads that have them; there just aren't any more to be had until enough
+    // have been released.
+    pub fn resize(&self, capacity: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.available += capacity as isize - state.capacity as isize;
+        state.capacity = capacity;
+        self.changed.notify_all();
+    }
+}
+
+impl<'a> CountingSemaphoreGuard<'a> {
+    pub fn permits(&self) -> usize {
+        self.permits
     }
 }
 
-#[unsafe_destructor]
 impl<'a> Drop for CountingSemaphoreGuard<'a> {
-    // When the guard is dropped, a resource is released back to the pool.
+    // When the guard is dropped, its resources are released back to the pool.
     fn drop(&mut self) {
-        self.sem.count.fetch_add(1, SeqCst);
+        let mut state = self.sem.state.lock().unwrap();
+        state.available += self.permits as isize;
+        self.sem.changed.notify_all();
     }
 }
 
-fn metered(duration: u32) {
+fn metered(duration: Duration) {
     static MAX_COUNT: usize = 4; // Total available resources
     static NUM_WORKERS: u8 = 10; // Number of workers contending for the resources
-    let backoff = 1; // Linear backoff time
     // Create a shared reference to the semaphore
-    let sem = Arc::new(CountingSemaphore::new(MAX_COUNT, backoff));
+    let sem = Arc::new(CountingSemaphore::new(MAX_COUNT));
     // Create a channel for notifying the main task that the workers are done
     let (tx, rx) = channel();
-    for i in (0..NUM_WORKERS) {
+    for i in 0..NUM_WORKERS {
         let sem = sem.clone();
         let tx = tx.clone();
         spawn(move || -> () {
@@ -80,7 +177,7 @@ fn metered(duration: u32) {
             assert!(count < MAX_COUNT);
             println!("Worker {} after acquire: count = {}", i, count);
             // Sleep for `duration`
-            thread::sleep_ms(duration);
+            thread::sleep(duration);
             // Release the resource
             drop(guard);
             // Make sure the count is legal
@@ -93,7 +190,7 @@ fn metered(duration: u32) {
     }
     drop(tx);
     // Wait for all the subtasks to finish
-    for _ in (0..NUM_WORKERS) {
+    for _ in 0..NUM_WORKERS {
         rx.recv().unwrap();
     }
 }
@@ -101,11 +198,142 @@ fn metered(duration: u32) {
 #[test]
 fn test_metered_concurrency() {
     // Hold each resource for 1/20 of a second per worker
-    metered(1000 / 20);
+    metered(Duration::from_millis(1000 / 20));
+}
+
+// Waits for `n` threads to be queued up.
+#[cfg(test)]
+fn wait_for_queue(sem: &CountingSemaphore, n: usize) {
+    while sem.waiting() < n {
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn test_fairness() {
+    let sem = Arc::new(CountingSemaphore::new(2));
+    let order = Arc::new(Mutex::new(vec![]));
+    let everything = sem.acquire_many(2);
+    // they queue up one at a time, wanting different numbers of resources
+    let threads = (0..6).map(|i| {
+        let (waiter, order) = (sem.clone(), order.clone());
+        let thread = spawn(move || {
+            let _guard = waiter.acquire_many(i % 2 + 1);
+            order.lock().unwrap().push(i);
+        });
+        wait_for_queue(&sem, i + 1);
+        thread
+    }).collect::<Vec<_>>();
+
+    // nobody gets to jump the queue
+    assert!(sem.try_acquire().is_none());
+    drop(everything);
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(*order.lock().unwrap(), [0, 1, 2, 3, 4, 5]);
+
+    // someone at the front wanting two holds up someone behind wanting one
+    let one = sem.acquire();
+    let (tx, rx) = channel();
+    let first = {
+        let (sem, tx) = (sem.clone(), tx.clone());
+        spawn(move || { let _guard = sem.acquire_many(2); tx.send(2).unwrap(); })
+    };
+    wait_for_queue(&sem, 1);
+    let second = {
+        let sem = sem.clone();
+        spawn(move || { let _guard = sem.acquire(); tx.send(1).unwrap(); })
+    };
+    wait_for_queue(&sem, 2);
+    assert_eq!(sem.count(), 1);
+    drop(one);
+    assert_eq!(rx.recv().unwrap(), 2);
+    assert_eq!(rx.recv().unwrap(), 1);
+    first.join().unwrap();
+    second.join().unwrap();
+}
+
+#[test]
+fn test_timeouts() {
+    let sem = Arc::new(CountingSemaphore::new(2));
+    let one = sem.try_acquire().unwrap();
+    assert_eq!(one.permits(), 1);
+    assert!(sem.try_acquire_many(2).is_none());
+    let started = Instant::now();
+    assert!(sem.acquire_many_timeout(2, Duration::from_millis(20)).is_none());
+    assert!(started.elapsed() >= Duration::from_millis(20));
+    assert_eq!(sem.waiting(), 0);
+
+    // when the one at the front gives up, the one behind it gets to go
+    let first = {
+        let sem = sem.clone();
+        spawn(move || sem.acquire_many_timeout(2, Duration::from_millis(50)).is_some())
+    };
+    wait_for_queue(&sem, 1);
+    let second = {
+        let sem = sem.clone();
+        spawn(move || sem.acquire_timeout(Duration::from_secs(10)).map(|g| g.permits()))
+    };
+    assert!(!first.join().unwrap());
+    assert_eq!(second.join().unwrap(), Some(1));
+    drop(one);
+    assert_eq!(sem.count(), 2);
+}
+
+#[test]
+fn test_resize() {
+    let sem = Arc::new(CountingSemaphore::new(1));
+    let one = sem.acquire();
+    let waiter = {
+        let sem = sem.clone();
+        spawn(move || sem.acquire_many(2).permits())
+    };
+    wait_for_queue(&sem, 1);
+    // growing it lets the waiter go
+    sem.resize(3);
+    assert_eq!(waiter.join().unwrap(), 2);
+    assert_eq!(sem.count(), 2);
+
+    // shrinking it leaves everyone with what they have
+    sem.resize(0);
+    assert_eq!(sem.capacity(), 0);
+    assert_eq!(sem.count(), 0);
+    drop(one);
+    assert_eq!(sem.count(), 0);
+    sem.resize(1);
+    assert_eq!(sem.count(), 1);
+}
+
+#[test]
+fn test_no_lost_wakeups() {
+    // Lots of threads taking and releasing different numbers of resources: if a wakeup ever
+    // went missing, someone would wait forever.
+    let sem = Arc::new(CountingSemaphore::new(3));
+    let threads = (0..8).map(|i| {
+        let sem = sem.clone();
+        spawn(move || {
+            for j in 0..500 {
+                let n = (i + j) % 3 + 1;
+                if j % 7 == 0 {
+                    let _ = sem.acquire_many_timeout(n, Duration::from_millis(1));
+                } else {
+                    let guard = sem.acquire_many(n);
+                    assert!(sem.count() <= 3 - n);
+                    drop(guard);
+                }
+            }
+        })
+    }).collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(sem.count(), 3);
+    assert_eq!(sem.waiting(), 0);
 }
 
 #[cfg(not(test))]
 fn main() {
     // Hold each resource for 2 seconds per worker
-    metered(2000);
+    metered(Duration::from_secs(2));
 }
@@ -4,20 +4,33 @@
 
 extern crate test;
 
-use std::vec::Vec;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
 use std::thread::spawn;
-use std::sync::mpsc::channel;
+use std::vec::Vec;
+use exact_cover::ExactCover;
+
+mod exact_cover;
 
 #[cfg(test)]
 use test::Bencher;
 
 #[cfg(not(test))]
 fn main() {
-    for num in 0i32..16 {
+    let first = n_queens_solutions(8).next().unwrap();
+    print!("{}", board(&first));
+
+    for num in 0..16 {
         println!("Sequential: {}: {}", num, n_queens(num));
     }
-    for num in 0i32..16 {
-        println!("Parallel: {}: {}", num, semi_parallel_n_queens(num));
+    for num in 0..18 {
+        println!("Parallel: {}: {}", num, parallel_n_queens(num, 8));
+    }
+    for num in 0..12 {
+        println!("Unique: {}: {}", num, n_queens_unique(num));
+    }
+    for num in 0..12 {
+        println!("Exact cover: {}: {}", num, n_queens_exact_cover(num));
     }
 }
 
@@ -29,11 +42,34 @@ fn main() {
 
 */
 
+// The masks have one bit per column, so n can go up to 64.
+const MAX_N: usize = 64;
+
+fn all_ones(n: usize) -> u64 {
+    assert!(n <= MAX_N, "the board can't be wider than {}", MAX_N);
+    if n == MAX_N { !0 } else { (1 << n) - 1 }
+}
+
 // Solves n-queens using a depth-first, backtracking solution.
 // Returns the number of solutions for a given n.
-fn n_queens(n: i32) -> usize {
-    // Pass off to our helper function.
-    return n_queens_helper((1 << n as usize) -1, 0, 0, 0);
+//
+// Every solution has a mirror image with the first queen on the other half
+// of the first row, so we only try the left half and count twice (and the
+// middle column once, when there is one).
+fn n_queens(n: usize) -> usize {
+    let all_ones = all_ones(n);
+    if n == 0 {
+        return 1;
+    }
+
+    let mut solutions = 0;
+    for col in 0..(n + 1) / 2 {
+        let spot = 1 << col;
+        let mirrored = if n % 2 == 1 && col == n / 2 { 1 } else { 2 };
+        // Pass off to our helper function.
+        solutions += mirrored * n_queens_helper(all_ones, spot << 1, spot, spot >> 1);
+    }
+    solutions
 }
 
 // The meat of the algorithm is in here, a recursive helper function
@@ -55,7 +91,7 @@ fn n_queens(n: i32) -> usize {
 //
 // This implementation is optimized for speed and memory by using
 // integers and bit shifting instead of arrays for storing the conflicts.
-fn n_queens_helper(all_ones: i32, left_diags: i32, columns: i32, right_diags: i32) -> usize {
+fn n_queens_helper(all_ones: u64, left_diags: u64, columns: u64, right_diags: u64) -> usize {
     // all_ones is a special value that simply has all 1s in the first n positions
     // and 0s elsewhere. We can use it to clear out areas that we don't care about.
 
@@ -76,8 +112,8 @@ fn n_queens_helper(all_ones: i32, left_diags: i32, columns: i32, right_diags: i3
         // This is just bit trickery. For reasons involving the weird
         // behavior of two's complement integers, this creates an integer
         // which is all 0s except for a single 1 in the position of the
-        // LSB of valid_spots.
-        let spot = -valid_spots & valid_spots;
+        // LSB of valid_spots. (!x + 1 is -x for unsigned integers.)
+        let spot = lowest_bit(valid_spots);
 
         // We then XOR that integer with the validSpots to flip it to 0
         // in valid_spots.
@@ -106,32 +142,230 @@ fn n_queens_helper(all_ones: i32, left_diags: i32, columns: i32, right_diags: i3
     solutions + ((columns == all_ones) as usize)
 }
 
-// This is the same as the regular nQueens except it creates
-// n threads in which to to do the work.
+fn lowest_bit(x: u64) -> u64 {
+    x & (!x + 1)
+}
+
+// A part of the search for the parallel version: the conflicts once queens
+// are on the first two rows, and how many solutions each solution found
+// from there stands for.
+struct Task {
+    left_diags: u64,
+    columns: u64,
+    right_diags: u64,
+    weight: usize
+}
+
+// The tasks for every way to put queens on the first two rows, with the
+// first queen on the left half of the board as in `n_queens`.
+fn tasks(n: usize) -> Vec<Task> {
+    let all_ones = all_ones(n);
+    let mut tasks = Vec::new();
+    for col in 0..(n + 1) / 2 {
+        let spot = 1 << col;
+        let first = Task {
+            left_diags: spot << 1,
+            columns: spot,
+            right_diags: spot >> 1,
+            weight: if n % 2 == 1 && col == n / 2 { 1 } else { 2 }
+        };
+        if n == 1 {
+            tasks.push(first);
+            continue;
+        }
+
+        let mut valid_spots = !(first.left_diags | first.columns | first.right_diags) & all_ones;
+        while valid_spots != 0 {
+            let spot = lowest_bit(valid_spots);
+            valid_spots = valid_spots ^ spot;
+            tasks.push(Task {
+                left_diags: (first.left_diags | spot) << 1,
+                columns: first.columns | spot,
+                right_diags: (first.right_diags | spot) >> 1,
+                weight: first.weight
+            });
+        }
+    }
+    tasks
+}
+
+// Takes the next task from a worker's own queue, or steals one from the
+// other end of someone else's once its own runs dry.
+fn next_task(queues: &[Mutex<VecDeque<Task>>], me: usize) -> Option<Task> {
+    if let Some(task) = queues[me].lock().unwrap().pop_back() {
+        return Some(task);
+    }
+    for i in 1..queues.len() {
+        let victim = (me + i) % queues.len();
+        if let Some(task) = queues[victim].lock().unwrap().pop_front() {
+            return Some(task);
+        }
+    }
+    None
+}
+
+// This is the same as the regular n_queens except it splits the work into
+// the (about n^2 / 2) ways to start on the first two rows, and shares them
+// out between `threads` threads. The tasks take very different times, so a
+// thread that's done with its own steals from the others rather than
+// sitting idle.
 //
 // This is much slower for smaller numbers (under 16~17) but outperforms
 // the sequential algorithm after that.
-fn semi_parallel_n_queens(n: i32) -> usize {
-    let all_ones = (1 << n as usize) - 1;
-    let (columns, left_diags, right_diags) = (0, 0, 0);
+fn parallel_n_queens(n: usize, threads: usize) -> usize {
+    let all_ones = all_ones(n);
+    if n == 0 {
+        return 1;
+    }
 
-    let mut receivers = Vec::new();
-    let mut valid_spots = !(left_diags | columns | right_diags) & all_ones;
-    while valid_spots != 0 {
-        let (tx, rx) = channel();
-        let spot = -valid_spots & valid_spots;
-        valid_spots = valid_spots ^ spot;
-        receivers.push(rx);
+    let threads = if threads == 0 { 1 } else { threads };
+    let mut queues = (0..threads).map(|_| VecDeque::new()).collect::<Vec<_>>();
+    for (i, task) in tasks(n).into_iter().enumerate() {
+        queues[i % threads].push_back(task);
+    }
+    let queues = Arc::new(queues.into_iter().map(Mutex::new).collect::<Vec<_>>());
+
+    let workers = (0..threads).map(|me| {
+        let queues = queues.clone();
+        spawn(move || {
+            let mut solutions = 0;
+            while let Some(task) = next_task(&queues, me) {
+                solutions += task.weight * n_queens_helper(all_ones,
+                                                           task.left_diags,
+                                                           task.columns,
+                                                           task.right_diags);
+            }
+            solutions
+        })
+    }).collect::<Vec<_>>();
+
+    workers.into_iter().map(|w| w.join().unwrap()).sum::<usize>()
+}
+
+// The same search, but keeping its own stack so that it can stop at each
+// solution and hand it out. A solution is the column of the queen on each
+// row.
+struct Solutions {
+    all_ones: u64,
+    // for each row being tried: the spots left to try on it, and the
+    // conflicts from the rows above
+    stack: Vec<(u64, u64, u64, u64)>,
+    queens: Vec<usize>,
+    // the 0x0 board has one (empty) solution
+    empty_board: bool
+}
 
-        spawn( move || -> () {
-            tx.send(n_queens_helper(all_ones,
-                                    (left_diags | spot) << 1,
-                                    (columns | spot),
-                                    (right_diags | spot) >> 1)).unwrap();
-        });
+fn n_queens_solutions(n: usize) -> Solutions {
+    let all_ones = all_ones(n);
+    Solutions {
+        all_ones: all_ones,
+        stack: vec![(all_ones, 0, 0, 0)],
+        queens: Vec::with_capacity(n),
+        empty_board: n == 0
     }
+}
+
+impl Iterator for Solutions {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        if self.empty_board {
+            self.empty_board = false;
+            return Some(vec![]);
+        }
+
+        loop {
+            let (spot, left_diags, columns, right_diags) = match self.stack.last_mut() {
+                None => return None,
+                Some(&mut (ref mut valid_spots, left_diags, columns, right_diags)) => {
+                    if *valid_spots == 0 {
+                        (0, 0, 0, 0)
+                    } else {
+                        let spot = lowest_bit(*valid_spots);
+                        *valid_spots = *valid_spots ^ spot;
+                        (spot, left_diags, columns, right_diags)
+                    }
+                }
+            };
+
+            // Every spot on this row has been tried: back up a row.
+            if spot == 0 {
+                self.stack.pop();
+                self.queens.pop();
+                continue;
+            }
+
+            self.queens.push(spot.trailing_zeros() as usize);
+            let (left_diags, columns, right_diags) =
+                ((left_diags | spot) << 1, columns | spot, (right_diags | spot) >> 1);
+            if columns == self.all_ones {
+                let solution = self.queens.clone();
+                self.queens.pop();
+                return Some(solution);
+            }
+            let valid_spots = !(left_diags | columns | right_diags) & self.all_ones;
+            self.stack.push((valid_spots, left_diags, columns, right_diags));
+        }
+    }
+}
+
+// The eight ways to turn and flip a solution over.
+fn symmetries(queens: &[usize]) -> Vec<Vec<usize>> {
+    let n = queens.len();
+    let mut images = vec![vec![0; n]; 8];
+    for (row, &col) in queens.iter().enumerate() {
+        let (r, c) = (n - 1 - row, n - 1 - col);
+        images[0][row] = col;
+        images[1][col] = r;
+        images[2][r] = c;
+        images[3][c] = row;
+        images[4][row] = c;
+        images[5][r] = col;
+        images[6][col] = row;
+        images[7][c] = r;
+    }
+    images
+}
 
-    receivers.iter().map(|r| r.recv().unwrap()).sum::<usize>() + ((columns == all_ones) as usize)
+// Counts the solutions which are different from each other even when the
+// board is turned or flipped over, by keeping only the smallest of each
+// family. This goes through every solution, so it's much slower than the
+// plain count.
+fn n_queens_unique(n: usize) -> usize {
+    n_queens_solutions(n)
+        .filter(|queens| symmetries(queens).iter().all(|image| queens <= image))
+        .count()
+}
+
+// Draws a solution, one row to a line.
+fn board(queens: &[usize]) -> String {
+    let mut board = String::new();
+    for &col in queens.iter() {
+        for c in 0..queens.len() {
+            board.push(if c == col { 'Q' } else { '.' });
+        }
+        board.push('\n');
+    }
+    board
+}
+
+// N-queens is also an exact cover problem: every row and every column
+// needs exactly one queen, and every diagonal can have at most one, so the
+// diagonals are secondary columns. The rows of the matrix are the squares.
+//
+// This is slower than the bit twiddling above, but it's a nice test for the
+// exact cover solver.
+fn n_queens_exact_cover(n: usize) -> usize {
+    let diagonals = if n == 0 { 0 } else { 2 * n - 1 };
+    let mut dlx = ExactCover::new(2 * n, 2 * diagonals);
+    for row in 0..n {
+        for col in 0..n {
+            dlx.add_row(&[row, n + col,
+                          2 * n + row + col,
+                          2 * n + diagonals + row + n - 1 - col]);
+        }
+    }
+    dlx.count()
 }
 
 // Tests
@@ -139,16 +373,61 @@ fn semi_parallel_n_queens(n: i32) -> usize {
 #[test]
 fn test_n_queens() {
     let real = vec!(1, 1, 0, 0, 2, 10, 4, 40, 92);
-    for num in (0..9i32) {
-        assert_eq!(n_queens(num), real[num as usize]);
+    for num in (0..9) {
+        assert_eq!(n_queens(num), real[num]);
     }
 }
 
 #[test]
 fn test_parallel_n_queens() {
     let real = vec!(1, 1, 0, 0, 2, 10, 4, 40, 92);
-    for num in (0..9i32) {
-        assert_eq!(semi_parallel_n_queens(num), real[num as usize]);
+    for num in (0..9) {
+        assert_eq!(parallel_n_queens(num, 3), real[num]);
+    }
+    assert_eq!(n_queens(12), 14200);
+    assert_eq!(parallel_n_queens(12, 4), 14200);
+}
+
+#[test]
+fn test_n_queens_solutions() {
+    let real = vec!(1, 1, 0, 0, 2, 10, 4, 40, 92);
+    for num in (0..9) {
+        let solutions = n_queens_solutions(num).collect::<Vec<_>>();
+        assert_eq!(solutions.len(), real[num]);
+        for queens in solutions.iter() {
+            for (r1, &c1) in queens.iter().enumerate() {
+                for (r2, &c2) in queens.iter().enumerate().skip(r1 + 1) {
+                    assert!(c1 != c2 && r2 - r1 != (c1 as isize - c2 as isize).abs() as usize);
+                }
+            }
+        }
+    }
+    assert_eq!(board(&n_queens_solutions(4).next().unwrap()), ".Q..\n...Q\nQ...\n..Q.\n");
+}
+
+#[test]
+fn test_wide_boards() {
+    assert_eq!(all_ones(8), 0xff);
+    assert_eq!(all_ones(40), 0xff_ffff_ffff);
+    assert_eq!(all_ones(64), !0);
+    assert_eq!(n_queens_solutions(64).all_ones, !0);
+    assert_eq!(lowest_bit(1 << 63), 1 << 63);
+    assert_eq!(lowest_bit(0b1011000), 0b1000);
+}
+
+#[test]
+fn test_n_queens_unique() {
+    let real = vec!(1, 1, 0, 0, 1, 2, 1, 6, 12, 46, 92);
+    for num in (0..11) {
+        assert_eq!(n_queens_unique(num), real[num]);
+    }
+}
+
+#[test]
+fn test_n_queens_exact_cover() {
+    let real = vec!(1, 1, 0, 0, 2, 10, 4, 40, 92);
+    for num in (0..9) {
+        assert_eq!(n_queens_exact_cover(num), real[num]);
     }
 }
 
@@ -158,6 +437,6 @@ fn bench_n_queens(b: &mut Bencher) {
 }
 
 #[bench]
-fn bench_semi_parallel_n_queens(b: &mut Bencher) {
-    b.iter(|| { test::black_box(semi_parallel_n_queens(16)); });
+fn bench_parallel_n_queens(b: &mut Bencher) {
+    b.iter(|| { test::black_box(parallel_n_queens(16, 8)); });
 }
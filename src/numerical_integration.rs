@@ -0,0 +1,266 @@
+// http://rosettacode.org/wiki/Numerical_integration
+// http://rosettacode.org/wiki/Numerical_integration/Gauss-Legendre_Quadrature
+//
+// Ways of working out the integral of a function over [a, b] from its
+// values at some points. Also used as a module by active_object.rs.
+use std::f64::consts::PI;
+use std::ops::{Add, Mul};
+
+// The trapezoid rule, over n equal intervals.
+pub fn trapezoid<F: Fn(f64) -> f64>(f: F, a: f64, b: f64, n: usize) -> f64 {
+    assert!(n > 0, "the trapezoid rule needs at least one interval");
+    let h = (b - a) / n as f64;
+    let inside = (1..n).fold(0., |sum, i| sum + f(a + i as f64 * h));
+    h * ((f(a) + f(b)) / 2. + inside)
+}
+
+// Simpson's rule, over n equal intervals (one more if n is odd, since they
+// go in pairs).
+pub fn simpson<F: Fn(f64) -> f64>(f: F, a: f64, b: f64, n: usize) -> f64 {
+    assert!(n > 0, "Simpson's rule needs at least one interval");
+    let n = n + n % 2;
+    let h = (b - a) / n as f64;
+    let inside = (1..n).fold(0., |sum, i| {
+        sum + if i % 2 == 1 { 4. } else { 2. } * f(a + i as f64 * h)
+    });
+    h / 3. * (f(a) + f(b) + inside)
+}
+
+// Simpson's rule on halves of the interval, then halves of those, and so on
+// wherever the halves don't yet agree with the whole to within `tolerance`
+// (but no more than `max_depth` times).
+pub fn adaptive_simpson<F: Fn(f64) -> f64>(f: F, a: f64, b: f64, tolerance: f64,
+                                           max_depth: usize) -> f64 {
+    let (fa, fm, fb) = (f(a), f((a + b) / 2.), f(b));
+    let whole = (b - a) / 6. * (fa + 4. * fm + fb);
+    adaptive_step(&f, a, b, fa, fm, fb, whole, tolerance, max_depth)
+}
+
+fn adaptive_step<F: Fn(f64) -> f64>(f: &F, a: f64, b: f64, fa: f64, fm: f64, fb: f64,
+                                    whole: f64, tolerance: f64, depth: usize) -> f64 {
+    let m = (a + b) / 2.;
+    let (lm, rm) = (f((a + m) / 2.), f((m + b) / 2.));
+    let left = (m - a) / 6. * (fa + 4. * lm + fm);
+    let right = (b - m) / 6. * (fm + 4. * rm + fb);
+    let error = left + right - whole;
+    if depth == 0 || error.abs() <= 15. * tolerance {
+        // Richardson extrapolation: the error of Simpson's rule goes down
+        // sixteen times each time the intervals are halved
+        left + right + error / 15.
+    } else {
+        adaptive_step(f, a, m, fa, lm, fm, left, tolerance / 2., depth - 1) +
+            adaptive_step(f, m, b, fm, rm, fb, right, tolerance / 2., depth - 1)
+    }
+}
+
+// Romberg's method: the trapezoid rule with 1, 2, 4, ... intervals, each
+// estimate extrapolated from the ones before, until two in a row agree to
+// within `tolerance` (or after `max_steps` halvings).
+pub fn romberg<F: Fn(f64) -> f64>(f: F, a: f64, b: f64, tolerance: f64, max_steps: usize) -> f64 {
+    let mut h = b - a;
+    let mut previous = vec![h * (f(a) + f(b)) / 2.];
+    for step in 1..max_steps + 1 {
+        h /= 2.;
+        let points = 1 << (step - 1);
+        let new = (0..points).fold(0., |sum, i| sum + f(a + (2 * i + 1) as f64 * h));
+        let mut row = vec![previous[0] / 2. + h * new];
+        let mut factor = 1.;
+        for j in 1..step + 1 {
+            factor *= 4.;
+            let better = row[j - 1] + (row[j - 1] - previous[j - 1]) / (factor - 1.);
+            row.push(better);
+        }
+        if (row[step] - previous[step - 1]).abs() < tolerance {
+            return row[step];
+        }
+        previous = row;
+    }
+    previous[max_steps]
+}
+
+const MAX_NEWTON_STEPS: usize = 100;
+
+// A rule for one interval: where in it to look at the function, from 0 at
+// the start to 1 at the end, and how much each value counts.
+pub struct Rule {
+    pub points: Vec<f64>,
+    pub weights: Vec<f64>,
+}
+
+impl Rule {
+    pub fn trapezoid() -> Rule {
+        Rule { points: vec![0., 1.], weights: vec![0.5, 0.5] }
+    }
+
+    pub fn simpson() -> Rule {
+        Rule { points: vec![0., 0.5, 1.], weights: vec![1. / 6., 4. / 6., 1. / 6.] }
+    }
+
+    // Gauss-Legendre quadrature with n points: the roots of the nth
+    // Legendre polynomial, found by Newton's method. That's exact for
+    // polynomials up to degree 2n - 1. Newton's method gets there in a handful
+    // of steps from these first guesses; the limit is only so that it can't go
+    // on forever if something goes wrong (a NaN, say).
+    pub fn gauss_legendre(n: usize) -> Rule {
+        assert!(n > 0, "Gauss-Legendre quadrature needs at least one point");
+        let mut points = vec![];
+        let mut weights = vec![];
+        for i in 0..n {
+            // a good first guess at the ith root, counting down from 1
+            let mut x = (PI * (i as f64 + 0.75) / (n as f64 + 0.5)).cos();
+            let mut derivative = 0.;
+            for _ in 0..MAX_NEWTON_STEPS {
+                let (p, dp) = legendre(n, x);
+                derivative = dp;
+                let dx = p / dp;
+                x -= dx;
+                if dx.abs() < 1e-15 {
+                    break;
+                }
+            }
+            // on [-1, 1], moved to [0, 1]
+            points.push((1. - x) / 2.);
+            weights.push(1. / ((1. - x * x) * derivative * derivative));
+        }
+        Rule { points: points, weights: weights }
+    }
+
+    // The integral of f over [a, b]. The values can be anything that can be
+    // scaled by an f64 and added up.
+    pub fn apply<S, T, F>(&self, f: F, a: f64, b: f64) -> T
+        where F: Fn(f64) -> S, S: Mul<f64, Output=T>, T: Add<Output=T>
+    {
+        let h = b - a;
+        let mut terms = self.points.iter().zip(self.weights.iter())
+            .map(|(&x, &w)| f(a + x * h) * (w * h));
+        let first = terms.next().expect("a rule needs at least one point");
+        terms.fold(first, |sum, term| sum + term)
+    }
+}
+
+// P_n(x) and its derivative, from (k + 1) P_(k+1) = (2k + 1) x P_k - k P_(k-1)
+fn legendre(n: usize, x: f64) -> (f64, f64) {
+    let (mut p0, mut p1) = (1., x);
+    for k in 1..n {
+        let p2 = ((2 * k + 1) as f64 * x * p1 - k as f64 * p0) / (k + 1) as f64;
+        p0 = p1;
+        p1 = p2;
+    }
+    if n == 0 {
+        return (1., 0.);
+    }
+    (p1, n as f64 * (x * p1 - p0) / (x * x - 1.))
+}
+
+pub fn gauss_legendre<F: Fn(f64) -> f64>(f: F, a: f64, b: f64, n: usize) -> f64 {
+    Rule::gauss_legendre(n).apply(f, a, b)
+}
+
+#[allow(dead_code)]
+#[cfg(not(test))]
+fn main() {
+    let functions: [(&str, fn(f64) -> f64, f64, f64, usize); 4] = [
+        ("x^3", cube, 0., 1., 100),
+        ("1/x", reciprocal, 1., 100., 1000),
+        ("x", identity, 0., 5000., 5000000),
+        ("x", identity, 0., 6000., 6000000),
+    ];
+    for &(name, f, a, b, n) in functions.iter() {
+        println!("{} over [{}, {}] with {} intervals:", name, a, b, n);
+        println!("    trapezoid:        {}", trapezoid(f, a, b, n));
+        println!("    Simpson:          {}", simpson(f, a, b, n));
+        println!("    adaptive Simpson: {}", adaptive_simpson(f, a, b, 1e-10, 50));
+        println!("    Romberg:          {}", romberg(f, a, b, 1e-10, 20));
+        println!("    Gauss-Legendre:   {}", gauss_legendre(f, a, b, 10));
+    }
+
+    println!("e^x over [-3, 3] with 5-point Gauss-Legendre: {:.8}",
+             gauss_legendre(|x: f64| x.exp(), -3., 3., 5));
+    println!("exactly: {:.8}", 3f64.exp() - (-3f64).exp());
+}
+
+#[cfg(not(test))]
+fn cube(x: f64) -> f64 { x * x * x }
+
+#[cfg(not(test))]
+fn reciprocal(x: f64) -> f64 { 1. / x }
+
+#[cfg(not(test))]
+fn identity(x: f64) -> f64 { x }
+
+#[cfg(test)]
+fn close(a: f64, b: f64, tolerance: f64) -> bool {
+    (a - b).abs() <= tolerance * b.abs().max(1.)
+}
+
+#[test]
+fn fixed_rules() {
+    let cube = |x: f64| x * x * x;
+    assert!(close(trapezoid(&cube, 0., 1., 100), 0.25, 1e-4));
+    assert!(close(simpson(&cube, 0., 1., 100), 0.25, 1e-12));
+    // an odd number of intervals is rounded up
+    assert!(close(simpson(&cube, 0., 1., 3), 0.25, 1e-12));
+    assert!(close(trapezoid(|x| x, 0., 5000., 5000), 12500000., 1e-12));
+    assert!(close(simpson(|x: f64| 1. / x, 1., 100., 1000), 100f64.ln(), 1e-6));
+}
+
+#[test]
+#[should_panic]
+fn no_intervals() {
+    trapezoid(|x| x, 0., 1., 0);
+}
+
+#[test]
+#[should_panic]
+fn no_simpson_intervals() {
+    simpson(|x| x, 0., 1., 0);
+}
+
+#[test]
+fn adaptive_rules() {
+    let sin = |x: f64| x.sin();
+    assert!(close(adaptive_simpson(&sin, 0., PI, 1e-10, 50), 2., 1e-9));
+    assert!(close(romberg(&sin, 0., PI, 1e-10, 20), 2., 1e-9));
+    assert!(close(adaptive_simpson(|x: f64| 1. / x, 1., 100., 1e-10, 50), 100f64.ln(), 1e-9));
+    assert!(close(romberg(|x: f64| 1. / x, 1., 100., 1e-10, 30), 100f64.ln(), 1e-9));
+    // a steep bump that a coarse grid would miss
+    let bump = |x: f64| (-1000. * (x - 0.3) * (x - 0.3)).exp();
+    assert!(close(adaptive_simpson(&bump, 0., 1., 1e-12, 50), (PI / 1000.).sqrt(), 1e-8));
+}
+
+#[test]
+fn gauss_legendre_rules() {
+    // the roots and weights for 5 points, on [-1, 1]
+    let rule = Rule::gauss_legendre(5);
+    let roots = [0.906179845938664, 0.538469310105683, 0., -0.538469310105683, -0.906179845938664];
+    let weights = [0.236926885056189, 0.478628670499366, 0.568888888888889,
+                   0.478628670499366, 0.236926885056189];
+    for i in 0..5 {
+        assert!(close(1. - 2. * rule.points[i], roots[i], 1e-12));
+        assert!(close(2. * rule.weights[i], weights[i], 1e-12));
+    }
+
+    assert!(close(gauss_legendre(|x: f64| x.exp(), -3., 3., 5), 20.0355777184, 1e-10));
+    // exact for polynomials of degree 2n - 1
+    let p = |x: f64| 7. * x.powi(5) - 3. * x * x + 1.;
+    assert!(close(gauss_legendre(&p, -1., 2., 3), 7. * 63. / 6. - 9. + 3., 1e-12));
+    assert!(close(gauss_legendre(|x: f64| x.sin(), 0., PI, 20), 2., 1e-14));
+}
+
+#[test]
+fn rules_on_one_interval() {
+    // other kinds of values: here pairs, as complex numbers would be
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    struct Pair(f64, f64);
+    impl Mul<f64> for Pair {
+        type Output = Pair;
+        fn mul(self, k: f64) -> Pair { Pair(self.0 * k, self.1 * k) }
+    }
+    impl Add for Pair {
+        type Output = Pair;
+        fn add(self, other: Pair) -> Pair { Pair(self.0 + other.0, self.1 + other.1) }
+    }
+    let Pair(x, y) = Rule::simpson().apply(|t| Pair(t, t * t), 0., 3.);
+    assert!(close(x, 4.5, 1e-12) && close(y, 9., 1e-12));
+    assert_eq!(Rule::trapezoid().apply(|t| t, 1., 3.), 4.);
+}
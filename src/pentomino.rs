@@ -0,0 +1,202 @@
+// http://rosettacode.org/wiki/Pentomino_tiling
+//
+// Tiling a board with the twelve pentominoes is an exact cover problem:
+// every piece is used once, and every square of the board is covered once.
+// The rows of the matrix are all the ways to put each piece on the board.
+extern crate rand;
+
+#[cfg(not(test))]
+use rand::Rng;
+use exact_cover::ExactCover;
+
+mod exact_cover;
+
+const PIECES: [(char, &'static str); 12] = [
+    ('F', ".##|##.|.#."),
+    ('I', "#####"),
+    ('L', "####|#..."),
+    ('N', "##..|.###"),
+    ('P', "##|##|#."),
+    ('T', "###|.#.|.#."),
+    ('U', "#.#|###"),
+    ('V', "#..|#..|###"),
+    ('W', "#..|##.|.##"),
+    ('X', ".#.|###|.#."),
+    ('Y', "####|.#.."),
+    ('Z', "##.|.#.|.##")
+];
+
+const HOLE: char = '-';
+
+// The squares of a piece, from a picture with rows separated by '|'.
+fn squares(picture: &str) -> Vec<(i32, i32)> {
+    let mut squares = vec![];
+    for (y, line) in picture.split('|').enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            if c == '#' {
+                squares.push((x as i32, y as i32));
+            }
+        }
+    }
+    squares
+}
+
+// The different shapes a piece takes when it's turned and flipped over,
+// each moved to touch both axes and sorted.
+fn orientations(squares: &[(i32, i32)]) -> Vec<Vec<(i32, i32)>> {
+    let mut shapes: Vec<Vec<(i32, i32)>> = vec![];
+    for flip in 0..2 {
+        for turn in 0..4 {
+            let mut shape = squares.iter().map(|&(x, y)| {
+                let (mut x, mut y) = if flip == 1 { (-x, y) } else { (x, y) };
+                for _ in 0..turn {
+                    let (x2, y2) = (-y, x);
+                    x = x2;
+                    y = y2;
+                }
+                (x, y)
+            }).collect::<Vec<_>>();
+            let min_x = shape.iter().map(|&(x, _)| x).min().unwrap();
+            let min_y = shape.iter().map(|&(_, y)| y).min().unwrap();
+            for s in shape.iter_mut() {
+                *s = (s.0 - min_x, s.1 - min_y);
+            }
+            shape.sort();
+            if !shapes.contains(&shape) {
+                shapes.push(shape);
+            }
+        }
+    }
+    shapes
+}
+
+// Up to `limit` ways to tile a width x height board, apart from the holes,
+// with the twelve pieces. Each tiling is the board as rows of the letters of
+// the pieces.
+fn tilings(width: usize, height: usize, holes: &[(usize, usize)], limit: usize)
+           -> Vec<Vec<String>> {
+    let (mut dlx, placements) = matrix(width, height, holes);
+    dlx.solutions(limit).into_iter().map(|rows| {
+        let mut board = vec![vec![HOLE; width]; height];
+        for r in rows {
+            let (piece, ref squares) = placements[r];
+            for &(x, y) in squares.iter() {
+                board[y][x] = PIECES[piece].0;
+            }
+        }
+        board.into_iter().map(|row| row.into_iter().collect()).collect()
+    }).collect()
+}
+
+fn count_tilings(width: usize, height: usize, holes: &[(usize, usize)]) -> usize {
+    matrix(width, height, holes).0.count()
+}
+
+// The columns are the pieces and then the squares which aren't holes. Each
+// row goes with a piece and the squares it covers. Unless the pieces add up
+// to the free squares there are no rows, rather than a long fruitless search.
+fn matrix(width: usize, height: usize, holes: &[(usize, usize)])
+          -> (ExactCover, Vec<(usize, Vec<(usize, usize)>)>) {
+    let mut column = vec![vec![None; width]; height];
+    let mut free = 0;
+    for y in 0..height {
+        for x in 0..width {
+            if !holes.contains(&(x, y)) {
+                column[y][x] = Some(PIECES.len() + free);
+                free += 1;
+            }
+        }
+    }
+
+    let mut dlx = ExactCover::new(PIECES.len() + free, 0);
+    let mut placements = vec![];
+    if free != 5 * PIECES.len() {
+        return (dlx, placements);
+    }
+    for (piece, &(_, picture)) in PIECES.iter().enumerate() {
+        for shape in orientations(&squares(picture)) {
+            for y0 in 0..height {
+                for x0 in 0..width {
+                    let placed = shape.iter()
+                        .map(|&(x, y)| (x0 + x as usize, y0 + y as usize))
+                        .filter(|&(x, y)| x < width && y < height)
+                        .collect::<Vec<_>>();
+                    let mut columns = vec![piece];
+                    columns.extend(placed.iter().filter_map(|&(x, y)| column[y][x]));
+                    if columns.len() == shape.len() + 1 {
+                        dlx.add_row(&columns);
+                        placements.push((piece, placed));
+                    }
+                }
+            }
+        }
+    }
+    (dlx, placements)
+}
+
+#[cfg(not(test))]
+fn main() {
+    // An 8x8 board with four random holes. Not every choice of holes can be
+    // tiled, so keep trying until one can.
+    let mut rng = rand::thread_rng();
+    loop {
+        let mut holes = vec![];
+        while holes.len() < 4 {
+            let hole = (rng.gen_range(0, 8), rng.gen_range(0, 8));
+            if !holes.contains(&hole) {
+                holes.push(hole);
+            }
+        }
+        if let Some(tiling) = tilings(8, 8, &holes, 1).pop() {
+            for row in tiling.iter() {
+                println!("{}", row);
+            }
+            break;
+        }
+    }
+
+    // No tiling of a rectangle is symmetric, so each comes in four copies
+    // turned and flipped around.
+    for &(width, height) in [(20, 3), (15, 4), (12, 5), (10, 6)].iter() {
+        println!("{}x{}: {} tilings", width, height, count_tilings(width, height, &[]) / 4);
+    }
+}
+
+#[test]
+fn orientations_of_pieces() {
+    let counts = PIECES.iter().map(|&(_, p)| orientations(&squares(p)).len())
+        .collect::<Vec<_>>();
+    // F I L N P T U V W X Y Z
+    assert_eq!(counts, [8, 2, 8, 8, 8, 4, 4, 4, 4, 1, 8, 4]);
+}
+
+#[test]
+fn narrow_rectangle() {
+    // two tilings, each turned and flipped four ways
+    let all = tilings(20, 3, &[], 100);
+    assert_eq!(all.len(), 8);
+    for tiling in all.iter() {
+        assert_eq!(tiling.len(), 3);
+        for &(name, _) in PIECES.iter() {
+            assert_eq!(tiling.iter().flat_map(|row| row.chars()).filter(|&c| c == name).count(),
+                       5);
+        }
+    }
+    // the same board upside down
+    let flipped = all[0].iter().rev().cloned().collect::<Vec<_>>();
+    assert!(all.contains(&flipped));
+}
+
+#[test]
+fn holes() {
+    let centre = [(3, 3), (4, 3), (3, 4), (4, 4)];
+    let tiling = tilings(8, 8, &centre, 1).pop().unwrap();
+    assert_eq!(&tiling[3][3..5], "--");
+    assert_eq!(&tiling[4][3..5], "--");
+
+    assert_eq!(tilings(8, 8, &[(0, 0), (7, 0), (0, 7), (7, 7)], 1).len(), 1);
+    // the top left square is cut off
+    assert_eq!(count_tilings(8, 8, &[(1, 0), (0, 1), (6, 7), (7, 6)]), 0);
+    // four squares too many
+    assert_eq!(count_tilings(8, 8, &[]), 0);
+}
@@ -0,0 +1,463 @@
+// Software transactional memory: shared variables (`TVar`s) that are only
+// ever read and written inside a transaction, with everything a transaction
+// does happening all at once or not at all. Used by atomic_updates.rs.
+//
+// A transaction keeps a log instead of touching anything: what it read, and
+// the version each variable was at, and what it means to write. When it's
+// done it locks the variables it wrote, checks that nothing it read has
+// changed since, and only then writes them. If something has changed, the
+// whole transaction runs again from the start, so it had better not do
+// anything but read and write TVars.
+//
+// `retry` gives up on a transaction until one of the variables it read
+// changes (a queue that's empty, say), and `or_else` tries another
+// transaction when the first one retries, as in Haskell's STM.
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+struct Cell<T> {
+    value: T,
+    // goes up by one every time the value is written
+    version: usize,
+}
+
+// Something blocked in a retry, waiting for a variable it read to change.
+struct Wakeup {
+    changed: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Wakeup {
+    fn wake(&self) {
+        *self.changed.lock().unwrap() = true;
+        self.condvar.notify_one();
+    }
+}
+
+struct Var<T> {
+    cell: Mutex<Cell<T>>,
+    waiting: Mutex<Vec<Arc<Wakeup>>>,
+}
+
+pub struct TVar<T> {
+    var: Arc<Var<T>>,
+}
+
+impl<T> Clone for TVar<T> {
+    fn clone(&self) -> TVar<T> {
+        TVar { var: self.var.clone() }
+    }
+}
+
+impl<T: Any + Send + Clone> TVar<T> {
+    pub fn new(value: T) -> TVar<T> {
+        TVar {
+            var: Arc::new(Var {
+                cell: Mutex::new(Cell { value: value, version: 0 }),
+                waiting: Mutex::new(vec![]),
+            }),
+        }
+    }
+
+    // The value right now, as a transaction of its own.
+    pub fn read_atomic(&self) -> T {
+        self.var.cell.lock().unwrap().value.clone()
+    }
+
+    // The variables are told apart, and locked in order, by where they are.
+    fn id(&self) -> usize {
+        &*self.var as *const Var<T> as usize
+    }
+}
+
+// A transaction's log holds variables of all sorts of types, so it sees them
+// through these.
+trait Versioned: Send + Sync {
+    fn version(&self) -> usize;
+    // None if someone else has it locked.
+    fn try_version(&self) -> Option<usize>;
+    fn lock<'a>(&'a self) -> Box<Locked + 'a>;
+    fn wait_on(&self, wakeup: Arc<Wakeup>);
+    fn wake(&self);
+}
+
+trait Locked {
+    fn version(&self) -> usize;
+    fn store(&mut self, value: Box<Any + Send>);
+}
+
+impl<'a, T: Any> Locked for MutexGuard<'a, Cell<T>> {
+    fn version(&self) -> usize {
+        (**self).version
+    }
+
+    fn store(&mut self, value: Box<Any + Send>) {
+        let value: Box<Any> = value;
+        (**self).value = *value.downcast::<T>().ok().expect("Wrote the wrong type to a TVar");
+        (**self).version += 1;
+    }
+}
+
+impl<T: Any + Send> Versioned for Var<T> {
+    fn version(&self) -> usize {
+        self.cell.lock().unwrap().version
+    }
+
+    fn try_version(&self) -> Option<usize> {
+        self.cell.try_lock().ok().map(|cell| cell.version)
+    }
+
+    fn lock<'a>(&'a self) -> Box<Locked + 'a> {
+        Box::new(self.cell.lock().unwrap())
+    }
+
+    fn wait_on(&self, wakeup: Arc<Wakeup>) {
+        let mut waiting = self.waiting.lock().unwrap();
+        // anything already woken up by some other variable is done waiting
+        waiting.retain(|w| !*w.changed.lock().unwrap());
+        waiting.push(wakeup);
+    }
+
+    fn wake(&self) {
+        for wakeup in self.waiting.lock().unwrap().drain(..) {
+            wakeup.wake();
+        }
+    }
+}
+
+enum Why {
+    Retry,
+    Conflict,
+}
+
+// Why a transaction stopped short. Either it called `retry`, or it read
+// something that had changed under it, and either way it runs again.
+pub struct Abort(Why);
+
+pub type Stm<T> = Result<T, Abort>;
+
+// Gives up on the transaction until one of the variables it has read
+// changes. A transaction that hasn't read anything waits forever.
+pub fn retry<T>() -> Stm<T> {
+    Err(Abort(Why::Retry))
+}
+
+pub struct Transaction {
+    // what was read, and the version it was at
+    reads: HashMap<usize, (Arc<Versioned>, usize)>,
+    // what's to be written, latest last
+    writes: Vec<(usize, Arc<Versioned>, Box<Any + Send>)>,
+}
+
+impl Transaction {
+    fn new() -> Transaction {
+        Transaction { reads: HashMap::new(), writes: vec![] }
+    }
+
+    pub fn read<T: Any + Send + Clone>(&mut self, tvar: &TVar<T>) -> Stm<T> {
+        let id = tvar.id();
+        // our own writes come first
+        for &(written, _, ref value) in self.writes.iter().rev() {
+            if written == id {
+                return Ok(value.downcast_ref::<T>().unwrap().clone());
+            }
+        }
+
+        let (value, version) = {
+            let cell = tvar.var.cell.lock().unwrap();
+            (cell.value.clone(), cell.version)
+        };
+        if let Some(&(_, seen)) = self.reads.get(&id) {
+            if seen != version {
+                return Err(Abort(Why::Conflict));
+            }
+        }
+        self.reads.insert(id, (tvar.var.clone(), version));
+        // Everything read so far has to still be as it was, or the values the
+        // transaction is working with might not go together, and it could
+        // do something silly with them (divide by zero, say) before it got as
+        // far as committing.
+        if !self.valid() {
+            return Err(Abort(Why::Conflict));
+        }
+        Ok(value)
+    }
+
+    pub fn write<T: Any + Send + Clone>(&mut self, tvar: &TVar<T>, value: T) {
+        self.writes.push((tvar.id(), tvar.var.clone(), Box::new(value)));
+    }
+
+    // Runs `first`, and if that retries, forgets what it wrote and runs
+    // `second` instead. If both retry, the transaction waits for anything
+    // either of them read.
+    pub fn or_else<R, F, G>(&mut self, first: F, second: G) -> Stm<R>
+        where F: FnOnce(&mut Transaction) -> Stm<R>,
+              G: FnOnce(&mut Transaction) -> Stm<R>
+    {
+        let mark = self.writes.len();
+        match first(self) {
+            Err(Abort(Why::Retry)) => {
+                self.writes.truncate(mark);
+                second(self)
+            }
+            result => result,
+        }
+    }
+
+    fn valid(&self) -> bool {
+        self.reads.values().all(|&(ref var, version)| var.version() == version)
+    }
+
+    fn commit(self) -> bool {
+        // only the last write to each variable counts
+        let mut vars = HashMap::new();
+        let mut values = HashMap::new();
+        for (id, var, value) in self.writes {
+            vars.insert(id, var);
+            values.insert(id, value);
+        }
+        // Locks are taken in order of where the variables are, so two
+        // transactions can't each be waiting for the other.
+        let mut ids = vars.keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+        let mut locked = HashMap::new();
+        for &id in ids.iter() {
+            locked.insert(id, vars[&id].lock());
+        }
+
+        // Anything read and not written mustn't be waited for while we hold
+        // locks, since whoever has it might be waiting for one of ours.
+        let valid = self.reads.iter().all(|(id, &(ref var, version))| {
+            match locked.get(id) {
+                Some(cell) => cell.version() == version,
+                None => var.try_version() == Some(version),
+            }
+        });
+        if !valid {
+            return false;
+        }
+
+        for (id, value) in values {
+            locked.get_mut(&id).unwrap().store(value);
+        }
+        drop(locked);
+        for var in vars.values() {
+            var.wake();
+        }
+        true
+    }
+
+    // Waits for something that was read to change.
+    fn wait(self) {
+        let wakeup = Arc::new(Wakeup { changed: Mutex::new(false), condvar: Condvar::new() });
+        for &(ref var, _) in self.reads.values() {
+            var.wait_on(wakeup.clone());
+        }
+        // If it's already changed, the wakeup came too late to hear about it.
+        if !self.valid() {
+            return;
+        }
+        let mut changed = wakeup.changed.lock().unwrap();
+        while !*changed {
+            changed = wakeup.condvar.wait(changed).unwrap();
+        }
+    }
+}
+
+// Runs `f` as a transaction, as many times as it takes, and returns what it
+// came to the time it went through.
+pub fn atomically<R, F>(f: F) -> R
+    where F: FnMut(&mut Transaction) -> Stm<R>
+{
+    atomically_counting(f).0
+}
+
+// The same, and also how many times it had to start again because of some
+// other transaction getting in the way (not counting retries).
+pub fn atomically_counting<R, F>(mut f: F) -> (R, usize)
+    where F: FnMut(&mut Transaction) -> Stm<R>
+{
+    let mut conflicts = 0;
+    loop {
+        let mut tx = Transaction::new();
+        match f(&mut tx) {
+            Ok(result) => {
+                if tx.commit() {
+                    return (result, conflicts);
+                }
+            }
+            Err(Abort(Why::Retry)) => {
+                tx.wait();
+                continue;
+            }
+            Err(Abort(Why::Conflict)) => {}
+        }
+        conflicts += 1;
+    }
+}
+
+// A queue of at most `capacity` things, which waits when it can't go on.
+#[cfg(not(test))]
+struct Queue<T> {
+    items: TVar<Vec<T>>,
+    capacity: usize,
+}
+
+#[cfg(not(test))]
+impl<T: Any + Send + Sync + Clone> Queue<T> {
+    fn new(capacity: usize) -> Queue<T> {
+        Queue { items: TVar::new(vec![]), capacity: capacity }
+    }
+
+    fn push(&self, tx: &mut Transaction, item: T) -> Stm<()> {
+        let mut items = try!(tx.read(&self.items));
+        if items.len() == self.capacity {
+            return retry();
+        }
+        items.push(item);
+        tx.write(&self.items, items);
+        Ok(())
+    }
+
+    fn pop(&self, tx: &mut Transaction) -> Stm<T> {
+        let mut items = try!(tx.read(&self.items));
+        if items.is_empty() {
+            return retry();
+        }
+        let item = items.remove(0);
+        tx.write(&self.items, items);
+        Ok(item)
+    }
+}
+
+#[allow(dead_code)]
+#[cfg(not(test))]
+fn main() {
+    use std::thread;
+
+    // Two producers, each with a small queue of its own, and a consumer
+    // taking from whichever has something.
+    let queues = Arc::new((Queue::new(2), Queue::new(2)));
+    let producers = (0..2).map(|p| {
+        let queues = queues.clone();
+        thread::spawn(move || {
+            for i in 0..5 {
+                let item = format!("{} from producer {}", i, p);
+                let queue = if p == 0 { &queues.0 } else { &queues.1 };
+                atomically(|tx| queue.push(tx, item.clone()));
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    for _ in 0..10 {
+        let item = atomically(|tx| tx.or_else(|tx| queues.0.pop(tx), |tx| queues.1.pop(tx)));
+        println!("{}", item);
+    }
+    for producer in producers {
+        producer.join().unwrap();
+    }
+}
+
+#[test]
+fn read_and_write() {
+    let (a, b) = (TVar::new(1), TVar::new("one".to_string()));
+    let seen = atomically(|tx| {
+        let n = try!(tx.read(&a));
+        tx.write(&a, n + 1);
+        tx.write(&b, "two".to_string());
+        // a transaction sees its own writes
+        Ok((try!(tx.read(&a)), try!(tx.read(&b))))
+    });
+    assert_eq!(seen, (2, "two".to_string()));
+    assert_eq!(a.read_atomic(), 2);
+    assert_eq!(b.read_atomic(), "two");
+}
+
+#[test]
+fn transfers() {
+    use std::thread;
+
+    let accounts = (0..5).map(|_| TVar::new(100)).collect::<Vec<_>>();
+    let threads = (0..4).map(|t| {
+        let accounts = accounts.clone();
+        thread::spawn(move || {
+            let mut conflicts = 0;
+            for i in 0..2000 {
+                let (from, to) = ((i + t) % 5, (i * 3 + t + 1) % 5);
+                if from == to { continue }
+                conflicts += atomically_counting(|tx| {
+                    let a = try!(tx.read(&accounts[from]));
+                    let b = try!(tx.read(&accounts[to]));
+                    let amount = ::std::cmp::min(a, 7);
+                    tx.write(&accounts[from], a - amount);
+                    tx.write(&accounts[to], b + amount);
+                    Ok(())
+                }).1;
+            }
+            conflicts
+        })
+    }).collect::<Vec<_>>();
+
+    for _ in 0..100 {
+        let total = atomically(|tx| {
+            let mut total = 0;
+            for account in accounts.iter() {
+                total += try!(tx.read(account));
+            }
+            Ok(total)
+        });
+        assert_eq!(total, 500);
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(accounts.iter().fold(0, |sum, a| sum + a.read_atomic()), 500);
+}
+
+#[test]
+fn retry_waits() {
+    use std::thread;
+    use std::time::Duration;
+
+    let flag = TVar::new(0);
+    let waiter = {
+        let flag = flag.clone();
+        thread::spawn(move || atomically(|tx| {
+            let n = try!(tx.read(&flag));
+            if n < 3 { retry() } else { Ok(n) }
+        }))
+    };
+    for _ in 0..3 {
+        thread::sleep(Duration::from_millis(10));
+        atomically(|tx| {
+            let n = try!(tx.read(&flag));
+            tx.write(&flag, n + 1);
+            Ok(())
+        });
+    }
+    assert_eq!(waiter.join().unwrap(), 3);
+}
+
+#[test]
+fn or_else() {
+    let (a, b) = (TVar::new(0), TVar::new(5));
+    let took = atomically(|tx| tx.or_else(|tx| {
+        // written, then forgotten when this retries
+        tx.write(&b, 100);
+        let n = try!(tx.read(&a));
+        if n == 0 { retry() } else { Ok(("a", n)) }
+    }, |tx| {
+        let n = try!(tx.read(&b));
+        tx.write(&b, n - 1);
+        Ok(("b", n))
+    }));
+    assert_eq!(took, ("b", 5));
+    assert_eq!(b.read_atomic(), 4);
+    assert_eq!(a.read_atomic(), 0);
+
+    atomically(|tx| Ok(tx.write(&a, 1)));
+    let took = atomically(|tx| tx.or_else(|tx| tx.read(&a).map(|n| ("a", n)),
+                                          |tx| tx.read(&b).map(|n| ("b", n))));
+    assert_eq!(took, ("a", 1));
+}
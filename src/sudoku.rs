@@ -1,203 +1,1021 @@
 // http://rosettacode.org/wiki/Sudoku
-#![feature(core)]
-#![feature(step_by)]
+// Any box size, plus the diagonal, jigsaw and killer variants.
+extern crate rand;
+
 use std::fmt;
+use std::rc::Rc;
 use std::str::FromStr;
+use rand::Rng;
+use exact_cover::ExactCover;
 
-const BOARD_WIDTH: usize = 9;
-const BOARD_HEIGHT: usize = 9;
-const GROUP_WIDTH: usize = 3;
-const GROUP_HEIGHT: usize = 3;
-const MAX_NUMBER: usize = 9;
+// not all of it is needed here
+#[allow(dead_code)]
+mod exact_cover;
 
-type BITS = u16;
-const MASK_ALL: BITS = 0x1ff;
+// One bit per digit, so the largest grid is 32x32.
+type BITS = u32;
+const MAX_NUMBER: usize = 32;
 const INVALID_CELL: u32 = !0;
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    BadSize(usize),
+    BadDigit(char),
+    BadRegion(char)
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::BadSize(n) => write!(f, "{} cells don't make a square grid", n),
+            ParseError::BadDigit(c) => write!(f, "'{}' is not a digit of this grid", c),
+            ParseError::BadRegion(c) => write!(f, "region '{}' has the wrong number of cells", c),
+        }
+    }
+}
+
+// A group of cells whose digits must add up to `sum`. As usual in killer
+// sudoku a digit can't repeat within a cage.
+#[derive(Clone)]
+struct Cage {
+    cells: Vec<usize>,
+    sum: usize
+}
+
+// What makes a puzzle: the size of the grid, the regions each digit must
+// appear in once, and any extra constraints. The classic puzzle is
+// `Rules::new(3, 3)`.
+#[derive(Clone)]
+struct Rules {
+    size: usize,
+    // zero for jigsaw puzzles, whose regions aren't boxes
+    box_width: usize,
+    box_height: usize,
+    regions: Vec<usize>,
+    diagonals: bool,
+    cages: Vec<Cage>
+}
+
+impl Rules {
+    fn new(box_width: usize, box_height: usize) -> Rules {
+        let size = box_width * box_height;
+        assert!(size > 0 && size <= MAX_NUMBER, "the grid can't be {}x{}", size, size);
+
+        let regions = (0 .. size * size)
+            .map(|i| (i / size / box_height) * box_height + (i % size) / box_width)
+            .collect();
+        Rules {
+            size: size,
+            box_width: box_width,
+            box_height: box_height,
+            regions: regions,
+            diagonals: false,
+            cages: vec![]
+        }
+    }
+
+    // The regions are given as a grid of names, one character per cell.
+    fn jigsaw(layout: &str) -> Result<Rules, ParseError> {
+        let cells = layout.chars().filter(|c| !c.is_whitespace()).collect::<Vec<_>>();
+        let size = (cells.len() as f64).sqrt() as usize;
+        if size == 0 || size > MAX_NUMBER || size * size != cells.len() {
+            return Err(ParseError::BadSize(cells.len()));
+        }
+
+        let mut names: Vec<char> = vec![];
+        let mut regions = Vec::with_capacity(cells.len());
+        for &c in cells.iter() {
+            match names.iter().position(|&n| n == c) {
+                Some(r) => regions.push(r),
+                None => {
+                    regions.push(names.len());
+                    names.push(c);
+                }
+            }
+        }
+        if names.len() > size {
+            return Err(ParseError::BadRegion(names[size]));
+        }
+        for (r, &name) in names.iter().enumerate() {
+            if regions.iter().filter(|&&s| s == r).count() != size {
+                return Err(ParseError::BadRegion(name));
+            }
+        }
+
+        Ok(Rules {
+            size: size,
+            box_width: 0,
+            box_height: 0,
+            regions: regions,
+            diagonals: false,
+            cages: vec![]
+        })
+    }
+
+    // Both long diagonals must hold every digit too.
+    fn diagonal(mut self) -> Rules {
+        self.diagonals = true;
+        self
+    }
+
+    fn cage(mut self, cells: &[(usize, usize)], sum: usize) -> Rules {
+        let cells = cells.iter().map(|&(x, y)| y * self.size + x).collect();
+        self.cages.push(Cage { cells: cells, sum: sum });
+        self
+    }
+}
+
+// The rules worked out into the groups the solver looks at. Every puzzle
+// with the same rules shares one of these.
+struct Layout {
+    rules: Rules,
+    // groups holding every digit exactly once
+    houses: Vec<Vec<usize>>,
+    // for each cell, the cells which can't hold the same digit
+    peers: Vec<Vec<usize>>
+}
+
+impl Layout {
+    fn new(rules: Rules) -> Layout {
+        let n = rules.size;
+        let mut houses: Vec<Vec<usize>> = vec![];
+        for y in 0 .. n {
+            houses.push((0 .. n).map(|x| y * n + x).collect());
+        }
+        for x in 0 .. n {
+            houses.push((0 .. n).map(|y| y * n + x).collect());
+        }
+        for r in 0 .. n {
+            houses.push((0 .. n * n).filter(|&i| rules.regions[i] == r).collect());
+        }
+        if rules.diagonals {
+            houses.push((0 .. n).map(|i| i * n + i).collect());
+            houses.push((0 .. n).map(|i| i * n + n - 1 - i).collect());
+        }
+
+        let mut peers: Vec<Vec<usize>> = vec![vec![]; n * n];
+        for group in houses.iter().chain(rules.cages.iter().map(|c| &c.cells)) {
+            for &a in group.iter() {
+                for &b in group.iter() {
+                    if a != b && !peers[a].contains(&b) {
+                        peers[a].push(b);
+                    }
+                }
+            }
+        }
+
+        Layout { rules: rules, houses: houses, peers: peers }
+    }
+}
+
+#[derive(Clone)]
 struct Sudoku {
-    map: [[BITS; BOARD_WIDTH]; BOARD_HEIGHT]
+    layout: Rc<Layout>,
+    // the candidates of each cell, row by row
+    map: Vec<BITS>
 }
 
 impl Sudoku {
-    fn new() -> Sudoku {
-        Sudoku { map: [[MASK_ALL; BOARD_WIDTH]; BOARD_HEIGHT] }
+    fn new(rules: Rules) -> Sudoku {
+        let cells = rules.size * rules.size;
+        let all = mask_all(rules.size);
+        Sudoku { layout: Rc::new(Layout::new(rules)), map: vec![all; cells] }
+    }
+
+    // Reads the digits row by row, ignoring whitespace. Empty cells are '.'
+    // or '0', and the digits after 9 are 'A', 'B', ...
+    fn parse(rules: Rules, s: &str) -> Result<Sudoku, ParseError> {
+        let mut sudoku = Sudoku::new(rules);
+        let size = sudoku.size();
+
+        let cells = s.chars().filter(|c| !c.is_whitespace()).collect::<Vec<_>>();
+        if cells.len() != size * size {
+            return Err(ParseError::BadSize(cells.len()));
+        }
+        for (i, &c) in cells.iter().enumerate() {
+            if c == '.' || c == '0' { continue }
+            match c.to_digit(36) {
+                Some(d) if d as usize <= size => sudoku.set(i % size, i / size, d),
+                _ => return Err(ParseError::BadDigit(c))
+            }
+        }
+
+        Ok(sudoku)
+    }
+
+    fn size(&self) -> usize {
+        self.layout.rules.size
     }
 
     fn get(&self, x: usize, y: usize) -> u32 {
-        match self.map[y][x].count_ones() {
+        let bits = self.map[y * self.size() + x];
+        match bits.count_ones() {
             0 => INVALID_CELL,
-            1 => self.map[y][x].trailing_zeros() + 1,
+            1 => bits.trailing_zeros() + 1,
             _ => 0
         }
     }
 
     fn set(&mut self, x: usize, y: usize, n: u32) {
-        self.map[y][x] = 1 << (n - 1);
+        let size = self.size();
+        self.map[y * size + x] = 1 << (n - 1);
     }
 
-}
+    // Removes candidates which can't be right, until nothing changes.
+    // Returns false when that leaves a cell or a digit with nowhere to go.
+    fn propagate(&mut self) -> bool {
+        let layout = self.layout.clone();
+        let size = self.size();
 
-impl FromStr for Sudoku {
-    type Err = ();
+        loop {
+            let bkup = self.map.clone();
 
-    fn from_str(s: &str) -> Result<Sudoku, ()> {
-        let mut sudoku = Sudoku::new();
+            // If the number at a cell is uniquely determined, that number must
+            // not appear at its peers.
+            for i in 0 .. self.map.len() {
+                let bits = self.map[i];
+                if bits.count_ones() != 1 { continue }
+                for &p in layout.peers[i].iter() {
+                    self.map[p] &= !bits;
+                }
+            }
 
-        for (y, line) in s.lines().filter(|l| !l.is_empty()).enumerate() {
-            let line = line.trim_matches(|c: char| c.is_whitespace());
-            for (x, c) in line.chars().enumerate() {
-                if let Some(d) = c.to_digit(10) {
-                    if d != 0 { sudoku.set(x, y, d); }
-                } else {
-                    return Err(())
+            // If `n` can only go in one cell of a house, the number of the
+            // cell must be `n`.
+            for house in layout.houses.iter() {
+                for n in 0 .. size {
+                    let bit = 1 << n;
+                    let next = {
+                        let mut it = house.iter().filter(|&&i| self.map[i] & bit != 0);
+                        let next = it.next();
+                        if next.is_none() { return false }
+                        if it.next().is_some() { continue }
+                        *next.unwrap()
+                    };
+                    self.map[next] = bit;
                 }
             }
+
+            for cage in layout.rules.cages.iter() {
+                if !self.restrict_cage(cage) { return false }
+            }
+
+            if self.map.iter().any(|&bits| bits == 0) { return false }
+
+            // Loop until no cell can be filled.
+            if self.map == bkup { return true }
         }
+    }
 
-        Ok(sudoku)
+    // Drops the candidates of a cage's open cells which leave a sum the
+    // other open cells can't make up with distinct digits.
+    fn restrict_cage(&mut self, cage: &Cage) -> bool {
+        let mut left = cage.sum as isize;
+        let mut open = vec![];
+        for &i in cage.cells.iter() {
+            if self.map[i].count_ones() == 1 {
+                left -= self.map[i].trailing_zeros() as isize + 1;
+            } else {
+                open.push(i);
+            }
+        }
+        if open.is_empty() || left < 0 { return left == 0 }
+
+        for (k, &i) in open.iter().enumerate() {
+            let others = open.iter().enumerate()
+                .filter(|&(j, _)| j != k)
+                .fold(0, |a, (_, &c)| a | self.map[c]);
+            let mut keep = 0;
+            for n in 0 .. self.size() {
+                let bit = 1 << n;
+                if self.map[i] & bit == 0 { continue }
+                let rest = left - (n as isize + 1);
+                if let Some((lo, hi)) = sum_range(others & !bit, open.len() - 1) {
+                    if lo <= rest && rest <= hi { keep |= bit }
+                }
+            }
+            self.map[i] = keep;
+        }
+        true
+    }
+
+    // A puzzle is only fair if it has exactly one solution.
+    fn is_unique(&self) -> bool {
+        solve_sudoku(self, 2).len() == 1
+    }
+
+    // How hard the puzzle is for a person: the hardest technique needed to
+    // solve it, and Fiendish when the techniques aren't enough and it takes
+    // guessing. None for puzzles without a unique solution.
+    fn rate(&self) -> Option<Difficulty> {
+        self.rate_up_to(Difficulty::Fiendish)
+    }
+
+    // The rating, or None if it's above `max`. The techniques never rule
+    // out a digit of any solution, so when they solve the puzzle that
+    // solution is the only one. Only a puzzle they can't solve needs the
+    // search to tell whether it's fair.
+    fn rate_up_to(&self, max: Difficulty) -> Option<Difficulty> {
+        let explanation = explain(self);
+        if explanation.solved {
+            let difficulty = explanation.difficulty();
+            if difficulty <= max { Some(difficulty) } else { None }
+        } else if max == Difficulty::Fiendish && self.is_unique() {
+            Some(Difficulty::Fiendish)
+        } else {
+            None
+        }
+    }
+
+    fn cages_add_up(&self) -> bool {
+        self.layout.rules.cages.iter().all(|c| {
+            c.cells.iter().fold(0, |a, &i| a + self.map[i].trailing_zeros() as usize + 1) == c.sum
+        })
+    }
+}
+
+fn mask_all(size: usize) -> BITS {
+    if size == MAX_NUMBER { !0 } else { (1 << size) - 1 }
+}
+
+// The smallest and largest sums of `count` different digits among `bits`.
+fn sum_range(bits: BITS, count: usize) -> Option<(isize, isize)> {
+    let digits = (0 .. MAX_NUMBER)
+        .filter(|&n| bits & 1 << n != 0)
+        .map(|n| n as isize + 1)
+        .collect::<Vec<_>>();
+    if digits.len() < count { return None }
+    let lo = digits[.. count].iter().fold(0, |a, &d| a + d);
+    let hi = digits[digits.len() - count ..].iter().fold(0, |a, &d| a + d);
+    Some((lo, hi))
+}
+
+// The digits after 9 are written 'A', 'B', ...
+fn digit_char(d: u32) -> char {
+    if d < 10 {
+        (b'0' + d as u8) as char
+    } else {
+        (b'A' + (d - 10) as u8) as char
+    }
+}
+
+impl PartialEq for Sudoku {
+    fn eq(&self, other: &Sudoku) -> bool {
+        self.map == other.map
+    }
+}
+
+impl FromStr for Sudoku {
+    type Err = ParseError;
+
+    // A classic puzzle with square boxes: 4x4, 9x9, 16x16 and so on.
+    fn from_str(s: &str) -> Result<Sudoku, ParseError> {
+        let cells = s.chars().filter(|c| !c.is_whitespace()).count();
+        let side = (cells as f64).sqrt().sqrt().round() as usize;
+        if side == 0 || side * side > MAX_NUMBER || side * side * side * side != cells {
+            return Err(ParseError::BadSize(cells));
+        }
+        Sudoku::parse(Rules::new(side, side), s)
     }
 }
 
 impl fmt::Display for Sudoku {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let hbar = "+---+---+---+";
+        let rules = &self.layout.rules;
+        let hbar = if rules.box_width == 0 {
+            String::new()
+        } else {
+            let bar = format!("+{}", (0 .. rules.box_width).map(|_| "-").collect::<String>());
+            format!("{}+", (0 .. rules.box_height).map(|_| &bar[..]).collect::<String>())
+        };
 
-        for y in (0 .. BOARD_HEIGHT) {
-            if y % GROUP_HEIGHT == 0 {
+        for y in 0 .. rules.size {
+            if rules.box_height > 0 && y % rules.box_height == 0 {
                 try!(writeln!(f, "{}", hbar));
             }
 
-            for x in (0 .. BOARD_WIDTH) {
-                if x % GROUP_WIDTH == 0 {
+            for x in 0 .. rules.size {
+                if rules.box_width > 0 && x % rules.box_width == 0 {
                     try!(write!(f, "|"));
                 }
 
                 match self.get(x, y) {
                     INVALID_CELL => try!(write!(f, "!")),
                     0 => try!(write!(f, " ")),
-                    d => try!(write!(f, "{}", d))
+                    d => try!(write!(f, "{}", digit_char(d)))
                 }
             }
-            try!(writeln!(f, "|"));
+            if rules.box_width > 0 {
+                try!(write!(f, "|"));
+            }
+            try!(writeln!(f, ""));
+        }
+        if rules.box_height > 0 {
+            try!(writeln!(f, "{}", hbar));
         }
-        try!(writeln!(f, "{}", hbar));
 
         Ok(())
     }
 }
 
-fn solve_sudoku(mut puzzle: Sudoku) -> Vec<Sudoku> {
-    let idx_in_grp = [(0, 0), (0, 1), (0, 2),
-                      (1, 0), (1, 1), (1, 2),
-                      (2, 0), (2, 1), (2, 2)];
+impl fmt::Debug for Sudoku {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
 
-    loop {
-        let bkup = puzzle;
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Fiendish
+}
+
+// A depth first search which fills the cell with the fewest candidates
+// first, and stops after `limit` solutions.
+struct Search<'a> {
+    limit: usize,
+    solutions: Vec<Sudoku>,
+    // puts the candidates of a cell in the order they are tried
+    shuffle: &'a mut FnMut(&mut [u32])
+}
+
+impl<'a> Search<'a> {
+    fn new(limit: usize, shuffle: &'a mut FnMut(&mut [u32])) -> Search<'a> {
+        Search { limit: limit, solutions: vec![], shuffle: shuffle }
+    }
+
+    fn run(&mut self, mut puzzle: Sudoku) {
+        if self.solutions.len() >= self.limit || !puzzle.propagate() { return }
+
+        // Find the undetermined cell with the fewest candidates. If every
+        // cell is determined, this is an answer.
+        let next = (0 .. puzzle.map.len())
+            .filter(|&i| puzzle.map[i].count_ones() > 1)
+            .fold(None, |best: Option<usize>, i| match best {
+                Some(b) if puzzle.map[b].count_ones() <= puzzle.map[i].count_ones() => best,
+                _ => Some(i)
+            });
+        let i = match next {
+            Some(i) => i,
+            None => {
+                self.solutions.push(puzzle);
+                return;
+            }
+        };
+
+        let mut digits = (0 .. MAX_NUMBER as u32)
+            .filter(|&n| puzzle.map[i] & 1 << n != 0)
+            .collect::<Vec<_>>();
+        (self.shuffle)(&mut digits);
 
-        // If the number at cell (x, y) is uniquely determined, that number must
-        // not have appeared at the cells in the same row/column/group.
-        for y in (0 .. BOARD_HEIGHT) {
-            for x in (0 .. BOARD_WIDTH) {
-                if puzzle.map[y][x].count_ones() != 1 { continue }
+        // Assuming the number at the cell is `n`, try to solve the problem
+        // again.
+        for n in digits {
+            let mut p2 = puzzle.clone();
+            p2.map[i] = 1 << n;
+            self.run(p2);
+            if self.solutions.len() >= self.limit { return }
+        }
+    }
+}
 
-                let (x0, y0) = ((x / GROUP_WIDTH) * GROUP_WIDTH,
-                                (y / GROUP_HEIGHT) * GROUP_HEIGHT);
+// Finds up to `limit` solutions. Asking for two is enough to tell whether a
+// puzzle has a unique solution.
+//
+// Without cages a sudoku is an exact cover problem: each cell holds one
+// digit, and each house holds each digit once. The sums of the cages don't
+// fit that mould, so puzzles with cages are left to the search.
+fn solve_sudoku(puzzle: &Sudoku, limit: usize) -> Vec<Sudoku> {
+    if puzzle.layout.rules.cages.is_empty() {
+        return exact_cover_solutions(puzzle, limit);
+    }
+    let mut no_shuffle = |_: &mut [u32]| {};
+    let mut search = Search::new(limit, &mut no_shuffle);
+    search.run(puzzle.clone());
+    search.solutions
+}
 
-                let row = (0 .. BOARD_WIDTH).map(|x| (x, y));
-                let col = (0 .. BOARD_HEIGHT).map(|y| (x, y));
-                let grp = idx_in_grp.iter().map(|&(dx, dy)| (x0 + dx, y0 + dy));
+// A column for every cell, and one for every digit of every house. Each
+// candidate of each cell is a row.
+fn exact_cover_solutions(puzzle: &Sudoku, limit: usize) -> Vec<Sudoku> {
+    let houses = &puzzle.layout.houses;
+    let n = puzzle.size();
+    let cells = puzzle.map.len();
 
-                let it = row.chain(col).chain(grp)
-                    .filter(|&pos: &(usize, usize)| pos != (x, y));
+    let mut houses_of = vec![vec![]; cells];
+    for (h, house) in houses.iter().enumerate() {
+        for &i in house.iter() {
+            houses_of[i].push(h);
+        }
+    }
+
+    let mut dlx = ExactCover::new(cells + houses.len() * n, 0);
+    let mut candidates = vec![];
+    for i in 0 .. cells {
+        for d in 0 .. n {
+            if puzzle.map[i] & 1 << d == 0 { continue }
+            let mut columns = vec![i];
+            columns.extend(houses_of[i].iter().map(|&h| cells + h * n + d));
+            dlx.add_row(&columns);
+            candidates.push((i, d));
+        }
+    }
+
+    dlx.solutions(limit).into_iter().map(|rows| {
+        let mut solution = puzzle.clone();
+        for r in rows {
+            let (i, d) = candidates[r];
+            solution.map[i] = 1 << d;
+        }
+        solution
+    }).collect()
+}
+
+// The techniques a person would use, from the simplest up. The solver
+// always uses the first one that gets anywhere.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Technique {
+    NakedSingle,
+    HiddenSingle,
+    NakedPair,
+    HiddenPair,
+    NakedTriple,
+    HiddenTriple,
+    PointingPair,
+    BoxLineReduction,
+    XWing,
+    Swordfish,
+    XYWing
+}
+
+const TECHNIQUES: [Technique; 11] = [
+    Technique::NakedSingle, Technique::HiddenSingle,
+    Technique::NakedPair, Technique::HiddenPair,
+    Technique::NakedTriple, Technique::HiddenTriple,
+    Technique::PointingPair, Technique::BoxLineReduction,
+    Technique::XWing, Technique::Swordfish, Technique::XYWing
+];
+
+impl fmt::Display for Technique {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Technique::NakedSingle => "Naked single",
+            Technique::HiddenSingle => "Hidden single",
+            Technique::NakedPair => "Naked pair",
+            Technique::HiddenPair => "Hidden pair",
+            Technique::NakedTriple => "Naked triple",
+            Technique::HiddenTriple => "Hidden triple",
+            Technique::PointingPair => "Pointing pair",
+            Technique::BoxLineReduction => "Box/line reduction",
+            Technique::XWing => "X-Wing",
+            Technique::Swordfish => "Swordfish",
+            Technique::XYWing => "XY-Wing",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// One deduction: the technique, the houses and cells it looks at and the
+// digits involved, and what follows from it. Cells are (x, y).
+#[derive(Clone, Debug, PartialEq)]
+struct Step {
+    technique: Technique,
+    houses: Vec<String>,
+    cells: Vec<(usize, usize)>,
+    digits: Vec<u32>,
+    placed: Option<(usize, usize, u32)>,
+    removed: Vec<(usize, usize, u32)>
+}
+
+fn cell_name(&(x, y): &(usize, usize)) -> String {
+    format!("r{}c{}", y + 1, x + 1)
+}
+
+fn join<T, F: Fn(&T) -> String>(xs: &[T], f: F) -> String {
+    xs.iter().map(|x| f(x)).collect::<Vec<_>>().join(", ")
+}
 
-                let mask = !puzzle.map[y][x] & MASK_ALL;
-                for (x, y) in it {
-                    puzzle.map[y][x] &= mask;
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}: {} at {}", self.technique,
+                    join(&self.digits, |&d| digit_char(d).to_string()),
+                    join(&self.cells, cell_name)));
+        if !self.houses.is_empty() {
+            try!(write!(f, " in {}", self.houses.join(" and ")));
+        }
+        match self.placed {
+            Some((x, y, d)) => write!(f, " => {} = {}", cell_name(&(x, y)), digit_char(d)),
+            None => write!(f, " => {}", join(&self.removed, |&(x, y, d)| {
+                format!("{} -{}", cell_name(&(x, y)), digit_char(d))
+            }))
+        }
+    }
+}
+
+// The digits whose bits are set, from 1 up.
+fn digits(bits: BITS) -> Vec<u32> {
+    (0 .. MAX_NUMBER as u32).filter(|&n| bits & 1 << n != 0).map(|n| n + 1).collect()
+}
+
+fn combinations(xs: &[usize], k: usize) -> Vec<Vec<usize>> {
+    if k == 0 { return vec![vec![]] }
+    if xs.len() < k { return vec![] }
+    let mut with_first = combinations(&xs[1 ..], k - 1);
+    for c in with_first.iter_mut() {
+        c.insert(0, xs[0]);
+    }
+    with_first.extend(combinations(&xs[1 ..], k).into_iter());
+    with_first
+}
+
+impl Layout {
+    // The houses are the rows, then the columns, then the regions and
+    // last the diagonals.
+    fn house_name(&self, h: usize) -> String {
+        let n = self.rules.size;
+        match h / n {
+            0 => format!("row {}", h + 1),
+            1 => format!("column {}", h - n + 1),
+            2 if self.rules.box_width > 0 => format!("box {}", h - 2 * n + 1),
+            2 => format!("region {}", h - 2 * n + 1),
+            _ if h == 3 * n => "the main diagonal".to_string(),
+            _ => "the anti-diagonal".to_string()
+        }
+    }
+}
+
+// Pencil marks for working through a puzzle like a person would. A cell is
+// placed once its digit has been struck out of its peers.
+struct Logic {
+    grid: Sudoku,
+    placed: Vec<bool>
+}
+
+impl Logic {
+    fn new(puzzle: &Sudoku) -> Logic {
+        let mut logic = Logic { grid: puzzle.clone(), placed: vec![false; puzzle.map.len()] };
+        for i in 0 .. puzzle.map.len() {
+            if puzzle.map[i].count_ones() == 1 {
+                logic.place(i);
+            }
+        }
+        logic
+    }
+
+    fn place(&mut self, i: usize) {
+        self.placed[i] = true;
+        let bits = self.grid.map[i];
+        for &p in self.grid.layout.peers[i].iter() {
+            self.grid.map[p] &= !bits;
+        }
+    }
+
+    fn xy(&self, i: usize) -> (usize, usize) {
+        (i % self.grid.size(), i / self.grid.size())
+    }
+
+    // The cells of a house that aren't placed yet.
+    fn open(&self, house: &[usize]) -> Vec<usize> {
+        house.iter().cloned().filter(|&i| !self.placed[i]).collect()
+    }
+
+    fn step(&self, technique: Technique, houses: &[usize], cells: &[usize], bits: BITS,
+            removed: &[(usize, BITS)]) -> Option<Step> {
+        let mut removals = vec![];
+        for &(i, bits) in removed.iter() {
+            let (x, y) = self.xy(i);
+            for d in digits(self.grid.map[i] & bits) {
+                removals.push((x, y, d));
+            }
+        }
+        if removals.is_empty() { return None }
+
+        Some(Step {
+            technique: technique,
+            houses: houses.iter().map(|&h| self.grid.layout.house_name(h)).collect(),
+            cells: cells.iter().map(|&i| self.xy(i)).collect(),
+            digits: digits(bits),
+            placed: None,
+            removed: removals
+        })
+    }
+
+    fn apply(&mut self, step: &Step) {
+        let size = self.grid.size();
+        for &(x, y, d) in step.removed.iter() {
+            self.grid.map[y * size + x] &= !(1 << (d - 1));
+        }
+        if let Some((x, y, d)) = step.placed {
+            self.grid.map[y * size + x] = 1 << (d - 1);
+            self.place(y * size + x);
+        }
+    }
+
+    fn find(&self, technique: Technique) -> Option<Step> {
+        match technique {
+            Technique::NakedSingle => self.naked_single(),
+            Technique::HiddenSingle => self.hidden_single(),
+            Technique::NakedPair => self.naked_subset(technique, 2),
+            Technique::NakedTriple => self.naked_subset(technique, 3),
+            Technique::HiddenPair => self.hidden_subset(technique, 2),
+            Technique::HiddenTriple => self.hidden_subset(technique, 3),
+            Technique::PointingPair | Technique::BoxLineReduction =>
+                self.intersection(technique),
+            Technique::XWing => self.fish(technique, 2),
+            Technique::Swordfish => self.fish(technique, 3),
+            Technique::XYWing => self.xy_wing()
+        }
+    }
+
+    // A cell with a single candidate left.
+    fn naked_single(&self) -> Option<Step> {
+        let map = &self.grid.map;
+        (0 .. map.len()).find(|&i| !self.placed[i] && map[i].count_ones() == 1).map(|i| {
+            let (x, y) = self.xy(i);
+            let d = map[i].trailing_zeros() + 1;
+            Step {
+                technique: Technique::NakedSingle,
+                houses: vec![],
+                cells: vec![(x, y)],
+                digits: vec![d],
+                placed: Some((x, y, d)),
+                removed: vec![]
+            }
+        })
+    }
+
+    // A digit with only one place to go in a house.
+    fn hidden_single(&self) -> Option<Step> {
+        let layout = &self.grid.layout;
+        for (h, house) in layout.houses.iter().enumerate() {
+            let open = self.open(house);
+            for n in 0 .. self.grid.size() {
+                let bit = 1 << n;
+                let places = open.iter().filter(|&&i| self.grid.map[i] & bit != 0)
+                    .collect::<Vec<_>>();
+                if places.len() == 1 {
+                    let (x, y) = self.xy(*places[0]);
+                    return Some(Step {
+                        technique: Technique::HiddenSingle,
+                        houses: vec![layout.house_name(h)],
+                        cells: vec![(x, y)],
+                        digits: vec![n as u32 + 1],
+                        placed: Some((x, y, n as u32 + 1)),
+                        removed: vec![]
+                    });
                 }
             }
         }
+        None
+    }
+
+    // k cells of a house with only k candidates between them: those
+    // digits go there, so the rest of the house can't have them.
+    fn naked_subset(&self, technique: Technique, k: usize) -> Option<Step> {
+        let map = &self.grid.map;
+        for (h, house) in self.grid.layout.houses.iter().enumerate() {
+            let open = self.open(house);
+            let small = open.iter().cloned()
+                .filter(|&i| map[i].count_ones() as usize <= k)
+                .collect::<Vec<_>>();
+            for cells in combinations(&small, k) {
+                let bits = cells.iter().fold(0, |a, &i| a | map[i]);
+                if bits.count_ones() as usize != k { continue }
+                let removed = open.iter()
+                    .filter(|i| !cells.contains(i))
+                    .map(|&i| (i, bits))
+                    .collect::<Vec<_>>();
+                let step = self.step(technique, &[h], &cells, bits, &removed);
+                if step.is_some() { return step }
+            }
+        }
+        None
+    }
 
-        // If `n` appears only once at the cell in the row/column/group, the
-        // number of the cell must be `n`.
-        for n in (0 .. MAX_NUMBER) {
-            let bit = 1 << n;
+    // k digits with only k places to go in a house: those cells take them,
+    // so they can't hold anything else.
+    fn hidden_subset(&self, technique: Technique, k: usize) -> Option<Step> {
+        let map = &self.grid.map;
+        for (h, house) in self.grid.layout.houses.iter().enumerate() {
+            let open = self.open(house);
+            let places = |n: usize| -> Vec<usize> {
+                open.iter().cloned().filter(|&i| map[i] & 1 << n != 0).collect()
+            };
+            let rare = (0 .. self.grid.size())
+                .filter(|&n| { let p = places(n).len(); p >= 2 && p <= k })
+                .collect::<Vec<_>>();
+            for ns in combinations(&rare, k) {
+                let mut cells = ns.iter().flat_map(|&n| places(n).into_iter())
+                    .collect::<Vec<_>>();
+                cells.sort();
+                cells.dedup();
+                if cells.len() != k { continue }
+                let bits = ns.iter().fold(0, |a, &n| a | 1 << n);
+                let removed = cells.iter().map(|&i| (i, !bits)).collect::<Vec<_>>();
+                let step = self.step(technique, &[h], &cells, bits, &removed);
+                if step.is_some() { return step }
+            }
+        }
+        None
+    }
 
-            // Check each rows
-            for y in (0 .. BOARD_HEIGHT) {
-                let next = {
-                    let mut it = (0 .. BOARD_WIDTH)
-                        .filter(|&x| puzzle.map[y][x] & bit != 0);
-                    let next = it.next();
-                    if next.is_none() || it.next().is_some() { continue }
-                    next
+    // When a digit's places in a region all lie on one row or column, the
+    // rest of the line can't have it (pointing pair), and the other way
+    // round (box/line reduction).
+    fn intersection(&self, technique: Technique) -> Option<Step> {
+        let layout = &self.grid.layout;
+        let n = self.grid.size();
+        for region in 2 * n .. 3 * n {
+            for line in 0 .. 2 * n {
+                let (from, to) = if technique == Technique::PointingPair {
+                    (region, line)
+                } else {
+                    (line, region)
                 };
-                puzzle.map[y][next.unwrap()] = bit;
+                let inside = self.open(&layout.houses[from]);
+                let outside = self.open(&layout.houses[to]).into_iter()
+                    .filter(|i| !inside.contains(i))
+                    .collect::<Vec<_>>();
+                for d in 0 .. n {
+                    let bit = 1 << d;
+                    let places = inside.iter().cloned()
+                        .filter(|&i| self.grid.map[i] & bit != 0)
+                        .collect::<Vec<_>>();
+                    if places.len() < 2 || !places.iter().all(|i| layout.houses[to].contains(i)) {
+                        continue;
+                    }
+                    let removed = outside.iter().map(|&i| (i, bit)).collect::<Vec<_>>();
+                    let step = self.step(technique, &[from, to], &places, bit, &removed);
+                    if step.is_some() { return step }
+                }
             }
+        }
+        None
+    }
 
-            // Check each column
-            for x in (0 .. BOARD_WIDTH) {
-                let next = {
-                    let mut it = (0 .. BOARD_HEIGHT)
-                        .filter(|&y| puzzle.map[y][x] & bit != 0);
-                    let next = it.next();
-                    if next.is_none() || it.next().is_some() { continue }
-                    next
+    // k rows where a digit has at most k places, all in the same k columns:
+    // the digit takes one of those places in each row, which covers the
+    // columns, so the rest of the columns can't have it. The same with rows
+    // and columns swapped.
+    fn fish(&self, technique: Technique, k: usize) -> Option<Step> {
+        let n = self.grid.size();
+        let houses = &self.grid.layout.houses;
+        for d in 0 .. n {
+            let bit = 1 << d;
+            for &(base, cover) in [(0, n), (n, 0)].iter() {
+                let places = |line: usize| -> Vec<usize> {
+                    self.open(&houses[base + line]).into_iter()
+                        .filter(|&i| self.grid.map[i] & bit != 0)
+                        .collect()
                 };
-                puzzle.map[next.unwrap()][x] = bit;
+                // which of the covering lines a cell is on
+                let across = |i: usize| if base == 0 { i % n } else { i / n };
+                let lines = (0 .. n)
+                    .filter(|&l| { let p = places(l).len(); p >= 2 && p <= k })
+                    .collect::<Vec<_>>();
+                for chosen in combinations(&lines, k) {
+                    let cells = chosen.iter().flat_map(|&l| places(l).into_iter())
+                        .collect::<Vec<_>>();
+                    let mut covers = cells.iter().map(|&i| across(i)).collect::<Vec<_>>();
+                    covers.sort();
+                    covers.dedup();
+                    if covers.len() != k { continue }
+                    let removed = covers.iter()
+                        .flat_map(|&c| self.open(&houses[cover + c]).into_iter())
+                        .filter(|i| !cells.contains(i))
+                        .map(|i| (i, bit))
+                        .collect::<Vec<_>>();
+                    let lines = chosen.iter().map(|&l| base + l).collect::<Vec<_>>();
+                    let step = self.step(technique, &lines, &cells, bit, &removed);
+                    if step.is_some() { return step }
+                }
             }
+        }
+        None
+    }
 
-            // Check each group
-            for y0 in (0..BOARD_HEIGHT).step_by(GROUP_WIDTH) {
-                for x0 in (0..BOARD_WIDTH).step_by(GROUP_HEIGHT) {
-                    let next = {
-                        let mut it = idx_in_grp
-                            .iter()
-                            .map(|&(dx, dy)| (x0 + dx, y0 + dy))
-                            .filter(|&(x, y)| puzzle.map[y][x] & bit != 0);
-                        let next = it.next();
-                        if next.is_none() || it.next().is_some() { continue }
-                        next
-                    };
-                    let (x, y) = next.unwrap();
-                    puzzle.map[y][x] = bit;
+    // A cell with candidates {x, y} that sees a {x, z} cell and a {y, z}
+    // cell: whichever of x and y it takes, one of the other two is z. So no
+    // cell which sees both of them can be z.
+    fn xy_wing(&self) -> Option<Step> {
+        let map = &self.grid.map;
+        let peers = &self.grid.layout.peers;
+        let pairs = (0 .. map.len())
+            .filter(|&i| !self.placed[i] && map[i].count_ones() == 2)
+            .collect::<Vec<_>>();
+        for &pivot in pairs.iter() {
+            for &a in pairs.iter().filter(|i| peers[pivot].contains(i)) {
+                let shared = map[a] & map[pivot];
+                if shared.count_ones() != 1 { continue }
+                let z = map[a] & !shared;
+                let b_bits = (map[pivot] & !shared) | z;
+                for &b in pairs.iter().filter(|&&i| peers[pivot].contains(&i) && map[i] == b_bits) {
+                    let removed = (0 .. map.len())
+                        .filter(|&i| !self.placed[i] && i != pivot && i != a && i != b)
+                        .filter(|i| peers[a].contains(i) && peers[b].contains(i))
+                        .map(|i| (i, z))
+                        .collect::<Vec<_>>();
+                    let step = self.step(Technique::XYWing, &[], &[pivot, a, b],
+                                         map[pivot] | z, &removed);
+                    if step.is_some() { return step }
                 }
             }
         }
+        None
+    }
+}
 
-        // Loop until no cell can be filled.
-        if puzzle == bkup { break }
+// How far the techniques get on a puzzle, and how.
+struct Explanation {
+    steps: Vec<Step>,
+    // the pencil marks where the techniques ran out
+    grid: Sudoku,
+    solved: bool
+}
+
+impl Explanation {
+    fn difficulty(&self) -> Difficulty {
+        match self.steps.iter().map(|s| s.technique).max() {
+            None | Some(Technique::NakedSingle) | Some(Technique::HiddenSingle) =>
+                Difficulty::Easy,
+            Some(t) if t <= Technique::BoxLineReduction => Difficulty::Medium,
+            _ => Difficulty::Hard
+        }
     }
+}
 
-    let it = (0 .. BOARD_HEIGHT * BOARD_WIDTH)
-        .map(|i| (i % BOARD_WIDTH, i / BOARD_WIDTH))
-        .map(|(x, y)| (x, y, puzzle.map[y][x].count_ones() as BITS))
-        .collect::<Vec<_>>();
+// Works through the puzzle one deduction at a time, always with the
+// simplest technique that gets anywhere, until it's solved or stuck.
+fn explain(puzzle: &Sudoku) -> Explanation {
+    let mut logic = Logic::new(puzzle);
+    let mut steps = vec![];
+    loop {
+        if logic.grid.map.iter().any(|&bits| bits == 0) { break }
+        if logic.placed.iter().all(|&p| p) { break }
+        match TECHNIQUES.iter().filter_map(|&t| logic.find(t)).next() {
+            Some(step) => {
+                logic.apply(&step);
+                steps.push(step);
+            }
+            None => break
+        }
+    }
 
-    // If some cells have no possible number, there is no answer.
-    if it.iter().any(|&(_x, _y, cnt)| cnt == 0) { return vec![]; }
+    let solved = logic.placed.iter().all(|&p| p) && logic.grid.map.iter().all(|&b| b != 0)
+        && logic.grid.cages_add_up();
+    Explanation { steps: steps, grid: logic.grid, solved: solved }
+}
 
-    // If all cells have exact one possible number, this is a answer.
-    if it.iter().all(|&(_x, _y, cnt)| cnt == 1) { return vec![puzzle]; }
+// How many puzzles `generate` makes to find one rated `max`.
+const ATTEMPTS: usize = 10;
 
-    // Find the first undetermined cell.
-    let (x, y, _cnt) = *it.iter()
-        .filter(|& &(_x, _y, cnt)| cnt > 1)
-        .min_by(|& &(_x, _y, cnt)| cnt)
-        .unwrap();
+// Makes a puzzle with a unique solution that is as hard as it can get
+// without being rated above `max`: fill a grid at random, then empty its
+// cells in random order as long as that keeps the puzzle fair and easy
+// enough. Most puzzles made like that are easy, so it keeps the hardest of
+// a few. Returns None if the rules can't be satisfied at all.
+fn generate<R: Rng>(rules: Rules, max: Difficulty, rng: &mut R) -> Option<Sudoku> {
+    let mut best: Option<(Difficulty, Sudoku)> = None;
+    for _ in 0 .. ATTEMPTS {
+        let puzzle = match generate_once(rules.clone(), max, rng) {
+            Some(puzzle) => puzzle,
+            None => return None
+        };
+        let difficulty = puzzle.rate().unwrap();
+        if best.as_ref().map_or(true, |&(d, _)| difficulty > d) {
+            best = Some((difficulty, puzzle));
+        }
+        if difficulty == max { break }
+    }
+    best.map(|(_, puzzle)| puzzle)
+}
 
-    let mut answers = vec![];
-    for n in (0 .. MAX_NUMBER) {
-        let bit = 1 << n;
-        if puzzle.map[y][x] & bit == 0 { continue }
+fn generate_once<R: Rng>(rules: Rules, max: Difficulty, rng: &mut R) -> Option<Sudoku> {
+    let empty = Sudoku::new(rules);
+    let all = mask_all(empty.size());
 
-        // Assuming the number at (x, y) is `n`, try to solve the problem again.
-        // If some answers are found, append them to the `answers`.
-        let mut p2 = puzzle;
-        p2.map[y][x] = bit;
-        answers.extend(solve_sudoku(p2).into_iter());
+    let mut puzzle = {
+        let mut shuffle = |digits: &mut [u32]| rng.shuffle(digits);
+        let mut search = Search::new(1, &mut shuffle);
+        search.run(empty);
+        match search.solutions.pop() {
+            Some(solution) => solution,
+            None => return None
+        }
+    };
+
+    let mut cells = (0 .. puzzle.map.len()).collect::<Vec<_>>();
+    rng.shuffle(&mut cells);
+    for i in cells {
+        let digit = puzzle.map[i];
+        puzzle.map[i] = all;
+        if puzzle.rate_up_to(max).is_none() {
+            puzzle.map[i] = digit;
+        }
     }
-    answers
+    Some(puzzle)
 }
 
 const INPUT: &'static str = "
@@ -212,15 +1030,67 @@ const INPUT: &'static str = "
     000036040
 ";
 
+#[cfg(not(test))]
+const JIGSAW: &'static str = "
+    AAABBBCCC
+    AABBBBCCC
+    AAAABBCCC
+    DDDEEFFFF
+    DDDEEEFFF
+    DDDEEEEFF
+    GGGHHHHII
+    GGHHHHIII
+    GGGGHIIII
+";
+
 #[cfg(not(test))]
 fn main() {
     let puzzle = INPUT.parse::<Sudoku>().unwrap();
 
     println!("{}", puzzle);
 
-    for answer in &solve_sudoku(puzzle) {
+    for answer in &solve_sudoku(&puzzle, 2) {
         println!("{}", answer);
     }
+    assert!(puzzle.is_unique());
+
+    let mut rng = rand::thread_rng();
+
+    println!("Solving a puzzle step by step:");
+    let puzzle = generate(Rules::new(3, 3), Difficulty::Hard, &mut rng).unwrap();
+    println!("{}", puzzle);
+    let explanation = explain(&puzzle);
+    for step in explanation.steps.iter() {
+        println!("{}", step);
+    }
+    println!("{}{:?}\n", explanation.grid, puzzle.rate().unwrap());
+
+    println!("A 16x16 puzzle:");
+    let puzzle = generate(Rules::new(4, 4), Difficulty::Medium, &mut rng).unwrap();
+    println!("{}{:?}\n", puzzle, puzzle.rate().unwrap());
+
+    println!("A diagonal puzzle:");
+    let puzzle = generate(Rules::new(3, 3).diagonal(), Difficulty::Hard, &mut rng).unwrap();
+    println!("{}{:?}\n", puzzle, puzzle.rate().unwrap());
+
+    println!("A jigsaw puzzle, with the regions");
+    println!("{}", JIGSAW);
+    let puzzle = generate(Rules::jigsaw(JIGSAW).unwrap(), Difficulty::Hard, &mut rng).unwrap();
+    println!("{}{:?}\n", puzzle, puzzle.rate().unwrap());
+
+    // cages of two cells across each row, and the last column on its own
+    println!("A killer puzzle, with the cages");
+    let solution = solve_sudoku(&INPUT.parse::<Sudoku>().unwrap(), 1).pop().unwrap();
+    let mut rules = Rules::new(3, 3);
+    for y in 0 .. 9 {
+        for x in (0 .. 4).map(|i| i * 2) {
+            let sum = solution.get(x, y) + solution.get(x + 1, y);
+            println!("({}, {}) + ({}, {}) = {}", x, y, x + 1, y, sum);
+            rules = rules.cage(&[(x, y), (x + 1, y)], sum as usize);
+        }
+    }
+    let puzzle = generate(rules, Difficulty::Fiendish, &mut rng).unwrap();
+    println!("{}{:?}", puzzle, puzzle.rate().unwrap());
 }
 
 #[cfg(test)]
@@ -236,10 +1106,184 @@ const SOLUTION: &'static str = "
     598736241
 ";
 
+// Every house holds every digit and every cage adds up.
+#[cfg(test)]
+fn is_solved(sudoku: &Sudoku) -> bool {
+    let all = mask_all(sudoku.size());
+    let single = sudoku.map.iter().all(|&bits| bits.count_ones() == 1);
+    let houses = sudoku.layout.houses.iter()
+        .all(|h| h.iter().fold(0, |a, &i| a | sudoku.map[i]) == all);
+    single && houses && sudoku.cages_add_up()
+}
+
+// The givens of a generated puzzle are part of its solution.
+#[cfg(test)]
+fn check_generated(puzzle: &Sudoku) {
+    let solutions = solve_sudoku(puzzle, 2);
+    assert_eq!(solutions.len(), 1);
+    assert!(is_solved(&solutions[0]));
+    for (&p, &s) in puzzle.map.iter().zip(solutions[0].map.iter()) {
+        assert!(p.count_ones() != 1 || p == s);
+    }
+}
+
 #[test]
 fn solution() {
     let puzzle = INPUT.parse::<Sudoku>().unwrap();
     let answer = SOLUTION.parse::<Sudoku>().unwrap();
-    let solution = solve_sudoku(puzzle);
+    let solution = solve_sudoku(&puzzle, 2);
     assert_eq!(solution, [answer]);
+    assert!(puzzle.is_unique());
+}
+
+#[test]
+fn sizes() {
+    for &(w, h) in [(2, 2), (3, 2), (2, 3), (4, 4), (5, 5)].iter() {
+        let solution = solve_sudoku(&Sudoku::new(Rules::new(w, h)), 1);
+        assert!(is_solved(&solution[0]));
+        let text = solution[0].to_string();
+        assert_eq!(Sudoku::parse(Rules::new(w, h), &text.replace("|", "").replace("-", "")
+                                                      .replace("+", "")),
+                   Ok(solution[0].clone()));
+    }
+
+    assert_eq!("1234".parse::<Sudoku>(), Err(ParseError::BadSize(4)));
+    assert_eq!("12.4 34.. .... ...5".parse::<Sudoku>(), Err(ParseError::BadDigit('5')));
+}
+
+#[test]
+fn uniqueness() {
+    let empty = Sudoku::new(Rules::new(2, 2));
+    assert_eq!(solve_sudoku(&empty, 10).len(), 10);
+    assert_eq!(solve_sudoku(&empty, 2).len(), 2);
+    assert!(!empty.is_unique());
+    assert_eq!(empty.rate(), None);
+
+    let mut broken = INPUT.parse::<Sudoku>().unwrap();
+    broken.set(1, 0, 8);
+    assert!(solve_sudoku(&broken, 2).is_empty());
+}
+
+#[test]
+fn variants() {
+    let mut rng: rand::XorShiftRng = rand::SeedableRng::from_seed([1, 2, 3, 4]);
+
+    let diagonal = generate(Rules::new(3, 3).diagonal(), Difficulty::Hard, &mut rng).unwrap();
+    check_generated(&diagonal);
+
+    let layout = "AAABBBCCC AABBBBCCC AAAABBCCC
+                  DDDEEFFFF DDDEEEFFF DDDEEEEFF
+                  GGGHHHHII GGHHHHIII GGGGHIIII";
+    let jigsaw = generate(Rules::jigsaw(layout).unwrap(), Difficulty::Hard, &mut rng).unwrap();
+    check_generated(&jigsaw);
+    assert_eq!(Rules::jigsaw("AABB AABB CCDD CCDC").err(), Some(ParseError::BadRegion('C')));
+    assert_eq!(Rules::jigsaw("AAB AAB AAB").err(), Some(ParseError::BadRegion('A')));
+
+    // a killer puzzle with no givens: cages across the rows of SOLUTION
+    let solution = SOLUTION.parse::<Sudoku>().unwrap();
+    let mut rules = Rules::new(3, 3);
+    for y in 0 .. 9 {
+        rules = rules.cage(&[(0, y), (1, y), (2, y)],
+                           (0 .. 3).fold(0, |a, x| a + solution.get(x, y) as usize));
+        for x in 3 .. 9 {
+            rules = rules.cage(&[(x, y)], solution.get(x, y) as usize);
+        }
+    }
+    let killer = Sudoku::new(rules.clone());
+    let solutions = solve_sudoku(&killer, 2);
+    assert!(solutions.len() >= 1);
+    assert!(is_solved(&solutions[0]));
+    check_generated(&generate(rules, Difficulty::Fiendish, &mut rng).unwrap());
+}
+
+#[test]
+fn generator() {
+    let mut rng: rand::XorShiftRng = rand::SeedableRng::from_seed([5, 6, 7, 8]);
+    for &max in [Difficulty::Easy, Difficulty::Hard].iter() {
+        let puzzle = generate(Rules::new(3, 3), max, &mut rng).unwrap();
+        check_generated(&puzzle);
+        assert!(puzzle.rate().unwrap() <= max);
+    }
+    let small = generate(Rules::new(2, 2), Difficulty::Easy, &mut rng).unwrap();
+    check_generated(&small);
+}
+
+// Every step of the explanation agrees with the solution.
+#[cfg(test)]
+fn check_explanation(puzzle: &Sudoku) -> Explanation {
+    let solution = solve_sudoku(puzzle, 1).pop().unwrap();
+    let explanation = explain(puzzle);
+    for step in explanation.steps.iter() {
+        if let Some((x, y, d)) = step.placed {
+            assert_eq!(solution.get(x, y), d);
+        }
+        for &(x, y, d) in step.removed.iter() {
+            assert!(solution.get(x, y) != d, "{}", step);
+        }
+    }
+    if explanation.solved {
+        assert_eq!(explanation.grid, solution);
+    }
+    explanation
+}
+
+#[test]
+fn explanations() {
+    let mut rng: rand::XorShiftRng = rand::SeedableRng::from_seed([9, 10, 11, 12]);
+    for &max in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard].iter() {
+        for _ in 0 .. 2 {
+            let puzzle = generate(Rules::new(3, 3), max, &mut rng).unwrap();
+            let explanation = check_explanation(&puzzle);
+            assert!(explanation.solved);
+            if max == Difficulty::Easy {
+                assert!(explanation.steps.iter().all(|s| s.placed.is_some()));
+            }
+        }
+    }
+    check_explanation(&INPUT.parse::<Sudoku>().unwrap());
+}
+
+#[test]
+fn techniques() {
+    let empty = Sudoku::new(Rules::new(3, 3));
+    let without = |cells: &[(usize, usize)], d: u32| {
+        let mut grid = empty.clone();
+        for &(x, y) in cells.iter() {
+            grid.map[y * 9 + x] &= !(1 << (d - 1));
+        }
+        grid
+    };
+
+    // 1 and 2 can only go in the first two cells of row 1
+    let mut grid = empty.clone();
+    grid.map[0] = 0b11;
+    grid.map[1] = 0b11;
+    let step = Logic::new(&grid).find(Technique::NakedPair).unwrap();
+    assert_eq!(step.houses, ["row 1"]);
+    assert_eq!(step.removed.len(), 14);
+    assert!(step.to_string().starts_with("Naked pair: 1, 2 at r1c1, r1c2 in row 1 => r1c3 -1"));
+
+    // 7 only fits the top row of box 1
+    let box1 = (0 .. 9).map(|i| (i % 3, i / 3)).filter(|&(_, y)| y > 0).collect::<Vec<_>>();
+    let step = Logic::new(&without(&box1, 7)).find(Technique::PointingPair).unwrap();
+    assert_eq!(step.removed, (3 .. 9).map(|x| (x, 0, 7)).collect::<Vec<_>>());
+    assert_eq!(step.houses, ["box 1", "row 1"]);
+
+    // 5 only fits columns 3 and 8 in rows 2 and 5
+    let others = (0 .. 9).filter(|&x| x != 2 && x != 7)
+        .flat_map(|x| vec![(x, 1), (x, 4)].into_iter())
+        .collect::<Vec<_>>();
+    let step = Logic::new(&without(&others, 5)).find(Technique::XWing).unwrap();
+    assert_eq!(step.cells, [(2, 1), (7, 1), (2, 4), (7, 4)]);
+    assert_eq!(step.removed.len(), 14);
+    assert!(step.removed.iter().all(|&(x, y, d)| (x == 2 || x == 7) && y != 1 && y != 4 && d == 5));
+
+    // {1, 2} at r1c1 sees {1, 3} at r1c5 and {2, 3} at r5c1
+    let mut grid = empty.clone();
+    grid.map[0] = 0b011;
+    grid.map[4] = 0b101;
+    grid.map[36] = 0b110;
+    let step = Logic::new(&grid).find(Technique::XYWing).unwrap();
+    assert_eq!(step.removed, [(4, 4, 3)]);
+    assert!(Logic::new(&grid).find(Technique::Swordfish).is_none());
 }
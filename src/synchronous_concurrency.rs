@@ -1,48 +1,303 @@
 // http://rosettacode.org/wiki/Synchronous_concurrency
 //
 // Reader unit reads lines from input.txt, send lines one at a time to printer
-// unit, which then prints lines, keeping track of lines printed. At the end of
-// the file, the reader unit requests number of lines printed from the printer
-// unit, and then prints them.
+// unit, which then prints lines, keeping track of lines printed. The task has
+// the reader ask the printer for the number of lines printed at the end of the
+// file; here nothing goes back to the reader. Instead the count is what the
+// printer returns once every stage has been joined, and that's what's printed.
+//
+// The units are stages of a pipeline, each on its own thread, connected by
+// bounded channels: a stage that gets ahead of the next one waits for it to
+// catch up (with a bound of 0, as here, every line is handed over in person).
+// A stage can be split into several workers sharing the work (fan-out), whose
+// results come back together into one channel (fan-in), and pipelines can be
+// merged. When a stage runs out of input, or fails, it drops its end of the
+// channel and the stages after it finish up; the stages before it find there
+// is nobody listening, and stop. Every stage is joined before the result is
+// handed back, along with anything that went wrong.
 use std::fs::File;
 use std::io::{BufReader, BufRead};
-use std::sync::mpsc::{channel, Sender, Receiver};
-use std::thread;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+const FILENAME: &'static str = "src/resources/input.txt";
+
+// Why a stage stopped before the end of its input.
+#[derive(Debug)]
+pub enum Stop {
+    // The next stage has gone, so there's no point going on.  This isn't an
+    // error: the stage that went will have said why, if it failed.
+    Closed,
+    Failed(String),
+}
+
+impl From<String> for Stop {
+    fn from(message: String) -> Stop {
+        Stop::Failed(message)
+    }
+}
 
-const FILENAME: &'static str = "resources/input.txt";
+// Where a stage sends what it makes.
+pub struct Output<T> {
+    sender: SyncSender<T>,
+}
+
+impl<T> Output<T> {
+    // Waits for room in the channel.
+    pub fn send(&self, item: T) -> Result<(), Stop> {
+        self.sender.send(item).map_err(|_| Stop::Closed)
+    }
+}
+
+type Stage = (String, JoinHandle<Result<(), Stop>>);
+
+pub struct Pipeline<T> {
+    bound: usize,
+    output: Receiver<T>,
+    stages: Vec<Stage>,
+}
 
-enum Message {
-    Line(String),
-    End
+// What the sink came to, and the stages that failed, in pipeline order.
+pub struct Finished<R> {
+    pub output: R,
+    pub errors: Vec<(String, String)>,
 }
 
-fn printer(i_snd: Sender<i32>, msg_rcv: Receiver<Message>) {
-    let mut count = 0;
-    loop {
-        match msg_rcv.recv().unwrap() {
-            Message::Line(line) => {
-                println!("{}", line);
-                count += 1;
+impl<T: Send + 'static> Pipeline<T> {
+    // A pipeline starting with `source`, whose channels each hold at most
+    // `bound` items.
+    pub fn source<F>(name: &str, bound: usize, source: F) -> Pipeline<T>
+        where F: FnOnce(&Output<T>) -> Result<(), Stop> + Send + 'static
+    {
+        let (sender, receiver) = sync_channel(bound);
+        let thread = thread::spawn(move || source(&Output { sender: sender }));
+        Pipeline { bound: bound, output: receiver, stages: vec![(name.to_string(), thread)] }
+    }
+
+    // Adds a stage, which is given each item in turn and can send on any
+    // number of its own.
+    pub fn stage<U, F>(self, name: &str, mut stage: F) -> Pipeline<U>
+        where U: Send + 'static,
+              F: FnMut(T, &Output<U>) -> Result<(), Stop> + Send + 'static
+    {
+        let (sender, receiver) = sync_channel(self.bound);
+        let input = self.output;
+        let thread = thread::spawn(move || {
+            let output = Output { sender: sender };
+            for item in input.iter() {
+                try!(stage(item, &output));
             }
-            Message::End => {break;}
+            Ok(())
+        });
+        let mut stages = self.stages;
+        stages.push((name.to_string(), thread));
+        Pipeline { bound: self.bound, output: receiver, stages: stages }
+    }
+
+    // Adds a stage with `workers` threads taking items as they're free, so
+    // what comes out may be in a different order.
+    pub fn parallel<U, F>(self, name: &str, workers: usize, stage: F) -> Pipeline<U>
+        where U: Send + 'static,
+              F: Fn(T, &Output<U>) -> Result<(), Stop> + Send + Sync + 'static
+    {
+        assert!(workers > 0, "A stage needs at least one worker");
+        let (sender, receiver) = sync_channel(self.bound);
+        let input = Arc::new(Mutex::new(self.output));
+        let stage = Arc::new(stage);
+        let mut stages = self.stages;
+        for worker in 0..workers {
+            let (input, stage) = (input.clone(), stage.clone());
+            let output = Output { sender: sender.clone() };
+            let thread = thread::spawn(move || {
+                loop {
+                    // The lock is only held while waiting for an item, not
+                    // while working on it.
+                    let item = match input.lock().unwrap().recv() {
+                        Ok(item) => item,
+                        Err(_) => return Ok(()),
+                    };
+                    try!(stage(item, &output));
+                }
+            });
+            stages.push((format!("{} {}", name, worker + 1), thread));
+        }
+        Pipeline { bound: self.bound, output: receiver, stages: stages }
+    }
+
+    // Both pipelines' items, in whatever order they come.
+    pub fn merge(self, other: Pipeline<T>) -> Pipeline<T> {
+        let (sender, receiver) = sync_channel(self.bound);
+        let mut stages = self.stages;
+        stages.extend(other.stages.into_iter());
+        for (i, input) in vec![self.output, other.output].into_iter().enumerate() {
+            let output = Output { sender: sender.clone() };
+            let thread = thread::spawn(move || {
+                for item in input.iter() {
+                    try!(output.send(item));
+                }
+                Ok(())
+            });
+            stages.push((format!("merge {}", i + 1), thread));
         }
+        Pipeline { bound: self.bound, output: receiver, stages: stages }
+    }
+
+    // Runs `sink` on each item that comes out of the pipeline, on this
+    // thread, starting from `init`, then waits for every stage to finish.
+    pub fn sink<R, F>(self, name: &str, init: R, mut sink: F) -> Finished<R>
+        where F: FnMut(&mut R, T) -> Result<(), Stop>
+    {
+        let mut output = init;
+        let mut result = Ok(());
+        for item in self.output.iter() {
+            result = sink(&mut output, item);
+            if result.is_err() {
+                break;
+            }
+        }
+        // Anything still on its way is dropped, so the stages can't be stuck
+        // waiting to send it.
+        drop(self.output);
+
+        let mut errors = vec![];
+        for (stage, thread) in self.stages {
+            match thread.join() {
+                Ok(Err(Stop::Failed(message))) => errors.push((stage, message)),
+                Ok(_) => {}
+                Err(_) => errors.push((stage, "panicked".to_string())),
+            }
+        }
+        if let Err(Stop::Failed(message)) = result {
+            errors.push((name.to_string(), message));
+        }
+        Finished { output: output, errors: errors }
     }
-    i_snd.send(count).unwrap();
 }
 
-fn reader(msg_snd: Sender<Message>, i_rcv: Receiver<i32>) {
-    let file = BufReader::new(File::open(FILENAME).unwrap());
-    for line in file.lines() {
-        msg_snd.send(Message::Line(line.unwrap())).unwrap();
+fn reader(filename: &str, output: &Output<String>) -> Result<(), Stop> {
+    let file = try!(File::open(filename).map_err(|e| format!("{}: {}", filename, e)));
+    for line in BufReader::new(file).lines() {
+        try!(output.send(try!(line.map_err(|e| e.to_string()))));
     }
-    msg_snd.send(Message::End).unwrap();
-    println!("Total Lines: {}", i_rcv.recv().unwrap());
+    Ok(())
+}
+
+fn lines(filename: &str) -> Pipeline<String> {
+    let filename = filename.to_string();
+    // A bound of 0: the reader waits for the printer to take each line.
+    Pipeline::source("reader", 0, move |output| reader(&filename, output))
 }
 
+#[cfg(not(test))]
 fn main() {
-    let (msg_snd, msg_rcv) = channel();
-    let (i_snd, i_rcv) = channel();
+    use std::env;
+
+    let filename = env::args().nth(1).unwrap_or(FILENAME.to_string());
+    let printed = lines(&filename).sink("printer", 0, |count, line| {
+        println!("{}", line);
+        *count += 1;
+        Ok(())
+    });
+    println!("Total Lines: {}", printed.output);
+    for (stage, message) in printed.errors {
+        println!("{} failed: {}", stage, message);
+    }
+}
+
+#[cfg(test)]
+fn count<T>(count: &mut usize, _: T) -> Result<(), Stop> {
+    *count += 1;
+    Ok(())
+}
+
+#[cfg(test)]
+fn numbers(bound: usize, n: u64) -> Pipeline<u64> {
+    Pipeline::source("numbers", bound, move |output| {
+        for i in 0..n {
+            try!(output.send(i));
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_lines() {
+    let printed = lines(FILENAME).sink("printer", 0, count);
+    assert_eq!(printed.output, 37);
+    assert!(printed.errors.is_empty());
 
-    thread::spawn(move || printer(i_snd, msg_rcv));
-    thread::spawn(move || reader(msg_snd, i_rcv));
+    let printed = lines("no such file").sink("printer", 0, count);
+    assert_eq!(printed.output, 0);
+    assert_eq!(printed.errors.len(), 1);
+    assert_eq!(printed.errors[0].0, "reader");
+}
+
+#[test]
+fn test_fan_out_and_in() {
+    let squares = numbers(4, 1000)
+        .parallel("square", 4, |i, output| output.send(i * i))
+        .stage("both", |i, output| {
+            try!(output.send(i));
+            output.send(i)
+        })
+        .sink("sum", 0, |sum, i| Ok(*sum += i));
+    assert_eq!(squares.output, 2 * 999 * 1000 * 1999 / 6);
+    assert!(squares.errors.is_empty());
+
+    let merged = numbers(1, 10).merge(numbers(1, 20)).merge(numbers(0, 30)).sink("count", 0, count);
+    assert_eq!(merged.output, 60);
+}
+
+#[test]
+fn test_backpressure() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    for &bound in [0, 1, 5].iter() {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let counted = sent.clone();
+        let received = Pipeline::source("source", bound, move |output| {
+            for i in 0..200 {
+                try!(output.send(i));
+                counted.fetch_add(1, Ordering::SeqCst);
+            }
+            Ok(())
+        }).sink("slow", 0, |received, _| {
+            *received += 1;
+            thread::yield_now();
+            // the source can't get more than the channel holds ahead
+            assert!(sent.load(Ordering::SeqCst) <= *received + bound + 1);
+            Ok(())
+        });
+        assert_eq!(received.output, 200);
+    }
+}
+
+#[test]
+fn test_errors() {
+    // a stage failing ends the stream for those after it and stops those
+    // before it, and everything is still joined
+    let finished = numbers(2, 1000000)
+        .stage("check", |i, output| {
+            if i == 10 {
+                return Err(Stop::Failed("too big".to_string()));
+            }
+            output.send(i)
+        })
+        .sink("count", 0, count);
+    assert_eq!(finished.output, 10);
+    assert_eq!(finished.errors, [("check".to_string(), "too big".to_string())]);
+
+    let finished = numbers(2, 1000000).parallel("panic", 2, |i, output| {
+        if i == 3 {
+            panic!("three");
+        }
+        output.send(i)
+    }).sink("stop", 0, |n, _| {
+        *n += 1;
+        if *n == 100 { Err(Stop::Failed("enough".to_string())) } else { Ok(()) }
+    });
+    assert!(finished.output <= 100);
+    assert!(finished.errors.iter().any(|e| e.1 == "panicked"));
+    assert!(finished.errors.iter().all(|e| e.1 == "panicked" || e.0 == "stop"));
 }
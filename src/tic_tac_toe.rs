@@ -1,158 +1,956 @@
 // Implements http://rosettacode.org/wiki/Tic-tac-toe
+//
+// The board can be any size, with any number in a row needed to win (an
+// m,n,k-game): 3,3,3 is tic-tac-toe, 4,4,4 needs a whole line of a 4x4 board
+// and 15,15,5 is gomoku. The computer plays by negamax with alpha-beta
+// pruning, remembering the positions it has worked out in a transposition
+// table. That searches small boards to the end, so it never loses at
+// tic-tac-toe; on big ones it looks a few moves ahead and then guesses.
+//
+// `tic_tac_toe tournament` has the computer play itself instead: random
+// moves, minimax and Monte Carlo tree search against each other, with the
+// results in a table.
 extern crate rand;
 
-use GameState::{PlayerWin, ComputerWin, Draw, Playing};
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::fmt;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use Player::{X, O};
+use GameState::{Won, Draw, Playing};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Player {
+	X,
+	O,
+}
+
+impl Player {
+	fn other(self) -> Player {
+		match self {
+			X => O,
+			O => X,
+		}
+	}
+
+	fn index(self) -> usize {
+		match self {
+			X => 0,
+			O => 1,
+		}
+	}
+}
 
 #[derive(PartialEq, Debug)]
 enum GameState {
-	PlayerWin,
-	ComputerWin,
+	Won(Player),
 	Draw,
 	Playing,
 }
 
-#[cfg(not(test))]
-fn main() {
-	let mut board: [[char; 3]; 3] = [
-		['1', '2', '3'],
-		['4', '5', '6'],
-		['7', '8', '9']
-	];
+// The directions a line can go in: across, down and the two diagonals.
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
 
-	draw_board(board);
-	loop {
-		player_turn(&mut board);
-		if check_win(board) != Playing {
-			break;
+#[derive(Clone)]
+struct Board {
+	width: usize,
+	height: usize,
+	k: usize,
+	cells: Vec<Option<Player>>,
+	filled: usize,
+	to_move: Player,
+	// Set by a winning move. Nobody plays on after that, so undoing a move
+	// always clears it.
+	winner: Option<Player>,
+	// Zobrist hashing: the xor of a random key for each stone on the board
+	keys: Vec<[u64; 2]>,
+	hash: u64,
+}
+
+impl Board {
+	fn new(width: usize, height: usize, k: usize) -> Board {
+		assert!(k >= 1 && k <= max(width, height), "can't get {} in a row on {}x{}", k, width, height);
+		let mut seed = 0x2545f4914f6cdd1d;
+		let mut next = || {
+			seed ^= seed << 13;
+			seed ^= seed >> 7;
+			seed ^= seed << 17;
+			seed
+		};
+		let keys = (0..width * height).map(|_| [next(), next()]).collect();
+		Board {
+			width: width,
+			height: height,
+			k: k,
+			cells: vec![None; width * height],
+			filled: 0,
+			to_move: X,
+			winner: None,
+			keys: keys,
+			hash: 0,
 		}
-		computer_turn(&mut board);
-		if check_win(board) != Playing {
-			break;
+	}
+
+	// Reads a board from rows of 'X', 'O' and '.', to get k in a row. X
+	// always goes first, which gives whose turn it is.
+	#[cfg(test)]
+	fn parse(rows: &[&str], k: usize) -> Board {
+		let mut board = Board::new(rows[0].len(), rows.len(), k);
+		for (y, row) in rows.iter().enumerate() {
+			for (x, c) in row.chars().enumerate() {
+				let player = match c {
+					'X' => X,
+					'O' => O,
+					_ => continue,
+				};
+				let cell = y * board.width + x;
+				board.cells[cell] = Some(player);
+				board.filled += 1;
+				board.hash ^= board.keys[cell][player.index()];
+			}
 		}
-		draw_board(board);
+		let xs = board.cells.iter().filter(|&&c| c == Some(X)).count();
+		board.to_move = if 2 * xs > board.filled { O } else { X };
+		board.winner = (0..board.cells.len()).filter(|&cell| board.wins_at(cell))
+			.map(|cell| board.cells[cell].unwrap()).next();
+		board
 	}
 
-	draw_board(board);
-	let announcement = match check_win(board) {
-		PlayerWin => "The Player has won!",
-		ComputerWin => "The Computer has won!",
-		Draw => "Draw!",
-		Playing => unreachable!(),
-	};
-	println!("{}", announcement);
+	fn state(&self) -> GameState {
+		match self.winner {
+			Some(player) => Won(player),
+			None if self.filled == self.cells.len() => Draw,
+			None => Playing,
+		}
+	}
+
+	fn is_free(&self, cell: usize) -> bool {
+		cell < self.cells.len() && self.cells[cell].is_none()
+	}
+
+	fn play(&mut self, cell: usize) {
+		assert!(self.is_free(cell) && self.winner.is_none());
+		let player = self.to_move;
+		self.cells[cell] = Some(player);
+		self.filled += 1;
+		self.hash ^= self.keys[cell][player.index()];
+		self.to_move = player.other();
+		if self.wins_at(cell) {
+			self.winner = Some(player);
+		}
+	}
+
+	fn undo(&mut self, cell: usize) {
+		let player = self.cells[cell].take().unwrap();
+		self.filled -= 1;
+		self.hash ^= self.keys[cell][player.index()];
+		self.to_move = player;
+		self.winner = None;
+	}
+
+	// The cell dx, dy away from x, y, if that's still on the board.
+	fn step(&self, x: usize, y: usize, dx: isize, dy: isize) -> Option<(usize, usize)> {
+		let (x, y) = (x as isize + dx, y as isize + dy);
+		if x < 0 || y < 0 || x >= self.width as isize || y >= self.height as isize {
+			None
+		} else {
+			Some((x as usize, y as usize))
+		}
+	}
+
+	// Whether the stone on a cell is part of k in a row.
+	fn wins_at(&self, cell: usize) -> bool {
+		let player = match self.cells[cell] {
+			Some(player) => player,
+			None => return false,
+		};
+		let (x0, y0) = (cell % self.width, cell / self.width);
+		DIRECTIONS.iter().any(|&(dx, dy)| {
+			let mut count = 1;
+			for &sign in [1, -1].iter() {
+				let (mut x, mut y) = (x0, y0);
+				while let Some((x2, y2)) = self.step(x, y, sign * dx, sign * dy) {
+					if self.cells[y2 * self.width + x2] != Some(player) {
+						break;
+					}
+					count += 1;
+					x = x2;
+					y = y2;
+				}
+			}
+			count >= self.k
+		})
+	}
+
+	// The moves worth looking at, the ones nearest the middle first. On a
+	// big board that's only the empty cells next to a stone.
+	fn candidates(&self) -> Vec<usize> {
+		let near_stones = self.cells.len() > 25 && self.filled > 0;
+		let mut moves = (0..self.cells.len()).filter(|&cell| {
+			self.cells[cell].is_none() && (!near_stones || {
+				let (x, y) = (cell % self.width, cell / self.width);
+				(-1..2).any(|dy| (-1..2).any(|dx| {
+					match self.step(x, y, dx, dy) {
+						Some((x, y)) => self.cells[y * self.width + x].is_some(),
+						None => false,
+					}
+				}))
+			})
+		}).collect::<Vec<_>>();
+		let distance = |cell: usize| {
+			let (x, y) = ((cell % self.width) as isize, (cell / self.width) as isize);
+			let (dx, dy) = (2 * x - self.width as isize + 1, 2 * y - self.height as isize + 1);
+			dx * dx + dy * dy
+		};
+		moves.sort_by(|&a, &b| distance(a).cmp(&distance(b)));
+		moves
+	}
+
+	// A guess at how good the position is for the player to move, from every
+	// run of k cells that only one player has stones in: the more stones, the
+	// better for them.
+	fn evaluate(&self) -> i64 {
+		let mut score = [0, 0];
+		for y in 0..self.height {
+			for x in 0..self.width {
+				for &(dx, dy) in DIRECTIONS.iter() {
+					let mut counts = [0, 0];
+					let (mut cx, mut cy) = (x, y);
+					let mut length = 1;
+					if let Some(player) = self.cells[y * self.width + x] {
+						counts[player.index()] += 1;
+					}
+					while length < self.k {
+						match self.step(cx, cy, dx, dy) {
+							Some((x2, y2)) => {
+								if let Some(player) = self.cells[y2 * self.width + x2] {
+									counts[player.index()] += 1;
+								}
+								cx = x2;
+								cy = y2;
+								length += 1;
+							}
+							None => break,
+						}
+					}
+					if length < self.k {
+						continue;
+					}
+					for p in 0..2 {
+						if counts[p] > 0 && counts[1 - p] == 0 {
+							score[p] += 1 << min(3 * counts[p], 30);
+						}
+					}
+				}
+			}
+		}
+		let me = self.to_move.index();
+		score[me] - score[1 - me]
+	}
 }
 
-fn check_win(board: [[char; 3]; 3]) -> GameState {
-	// check for win
-	for i in (0..3) {
-		if board[i][0] == board[i][1] && board[i][0] == board[i][2] {
-			return which_win(board[i][0])
-		} else if board[0][i] == board[1][i] && board[0][i] == board[2][i] {
-			return which_win(board[0][i])
+impl fmt::Display for Board {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		try!(write!(f, "   "));
+		for x in 0..self.width {
+			try!(write!(f, "{:>3}", x + 1));
+		}
+		try!(writeln!(f, ""));
+		for y in 0..self.height {
+			try!(write!(f, "{:>3}", y + 1));
+			for x in 0..self.width {
+				let c = match self.cells[y * self.width + x] {
+					Some(X) => 'X',
+					Some(O) => 'O',
+					None => '.',
+				};
+				try!(write!(f, "{:>3}", c));
+			}
+			try!(writeln!(f, ""));
 		}
+		Ok(())
 	}
-	if board[0][0] == board[1][1] && board[0][0] == board[2][2] {
-		return which_win(board[0][0])
-	} else if board[0][2] == board[1][1] && board[0][2] == board[2][0] {
-		return which_win(board[0][2])
+}
+
+// A win is worth more than any position the heuristic can judge.
+const WIN: i64 = 1 << 40;
+const INFINITY: i64 = 1 << 50;
+
+// Too many positions to keep: start again.
+const TABLE_SIZE: usize = 1 << 22;
+
+#[derive(Clone, Copy)]
+enum Bound {
+	Exact,
+	// the real value is at least this
+	Lower,
+	// the real value is at most this
+	Upper,
+}
+
+struct Entry {
+	depth: usize,
+	value: i64,
+	bound: Bound,
+	best: usize,
+}
+
+struct Ai {
+	// How many moves to look ahead; None looks all the way.
+	depth: Option<usize>,
+	table: HashMap<u64, Entry>,
+}
+
+impl Ai {
+	fn perfect() -> Ai {
+		Ai { depth: None, table: HashMap::new() }
 	}
 
-	// check if it's not a draw
-	for row in board.iter() {
-		for &c in row.iter() {
-			if c != 'X' && c != 'O' {
-				return Playing
+	fn with_depth(depth: usize) -> Ai {
+		Ai { depth: Some(depth), table: HashMap::new() }
+	}
+
+	fn best_move(&mut self, board: &mut Board) -> usize {
+		assert_eq!(board.state(), Playing);
+		if self.table.len() > TABLE_SIZE {
+			self.table.clear();
+		}
+		let empty = board.cells.len() - board.filled;
+		let depth = max(min(self.depth.unwrap_or(empty), empty), 1);
+		self.negamax(board, depth, -INFINITY, INFINITY);
+		self.table[&board.hash].best
+	}
+
+	// The value of the position to the player about to move, looking `depth`
+	// moves ahead. A win is worth more the more empty cells are left, so the
+	// computer wins as quickly as it can and loses as slowly as it can; since
+	// that doesn't depend on the moves that got there, the values can be
+	// shared between positions reached in different orders.
+	fn negamax(&mut self, board: &mut Board, depth: usize, mut alpha: i64, beta: i64) -> i64 {
+		let empty = board.cells.len() - board.filled;
+		if empty == 0 {
+			return 0;
+		}
+		if depth == 0 {
+			return board.evaluate();
+		}
+
+		let mut moves = board.candidates();
+		if let Some(entry) = self.table.get(&board.hash) {
+			if entry.depth >= depth {
+				match entry.bound {
+					Bound::Exact => return entry.value,
+					Bound::Lower if entry.value >= beta => return entry.value,
+					Bound::Upper if entry.value <= alpha => return entry.value,
+					_ => {}
+				}
+			}
+			// try the best move from last time first
+			if let Some(i) = moves.iter().position(|&cell| cell == entry.best) {
+				moves.remove(i);
+				moves.insert(0, entry.best);
 			}
 		}
+
+		let original_alpha = alpha;
+		let (mut best_value, mut best_move) = (-INFINITY, moves[0]);
+		for &cell in moves.iter() {
+			board.play(cell);
+			let value = if board.winner.is_some() {
+				WIN + empty as i64 - 1
+			} else {
+				-self.negamax(board, depth - 1, -beta, -alpha)
+			};
+			board.undo(cell);
+
+			if value > best_value {
+				best_value = value;
+				best_move = cell;
+			}
+			alpha = max(alpha, value);
+			if alpha >= beta {
+				break;
+			}
+		}
+
+		let bound = if best_value <= original_alpha {
+			Bound::Upper
+		} else if best_value >= beta {
+			Bound::Lower
+		} else {
+			Bound::Exact
+		};
+		self.table.insert(board.hash, Entry {
+			depth: depth,
+			value: best_value,
+			bound: bound,
+			best: best_move,
+		});
+		best_value
+	}
+}
+
+// A game two players take turns at, knowing everything and leaving nothing
+// to chance, for the computer to play without anyone at the keyboard.
+trait Game: Clone {
+	type Move: Copy;
+
+	fn to_move(&self) -> Player;
+	fn legal_moves(&self) -> Vec<Self::Move>;
+	fn apply(&mut self, m: Self::Move);
+	fn is_over(&self) -> bool;
+	// How the game went for a player: 1 for a win, 0 for a draw (or if it
+	// isn't over) and -1 for a loss.
+	fn score(&self, player: Player) -> i32;
+}
+
+impl Game for Board {
+	type Move = usize;
+
+	fn to_move(&self) -> Player {
+		self.to_move
+	}
+
+	fn legal_moves(&self) -> Vec<usize> {
+		if self.winner.is_some() {
+			return vec![];
+		}
+		(0..self.cells.len()).filter(|&cell| self.cells[cell].is_none()).collect()
 	}
-	Draw
+
+	fn apply(&mut self, cell: usize) {
+		self.play(cell);
+	}
+
+	fn is_over(&self) -> bool {
+		self.state() != Playing
+	}
+
+	fn score(&self, player: Player) -> i32 {
+		match self.state() {
+			Won(winner) if winner == player => 1,
+			Won(_) => -1,
+			Draw | Playing => 0,
+		}
+	}
+}
+
+// A way of choosing moves. Anything random comes from `rng`, so that a game
+// can be played again from the same seed.
+trait Strategy<G: Game> {
+	fn name(&self) -> String;
+	fn choose(&mut self, game: &G, rng: &mut XorShiftRng) -> G::Move;
 }
 
-fn which_win(s: char) -> GameState {
-	match s {
-		'X' => PlayerWin,
-		'O' => ComputerWin,
-		_ => unreachable!()
+struct RandomMoves;
+
+impl<G: Game> Strategy<G> for RandomMoves {
+	fn name(&self) -> String {
+		"random".to_string()
+	}
+
+	fn choose(&mut self, game: &G, rng: &mut XorShiftRng) -> G::Move {
+		*rng.choose(&game.legal_moves()).unwrap()
+	}
+}
+
+impl Strategy<Board> for Ai {
+	fn name(&self) -> String {
+		match self.depth {
+			Some(depth) => format!("minimax (depth {})", depth),
+			None => "minimax".to_string(),
+		}
+	}
+
+	fn choose(&mut self, board: &Board, _: &mut XorShiftRng) -> usize {
+		self.best_move(&mut board.clone())
+	}
+}
+
+// Monte Carlo tree search (UCT): grows a tree of the moves from here, going
+// down the branches that have done well so far but now and then trying the
+// others, and plays each new position out at random to see who wins.
+struct Mcts {
+	iterations: usize,
+}
+
+struct Node<M> {
+	// the moves from here that aren't in the tree yet
+	untried: Vec<M>,
+	children: Vec<(M, usize)>,
+	// who made the move to get here, and how well it's done for them
+	player: Player,
+	visits: f64,
+	reward: f64,
+}
+
+impl<M> Node<M> {
+	fn new<G: Game<Move = M>>(game: &G) -> Node<M> {
+		Node {
+			untried: game.legal_moves(),
+			children: vec![],
+			player: game.to_move().other(),
+			visits: 0.0,
+			reward: 0.0,
+		}
+	}
+}
+
+// How much to try the moves that haven't been tried much.
+const EXPLORATION: f64 = 1.4;
+
+impl<G: Game> Strategy<G> for Mcts {
+	fn name(&self) -> String {
+		format!("mcts ({})", self.iterations)
+	}
+
+	fn choose(&mut self, game: &G, rng: &mut XorShiftRng) -> G::Move {
+		let mut tree = vec![Node::new(game)];
+		for _ in 0..self.iterations {
+			let mut game = game.clone();
+			let mut path = vec![0];
+
+			// Go down the tree as far as it goes...
+			loop {
+				let node = *path.last().unwrap();
+				if !tree[node].untried.is_empty() || tree[node].children.is_empty() {
+					break;
+				}
+				let log_visits = tree[node].visits.ln();
+				let mut best = (-1.0, tree[node].children[0]);
+				for &(m, child) in tree[node].children.iter() {
+					let n = &tree[child];
+					let ucb = n.reward / n.visits + EXPLORATION * (log_visits / n.visits).sqrt();
+					if ucb > best.0 {
+						best = (ucb, (m, child));
+					}
+				}
+				let (m, child) = best.1;
+				game.apply(m);
+				path.push(child);
+			}
+
+			// ...add one move to it...
+			let node = *path.last().unwrap();
+			if !tree[node].untried.is_empty() {
+				let i = rng.gen_range(0, tree[node].untried.len());
+				let m = tree[node].untried.swap_remove(i);
+				game.apply(m);
+				let child = tree.len();
+				tree.push(Node::new(&game));
+				tree[node].children.push((m, child));
+				path.push(child);
+			}
+
+			// ...play on at random...
+			while !game.is_over() {
+				let m = *rng.choose(&game.legal_moves()).unwrap();
+				game.apply(m);
+			}
+
+			// ...and count how it went on the way back up.
+			for &node in path.iter() {
+				tree[node].visits += 1.0;
+				tree[node].reward += (game.score(tree[node].player) + 1) as f64 / 2.0;
+			}
+		}
+
+		// the move tried the most is the one that's done best
+		let mut best = (0.0, tree[0].children[0].0);
+		for &(m, child) in tree[0].children.iter() {
+			if tree[child].visits > best.0 {
+				best = (tree[child].visits, m);
+			}
+		}
+		best.1
+	}
+}
+
+// Plays one game between two of the players, and returns how it went for
+// the first of them, who goes first.
+fn play_game<G: Game>(start: &G, players: &mut [Box<Strategy<G>>], first: usize, second: usize,
+                      rng: &mut XorShiftRng) -> i32 {
+	let mut game = start.clone();
+	let player = game.to_move();
+	while !game.is_over() {
+		let turn = if game.to_move() == player { first } else { second };
+		let m = players[turn].choose(&game, rng);
+		game.apply(m);
+	}
+	game.score(player)
+}
+
+struct Standing {
+	name: String,
+	wins: usize,
+	draws: usize,
+	losses: usize,
+	elo: f64,
+}
+
+struct Results {
+	standings: Vec<Standing>,
+	// points[i][j] is what i scored against j, with half a point for a draw
+	points: Vec<Vec<f64>>,
+}
+
+const START_ELO: f64 = 1500.0;
+const ELO_K: f64 = 16.0;
+
+// Every player plays every other `games` times, taking turns to go first.
+// Each game has its own random numbers from the seed, the pair and the
+// number of the game, so the same seed always gives the same results. Elo
+// ratings are updated after each game, in the order they're played.
+fn tournament<G: Game>(start: &G, players: &mut [Box<Strategy<G>>], games: usize, seed: u32)
+                       -> Results {
+	let n = players.len();
+	let mut standings = players.iter().map(|p| Standing {
+		name: p.name(),
+		wins: 0,
+		draws: 0,
+		losses: 0,
+		elo: START_ELO,
+	}).collect::<Vec<_>>();
+	let mut points = vec![vec![0.0; n]; n];
+
+	for i in 0..n {
+		for j in i + 1..n {
+			for game in 0..games {
+				let mut rng: XorShiftRng =
+					SeedableRng::from_seed([seed, i as u32 + 1, j as u32 + 1, game as u32 + 1]);
+				let (a, b) = if game % 2 == 0 { (i, j) } else { (j, i) };
+				let result = play_game(start, players, a, b, &mut rng);
+
+				let score = (result + 1) as f64 / 2.0;
+				points[a][b] += score;
+				points[b][a] += 1.0 - score;
+				match result {
+					1 => { standings[a].wins += 1; standings[b].losses += 1; }
+					-1 => { standings[a].losses += 1; standings[b].wins += 1; }
+					_ => { standings[a].draws += 1; standings[b].draws += 1; }
+				}
+				let expected = 1.0 / (1.0 + 10f64.powf((standings[b].elo - standings[a].elo) / 400.0));
+				standings[a].elo += ELO_K * (score - expected);
+				standings[b].elo -= ELO_K * (score - expected);
+			}
+		}
+	}
+	Results { standings: standings, points: points }
+}
+
+impl fmt::Display for Results {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let width = self.standings.iter().map(|s| s.name.len()).max().unwrap_or(0);
+		let mut order = (0..self.standings.len()).collect::<Vec<_>>();
+		order.sort_by(|&a, &b| self.standings[b].elo.partial_cmp(&self.standings[a].elo).unwrap());
+
+		try!(writeln!(f, "{:<w$} {:>5} {:>5} {:>5} {:>6}", "", "won", "drawn", "lost", "elo", w = width));
+		for &i in order.iter() {
+			let s = &self.standings[i];
+			try!(writeln!(f, "{:<w$} {:>5} {:>5} {:>5} {:>6.0}", s.name, s.wins, s.draws, s.losses, s.elo,
+			              w = width));
+		}
+		try!(writeln!(f, ""));
+
+		// who scored what against whom
+		try!(write!(f, "{:<w$}", "", w = width + 2));
+		for j in 0..order.len() {
+			try!(write!(f, " {:>6}", j + 1));
+		}
+		try!(writeln!(f, ""));
+		for (row, &i) in order.iter().enumerate() {
+			try!(write!(f, "{:<w$}", format!("{} {}", row + 1, self.standings[i].name), w = width + 2));
+			for &j in order.iter() {
+				if i == j {
+					try!(write!(f, " {:>6}", "-"));
+				} else {
+					try!(write!(f, " {:>6.1}", self.points[i][j]));
+				}
+			}
+			try!(writeln!(f, ""));
+		}
+		Ok(())
 	}
 }
 
 #[cfg(not(test))]
-fn player_turn(board: &mut [[char; 3]; 3]) {
-    use std::io;
-
-	println!("Player, enter your field of choice!: ");
-    let mut ln = String::new();
-	let _ = io::stdin().read_line(&mut ln);
-	let choice = ln.trim().parse::<usize>().unwrap();
-	let row = (choice - 1) / 3;
-	let col = (choice - 1) % 3;
-
-	if board[row][col] == 'X' || board[row][col] == 'O' {
-		println!("Someone already took this field!");
-		player_turn(board);
-	} else {
-		board[row][col] = 'X';
+fn main() {
+	use std::env;
+
+	let usage = "usage: tic_tac_toe [x|o|both|none] [WIDTH HEIGHT K [DEPTH]]\n       \
+	             tic_tac_toe tournament [GAMES [SEED [WIDTH HEIGHT K]]]";
+	let args = env::args().skip(1).collect::<Vec<_>>();
+	if args.get(0).map(|s| &s[..]) == Some("tournament") {
+		return match args.iter().skip(1).map(|s| s.parse::<usize>()).collect::<Result<Vec<_>, _>>() {
+			Ok(ref numbers) if numbers.len() <= 2 || numbers.len() == 5 => run_tournament(numbers),
+			_ => println!("{}", usage),
+		};
+	}
+	let computer = match args.get(0).map(|s| &s[..]) {
+		Some("x") | Some("X") => vec![X],
+		None | Some("o") | Some("O") => vec![O],
+		Some("both") => vec![X, O],
+		Some("none") => vec![],
+		Some(_) => return println!("{}", usage),
+	};
+	let numbers = match args.iter().skip(1).map(|s| s.parse::<usize>()).collect::<Result<Vec<_>, _>>() {
+		Ok(numbers) => numbers,
+		Err(_) => return println!("{}", usage),
+	};
+	// Small boards are searched to the end.
+	let (width, height, k, depth) = match numbers.len() {
+		0 => (3, 3, 3, None),
+		3 if numbers[0] * numbers[1] <= 16 => (numbers[0], numbers[1], numbers[2], None),
+		3 => (numbers[0], numbers[1], numbers[2], Some(4)),
+		4 => (numbers[0], numbers[1], numbers[2], Some(numbers[3])),
+		_ => return println!("{}", usage),
+	};
+	if k == 0 || k > max(width, height) {
+		return println!("can't get {} in a row on {}x{}", k, width, height);
+	}
+
+	let mut board = Board::new(width, height, k);
+	let mut ai = match depth {
+		Some(depth) => Ai::with_depth(depth),
+		None => Ai::perfect(),
+	};
+	print!("{}", board);
+	while board.state() == Playing {
+		let cell = if computer.contains(&board.to_move) {
+			let cell = ai.best_move(&mut board);
+			println!("Computer ({:?}) chose: {} {}", board.to_move, cell / width + 1, cell % width + 1);
+			cell
+		} else {
+			match player_turn(&board) {
+				Some(cell) => cell,
+				None => return,
+			}
+		};
+		board.play(cell);
+		print!("{}", board);
+	}
+
+	match board.state() {
+		Won(player) if computer.contains(&player) => println!("The Computer ({:?}) has won!", player),
+		Won(player) => println!("The Player ({:?}) has won!", player),
+		Draw => println!("Draw!"),
+		Playing => unreachable!(),
 	}
 }
 
 #[cfg(not(test))]
-fn computer_turn(board: &mut [[char; 3]; 3]) {
-    use rand::{thread_rng, Rng};
+fn run_tournament(numbers: &[usize]) {
+	let games = numbers.get(0).cloned().unwrap_or(20);
+	let seed = numbers.get(1).cloned().unwrap_or(1) as u32;
+	let (width, height, k) = if numbers.len() == 5 {
+		(numbers[2], numbers[3], numbers[4])
+	} else {
+		(3, 3, 3)
+	};
+	if k == 0 || k > max(width, height) {
+		return println!("can't get {} in a row on {}x{}", k, width, height);
+	}
+
+	let minimax = if width * height <= 16 { Ai::perfect() } else { Ai::with_depth(2) };
+	let mut players: Vec<Box<Strategy<Board>>> = vec![
+		Box::new(RandomMoves),
+		Box::new(minimax),
+		Box::new(Mcts { iterations: 200 }),
+		Box::new(Mcts { iterations: 2000 }),
+	];
+	println!("{}x{}, {} in a row, {} games a pair, seed {}\n", width, height, k, games, seed);
+	print!("{}", tournament(&Board::new(width, height, k), &mut players, games, seed));
+}
 
-	// Computer just does a random possible move
-	let mut rng = thread_rng();
+// Asks for a row and column, or the number of the cell counting along the
+// rows from 1. None once the input runs out.
+#[cfg(not(test))]
+fn player_turn(board: &Board) -> Option<usize> {
+	use std::io;
 
-	let mut possible_choices: Vec<char> = vec![];
-	for row_i in (0..3) {
-		for col_i in (0..3) {
-			if board[row_i][col_i] != 'X' && board[row_i][col_i] != 'O' {
-				possible_choices.push(board[row_i][col_i]);
+	loop {
+		println!("Player ({:?}), enter your field of choice (row column)!: ", board.to_move);
+		let mut ln = String::new();
+		match io::stdin().read_line(&mut ln) {
+			Ok(0) | Err(_) => return None,
+			Ok(_) => {}
+		}
+		let numbers = ln.split_whitespace().map(|s| s.parse::<usize>().unwrap_or(0)).collect::<Vec<_>>();
+		let cell = match numbers.len() {
+			1 if numbers[0] >= 1 => numbers[0] - 1,
+			2 if numbers[0] >= 1 && numbers[1] >= 1 && numbers[1] <= board.width => {
+				(numbers[0] - 1) * board.width + numbers[1] - 1
 			}
+			_ => board.cells.len(),
+		};
+		if cell >= board.cells.len() {
+			println!("That's not a field!");
+		} else if !board.is_free(cell) {
+			println!("Someone already took this field!");
+		} else {
+			return Some(cell);
 		}
 	}
+}
 
-	let choice_index: usize = rng.gen_range(0, possible_choices.len());
-	let choice = possible_choices[choice_index] as usize - 48;
-	println!("Computer chose: {}", choice);
-	let row = (choice - 1) / 3;
-	let col = (choice - 1) % 3;
-	board[row][col] = 'O';
+#[cfg(test)]
+fn cell(board: &Board, row: usize, col: usize) -> usize {
+	row * board.width + col
 }
 
-#[cfg(not(test))]
-fn draw_board(board: [[char; 3]; 3]) {
-	for row in board.iter() {
-		println!("{} {} {}", row[0], row[1], row[2]);
+#[test]
+fn test_state() {
+	assert_eq!(Board::parse(&["XXX", ".OO", "..."], 3).state(), Won(X));
+	assert_eq!(Board::parse(&["OX.", "XO.", "X.O"], 3).state(), Won(O));
+	assert_eq!(Board::parse(&["OXX", "XOO", "OXX"], 3).state(), Draw);
+	assert_eq!(Board::parse(&["OX.", "X..", "..."], 3).state(), Playing);
+
+	// k in a row on bigger boards
+	assert_eq!(Board::parse(&["XXX.", "OOO.", "....", "...."], 4).state(), Playing);
+	assert_eq!(Board::parse(&["X...", ".X..", "..XO", "OOOX"], 4).state(), Won(X));
+	assert_eq!(Board::parse(&["...O.", "..O..", ".O...", "O....", "XXX.X"], 4).state(), Won(O));
+}
+
+#[test]
+fn test_play_and_undo() {
+	let mut board = Board::new(3, 3, 3);
+	let empty = board.hash;
+	for &c in [4, 0, 2, 6, 3, 5].iter() {
+		board.play(c);
+	}
+	assert_eq!(board.state(), Playing);
+	assert_eq!(board.to_move, X);
+	let before = board.hash;
+	board.play(1);
+	assert_eq!(board.to_move, O);
+	board.undo(1);
+	assert_eq!(board.hash, before);
+
+	// the same position in a different order
+	let mut other = Board::new(3, 3, 3);
+	for &c in [3, 5, 2, 0, 4, 6].iter() {
+		other.play(c);
 	}
+	assert_eq!(other.hash, before);
+	for &c in [3, 5, 2, 0, 4, 6].iter() {
+		other.undo(c);
+	}
+	assert_eq!(other.hash, empty);
 }
 
 #[test]
-fn test_which_win() {
-	assert_eq!(which_win('X'), PlayerWin);
-	assert_eq!(which_win('O'), ComputerWin);
+fn test_takes_win_and_blocks() {
+	let mut ai = Ai::perfect();
+	// O to move can win at once
+	let mut board = Board::parse(&["XX.", "OO.", "X.."], 3);
+	assert_eq!(ai.best_move(&mut board), cell(&board, 1, 2));
+	// X has to stop O
+	let mut board = Board::parse(&["X..", "OO.", "X.."], 3);
+	assert_eq!(board.to_move, X);
+	assert_eq!(ai.best_move(&mut board), cell(&board, 1, 2));
+
+	// the same on a gomoku board, looking only two moves ahead
+	let mut ai = Ai::with_depth(2);
+	let mut board = Board::parse(&["...........",
+	                               "...........",
+	                               "..XXXXO....",
+	                               "..OO.......",
+	                               "....O......",
+	                               "X.........."], 5);
+	assert_eq!(board.to_move, O);
+	assert_eq!(ai.best_move(&mut board), cell(&board, 2, 1));
+	let mut board = Board::parse(&["...........",
+	                               "...........",
+	                               "..XXXX.....",
+	                               "..OO.......",
+	                               "....OO.....",
+	                               "..........."], 5);
+	assert_eq!(board.to_move, X);
+	let win = ai.best_move(&mut board);
+	board.play(win);
+	assert_eq!(board.state(), Won(X));
+}
+
+// Plays every way the other player can, and checks the computer never loses.
+#[cfg(test)]
+fn never_loses(ai: &mut Ai, board: &mut Board, computer: Player) {
+	match board.state() {
+		Won(player) => assert_eq!(player, computer),
+		Draw => {}
+		Playing if board.to_move == computer => {
+			let cell = ai.best_move(board);
+			board.play(cell);
+			never_loses(ai, board, computer);
+			board.undo(cell);
+		}
+		Playing => {
+			for cell in 0..board.cells.len() {
+				if board.is_free(cell) {
+					board.play(cell);
+					never_loses(ai, board, computer);
+					board.undo(cell);
+				}
+			}
+		}
+	}
+}
+
+#[test]
+fn test_perfect_play() {
+	let mut ai = Ai::perfect();
+	never_loses(&mut ai, &mut Board::new(3, 3, 3), X);
+	never_loses(&mut ai, &mut Board::new(3, 3, 3), O);
+
+	// against itself it's always a draw
+	let mut board = Board::new(3, 3, 3);
+	while board.state() == Playing {
+		let cell = ai.best_move(&mut board);
+		board.play(cell);
+	}
+	assert_eq!(board.state(), Draw);
+
+	// 3 in a row on 4x3 is a win for whoever goes first
+	let mut board = Board::new(4, 3, 3);
+	let mut ai = Ai::perfect();
+	while board.state() == Playing {
+		let cell = ai.best_move(&mut board);
+		board.play(cell);
+	}
+	assert_eq!(board.state(), Won(X));
+}
+
+#[test]
+fn test_tournament() {
+	let board = Board::new(3, 3, 3);
+	let players = || -> Vec<Box<Strategy<Board>>> {
+		vec![Box::new(RandomMoves), Box::new(Ai::perfect()), Box::new(Mcts { iterations: 500 })]
+	};
+	let results = tournament(&board, &mut players(), 10, 7);
+	let (random, minimax, mcts) = (&results.standings[0], &results.standings[1], &results.standings[2]);
+	assert_eq!(minimax.name, "minimax");
+	for s in results.standings.iter() {
+		assert_eq!(s.wins + s.draws + s.losses, 20);
+	}
+	assert_eq!(minimax.losses, 0);
+	assert!(mcts.wins > random.wins);
+	assert!(minimax.elo > random.elo && mcts.elo > random.elo);
+	assert_eq!(results.points[0][1] + results.points[1][0], 10.0);
+
+	// the same seed plays the same games
+	let again = tournament(&board, &mut players(), 10, 7);
+	assert_eq!(again.points, results.points);
+	assert_eq!(format!("{}", again), format!("{}", results));
 }
 
 #[test]
-fn test_check_win() {
-	assert_eq!(check_win([
-			['X','X','X'],
-			['4','O','O'],
-			['7','8','9']
-		]),
-		PlayerWin
-	);
-	assert_eq!(check_win([
-			['O','X','3'],
-			['X','O','6'],
-			['X','8','O']
-		]),
-		ComputerWin
-	);
-	assert_eq!(check_win([
-			['O','X','X'],
-			['X','O','O'],
-			['O','X','X']
-		]),
-		Draw
-	);
+fn test_mcts() {
+	// O can win at once, or has to stop X
+	let mut rng: XorShiftRng = SeedableRng::from_seed([1, 2, 3, 4]);
+	let mut mcts = Mcts { iterations: 1000 };
+	let board = Board::parse(&["XX.", "OO.", "X.."], 3);
+	assert_eq!(mcts.choose(&board, &mut rng), 5);
+	let board = Board::parse(&["X..", "OO.", "X.."], 3);
+	assert_eq!(mcts.choose(&board, &mut rng), 5);
 }
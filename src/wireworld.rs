@@ -1,17 +1,74 @@
 // Implements http://rosettacode.org/wiki/Wireworld
+//
+// Wireworld is implemented here as one rule set of a small, generic engine for
+// two-dimensional cellular automata on the Moore neighbourhood. The same engine
+// runs Conway's Life, Brian's Brain and any other rule that can be written as a
+// B/S rule string (or a Generations B/S/C string), on bounded or toroidal
+// worlds.
+//
+// Usage: wireworld [--rule RULE] [--wrap | --hashlife | --unbounded]
+//                  [--generations N] [--frames PREFIX] [FILE]
+//
+// FILE may be an RLE pattern (`.rle`), a Wireworld `.wi` file or plain text
+// using the characters of the Rosetta Code example below. Without a file the
+// example is used. Without `--generations` the world is animated in the
+// terminal until interrupted; with it the final state is written to stdout and,
+// if `--frames` is given, every generation is written as a PPM image.
+//
+// `--hashlife` runs the world on the Hashlife engine, which is much faster for
+// large, long runs of Wireworld. Rules whose background cells can come alive,
+// like Life, need `--unbounded`: the world is then placed on an infinite plane,
+// and only the area of the file is shown.
 
+use std::io::{self, Write};
 use std::mem;
 
-// Use VT100 cursor control sequences to animate in-place
-#[cfg(not(test))] const ANIMATE: bool = true;
+/// The example from the Rosetta Code task.
+#[cfg(not(test))]
+const EXAMPLE: &'static str = "\
++-----------+
+|tH.........|
+|.   .      |
+|   ...     |
+|.   .      |
+|Ht.. ......|
++-----------+
+";
 
-#[derive(Copy, Clone)]
-enum Cell {
+/// A set of rules for a cellular automaton on the Moore neighbourhood.
+///
+/// Cells can be converted to and from the numbered states used by the RLE
+/// format, where state 0 is the background (and the state of everything
+/// outside a bounded world).
+pub trait Rule {
+    type Cell: Copy + PartialEq;
+
+    /// Computes the next state of `cell` given its eight neighbours.
+    fn next(&self, cell: Self::Cell, neighbours: &[Self::Cell; 8]) -> Self::Cell;
+
+    fn from_state(&self, state: u8) -> Self::Cell;
+    fn to_state(&self, cell: Self::Cell) -> u8;
+
+    fn from_char(&self, c: char) -> Self::Cell;
+    fn to_char(&self, cell: Self::Cell) -> char;
+
+    /// The colour used for `cell` when writing PPM frames.
+    fn color(&self, cell: Self::Cell) -> (u8, u8, u8);
+
+    /// Whether background cells stay background whatever their neighbours.
+    fn background_is_inert(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Cell {
     Empty(char),
     Head,
     Tail,
     Conductor,
 }
+
 impl Cell {
     fn from_char(c: char) -> Cell {
         match c {
@@ -31,61 +88,959 @@ impl Cell {
     }
 }
 
-fn next_world(input: &[Cell], output: &mut [Cell], w: usize, h: usize) {
-    for i in 0..(w*h) {
-        match input[i] {
-            Cell::Empty(c)  => output[i] = Cell::Empty(c),
-            Cell::Tail      => output[i] = Cell::Conductor,
-            Cell::Head      => output[i] = Cell::Tail,
+/// The Wireworld rules. State numbers follow Golly: 0 is empty, 1 an electron
+/// head, 2 an electron tail and 3 a conductor.
+#[derive(Copy, Clone)]
+pub struct Wireworld;
+
+impl Rule for Wireworld {
+    type Cell = Cell;
+
+    fn next(&self, cell: Cell, neighbours: &[Cell; 8]) -> Cell {
+        match cell {
+            Cell::Empty(c)  => Cell::Empty(c),
+            Cell::Tail      => Cell::Conductor,
+            Cell::Head      => Cell::Tail,
             Cell::Conductor => {
-                let nc = vec![
-                    input.get(i-w-1), input.get(i-w), input.get(i-w+1),
-                    input.get(i-1),                   input.get(i+1),
-                    input.get(i+w-1), input.get(i+w), input.get(i+w+1)
-                ].iter().fold(0, |sum, &o| {
-                    if let Some(&Cell::Head) = o { sum + 1 } else { sum }
-                });
-                output[i] = if nc == 1 || nc == 2 { Cell::Head } else { Cell::Conductor };
+                let heads = neighbours.iter().filter(|&&n| n == Cell::Head).count();
+                if heads == 1 || heads == 2 { Cell::Head } else { Cell::Conductor }
+            }
+        }
+    }
+
+    fn from_state(&self, state: u8) -> Cell {
+        match state {
+            1 => Cell::Head,
+            2 => Cell::Tail,
+            3 => Cell::Conductor,
+            _ => Cell::Empty(' '),
+        }
+    }
+
+    fn to_state(&self, cell: Cell) -> u8 {
+        match cell {
+            Cell::Empty(_)  => 0,
+            Cell::Head      => 1,
+            Cell::Tail      => 2,
+            Cell::Conductor => 3,
+        }
+    }
+
+    fn from_char(&self, c: char) -> Cell { Cell::from_char(c) }
+    fn to_char(&self, cell: Cell) -> char { cell.to_char() }
+
+    fn color(&self, cell: Cell) -> (u8, u8, u8) {
+        match cell {
+            Cell::Empty(_)  => (0, 0, 0),
+            Cell::Head      => (0, 128, 255),
+            Cell::Tail      => (255, 255, 255),
+            Cell::Conductor => (255, 128, 0),
+        }
+    }
+
+    fn background_is_inert(&self) -> bool {
+        true
+    }
+}
+
+/// A Life-like rule, optionally with extra refractory ("dying") states as in
+/// the Generations family. State 0 is dead, 1 is alive, and states 2 up to
+/// `states - 1` are dying cells which count as dead for their neighbours.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LifeLike {
+    birth: [bool; 9],
+    survival: [bool; 9],
+    states: u8,
+}
+
+impl LifeLike {
+    /// Conway's Game of Life, B3/S23.
+    pub fn life() -> LifeLike {
+        LifeLike::parse("B3/S23").unwrap()
+    }
+
+    /// Brian's Brain, B2/S/3.
+    pub fn brians_brain() -> LifeLike {
+        LifeLike::parse("B2/S/3").unwrap()
+    }
+
+    /// Parses a rule string. Accepted forms are `B3/S23` and the older `23/3`
+    /// (survival first), each optionally followed by a number of states, as in
+    /// `B2/S/3`, `B2/S/C3` or `/2/3`.
+    pub fn parse(rule: &str) -> Result<LifeLike, String> {
+        let parts: Vec<&str> = rule.trim().split('/').collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err(format!("invalid rule string '{}'", rule));
+        }
+        let upper = |s: &str| s.to_uppercase();
+        let (b, s) = if upper(parts[0]).starts_with('B') || upper(parts[1]).starts_with('S') {
+            (parts[0], parts[1])
+        } else if upper(parts[0]).starts_with('S') || upper(parts[1]).starts_with('B') {
+            (parts[1], parts[0])
+        } else {
+            // Without letters the survival conditions come first.
+            (parts[1], parts[0])
+        };
+
+        let states = if parts.len() == 3 {
+            let c = parts[2].trim_left_matches(|c| c == 'C' || c == 'c');
+            match c.parse::<u8>() {
+                Ok(n) if n >= 2 => n,
+                _ => return Err(format!("invalid number of states in rule '{}'", rule)),
+            }
+        } else {
+            2
+        };
+
+        Ok(LifeLike {
+            birth: try!(LifeLike::parse_counts(b, 'B', rule)),
+            survival: try!(LifeLike::parse_counts(s, 'S', rule)),
+            states: states,
+        })
+    }
+
+    fn parse_counts(part: &str, letter: char, rule: &str) -> Result<[bool; 9], String> {
+        let mut counts = [false; 9];
+        let digits = part.trim_left_matches(|c: char| c.to_ascii_uppercase() == letter);
+        for c in digits.chars() {
+            match c.to_digit(10) {
+                Some(d) if d <= 8 => counts[d as usize] = true,
+                _ => return Err(format!("invalid neighbour count '{}' in rule '{}'", c, rule)),
+            }
+        }
+        Ok(counts)
+    }
+}
+
+impl Rule for LifeLike {
+    type Cell = u8;
+
+    fn next(&self, cell: u8, neighbours: &[u8; 8]) -> u8 {
+        match cell {
+            0 => {
+                let alive = neighbours.iter().filter(|&&n| n == 1).count();
+                if self.birth[alive] { 1 } else { 0 }
+            }
+            1 => {
+                let alive = neighbours.iter().filter(|&&n| n == 1).count();
+                if self.survival[alive] { 1 } else if self.states > 2 { 2 } else { 0 }
+            }
+            dying => if dying + 1 < self.states { dying + 1 } else { 0 },
+        }
+    }
+
+    /// States the rule does not have become dead cells.
+    fn from_state(&self, state: u8) -> u8 {
+        if state < self.states { state } else { 0 }
+    }
+
+    fn to_state(&self, cell: u8) -> u8 { cell }
+
+    fn from_char(&self, c: char) -> u8 {
+        match c {
+            'o' | 'O' | '*' | '#' | 'H' => 1,
+            'x' | 't' if self.states > 2 => 2,
+            _ => 0,
+        }
+    }
+
+    fn to_char(&self, cell: u8) -> char {
+        match cell {
+            0 => '.',
+            1 => 'O',
+            _ => 'x',
+        }
+    }
+
+    fn color(&self, cell: u8) -> (u8, u8, u8) {
+        match cell {
+            0 => (0, 0, 0),
+            1 => (255, 255, 255),
+            // Fade dying cells out towards black.
+            n => {
+                let shade = (192 * (self.states - n) as u32 / self.states as u32) as u8;
+                (0, 0, shade)
+            }
+        }
+    }
+}
+
+/// What happens at the border of the world.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Edges {
+    /// Everything outside the world is background.
+    Bounded,
+    /// The world wraps around in both directions.
+    Toroidal,
+}
+
+/// A rectangular grid of cells, stored row by row.
+#[derive(Clone, PartialEq, Debug)]
+pub struct World<C> {
+    width: usize,
+    height: usize,
+    edges: Edges,
+    cells: Vec<C>,
+}
+
+impl<C: Copy + PartialEq> World<C> {
+    pub fn new(width: usize, height: usize, edges: Edges, fill: C) -> World<C> {
+        World { width: width, height: height, edges: edges, cells: vec![fill; width * height] }
+    }
+
+    pub fn width(&self) -> usize { self.width }
+    pub fn height(&self) -> usize { self.height }
+
+    pub fn get(&self, x: usize, y: usize) -> C {
+        self.cells[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, cell: C) {
+        self.cells[y * self.width + x] = cell;
+    }
+
+    /// Returns the cell at the given offset from (x, y), or `outside` if that
+    /// lies beyond the border of a bounded world.
+    fn neighbour(&self, x: usize, y: usize, dx: isize, dy: isize, outside: C) -> C {
+        let (w, h) = (self.width as isize, self.height as isize);
+        let (mut nx, mut ny) = (x as isize + dx, y as isize + dy);
+        match self.edges {
+            Edges::Bounded => {
+                if nx < 0 || ny < 0 || nx >= w || ny >= h {
+                    return outside;
+                }
+            }
+            Edges::Toroidal => {
+                nx = (nx + w) % w;
+                ny = (ny + h) % h;
             }
         }
+        self.get(nx as usize, ny as usize)
     }
+
+    /// Computes the next generation of this world into `next`, which must have
+    /// the same dimensions.
+    pub fn step<R: Rule<Cell=C>>(&self, rule: &R, next: &mut World<C>) {
+        assert!(next.width == self.width && next.height == self.height);
+        let outside = rule.from_state(0);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let n = |dx, dy| self.neighbour(x, y, dx, dy, outside);
+                let neighbours = [
+                    n(-1, -1), n(0, -1), n(1, -1),
+                    n(-1,  0),           n(1,  0),
+                    n(-1,  1), n(0,  1), n(1,  1),
+                ];
+                next.set(x, y, rule.next(self.get(x, y), &neighbours));
+            }
+        }
+        next.edges = self.edges;
+    }
+
+    /// Renders the world as text, one line per row.
+    pub fn to_text<R: Rule<Cell=C>>(&self, rule: &R) -> String {
+        let mut text = String::with_capacity((self.width + 1) * self.height);
+        for row in self.cells.chunks(self.width) {
+            text.extend(row.iter().map(|&c| rule.to_char(c)));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Writes the world as a binary PPM image with one pixel per cell.
+    pub fn write_ppm<R: Rule<Cell=C>, W: Write>(&self, rule: &R, out: &mut W) -> io::Result<()> {
+        try!(write!(out, "P6\n{} {}\n255\n", self.width, self.height));
+        for &cell in &self.cells {
+            let (r, g, b) = rule.color(cell);
+            try!(out.write_all(&[r, g, b]));
+        }
+        Ok(())
+    }
+}
+
+/// The stepping and query operations shared by the simulation engines.
+pub trait Engine {
+    type Rule: Rule;
+
+    fn rule(&self) -> &Self::Rule;
+    fn generation(&self) -> u64;
+
+    /// Advances the simulation by the given number of generations.
+    fn run(&mut self, generations: u64);
+
+    fn step(&mut self) {
+        self.run(1);
+    }
+
+    /// Returns the cell at (x, y) of the initial world's area.
+    fn get(&self, x: usize, y: usize) -> <Self::Rule as Rule>::Cell;
+
+    /// Returns a copy of the initial world's area in its current state.
+    fn snapshot(&self) -> World<<Self::Rule as Rule>::Cell>;
+}
+
+/// A rule together with the world it is applied to. Every cell is recomputed
+/// on every generation.
+pub struct Automaton<R: Rule> {
+    rule: R,
+    world: World<R::Cell>,
+    scratch: World<R::Cell>,
+    generation: u64,
 }
 
+impl<R: Rule> Automaton<R> {
+    pub fn new(rule: R, world: World<R::Cell>) -> Automaton<R> {
+        let scratch = world.clone();
+        Automaton { rule: rule, world: world, scratch: scratch, generation: 0 }
+    }
+
+    pub fn world(&self) -> &World<R::Cell> { &self.world }
+}
+
+impl<R: Rule> Engine for Automaton<R> {
+    type Rule = R;
+
+    fn rule(&self) -> &R { &self.rule }
+    fn generation(&self) -> u64 { self.generation }
+
+    fn run(&mut self, generations: u64) {
+        for _ in 0..generations {
+            self.world.step(&self.rule, &mut self.scratch);
+            mem::swap(&mut self.world, &mut self.scratch);
+            self.generation += 1;
+        }
+    }
+
+    fn get(&self, x: usize, y: usize) -> R::Cell { self.world.get(x, y) }
+    fn snapshot(&self) -> World<R::Cell> { self.world.clone() }
+}
+
+pub use hashlife::Hashlife;
+
+/// Hashlife (Gosper's algorithm): the plane is stored as a quadtree of
+/// canonical, shared nodes, and the future of each node is memoised, so
+/// repetitive patterns can be advanced by huge numbers of generations at once.
+///
+/// A node of level k covers 2^k x 2^k cells. Its successor by 2^j generations
+/// (for j <= k - 2) is the centre node of level k - 1, which only depends on
+/// the node itself because no signal travels faster than one cell per
+/// generation.
+///
+/// Cells are stored by their state number, so Wireworld's empty cells all come
+/// back as blanks.
+mod hashlife {
+    use std::collections::HashMap;
+    use super::{Rule, World, Edges, Engine};
+
+    #[derive(Copy, Clone)]
+    struct Node {
+        level: u8,
+        /// The north-west, north-east, south-west and south-east quadrants.
+        children: [usize; 4],
+        /// The state of a level 0 node.
+        state: u8,
+        /// The number of cells not in the background state.
+        population: u64,
+    }
+
+    #[derive(PartialEq, Eq, Hash)]
+    enum Key {
+        Leaf(u8),
+        Branch([usize; 4]),
+    }
+
+    pub struct Hashlife<R: Rule> {
+        rule: R,
+        nodes: Vec<Node>,
+        index: HashMap<Key, usize>,
+        /// The empty node of each level.
+        empty: Vec<usize>,
+        successors: HashMap<(usize, u8), usize>,
+        root: usize,
+        /// The plane coordinates of the north-west corner of the root.
+        origin: (i64, i64),
+        width: usize,
+        height: usize,
+        generation: u64,
+    }
+
+    impl<R: Rule> Hashlife<R> {
+        /// Builds a Hashlife engine for a bounded world. This is only possible
+        /// for rules whose background cells never change, like Wireworld's,
+        /// since Hashlife itself always simulates an unbounded plane.
+        pub fn new(rule: R, world: World<R::Cell>) -> Result<Hashlife<R>, String> {
+            if world.edges == Edges::Toroidal {
+                return Err("Hashlife cannot simulate toroidal worlds".to_string());
+            }
+            if !rule.background_is_inert() {
+                return Err("Hashlife can only bound worlds whose background never changes; \
+                            use an unbounded plane instead".to_string());
+            }
+            Hashlife::unbounded(rule, world)
+        }
+
+        /// Builds a Hashlife engine for an unbounded plane, initially empty
+        /// except for the given world, whose edges are ignored.
+        pub fn unbounded(rule: R, world: World<R::Cell>) -> Result<Hashlife<R>, String> {
+            let background = rule.from_state(0);
+            if rule.next(background, &[background; 8]) != background {
+                return Err("the rule changes empty space, so the plane cannot be unbounded"
+                           .to_string());
+            }
+
+            let mut level = 2;
+            while (1 << level) < world.width || (1 << level) < world.height {
+                level += 1;
+            }
+            let mut life = Hashlife {
+                rule: rule,
+                nodes: vec![],
+                index: HashMap::new(),
+                empty: vec![],
+                successors: HashMap::new(),
+                root: 0,
+                origin: (0, 0),
+                width: world.width,
+                height: world.height,
+                generation: 0,
+            };
+            life.root = life.build(&world, level, 0, 0);
+            Ok(life)
+        }
+
+        /// The number of cells not in the background state, on the whole plane.
+        pub fn population(&self) -> u64 {
+            self.nodes[self.root].population
+        }
+
+        /// Returns the cell at (x, y) of the plane, where (0, 0) is the north-west
+        /// corner of the initial world.
+        pub fn get_at(&self, x: i64, y: i64) -> R::Cell {
+            let (mut x, mut y) = (x - self.origin.0, y - self.origin.1);
+            let mut node = self.nodes[self.root];
+            let size = 1i64 << node.level;
+            if x < 0 || y < 0 || x >= size || y >= size {
+                return self.rule.from_state(0);
+            }
+            while node.level > 0 {
+                let half = 1i64 << (node.level - 1);
+                let quadrant = (if x >= half { 1 } else { 0 }) + (if y >= half { 2 } else { 0 });
+                x %= half;
+                y %= half;
+                node = self.nodes[node.children[quadrant]];
+            }
+            self.rule.from_state(node.state)
+        }
+
+        fn insert(&mut self, key: Key, node: Node) -> usize {
+            if let Some(&id) = self.index.get(&key) {
+                return id;
+            }
+            self.nodes.push(node);
+            self.index.insert(key, self.nodes.len() - 1);
+            self.nodes.len() - 1
+        }
+
+        fn leaf(&mut self, state: u8) -> usize {
+            let node = Node {
+                level: 0,
+                children: [0; 4],
+                state: state,
+                population: if state == 0 { 0 } else { 1 },
+            };
+            self.insert(Key::Leaf(state), node)
+        }
+
+        fn join(&mut self, nw: usize, ne: usize, sw: usize, se: usize) -> usize {
+            let children = [nw, ne, sw, se];
+            let node = Node {
+                level: self.nodes[nw].level + 1,
+                children: children,
+                state: 0,
+                population: children.iter().map(|&c| self.nodes[c].population).sum(),
+            };
+            self.insert(Key::Branch(children), node)
+        }
+
+        fn empty(&mut self, level: u8) -> usize {
+            while self.empty.len() <= level as usize {
+                let id = match self.empty.last() {
+                    None => self.leaf(0),
+                    Some(&e) => self.join(e, e, e, e),
+                };
+                self.empty.push(id);
+            }
+            self.empty[level as usize]
+        }
+
+        fn build(&mut self, world: &World<R::Cell>, level: u8, x: usize, y: usize) -> usize {
+            if x >= world.width || y >= world.height {
+                return self.empty(level);
+            }
+            if level == 0 {
+                let state = self.rule.to_state(world.get(x, y));
+                return self.leaf(state);
+            }
+            let half = 1 << (level - 1);
+            let nw = self.build(world, level - 1, x, y);
+            let ne = self.build(world, level - 1, x + half, y);
+            let sw = self.build(world, level - 1, x, y + half);
+            let se = self.build(world, level - 1, x + half, y + half);
+            self.join(nw, ne, sw, se)
+        }
+
+        /// Returns the centre of a node, one level down.
+        fn centre(&mut self, id: usize) -> usize {
+            let c = self.nodes[id].children;
+            let (nw, ne, sw, se) = (self.nodes[c[0]], self.nodes[c[1]], self.nodes[c[2]], self.nodes[c[3]]);
+            self.join(nw.children[3], ne.children[2], sw.children[1], se.children[0])
+        }
+
+        /// Advances the 4x4 cells of a level 2 node by one generation, the
+        /// naive way, and returns the 2x2 centre.
+        fn base(&mut self, node: Node) -> usize {
+            let mut grid = [[0u8; 4]; 4];
+            for (q, &quadrant) in node.children.iter().enumerate() {
+                for (p, &leaf) in self.nodes[quadrant].children.iter().enumerate() {
+                    grid[(q / 2) * 2 + p / 2][(q % 2) * 2 + p % 2] = self.nodes[leaf].state;
+                }
+            }
+            let mut centre = [0; 4];
+            for (i, &(x, y)) in [(1, 1), (2, 1), (1, 2), (2, 2)].iter().enumerate() {
+                let state = {
+                    let rule = &self.rule;
+                    let n = |dx: usize, dy: usize| rule.from_state(grid[y + dy - 1][x + dx - 1]);
+                    let neighbours = [
+                        n(0, 0), n(1, 0), n(2, 0),
+                        n(0, 1),          n(2, 1),
+                        n(0, 2), n(1, 2), n(2, 2),
+                    ];
+                    rule.to_state(rule.next(n(1, 1), &neighbours))
+                };
+                centre[i] = self.leaf(state);
+            }
+            self.join(centre[0], centre[1], centre[2], centre[3])
+        }
+
+        /// Returns the centre of node `id` advanced by 2^j generations.
+        fn successor(&mut self, id: usize, j: u8) -> usize {
+            if let Some(&result) = self.successors.get(&(id, j)) {
+                return result;
+            }
+            let node = self.nodes[id];
+            let k = node.level;
+            assert!(k >= 2 && j <= k - 2);
+
+            let result = if node.population == 0 {
+                self.empty(k - 1)
+            } else if k == 2 {
+                self.base(node)
+            } else {
+                // The nine overlapping sub-squares of level k - 1.
+                let (a, b, c, d) = (node.children[0], node.children[1],
+                                    node.children[2], node.children[3]);
+                let (an, bn, cn, dn) = (self.nodes[a], self.nodes[b], self.nodes[c], self.nodes[d]);
+                let n01 = self.join(an.children[1], bn.children[0], an.children[3], bn.children[2]);
+                let n10 = self.join(an.children[2], an.children[3], cn.children[0], cn.children[1]);
+                let n11 = self.join(an.children[3], bn.children[2], cn.children[1], dn.children[0]);
+                let n12 = self.join(bn.children[2], bn.children[3], dn.children[0], dn.children[1]);
+                let n21 = self.join(cn.children[1], dn.children[0], cn.children[3], dn.children[2]);
+                let squares = [a, n01, b, n10, n11, n12, c, n21, d];
+
+                // At full speed both halves of the step advance by 2^(k - 3)
+                // generations; otherwise the first half only takes centres and
+                // the second half does all 2^j generations.
+                let full = j == k - 2;
+                let mut r = [0; 9];
+                for (i, &square) in squares.iter().enumerate() {
+                    r[i] = if full { self.successor(square, k - 3) } else { self.centre(square) };
+                }
+                let second = if full { k - 3 } else { j };
+                let nw = self.join(r[0], r[1], r[3], r[4]);
+                let ne = self.join(r[1], r[2], r[4], r[5]);
+                let sw = self.join(r[3], r[4], r[6], r[7]);
+                let se = self.join(r[4], r[5], r[7], r[8]);
+                let nw = self.successor(nw, second);
+                let ne = self.successor(ne, second);
+                let sw = self.successor(sw, second);
+                let se = self.successor(se, second);
+                self.join(nw, ne, sw, se)
+            };
+            self.successors.insert((id, j), result);
+            result
+        }
+
+        /// Surrounds the root with empty space, doubling its size.
+        fn expand(&mut self) {
+            let root = self.nodes[self.root];
+            let e = self.empty(root.level - 1);
+            let nw = self.join(e, e, e, root.children[0]);
+            let ne = self.join(e, e, root.children[1], e);
+            let sw = self.join(e, root.children[2], e, e);
+            let se = self.join(root.children[3], e, e, e);
+            self.root = self.join(nw, ne, sw, se);
+            let half = 1i64 << (root.level - 1);
+            self.origin = (self.origin.0 - half, self.origin.1 - half);
+        }
+
+        /// Advances the whole plane by 2^j generations.
+        fn advance(&mut self, j: u8) {
+            // Everything must lie in the centre of the root, far enough from
+            // its edges that 2^j generations cannot carry it outside the result.
+            loop {
+                let root = self.root;
+                let centre = self.centre(root);
+                if self.nodes[root].level >= j + 3 &&
+                   self.nodes[centre].population == self.nodes[root].population {
+                    break;
+                }
+                self.expand();
+            }
+            self.expand();
+            let level = self.nodes[self.root].level;
+            let root = self.root;
+            self.root = self.successor(root, j);
+            let quarter = 1i64 << (level - 2);
+            self.origin = (self.origin.0 + quarter, self.origin.1 + quarter);
+            self.generation += 1 << j;
+        }
+
+        fn fill(&self, id: usize, x: i64, y: i64, world: &mut World<R::Cell>) {
+            let node = self.nodes[id];
+            let size = 1i64 << node.level;
+            if node.population == 0 || x >= self.width as i64 || y >= self.height as i64 ||
+               x + size <= 0 || y + size <= 0 {
+                return;
+            }
+            if node.level == 0 {
+                world.set(x as usize, y as usize, self.rule.from_state(node.state));
+                return;
+            }
+            let half = size / 2;
+            for (q, &child) in node.children.iter().enumerate() {
+                let (dx, dy) = ((q % 2) as i64 * half, (q / 2) as i64 * half);
+                self.fill(child, x + dx, y + dy, world);
+            }
+        }
+    }
+
+    impl<R: Rule> Engine for Hashlife<R> {
+        type Rule = R;
+
+        fn rule(&self) -> &R { &self.rule }
+        fn generation(&self) -> u64 { self.generation }
+
+        fn run(&mut self, generations: u64) {
+            for j in 0..64 {
+                if generations & (1 << j) != 0 {
+                    self.advance(j);
+                }
+            }
+        }
+
+        fn get(&self, x: usize, y: usize) -> R::Cell {
+            assert!(x < self.width && y < self.height);
+            self.get_at(x as i64, y as i64)
+        }
+
+        fn snapshot(&self) -> World<R::Cell> {
+            let mut world = World::new(self.width, self.height, Edges::Bounded,
+                                       self.rule.from_state(0));
+            self.fill(self.root, self.origin.0, self.origin.1, &mut world);
+            world
+        }
+    }
+}
+
+/// Reads plain text, one row per line, using the rule's own characters. Short
+/// lines are padded with background cells.
+pub fn parse_text<R: Rule>(text: &str, rule: &R, edges: Edges) -> World<R::Cell> {
+    let lines: Vec<&str> = text.lines().collect();
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let mut world = World::new(width, lines.len(), edges, rule.from_state(0));
+    for (y, line) in lines.iter().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            world.set(x, y, rule.from_char(c));
+        }
+    }
+    world
+}
+
+/// Reads a Wireworld `.wi` file: an optional first line holding the width and
+/// height, followed by one line per row where `#` is a conductor, `@` an
+/// electron head, `~` an electron tail and anything else is empty.
+pub fn parse_wi<R: Rule>(text: &str, rule: &R, edges: Edges) -> Result<World<R::Cell>, String> {
+    let mut lines: Vec<&str> = text.lines().collect();
+    let header: Vec<usize> = lines.first()
+        .map(|l| l.split_whitespace().filter_map(|n| n.parse().ok()).collect())
+        .unwrap_or(vec![]);
+    let (width, height) = if header.len() == 2 {
+        lines.remove(0);
+        (header[0], header[1])
+    } else {
+        (lines.iter().map(|l| l.chars().count()).max().unwrap_or(0), lines.len())
+    };
+
+    let mut world = World::new(width, height, edges, rule.from_state(0));
+    for (y, line) in lines.iter().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            let state = match c {
+                '@' => 1,
+                '~' => 2,
+                '#' => 3,
+                _ => continue,
+            };
+            if x >= width || y >= height {
+                return Err(format!("cell ({}, {}) lies outside the {}x{} world", x, y, width, height));
+            }
+            world.set(x, y, rule.from_state(state));
+        }
+    }
+    Ok(world)
+}
+
+/// Returns the rule named in the header line of an RLE pattern, if any.
+pub fn rle_rule(text: &str) -> Option<String> {
+    text.lines()
+        .map(|l| l.trim())
+        .find(|l| l.starts_with('x'))
+        .and_then(|header| {
+            header.split(',')
+                .map(|field| field.splitn(2, '=').map(|s| s.trim()).collect::<Vec<_>>())
+                .find(|kv| kv.len() == 2 && kv[0] == "rule")
+                .map(|kv| kv[1].to_string())
+        })
+}
+
+/// Reads a pattern in the run length encoded format used by Golly and most
+/// other Life programs, including its multi-state extension.
+pub fn parse_rle<R: Rule>(text: &str, rule: &R, edges: Edges) -> Result<World<R::Cell>, String> {
+    let mut lines = text.lines().map(|l| l.trim()).filter(|l| !l.starts_with('#'));
+
+    let header = try!(lines.next().ok_or("missing RLE header".to_string()));
+    let mut size = (None, None);
+    for field in header.split(',') {
+        let kv: Vec<&str> = field.splitn(2, '=').map(|s| s.trim()).collect();
+        if kv.len() != 2 {
+            continue;
+        }
+        let value = kv[1].parse::<usize>();
+        match kv[0] {
+            "x" => size.0 = value.ok(),
+            "y" => size.1 = value.ok(),
+            _ => {}
+        }
+    }
+    let (width, height) = match size {
+        (Some(w), Some(h)) => (w, h),
+        _ => return Err(format!("invalid RLE header '{}'", header)),
+    };
+
+    let mut world = World::new(width, height, edges, rule.from_state(0));
+    let (mut x, mut y) = (0, 0);
+    let mut run = 0;
+    let mut prefix = None;
+    'body: for line in lines {
+        for c in line.chars() {
+            let state = match c {
+                '0'...'9' => {
+                    run = run * 10 + c.to_digit(10).unwrap() as usize;
+                    continue;
+                }
+                'p'...'y' => {
+                    prefix = Some((c as u8 - b'p' + 1) as usize);
+                    continue;
+                }
+                '!' => break 'body,
+                '$' => {
+                    y += if run == 0 { 1 } else { run };
+                    x = 0;
+                    run = 0;
+                    continue;
+                }
+                'b' | '.' => 0,
+                'o' => 1,
+                'A'...'X' => {
+                    // Only states up to 255 fit, so "yP" to "yX" are out of range.
+                    let state = prefix.unwrap_or(0) * 24 + (c as u8 - b'A' + 1) as usize;
+                    if state > 255 {
+                        return Err(format!("RLE state {} out of range", state));
+                    }
+                    state as u8
+                }
+                c if c.is_whitespace() => continue,
+                c => return Err(format!("unexpected '{}' in RLE pattern", c)),
+            };
+            let count = if run == 0 { 1 } else { run };
+            if state != 0 {
+                if x + count > width || y >= height {
+                    return Err(format!("RLE pattern exceeds its {}x{} header", width, height));
+                }
+                for i in 0..count {
+                    world.set(x + i, y, rule.from_state(state));
+                }
+            }
+            x += count;
+            run = 0;
+            prefix = None;
+        }
+    }
+    Ok(world)
+}
+
+/// Command line options, see the usage note at the top of the file.
+#[derive(Debug, PartialEq)]
+struct Options {
+    rule: Option<String>,
+    edges: Edges,
+    hashlife: bool,
+    unbounded: bool,
+    generations: Option<u64>,
+    frames: Option<String>,
+    file: Option<String>,
+}
+
+fn parse_args<I: Iterator<Item=String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        rule: None,
+        edges: Edges::Bounded,
+        hashlife: false,
+        unbounded: false,
+        generations: None,
+        frames: None,
+        file: None,
+    };
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--rule" => options.rule = Some(try!(args.next().ok_or("--rule needs a value"))),
+            "--wrap" => options.edges = Edges::Toroidal,
+            "--hashlife" => options.hashlife = true,
+            "--unbounded" => {
+                options.hashlife = true;
+                options.unbounded = true;
+            }
+            "--generations" => {
+                let n = try!(args.next().ok_or("--generations needs a value"));
+                options.generations = Some(try!(n.parse().map_err(|_| {
+                    format!("invalid number of generations '{}'", n)
+                })));
+            }
+            "--frames" => options.frames = Some(try!(args.next().ok_or("--frames needs a value"))),
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+            _ => options.file = Some(arg),
+        }
+    }
+    if options.frames.is_some() && options.generations.is_none() {
+        return Err("--frames can only be used together with --generations".to_string());
+    }
+    if options.unbounded && options.edges == Edges::Toroidal {
+        return Err("--unbounded and --wrap cannot be used together".to_string());
+    }
+    Ok(options)
+}
+
+/// Loads the world and runs it on the engine chosen on the command line.
 #[cfg(not(test))]
-fn main() {
+fn run<R: Rule>(rule: R, text: &str, options: &Options) -> Result<(), String> {
+    let file = options.file.as_ref().map(|f| &f[..]).unwrap_or("");
+    let world = if file.ends_with(".rle") {
+        try!(parse_rle(text, &rule, options.edges))
+    } else if file.ends_with(".wi") {
+        try!(parse_wi(text, &rule, options.edges))
+    } else {
+        parse_text(text, &rule, options.edges)
+    };
+
+    if options.unbounded {
+        drive(try!(Hashlife::unbounded(rule, world)), options)
+    } else if options.hashlife {
+        drive(try!(Hashlife::new(rule, world)), options)
+    } else {
+        drive(Automaton::new(rule, world), options)
+    }
+}
+
+/// Either animates the engine forever or runs it headless.
+#[cfg(not(test))]
+fn drive<E: Engine>(mut engine: E, options: &Options) -> Result<(), String> {
+    use std::fs::File;
+    use std::io::BufWriter;
     use std::thread::sleep_ms;
-    let (w, h) = (14usize, 7usize);
-    let mut world: Vec<Cell> = "
-+-----------+
-|tH.........|
-|.   .      |
-|   ...     |
-|.   .      |
-|Ht.. ......|
-+-----------+
-".chars().map(|c| Cell::from_char(c)).collect();
-    let mut next: Vec<Cell> = world.clone();
 
-    loop {
-        for i in 0..(w*h) {
-            print!("{}", world[i].to_char());
+    let generations = match options.generations {
+        Some(n) => n,
+        None => {
+            loop {
+                let world = engine.snapshot();
+                print!("{}", world.to_text(engine.rule()));
+                engine.step();
+                // Use VT100 cursor control sequences to animate in-place
+                print!("\x1b[{}A", world.height());
+                sleep_ms(100);
+            }
+        }
+    };
+
+    let write_frame = |engine: &E, prefix: &str| -> io::Result<()> {
+        let name = format!("{}{:06}.ppm", prefix, engine.generation());
+        let mut out = BufWriter::new(try!(File::create(&name)));
+        engine.snapshot().write_ppm(engine.rule(), &mut out)
+    };
+    match options.frames {
+        Some(ref prefix) => {
+            for _ in 0..generations {
+                try!(write_frame(&engine, prefix).map_err(|e| e.to_string()));
+                engine.step();
+            }
+            try!(write_frame(&engine, prefix).map_err(|e| e.to_string()));
         }
-        print!("\n");
-        next_world(&world, &mut next, 14, 7);
-        mem::swap(&mut world, &mut next);
+        None => engine.run(generations),
+    }
+    print!("{}", engine.snapshot().to_text(engine.rule()));
+    Ok(())
+}
+
+#[cfg(not(test))]
+fn main() {
+    use std::env;
+    use std::fs::File;
+    use std::io::Read;
+    use std::process;
+
+    let result = parse_args(env::args().skip(1)).and_then(|options| {
+        let text = match options.file {
+            Some(ref file) => {
+                let mut text = String::new();
+                try!(File::open(file).and_then(|mut f| f.read_to_string(&mut text))
+                                     .map_err(|e| format!("{}: {}", file, e)));
+                text
+            }
+            None => EXAMPLE.to_string(),
+        };
 
-        if ANIMATE {
-            print!("\x1b[{}A", 8);
-            print!("\x1b[{}D", 14);
-            sleep_ms(100);
+        let rule = options.rule.clone()
+            .or_else(|| rle_rule(&text))
+            .unwrap_or("wireworld".to_string());
+        match &rule.to_lowercase()[..] {
+            "wireworld" => run(Wireworld, &text, &options),
+            "life" => run(LifeLike::life(), &text, &options),
+            "brain" | "briansbrain" | "brians-brain" => run(LifeLike::brians_brain(), &text, &options),
+            _ => run(try!(LifeLike::parse(&rule)), &text, &options),
         }
+    });
+
+    if let Err(e) = result {
+        let _ = writeln!(&mut io::stderr(), "wireworld: {}", e);
+        process::exit(1);
     }
 }
 
-#[test]
-fn test() {
-    let (w, h) = (14usize, 7usize);
-    let mut world: Vec<Cell> = "
+#[cfg(test)]
+mod test {
+    use super::{Wireworld, LifeLike, Cell, Edges, World, Automaton, Hashlife, Engine, Options, Rule};
+    use super::{parse_text, parse_wi, parse_rle, rle_rule, parse_args};
+
+    const EXAMPLE: &'static str = "\
 +-----------+
 |tH.........|
 |.   .      |
@@ -93,16 +1048,48 @@ fn test() {
 |.   .      |
 |Ht.. ......|
 +-----------+
-".chars().map(|c| Cell::from_char(c)).collect();
-    let mut next: Vec<Cell> = world.clone();
+";
 
-    for _ in 0..10 {
-        next_world(&world, &mut next, w, h);
-        mem::swap(&mut world, &mut next);
+    /// A small xorshift generator, so the random worlds are reproducible.
+    fn random_world<R: Rule>(rule: &R, width: usize, height: usize, states: u32,
+                             seed: u32) -> World<R::Cell> {
+        let mut x = seed;
+        let mut world = World::new(width, height, Edges::Bounded, rule.from_state(0));
+        for y in 0..height {
+            for i in 0..width {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                world.set(i, y, rule.from_state((x % states) as u8));
+            }
+        }
+        world
     }
 
-    let result: String = world.iter().map(|c| c.to_char()).collect();
-    let correct = "
+    /// Checks that both engines agree after each of the given numbers of
+    /// generations.
+    fn cross_validate<R: Rule, A: Engine<Rule=R>, B: Engine<Rule=R>>(mut a: A, mut b: B,
+                                                                   runs: &[u64]) {
+        for &n in runs {
+            a.run(n);
+            b.run(n);
+            assert_eq!(a.generation(), b.generation());
+            let (wa, wb) = (a.snapshot(), b.snapshot());
+            let states = |w: &World<R::Cell>, rule: &R| {
+                w.cells.iter().map(|&c| rule.to_state(c)).collect::<Vec<_>>()
+            };
+            assert!(states(&wa, a.rule()) == states(&wb, b.rule()),
+                    "engines disagree at generation {}", a.generation());
+        }
+    }
+
+    #[test]
+    fn test() {
+        let world = parse_text(EXAMPLE, &Wireworld, Edges::Bounded);
+        let mut automaton = Automaton::new(Wireworld, world);
+        automaton.run(10);
+
+        let correct = "\
 +-----------+
 |.tH.tH.tH.t|
 |H   t      |
@@ -111,5 +1098,188 @@ fn test() {
 |t.tH ......|
 +-----------+
 ";
-    assert_eq!(result, correct);
+        assert_eq!(automaton.world().to_text(&Wireworld), correct);
+    }
+
+    #[test]
+    fn corners_do_not_wrap_when_bounded() {
+        // A head in a corner used to make the neighbour lookup underflow.
+        let world = parse_text("H.\n..\n", &Wireworld, Edges::Bounded);
+        let mut automaton = Automaton::new(Wireworld, world);
+        automaton.step();
+        assert_eq!(automaton.world().to_text(&Wireworld), "tH\nHH\n");
+        automaton.step();
+        assert_eq!(automaton.world().to_text(&Wireworld), ".t\ntt\n");
+    }
+
+    #[test]
+    fn glider_on_torus() {
+        let glider = ".O...\n..O..\nOOO..\n.....\n.....\n";
+        let world = parse_text(glider, &LifeLike::life(), Edges::Toroidal);
+        let mut automaton = Automaton::new(LifeLike::life(), world.clone());
+        // A glider moves one cell diagonally every four generations, so it
+        // returns to its starting point after going once around the torus.
+        automaton.run(20);
+        assert_eq!(automaton.world(), &world);
+        assert_eq!(automaton.generation(), 20);
+    }
+
+    #[test]
+    fn brians_brain() {
+        let brain = LifeLike::brians_brain();
+        let world = parse_text("....\n.OO.\n....\n", &brain, Edges::Bounded);
+        let mut automaton = Automaton::new(brain, world);
+        automaton.step();
+        assert_eq!(automaton.world().to_text(&brain), ".OO.\n.xx.\n.OO.\n");
+        automaton.step();
+        assert_eq!(automaton.world().to_text(&brain), ".xx.\nO..O\n.xx.\n");
+    }
+
+    #[test]
+    fn rule_strings() {
+        let life = LifeLike::life();
+        assert_eq!(LifeLike::parse("b3/s23"), Ok(life));
+        assert_eq!(LifeLike::parse("S23/B3"), Ok(life));
+        assert_eq!(LifeLike::parse("23/3"), Ok(life));
+        assert_eq!(LifeLike::parse("B2/S/C3"), Ok(LifeLike::brians_brain()));
+        assert_eq!(LifeLike::parse("/2/3"), Ok(LifeLike::brians_brain()));
+        assert!(LifeLike::parse("B9/S23").is_err());
+        assert!(LifeLike::parse("B3S23").is_err());
+        assert!(LifeLike::parse("B2/S/1").is_err());
+    }
+
+    #[test]
+    fn rle() {
+        let text = "#N Glider\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+        assert_eq!(rle_rule(text), Some("B3/S23".to_string()));
+        let life = LifeLike::life();
+        let world = parse_rle(text, &life, Edges::Bounded).unwrap();
+        assert_eq!(world.to_text(&life), ".O.\n..O\nOOO\n");
+
+        let wires = "x = 4, y = 2, rule = WireWorld\nBC2A$3C!";
+        let world = parse_rle(wires, &Wireworld, Edges::Bounded).unwrap();
+        assert_eq!(world.to_text(&Wireworld), "t.HH\n... \n");
+
+        assert!(parse_rle("x = 2, y = 1\n3o!", &life, Edges::Bounded).is_err());
+
+        // the highest state there is, and one past it
+        assert!(parse_rle("x = 1, y = 1\nyO!", &Wireworld, Edges::Bounded).is_ok());
+        assert_eq!(parse_rle("x = 1, y = 1\nyP!", &Wireworld, Edges::Bounded).err(),
+                   Some("RLE state 256 out of range".to_string()));
+        assert!(parse_rle("x = 1, y = 1\nyX!", &Wireworld, Edges::Bounded).is_err());
+    }
+
+    #[test]
+    fn wi() {
+        let world = parse_wi("4 2\n~@##\n #\n", &Wireworld, Edges::Bounded).unwrap();
+        assert_eq!(world.width(), 4);
+        assert_eq!(world.get(0, 0), Cell::Tail);
+        assert_eq!(world.get(1, 0), Cell::Head);
+        assert_eq!(world.get(1, 1), Cell::Conductor);
+        assert_eq!(world.get(3, 1), Wireworld.from_state(0));
+        let unsized_world = parse_wi("~@##\n #\n", &Wireworld, Edges::Bounded).unwrap();
+        assert_eq!(unsized_world, world);
+    }
+
+    #[test]
+    fn ppm_frame() {
+        let mut world = World::new(2, 1, Edges::Bounded, Cell::Empty(' '));
+        world.set(1, 0, Cell::Head);
+        let mut out = Vec::new();
+        world.write_ppm(&Wireworld, &mut out).unwrap();
+        assert_eq!(out, b"P6\n2 1\n255\n\x00\x00\x00\x00\x80\xff".to_vec());
+    }
+
+    #[test]
+    fn arguments() {
+        let args = |s: &str| parse_args(s.split_whitespace().map(|a| a.to_string()));
+        assert_eq!(args("--wrap --rule B36/S23 --generations 10 --frames out/f glider.rle"),
+                   Ok(Options {
+                       rule: Some("B36/S23".to_string()),
+                       edges: Edges::Toroidal,
+                       hashlife: false,
+                       unbounded: false,
+                       generations: Some(10),
+                       frames: Some("out/f".to_string()),
+                       file: Some("glider.rle".to_string()),
+                   }));
+        assert!(args("--generations ten").is_err());
+        assert!(args("--frames f").is_err());
+        assert!(args("--fast").is_err());
+        assert!(args("--wrap --unbounded").is_err());
+        let unbounded = args("--unbounded").unwrap();
+        assert!(unbounded.hashlife && unbounded.unbounded);
+    }
+
+    #[test]
+    fn hashlife_wireworld() {
+        let world = parse_text(EXAMPLE, &Wireworld, Edges::Bounded);
+        let naive = Automaton::new(Wireworld, world.clone());
+        let hashlife = Hashlife::new(Wireworld, world).unwrap();
+        assert_eq!(hashlife.get(2, 1), Cell::Head);
+        cross_validate(naive, hashlife, &[1, 1, 2, 3, 10, 37, 64, 100]);
+    }
+
+    #[test]
+    fn hashlife_random_wireworld() {
+        for seed in 1..6 {
+            let world = random_world(&Wireworld, 37, 21, 4, seed);
+            let naive = Automaton::new(Wireworld, world.clone());
+            let hashlife = Hashlife::new(Wireworld, world).unwrap();
+            cross_validate(naive, hashlife, &[1, 5, 8, 13, 64]);
+        }
+    }
+
+    #[test]
+    fn hashlife_unbounded_life() {
+        // An R-pentomino in the middle of a world big enough that it does not
+        // reach the edges, so bounded and unbounded runs are the same.
+        let life = LifeLike::life();
+        let mut world = World::new(160, 160, Edges::Bounded, 0);
+        for &(x, y) in &[(80, 79), (81, 79), (79, 80), (80, 80), (80, 81)] {
+            world.set(x, y, 1);
+        }
+        let naive = Automaton::new(life, world.clone());
+        let hashlife = Hashlife::unbounded(life, world).unwrap();
+        cross_validate(naive, hashlife, &[1, 2, 7, 16, 24]);
+    }
+
+    #[test]
+    fn hashlife_unbounded_glider() {
+        let life = LifeLike::life();
+        let world = parse_text(".O.\n..O\nOOO\n", &life, Edges::Bounded);
+        let mut hashlife = Hashlife::unbounded(life, world).unwrap();
+        hashlife.run(4 * 1000000000);
+        assert_eq!(hashlife.generation(), 4000000000);
+        assert_eq!(hashlife.population(), 5);
+        // The glider has left the original area.
+        assert_eq!(hashlife.snapshot().to_text(&life), "...\n...\n...\n");
+        assert_eq!(hashlife.get_at(1000000001, 1000000000), 1);
+        assert_eq!(hashlife.get_at(1000000000, 1000000002), 1);
+    }
+
+    #[test]
+    fn hashlife_random_brians_brain() {
+        let brain = LifeLike::brians_brain();
+        let world = random_world(&brain, 30, 30, 3, 7);
+        let mut padded = World::new(120, 120, Edges::Bounded, 0);
+        for y in 0..30 {
+            for x in 0..30 {
+                padded.set(x + 45, y + 45, world.get(x, y));
+            }
+        }
+        let naive = Automaton::new(brain, padded.clone());
+        let hashlife = Hashlife::unbounded(brain, padded).unwrap();
+        cross_validate(naive, hashlife, &[1, 3, 4, 9, 16]);
+    }
+
+    #[test]
+    fn hashlife_rejects_unsupported_worlds() {
+        let life = LifeLike::life();
+        let world = World::new(4, 4, Edges::Bounded, 0);
+        assert!(Hashlife::new(life, world.clone()).is_err());
+        let wrapped = World::new(4, 4, Edges::Toroidal, Cell::Empty(' '));
+        assert!(Hashlife::new(Wireworld, wrapped).is_err());
+        assert!(Hashlife::unbounded(LifeLike::parse("B0/S8").unwrap(), world).is_err());
+    }
 }